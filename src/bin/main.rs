@@ -1,29 +1,52 @@
 use actix_web::{
     get,
+    http::header::{CacheControl, CacheDirective, ContentDisposition},
     middleware::Logger,
     post,
     web::{self, Data},
-    App, HttpResponse, HttpServer, Responder, Result,
+    App, HttpRequest, HttpResponse, HttpServer, Responder, Result,
 };
+use anyhow::anyhow;
 use env_logger::Env;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use rustix::analytics;
+use rustix::columnar::ColumnarFormat;
 use rustix::envs::Envs;
-use rustix::error::RustixErr;
+use rustix::error::{self, ErrorBody, RustixErr};
+use rustix::fx;
+use rustix::jobs::{JobDef, JobInfo, JobRun, Scheduler};
+use rustix::ledger::{self, Ledgers};
+use rustix::lots::{self, Lots};
+use rustix::market::{self, MarketStatusReq};
+use rustix::stream::{FormatQuery, StreamFormat};
 use rustix::trading::{self, Trading};
+use rustix::validate::Validate;
 
 extern crate lazy_static;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct Filter {
     filter: String,
 }
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct Id {
     id: String,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct Name {
+    name: String,
+}
+
+#[derive(Serialize, ToSchema)]
 struct Success {
     success: bool,
     error: Option<String>,
@@ -35,97 +58,611 @@ fn success() -> Success {
     }
 }
 
+#[utoipa::path(
+    tag = "tickers",
+    description = "Details of a ticker.",
+    params(trading::BasicTicker),
+    responses(
+        (status = 200, description = "ok", body = trading::Ticker),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[get("/ticker")]
+async fn ticker_details(
+    data: Data<Trading>,
+    query: web::Query<trading::BasicTicker>,
+) -> Result<impl Responder> {
+    query.validate().map_err(RustixErr::from)?;
+    let resp = data
+        .ticker_details(query.0)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "tickers",
+    description = "Tickers of a security type, optionally filtered by name.",
+    request_body = trading::TickerFilter,
+    params(FormatQuery),
+    responses(
+        (status = 200, description = "tickers", content(
+            (Vec<trading::Ticker> = "application/json"),
+            (trading::Ticker = "application/x-ndjson"),
+            (String = "text/event-stream"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
 #[post("/tickers")]
 async fn tickers(
     data: Data<Trading>,
+    http_req: HttpRequest,
     req: web::Json<trading::TickerFilter>,
 ) -> Result<HttpResponse> {
+    req.validate().map_err(RustixErr::from)?;
     println!("in tickers endpoint");
-    let body = data
-        .tickers(req.0)
-        .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+    let format = StreamFormat::negotiate(&http_req).map_err(RustixErr::bad_request)?;
+    let body = data.tickers(req.0, format).await.map_err(RustixErr::from)?;
 
     Ok(HttpResponse::Ok()
-        .content_type("application/json")
+        .content_type(format.content_type())
         .streaming(body))
 }
+#[utoipa::path(
+    tag = "securityData",
+    description = "Prices of a ticker, optionally resampled and split adjusted.",
+    request_body = trading::TimeSeriesReq,
+    params(FormatQuery),
+    responses(
+        (status = 200, description = "time series", content(
+            (Vec<trading::TimeSeriesData> = "application/json"),
+            (trading::TimeSeriesData = "application/x-ndjson"),
+            (String = "text/event-stream"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
 #[post("/securityData")]
 async fn security_data(
     data: Data<Trading>,
+    http_req: HttpRequest,
     req: web::Json<trading::TimeSeriesReq>,
 ) -> Result<HttpResponse> {
+    req.validate().map_err(RustixErr::from)?;
+    let format = StreamFormat::negotiate(&http_req).map_err(RustixErr::bad_request)?;
     let body = data
-        .security_data(req.0)
+        .security_data(req.0, format)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
 
     Ok(HttpResponse::Ok()
-        .content_type("application/json")
+        .content_type(format.content_type())
         .streaming(body))
 }
+#[utoipa::path(
+    tag = "securityData",
+    description = "Technical indicators computed on the prices of a ticker.",
+    request_body = trading::IndicatorsReq,
+    params(FormatQuery),
+    responses(
+        (status = 200, description = "indicator values", content(
+            (Vec<trading::TimeSeriesData> = "application/json"),
+            (trading::TimeSeriesData = "application/x-ndjson"),
+            (String = "text/event-stream"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/indicators")]
+async fn indicators(
+    data: Data<Trading>,
+    http_req: HttpRequest,
+    req: web::Json<trading::IndicatorsReq>,
+) -> Result<HttpResponse> {
+    req.validate().map_err(RustixErr::from)?;
+    let format = StreamFormat::negotiate(&http_req).map_err(RustixErr::bad_request)?;
+    let body = data
+        .indicators(req.0, format)
+        .await
+        .map_err(RustixErr::from)?;
 
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(body))
+}
+#[utoipa::path(
+    tag = "securityData",
+    description = "Date of the latest prices of a ticker.",
+    params(trading::DateReq),
+    responses(
+        (status = 200, description = "ok", body = trading::LatestDate),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[get("/securityData/latestDate")]
+async fn latest_security_data_date(
+    data: Data<Trading>,
+    query: web::Query<trading::DateReq>,
+) -> Result<impl Responder> {
+    query.validate().map_err(RustixErr::from)?;
+    let resp = data
+        .latest_security_data_date(query.0)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+
+async fn columnar_security_data(
+    data: Data<Trading>,
+    req: trading::TimeSeriesReq,
+    format: ColumnarFormat,
+) -> Result<HttpResponse> {
+    req.validate().map_err(RustixErr::from)?;
+    let filename = format!(
+        "{}_{}_{}.{}",
+        req.ticker.ticker,
+        req.from,
+        req.until,
+        format.extension()
+    );
+    let body = data
+        .security_data_columnar(req, format)
+        .await
+        .map_err(RustixErr::from)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition::attachment(filename))
+        .streaming(body))
+}
+#[utoipa::path(
+    tag = "securityData",
+    description = "Prices of a ticker as an arrow ipc stream.",
+    request_body = trading::TimeSeriesReq,
+    responses(
+        (status = 200, description = "arrow ipc stream", body = [u8], content_type = "application/vnd.apache.arrow.stream"),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/securityData/arrow")]
+async fn security_data_arrow(
+    data: Data<Trading>,
+    req: web::Json<trading::TimeSeriesReq>,
+) -> Result<HttpResponse> {
+    columnar_security_data(data, req.0, ColumnarFormat::ArrowIpc).await
+}
+#[utoipa::path(
+    tag = "securityData",
+    description = "Prices of a ticker as a parquet file.",
+    request_body = trading::TimeSeriesReq,
+    responses(
+        (status = 200, description = "parquet file", body = [u8], content_type = "application/vnd.apache.parquet"),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/securityData/parquet")]
+async fn security_data_parquet(
+    data: Data<Trading>,
+    req: web::Json<trading::TimeSeriesReq>,
+) -> Result<HttpResponse> {
+    columnar_security_data(data, req.0, ColumnarFormat::Parquet).await
+}
+
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Creates a portfolio, the id of the request is ignored.",
+    request_body = trading::Portfolio,
+    responses(
+        (status = 200, description = "the created portfolio", body = trading::Portfolio),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
 #[post("/portfolio/create")]
 async fn create_portfolio(
     data: Data<Trading>,
     req: web::Json<trading::Portfolio>,
 ) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
     let resp = data
         .create_portfolio(&req.name, &req.description)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Adds a security to a portfolio.",
+    request_body = trading::PortfolioSecurity,
+    responses(
+        (status = 200, description = "ok", body = Success),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
 #[post("/portfolio/buy")]
 async fn buy_portfolio(
     data: Data<Trading>,
     req: web::Json<trading::PortfolioSecurity>,
 ) -> Result<impl Responder> {
-    data.buy_security(req.0)
-        .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+    req.validate().map_err(RustixErr::from)?;
+    data.buy_security(req.0).await.map_err(RustixErr::from)?;
     Ok(web::Json(success()))
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Sets the sell date of a portfolio security. Refused for the securities of a day with sales of lots.",
+    request_body = trading::PortfolioSecurity,
+    responses(
+        (status = 200, description = "ok", body = Success),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
 #[post("/portfolio/sell")]
 async fn sell_portfolio(
-    data: Data<Trading>,
+    data: Data<Lots>,
     req: web::Json<trading::PortfolioSecurity>,
 ) -> Result<impl Responder> {
-    data.sell_security(req.0)
+    req.validate().map_err(RustixErr::from)?;
+    data.sell_security(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(success()))
+}
+
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Deletes a portfolio with its ledger and sales of lots. Refused while a job values it.",
+    request_body = Id,
+    responses(
+        (status = 200, description = "ok", body = Success),
+        (status = 404, description = "not found", body = ErrorBody),
+        (status = 409, description = "valued by a job", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/delete")]
+async fn delete_portfolio(
+    data: Data<Trading>,
+    scheduler: Data<Scheduler>,
+    ledgers: Data<Ledgers>,
+    lots: Data<Lots>,
+    req: web::Json<Id>,
+) -> Result<impl Responder> {
+    let id = req.0.id;
+    scheduler.check_unused(&id).map_err(RustixErr::from)?;
+    data.delete_portfolio(id.to_string())
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
+    ledgers.remove(&id).await.map_err(RustixErr::from)?;
+    lots.remove(&id).await.map_err(RustixErr::from)?;
+    Ok(web::Json(success()))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Removes a security from a portfolio. Refused for the securities of a day with sales of lots.",
+    request_body = trading::PortfolioSecurity,
+    responses(
+        (status = 200, description = "ok", body = Success),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/security/delete")]
+async fn delete_portfolio_security(
+    data: Data<Lots>,
+    req: web::Json<trading::PortfolioSecurity>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    data.delete_security(req.0).await.map_err(RustixErr::from)?;
     Ok(web::Json(success()))
 }
 
+#[utoipa::path(
+    tag = "portfolio",
+    description = "A portfolio by id.",
+    params(Id),
+    responses(
+        (status = 200, description = "ok", body = trading::Portfolio),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
 #[get("/portfolio")]
 async fn portfolio(data: Data<Trading>, query: web::Query<Id>) -> Result<impl Responder> {
-    let resp = data
-        .portfolio(query.0.id)
-        .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+    let resp = data.portfolio(query.0.id).await.map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Portfolios, whose name contains the filter.",
+    params(Filter),
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::Portfolio>),
+    )
+)]
 #[get("/portfolios")]
 async fn portfolios(data: Data<Trading>, query: web::Query<Filter>) -> Result<impl Responder> {
     let resp = data
         .portfolios(query.0.filter)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Profits of securities until a date.",
+    request_body = trading::SecurityProfitReq,
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::SecurityProfit>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
 #[post("/portfolio/profits")]
 async fn portfolio_profits(
     data: Data<Trading>,
     req: web::Json<trading::SecurityProfitReq>,
 ) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
     let resp = data
         .portfolio_profits(req.0)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Performance of a portfolio between two dates: time- and money-weighted returns, \
+        the maximum drawdown, volatility, sharpe and sortino ratios and the daily equity curve. \
+        With a benchmark ticker, also beta, alpha, tracking error, information ratio, up and down \
+        capture and the rebased benchmark curve. Sold lots leave the portfolio at their sale.",
+    request_body = trading::PortfolioAnalyticsReq,
+    responses(
+        (status = 200, description = "ok", body = analytics::Performance),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/analytics")]
+async fn portfolio_analytics(
+    data: Data<Trading>,
+    lots: Data<Lots>,
+    req: web::Json<trading::PortfolioAnalyticsReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let sales = lots.sales(&req.portfolio_id).await;
+    let resp = data
+        .portfolio_analytics(req.0, &sales)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Tax lots of a portfolio: the gains of the lots sold until the date (realized) \
+        and of the open lots at its close (unrealized), each short- or long-term, with a summary. \
+        Lots are split adjusted in shares and cost basis.",
+    request_body = trading::LotReportReq,
+    responses(
+        (status = 200, description = "ok", body = lots::LotReport),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/lots")]
+async fn portfolio_lots(
+    lots: Data<Lots>,
+    req: web::Json<trading::LotReportReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = lots.report(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Sells shares of a ticker from its open lots, chosen first in first out (fifo), \
+        last in first out (lifo), highest cost first (hifo) or by lot ids (specific). Returns the \
+        realized gains of the sold lots. The sales are kept by rustix, the securities of the \
+        DataLoader stay as they were bought.",
+    request_body = trading::LotSellReq,
+    responses(
+        (status = 200, description = "ok", body = Vec<lots::LotGain>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/lots/sell")]
+async fn sell_lots(
+    lots: Data<Lots>,
+    req: web::Json<trading::LotSellReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = lots.sell(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Cash ledger of a portfolio: its transactions by date with the running balance.",
+    params(Id),
+    responses(
+        (status = 200, description = "ok", body = ledger::Ledger),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[get("/portfolio/ledger")]
+async fn portfolio_ledger(ledgers: Data<Ledgers>, query: web::Query<Id>) -> Result<impl Responder> {
+    let resp = ledgers.ledger(&query.id).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Books a deposit, withdrawal, buy, sell, fee, dividend, interest or split. Buys \
+        and withdrawals must not exceed the cash, unless the portfolio trades on margin, and \
+        sells must not exceed the held shares.",
+    request_body = ledger::TransactionReq,
+    responses(
+        (status = 200, description = "ok", body = ledger::Ledger),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/ledger/transaction")]
+async fn add_transaction(
+    ledgers: Data<Ledgers>,
+    req: web::Json<ledger::TransactionReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = ledgers.add(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Deletes a transaction, unless the ledger would no longer be covered without it.",
+    request_body = ledger::TransactionId,
+    responses(
+        (status = 200, description = "ok", body = ledger::Ledger),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/ledger/delete")]
+async fn delete_transaction(
+    ledgers: Data<Ledgers>,
+    req: web::Json<ledger::TransactionId>,
+) -> Result<impl Responder> {
+    let resp = ledgers.delete(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Enables or disables margin, which allows buys beyond the cash.",
+    request_body = ledger::MarginReq,
+    responses(
+        (status = 200, description = "ok", body = ledger::Ledger),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/ledger/margin")]
+async fn ledger_margin(
+    ledgers: Data<Ledgers>,
+    req: web::Json<ledger::MarginReq>,
+) -> Result<impl Responder> {
+    let resp = ledgers.set_margin(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Profits of a portfolio's ledger until a date: cash, market value and equity, \
+        deposits and withdrawals, fees and commissions, dividends and interest, and the realized \
+        and unrealized gains of each position at its average cost.",
+    request_body = ledger::LedgerProfitsReq,
+    responses(
+        (status = 200, description = "ok", body = ledger::LedgerProfits),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/ledger/profits")]
+async fn ledger_profits(
+    ledgers: Data<Ledgers>,
+    req: web::Json<ledger::LedgerProfitsReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = ledgers.profits(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Base currency of a portfolio and the trading currencies set for its securities. \
+        Securities without one trade in the currency of their ticker's custom fields, or in USD.",
+    params(Id),
+    responses(
+        (status = 200, description = "ok", body = fx::PortfolioCurrencies),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[get("/portfolio/currencies")]
+async fn portfolio_currencies(
+    ledgers: Data<Ledgers>,
+    query: web::Query<Id>,
+) -> Result<impl Responder> {
+    let resp = ledgers
+        .currencies(&query.id)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Sets the base currency of a portfolio and the trading currencies of its \
+        securities.",
+    request_body = fx::PortfolioCurrencies,
+    responses(
+        (status = 200, description = "ok", body = fx::PortfolioCurrencies),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/currencies")]
+async fn set_portfolio_currencies(
+    ledgers: Data<Ledgers>,
+    req: web::Json<fx::PortfolioCurrencies>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = ledgers
+        .set_currencies(req.0)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Valuation of a portfolio's securities in its base currency, converted with the \
+        daily closes of the currency pairs. The returns are split into the local return in the \
+        trading currency and the currency return.",
+    request_body = fx::FxValuationReq,
+    responses(
+        (status = 200, description = "ok", body = fx::FxValuation),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found, or no rates of a currency pair", body = ErrorBody),
+    )
+)]
+#[post("/portfolio/fx")]
+async fn portfolio_fx(
+    data: Data<Trading>,
+    ledgers: Data<Ledgers>,
+    lots: Data<Lots>,
+    req: web::Json<fx::FxValuationReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let currencies = ledgers
+        .currencies(&req.portfolio_id)
+        .await
+        .map_err(RustixErr::from)?;
+    let sales = lots.sales(&req.portfolio_id).await;
+    let resp = data
+        .fx_valuation(req.0, &currencies, &sales)
+        .await
+        .map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
+#[utoipa::path(
+    tag = "portfolio",
+    description = "Securities of a portfolio.",
+    params(Id),
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::PortfolioSecurity>),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
 #[get("/portfolio/securities")]
 async fn portfolio_securities(
     data: Data<Trading>,
@@ -134,44 +671,453 @@ async fn portfolio_securities(
     let resp = data
         .portfolio_securities(query.0.id)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
 
+#[utoipa::path(
+    tag = "movements",
+    description = "Movement of a ticker within a period.",
+    request_body = trading::MovementReq,
+    responses(
+        (status = 200, description = "ok", body = trading::Movement),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/movement")]
+async fn movement(
+    data: Data<Trading>,
+    req: web::Json<trading::MovementReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = data.movement(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "movements",
+    description = "Average movement of a ticker within a period.",
+    request_body = trading::MovementReq,
+    responses(
+        (status = 200, description = "ok", body = trading::Movement),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/avgMovement")]
+async fn avg_movement(
+    data: Data<Trading>,
+    req: web::Json<trading::MovementReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = data.avg_movement(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "movements",
+    description = "Average movements of the tickers of a security type.",
+    request_body = trading::MovementsReq,
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::Movement>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
+#[post("/avgMovements")]
+async fn avg_movements(
+    data: Data<Trading>,
+    req: web::Json<trading::MovementsReq>,
+) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
+    let resp = data.avg_movements(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "movements",
+    description = "Movements of the tickers of a security type.",
+    request_body = trading::MovementsReq,
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::Movement>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
 #[post("/movements")]
 async fn movements(
     data: Data<Trading>,
     req: web::Json<trading::MovementsReq>,
 ) -> Result<impl Responder> {
-    let resp = data
-        .movements(req.0)
-        .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+    req.validate().map_err(RustixErr::from)?;
+    let resp = data.movements(req.0).await.map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
+#[utoipa::path(
+    tag = "correlations",
+    description = "The most (or least) correlating pairs of tickers.",
+    request_body = trading::CorrelatingTickersReq,
+    params(FormatQuery),
+    responses(
+        (status = 200, description = "correlating tickers", content(
+            (Vec<trading::CorrelatingTickers> = "application/json"),
+            (trading::CorrelatingTickers = "application/x-ndjson"),
+            (String = "text/event-stream"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
 #[post("/correlatingTickers")]
 async fn correlating_tickers(
     data: Data<Trading>,
+    http_req: HttpRequest,
     req: web::Json<trading::CorrelatingTickersReq>,
 ) -> Result<HttpResponse> {
+    req.validate().map_err(RustixErr::from)?;
+    let format = StreamFormat::negotiate(&http_req).map_err(RustixErr::bad_request)?;
     let body = data
-        .correlating_tickers(req.0)
+        .correlating_tickers(req.0, format)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
 
     Ok(HttpResponse::Ok()
-        .content_type("application/json")
+        .content_type(format.content_type())
         .streaming(body))
 }
+#[utoipa::path(
+    tag = "correlations",
+    description = "Correlations between the requested tickers.",
+    request_body = trading::CorrelReq,
+    params(FormatQuery),
+    responses(
+        (status = 200, description = "correlations", content(
+            (Vec<trading::CorrelatingTickers> = "application/json"),
+            (trading::CorrelatingTickers = "application/x-ndjson"),
+            (String = "text/event-stream"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/correlations")]
+async fn correlations(
+    data: Data<Trading>,
+    http_req: HttpRequest,
+    req: web::Json<trading::CorrelReq>,
+) -> Result<HttpResponse> {
+    req.validate().map_err(RustixErr::from)?;
+    let format = StreamFormat::negotiate(&http_req).map_err(RustixErr::bad_request)?;
+    let body = data
+        .correlations(req.0, format)
+        .await
+        .map_err(RustixErr::from)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(body))
+}
+#[utoipa::path(
+    tag = "correlations",
+    description = "Correlations of each requested ticker with the others.",
+    request_body = trading::CorrelReq,
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::MutualCorrel>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
 #[post("/mutualCorrelations")]
 async fn mutual_correlations(
     data: Data<Trading>,
     req: web::Json<trading::CorrelReq>,
 ) -> Result<impl Responder> {
+    req.validate().map_err(RustixErr::from)?;
     let resp = data
         .mutual_correlations(req.0)
         .await
-        .map_err(|err| RustixErr::new(err, 500))?;
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+
+// Routes describes the routes of the /api scope. Every route has to be listed here, which is
+// checked by the routes_documented test.
+#[derive(OpenApi)]
+#[openapi(paths(
+    ticker_details,
+    tickers,
+    portfolio,
+    portfolios,
+    create_portfolio,
+    buy_portfolio,
+    sell_portfolio,
+    delete_portfolio,
+    delete_portfolio_security,
+    portfolio_profits,
+    portfolio_analytics,
+    portfolio_lots,
+    sell_lots,
+    portfolio_ledger,
+    add_transaction,
+    delete_transaction,
+    ledger_margin,
+    ledger_profits,
+    portfolio_currencies,
+    set_portfolio_currencies,
+    portfolio_fx,
+    portfolio_securities,
+    security_data,
+    latest_security_data_date,
+    security_data_arrow,
+    security_data_parquet,
+    indicators,
+    movement,
+    movements,
+    avg_movement,
+    avg_movements,
+    correlations,
+    correlating_tickers,
+    mutual_correlations,
+    stock_splits,
+    market_status,
+    market_feed,
+    jobs,
+    upsert_job,
+    delete_job,
+    trigger_job,
+    pause_job,
+    resume_job,
+    job_runs,
+))]
+struct Routes;
+
+// ApiDoc is the OpenAPI document served at /api/openapi.json, with a Swagger UI at /api/docs/:
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rustix",
+        description = "Market data, portfolios and scheduled jobs on top of the DataLoader."
+    ),
+    nest((path = "/api", api = Routes))
+)]
+struct ApiDoc;
+
+fn routes(cfg: &mut web::ServiceConfig) {
+    // registered before the scope, which would answer unknown /api routes with a 404:
+    cfg.service(SwaggerUi::new("/api/docs/{_:.*}").url("/api/openapi.json", ApiDoc::openapi()));
+    cfg.service(
+        web::scope("/api")
+            .wrap_fn(error::track_request)
+            .app_data(web::JsonConfig::default().error_handler(|err, _| {
+                RustixErr::bad_request(anyhow!("invalid request body: {}", err)).into()
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| {
+                RustixErr::bad_request(anyhow!("invalid query: {}", err)).into()
+            }))
+            .service(ticker_details)
+            .service(tickers)
+            .service(portfolio)
+            .service(portfolios)
+            .service(create_portfolio)
+            .service(buy_portfolio)
+            .service(sell_portfolio)
+            .service(delete_portfolio)
+            .service(delete_portfolio_security)
+            .service(portfolio_profits)
+            .service(portfolio_analytics)
+            .service(portfolio_lots)
+            .service(sell_lots)
+            .service(portfolio_ledger)
+            .service(add_transaction)
+            .service(delete_transaction)
+            .service(ledger_margin)
+            .service(ledger_profits)
+            .service(portfolio_currencies)
+            .service(set_portfolio_currencies)
+            .service(portfolio_fx)
+            .service(portfolio_securities)
+            .service(security_data)
+            .service(latest_security_data_date)
+            .service(security_data_arrow)
+            .service(security_data_parquet)
+            .service(indicators)
+            .service(movement)
+            .service(movements)
+            .service(avg_movement)
+            .service(avg_movements)
+            .service(correlations)
+            .service(correlating_tickers)
+            .service(mutual_correlations)
+            .service(stock_splits)
+            .service(market_status)
+            .service(market_feed)
+            .service(jobs)
+            .service(upsert_job)
+            .service(delete_job)
+            .service(trigger_job)
+            .service(pause_job)
+            .service(resume_job)
+            .service(job_runs),
+    );
+}
+
+#[utoipa::path(
+    tag = "securityData",
+    description = "Stock splits within a date range.",
+    params(trading::StockSplitsReq),
+    responses(
+        (status = 200, description = "ok", body = Vec<trading::StockSplit>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
+#[get("/stockSplits")]
+async fn stock_splits(
+    data: Data<Trading>,
+    query: web::Query<trading::StockSplitsReq>,
+) -> Result<impl Responder> {
+    query.validate().map_err(RustixErr::from)?;
+    let resp = data.stock_splits(query.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+
+#[utoipa::path(
+    tag = "market",
+    description = "Current state of the exchanges.",
+    params(MarketStatusReq),
+    responses(
+        (status = 200, description = "ok", body = Vec<market::MarketStatus>),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
+#[get("/market/status")]
+async fn market_status(query: web::Query<MarketStatusReq>) -> Result<impl Responder> {
+    let calendars = query.calendars().map_err(RustixErr::bad_request)?;
+    let now = chrono::Utc::now();
+    let resp = calendars
+        .iter()
+        .map(|c| market::status(c, now))
+        .collect::<Vec<_>>();
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "market",
+    description = "Server-sent events with the status of the exchanges on every state change.",
+    params(MarketStatusReq),
+    responses(
+        (status = 200, description = "market status events", body = market::MarketStatus, content_type = "text/event-stream"),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
+#[get("/market/feed")]
+async fn market_feed(query: web::Query<MarketStatusReq>) -> Result<HttpResponse> {
+    let calendars = query.calendars().map_err(RustixErr::bad_request)?;
+    Ok(HttpResponse::Ok()
+        .content_type(StreamFormat::Sse.content_type())
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(market::feed(calendars)))
+}
+
+#[utoipa::path(
+    tag = "jobs",
+    description = "Scheduled jobs with their next and last run.",
+    responses(
+        (status = 200, description = "ok", body = Vec<JobInfo>),
+    )
+)]
+#[get("/jobs")]
+async fn jobs(scheduler: Data<Scheduler>) -> Result<impl Responder> {
+    Ok(web::Json(scheduler.jobs()))
+}
+#[utoipa::path(
+    tag = "jobs",
+    description = "Creates or replaces a job.",
+    request_body = JobDef,
+    responses(
+        (status = 200, description = "ok", body = JobInfo),
+        (status = 400, description = "invalid request", body = ErrorBody),
+    )
+)]
+#[post("/jobs")]
+async fn upsert_job(scheduler: Data<Scheduler>, req: web::Json<JobDef>) -> Result<impl Responder> {
+    let resp = scheduler.upsert(req.0).await.map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "jobs",
+    description = "Deletes a job and its run history.",
+    request_body = Name,
+    responses(
+        (status = 200, description = "ok", body = Success),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/jobs/delete")]
+async fn delete_job(scheduler: Data<Scheduler>, req: web::Json<Name>) -> Result<impl Responder> {
+    scheduler.delete(&req.name).await.map_err(RustixErr::from)?;
+    Ok(web::Json(success()))
+}
+#[utoipa::path(
+    tag = "jobs",
+    description = "Runs a job now.",
+    request_body = Name,
+    responses(
+        (status = 200, description = "the started run", body = JobRun),
+        (status = 404, description = "not found", body = ErrorBody),
+        (status = 409, description = "the job is running already", body = ErrorBody),
+    )
+)]
+#[post("/jobs/trigger")]
+async fn trigger_job(scheduler: Data<Scheduler>, req: web::Json<Name>) -> Result<impl Responder> {
+    let resp = scheduler
+        .into_inner()
+        .trigger(&req.name)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "jobs",
+    description = "Pauses the scheduled runs of a job.",
+    request_body = Name,
+    responses(
+        (status = 200, description = "ok", body = JobInfo),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/jobs/pause")]
+async fn pause_job(scheduler: Data<Scheduler>, req: web::Json<Name>) -> Result<impl Responder> {
+    let resp = scheduler
+        .set_paused(&req.name, true)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "jobs",
+    description = "Resumes the scheduled runs of a job.",
+    request_body = Name,
+    responses(
+        (status = 200, description = "ok", body = JobInfo),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[post("/jobs/resume")]
+async fn resume_job(scheduler: Data<Scheduler>, req: web::Json<Name>) -> Result<impl Responder> {
+    let resp = scheduler
+        .set_paused(&req.name, false)
+        .await
+        .map_err(RustixErr::from)?;
+    Ok(web::Json(resp))
+}
+#[utoipa::path(
+    tag = "jobs",
+    description = "Run history of a job, newest first.",
+    params(Name),
+    responses(
+        (status = 200, description = "ok", body = Vec<JobRun>),
+        (status = 404, description = "not found", body = ErrorBody),
+    )
+)]
+#[get("/jobs/runs")]
+async fn job_runs(scheduler: Data<Scheduler>, query: web::Query<Name>) -> Result<impl Responder> {
+    let resp = scheduler.runs(&query.name).map_err(RustixErr::from)?;
     Ok(web::Json(resp))
 }
 
@@ -182,27 +1128,28 @@ async fn main() -> std::io::Result<()> {
         "listening on {}:{} in mode '{}'",
         envs.host, envs.port, envs.mode,
     );
-    env_logger::init_from_env(Env::default().default_filter_or(envs.mode));
-    HttpServer::new(|| {
+    env_logger::init_from_env(Env::default().default_filter_or(&envs.mode));
+    // all workers share one Trading, and thereby one connection to the DataLoader:
+    let trading = Data::new(Trading::new(envs.clone()).expect("invalid DataLoader address"));
+    let scheduler = Arc::new(
+        Scheduler::new(trading.clone().into_inner(), &envs).expect("cannot load the jobs"),
+    );
+    scheduler.start();
+    let scheduler = Data::from(scheduler);
+    let ledgers = Data::new(
+        Ledgers::new(trading.clone().into_inner(), &envs).expect("cannot load the ledgers"),
+    );
+    let lots =
+        Data::new(Lots::new(trading.clone().into_inner(), &envs).expect("cannot load the lots"));
+    HttpServer::new(move || {
         App::new()
-            .app_data(Data::new(Trading::new(Envs::parse())))
+            .app_data(trading.clone())
+            .app_data(scheduler.clone())
+            .app_data(ledgers.clone())
+            .app_data(lots.clone())
             .wrap(Logger::default())
             .wrap(Logger::new("%a %{User-Agent}i"))
-            .service(
-                web::scope("/api")
-                    .service(tickers)
-                    .service(portfolio)
-                    .service(portfolios)
-                    .service(create_portfolio)
-                    .service(buy_portfolio)
-                    .service(sell_portfolio)
-                    .service(portfolio_profits)
-                    .service(portfolio_securities)
-                    .service(security_data)
-                    .service(movements)
-                    .service(correlating_tickers)
-                    .service(mutual_correlations),
-            )
+            .configure(routes)
     })
     .bind((envs.host, envs.port))?
     .run()
@@ -211,94 +1158,1568 @@ async fn main() -> std::io::Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use anyhow::Result;
-    use reqwest;
-    use rustix::envs::Envs;
-    use rustix::trading::{self};
-    use serde;
-    use std::time::Instant;
-    use tokio;
+    use super::*;
+    use actix_web::{
+        body::BoxBody,
+        dev::{Service, ServiceResponse},
+        http::StatusCode,
+        test,
+    };
+    use futures::TryStreamExt;
+    use rustix::client::{ApiError, Client, ClientOptions};
+    use rustix::enums::{Period, SecurityType, SortBy};
+    use rustix::error::ErrorBody;
+    use rustix::mock::MockServer;
+    use serde_json::{json, Value};
 
-    lazy_static! {
-        static ref ENV: Envs = Envs::parse();
+    async fn app(
+        server: &MockServer,
+    ) -> impl Service<actix_http::Request, Response = ServiceResponse<BoxBody>, Error = actix_web::Error>
+    {
+        let trading = Data::new(Trading::new(server.envs()).unwrap());
+        let scheduler = Scheduler::new(trading.clone().into_inner(), &server.envs()).unwrap();
+        let ledgers = Ledgers::new(trading.clone().into_inner(), &server.envs()).unwrap();
+        let lots = Lots::new(trading.clone().into_inner(), &server.envs()).unwrap();
+        test::init_service(
+            App::new()
+                .app_data(trading)
+                .app_data(Data::new(scheduler))
+                .app_data(Data::new(ledgers))
+                .app_data(Data::new(lots))
+                .configure(routes),
+        )
+        .await
     }
 
-    fn url(endpoint: &str) -> String {
-        format!("http://{}:{}/api{}", ENV.host, ENV.port, endpoint)
-    }
-    async fn get_json<T>(endpoint: &str, query: Option<&[(&str, &str)]>) -> Result<T>
+    async fn get_json<T>(
+        app: &impl Service<
+            actix_http::Request,
+            Response = ServiceResponse<BoxBody>,
+            Error = actix_web::Error,
+        >,
+        uri: &str,
+    ) -> T
     where
         T: serde::de::DeserializeOwned,
     {
-        let url = url(endpoint);
-        let mut req = reqwest::Client::new().get(url);
-        if let Some(query) = query {
-            req = req.query(&query);
-        }
-        let res = req.send().await?.json().await?;
-        Ok(res)
+        let req = test::TestRequest::get().uri(uri).to_request();
+        test::call_and_read_body_json(app, req).await
     }
 
-    async fn post_json<T, ToJSON>(endpoint: &str, body: ToJSON) -> Result<T>
+    async fn post_json<T, ToJSON>(
+        app: &impl Service<
+            actix_http::Request,
+            Response = ServiceResponse<BoxBody>,
+            Error = actix_web::Error,
+        >,
+        uri: &str,
+        body: ToJSON,
+    ) -> T
     where
         ToJSON: serde::ser::Serialize,
         T: serde::de::DeserializeOwned,
     {
-        let url: String = url(endpoint);
-        let body_json = serde_json::to_string(&body)?;
-        println!("body_json for endpoint {}: {}", url, body_json);
-        let res = reqwest::Client::new()
-            .post(url)
-            .body(body_json)
-            .header("Content-Type", "application/json")
-            .send()
-            .await?
-            .json()
-            .await?;
-        Ok(res)
-    }
-
-    #[tokio::test]
-    async fn get_portfolios() -> Result<()> {
-        let query = Some(&[("filter", "pack")][..]);
-        let portfolios: Vec<trading::Portfolio> = get_json("/portfolios", query).await?;
-
-        assert!(portfolios.len() > 0);
-        Ok(())
-    }
-
-    #[tokio::test]
-    async fn tickers() -> Result<()> {
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .set_json(body)
+            .to_request();
+        test::call_and_read_body_json(app, req).await
+    }
+
+    #[actix_web::test]
+    async fn get_portfolios() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let ps: Vec<trading::Portfolio> = get_json(&app, "/api/portfolios?filter=pack").await;
+        assert_eq!(ps.len(), 1);
+        assert_eq!(ps[0].name, "tech pack");
+
+        let ps: Vec<trading::Portfolio> = get_json(&app, "/api/portfolios?filter=").await;
+        assert_eq!(ps.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn get_portfolio() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let p: trading::Portfolio = get_json(&app, "/api/portfolio?id=2").await;
+        assert_eq!(p.id, "2");
+        assert_eq!(p.name, "index");
+
+        let req = test::TestRequest::get()
+            .uri("/api/portfolio?id=42")
+            .insert_header(("x-request-id", "req-42"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.headers().get("x-request-id").unwrap(), "req-42");
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.code, "not_found");
+        assert_eq!(body.message, "unknown portfolio 42");
+        assert_eq!(body.request_id, Some("req-42".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn error_responses() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        // invalid dates are rejected as bad requests:
+        let req = test::TestRequest::post()
+            .uri("/api/movements")
+            .set_json(json!({
+                "security_type": 0,
+                "sort_by": 0,
+                "until": "31.01.2024",
+                "period": 3,
+                "limit": 10,
+                "min_volume": 0,
+                "min_variance": 0.0,
+                "max_variance": 0.0,
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(resp.headers().contains_key("x-request-id"));
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.code, "invalid_request");
+        assert_eq!(body.fields[0].field, "until");
+        assert!(body.request_id.is_some());
+
+        // so are malformed bodies:
+        let req = test::TestRequest::post()
+            .uri("/api/tickers")
+            .set_json(json!({"filter": "aapl"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.code, "bad_request");
+
+        // a downed DataLoader is reported as unavailable:
+        server.stop().await;
+        let req = test::TestRequest::get()
+            .uri("/api/portfolios?filter=")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.code, "unavailable");
+    }
+
+    // invalid_fields posts an invalid request and returns the fields of the errors:
+    async fn invalid_fields(
+        app: &impl Service<
+            actix_http::Request,
+            Response = ServiceResponse<BoxBody>,
+            Error = actix_web::Error,
+        >,
+        uri: &str,
+        body: Value,
+    ) -> Vec<String> {
+        let body: ErrorBody = post_json(app, uri, body).await;
+        assert_eq!(body.code, "invalid_request");
+        body.fields.into_iter().map(|f| f.field).collect()
+    }
+
+    #[actix_web::test]
+    async fn request_validation() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+        // all failed checks are reported at once:
+        let movements = json!({
+            "security_type": "stock",
+            "sort_by": "winner",
+            "until": "2024-01-31",
+            "period": "quarter",
+            "limit": 0,
+            "min_volume": 0,
+            "min_variance": 2.0,
+            "max_variance": 1.0,
+        });
+        assert_eq!(
+            invalid_fields(&app, "/api/movements", movements).await,
+            vec!["limit", "min_variance"]
+        );
+        // without an upper bound, any min_variance is valid:
+        let unbounded = json!({
+            "security_type": "stock",
+            "sort_by": "winner",
+            "until": "2024-01-31",
+            "period": "quarter",
+            "limit": 5,
+            "min_volume": 0,
+            "min_variance": 2.0,
+            "max_variance": 0.0,
+        });
+        let req = test::TestRequest::post()
+            .uri("/api/movements")
+            .set_json(unbounded)
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let series = json!({
+            "ticker": {"ticker": " ", "security_type": "stock"},
+            "from": "2024-02-01",
+            "until": "2024-01-31",
+        });
+        assert_eq!(
+            invalid_fields(&app, "/api/securityData", series).await,
+            vec!["ticker.ticker", "from"]
+        );
+        let profits = json!({"util": "2024-01-31", "parition": 3, "securities": []});
+        assert_eq!(
+            invalid_fields(&app, "/api/portfolio/profits", profits).await,
+            vec!["securities"]
+        );
+        let security = json!({
+            "portfolio_id": "1",
+            "security_type": 0,
+            "ticker": "AAPL",
+            "volume": -3.0,
+            "purchase_date": "2024-01-10",
+            "sell_date": "2024-01-03",
+        });
+        assert_eq!(
+            invalid_fields(&app, "/api/portfolio/buy", security).await,
+            vec!["volume", "purchase_date"]
+        );
+        let correlations = json!({"tickers": [], "period": "month"});
+        assert_eq!(
+            invalid_fields(&app, "/api/correlations", correlations).await,
+            vec!["tickers"]
+        );
+    }
+
+    #[actix_web::test]
+    async fn create_buy_and_sell() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let created: trading::Portfolio = post_json(
+            &app,
+            "/api/portfolio/create",
+            json!({"id": "", "name": "dividends", "description": "income"}),
+        )
+        .await;
+        assert_eq!(created.id, "3");
+        assert_eq!(created.name, "dividends");
+
+        let security = json!({
+            "portfolio_id": "3",
+            "security_type": 0,
+            "ticker": "AAPL",
+            "volume": 3.0,
+            "purchase_date": "2024-01-03",
+            "sell_date": "",
+        });
+        let resp: Value = post_json(&app, "/api/portfolio/buy", &security).await;
+        assert_eq!(resp, json!({"success": true, "error": null}));
+
+        let securities: Vec<Value> = get_json(&app, "/api/portfolio/securities?id=3").await;
+        assert_eq!(securities.len(), 1);
+        assert_eq!(securities[0]["ticker"], "AAPL");
+        assert_eq!(securities[0]["sell_date"], "");
+
+        let mut sold = security.clone();
+        sold["sell_date"] = json!("2024-01-10");
+        let resp: Value = post_json(&app, "/api/portfolio/sell", &sold).await;
+        assert_eq!(resp["success"], true);
+
+        let securities: Vec<Value> = get_json(&app, "/api/portfolio/securities?id=3").await;
+        assert_eq!(securities[0]["sell_date"], "2024-01-10");
+    }
+
+    #[actix_web::test]
+    async fn portfolio_securities() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let securities: Vec<Value> = get_json(&app, "/api/portfolio/securities?id=1").await;
+        let tickers: Vec<&str> = securities
+            .iter()
+            .map(|s| s["ticker"].as_str().unwrap())
+            .collect();
+        assert_eq!(tickers, vec!["AAPL", "MSFT"]);
+    }
+
+    #[actix_web::test]
+    async fn portfolio_profits() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let profits: Vec<Value> = post_json(
+            &app,
+            "/api/portfolio/profits",
+            json!({
+                "util": "2024-01-31",
+                "parition": 3,
+                "securities": [
+                    {"security_type": 0, "ticker": "AAPL", "volume": 10.0, "purchase_date": "2024-01-02"},
+                    {"security_type": 0, "ticker": "MSFT", "volume": 5.0, "purchase_date": "2024-01-08", "sell_date": "2024-01-25"},
+                ],
+            }),
+        )
+        .await;
+        assert_eq!(profits.len(), 2);
+        assert_eq!(profits[0]["ticker"], "AAPL");
+        assert_eq!(profits[0]["until"], "2024-01-31");
+        assert_eq!(profits[1]["until"], "2024-01-25");
+        for p in profits {
+            let per_share = p["profit_per_share"].as_f64().unwrap();
+            let total = p["total_profit"].as_f64().unwrap();
+            let volume = p["volume"].as_f64().unwrap();
+            assert!((per_share * volume - total).abs() < 1e-9);
+        }
+    }
+
+    #[actix_web::test]
+    async fn portfolio_analytics() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req = |id: &str, from: &str| json!({"portfolio_id": id, "from": from, "until": "2024-01-31", "risk_free_rate": 0.04});
+        let perf: Value = post_json(&app, "/api/portfolio/analytics", req("1", "2024-01-01")).await;
+        let curve = perf["equity_curve"].as_array().unwrap();
+        // the trading days of january, without new year and mlk day:
+        assert_eq!(curve.len(), 21);
+        assert_eq!(curve[0]["date"], "2024-01-02");
+        assert_eq!(curve[0]["flow"], curve[0]["value"]);
+        assert_eq!(curve[0]["index"], 1.0);
+        let point = |date: &str| curve.iter().find(|p| p["date"] == date).unwrap();
+        // msft was bought on the 8th and sold on the 25th:
+        assert!(point("2024-01-08")["flow"].as_f64().unwrap() > 0.0);
+        assert!(point("2024-01-25")["flow"].as_f64().unwrap() < 0.0);
+        assert_eq!(point("2024-01-26")["flow"], 0.0);
+
+        let twr = perf["time_weighted_return"].as_f64().unwrap();
+        let index = curve.last().unwrap()["index"].as_f64().unwrap();
+        assert!((twr - (index - 1.0)).abs() < 1e-12);
+        assert!(perf["money_weighted_return"].is_f64());
+        assert!(perf["max_drawdown"]["depth"].as_f64().unwrap() <= 0.0);
+        assert!(perf["volatility"].as_f64().unwrap() > 0.0);
+        assert!(perf["sharpe_ratio"].is_f64());
+        assert!(perf["sortino_ratio"].is_f64());
+        assert!(perf.get("benchmark").is_none());
+
+        let mut spy = req("1", "2024-01-01");
+        spy["benchmark"] = json!({"ticker": "SPY", "security_type": "etf"});
+        let perf: Value = post_json(&app, "/api/portfolio/analytics", &spy).await;
+        let benchmark = &perf["benchmark"];
+        assert_eq!(benchmark["ticker"], "SPY");
+        let rebased = benchmark["curve"].as_array().unwrap();
+        assert_eq!(rebased.len(), 21);
+        assert_eq!(rebased[0]["date"], "2024-01-02");
+        assert_eq!(rebased[0]["index"], 1.0);
+        let benchmark_return = benchmark["benchmark_return"].as_f64().unwrap();
+        let last = rebased.last().unwrap()["index"].as_f64().unwrap();
+        assert!((benchmark_return - (last - 1.0)).abs() < 1e-12);
+        let relative = benchmark["relative_return"].as_f64().unwrap();
+        let twr = perf["time_weighted_return"].as_f64().unwrap();
+        assert!((relative - (twr - benchmark_return)).abs() < 1e-12);
+        for ratio in [
+            "beta",
+            "alpha",
+            "information_ratio",
+            "up_capture",
+            "down_capture",
+        ] {
+            assert!(benchmark[ratio].is_f64(), "{}", ratio);
+        }
+        assert!(benchmark["tracking_error"].as_f64().unwrap() > 0.0);
+
+        // the sales of lots are flows out of the portfolio:
+        assert_eq!(point("2024-01-19")["flow"], 0.0);
+        let sell = json!({
+            "portfolio_id": "1", "ticker": "AAPL", "security_type": "stock",
+            "volume": 4.0, "method": "fifo", "date": "2024-01-19",
+        });
+        let _: Vec<lots::LotGain> = post_json(&app, "/api/portfolio/lots/sell", sell).await;
+        let perf: Value = post_json(&app, "/api/portfolio/analytics", req("1", "2024-01-01")).await;
+        let curve = perf["equity_curve"].as_array().unwrap();
+        let flow = curve.iter().find(|p| p["date"] == "2024-01-19").unwrap()["flow"].as_f64();
+        assert!(flow.unwrap() < 0.0);
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/portfolio/analytics")
+                .set_json(req("42", "2024-01-01"))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let mut invalid = req("1", "2024-02-01");
+        invalid["benchmark"] = json!({"ticker": "", "security_type": "etf"});
+        let fields = invalid_fields(&app, "/api/portfolio/analytics", invalid).await;
+        assert_eq!(fields, vec!["from", "benchmark.ticker"]);
+    }
+
+    #[actix_web::test]
+    async fn portfolio_lots() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let created: trading::Portfolio = post_json(
+            &app,
+            "/api/portfolio/create",
+            json!({"id": "", "name": "lots", "description": ""}),
+        )
+        .await;
+        for (ticker, volume, purchase_date) in [
+            ("AAPL", 10.0, "2024-01-02"),
+            ("AAPL", 10.0, "2024-01-10"),
+            ("AAPL", 5.0, "2024-01-10"),
+            ("NVDA", 4.0, "2024-01-03"),
+        ] {
+            let security = json!({
+                "portfolio_id": created.id, "security_type": 0, "ticker": ticker,
+                "volume": volume, "purchase_date": purchase_date, "sell_date": "",
+            });
+            let _: Value = post_json(&app, "/api/portfolio/buy", security).await;
+        }
+        let sell = |ticker: &str, volume: f64, method: &str, date: &str| {
+            json!({
+                "portfolio_id": "3", "ticker": ticker, "security_type": "stock",
+                "volume": volume, "method": method, "date": date,
+            })
+        };
+        let sold = |gains: &[lots::LotGain]| {
+            gains
+                .iter()
+                .map(|g| (g.lot_id.to_string(), g.volume))
+                .collect::<Vec<_>>()
+        };
+
+        let gains: Vec<lots::LotGain> = post_json(
+            &app,
+            "/api/portfolio/lots/sell",
+            sell("AAPL", 15.0, "fifo", "2024-01-19"),
+        )
+        .await;
+        assert_eq!(
+            sold(&gains),
+            vec![
+                ("AAPL:2024-01-02".to_string(), 10.0),
+                ("AAPL:2024-01-10".to_string(), 5.0)
+            ]
+        );
+        assert!(gains.iter().all(|g| g.term == lots::Term::ShortTerm));
+        assert!(gains.iter().all(|g| g.date == "2024-01-19"));
+        // the securities are left as they were bought, the partially sold lot is split into a
+        // sold and an open lot:
+        let securities: Vec<trading::PortfolioSecurity> =
+            get_json(&app, "/api/portfolio/securities?id=3").await;
+        assert!(securities.iter().all(|s| s.sell_date.is_empty()));
+        assert_eq!(securities.len(), 4);
+        let report: lots::LotReport = post_json(
+            &app,
+            "/api/portfolio/lots",
+            json!({"portfolio_id": "3", "until": "2024-01-19"}),
+        )
+        .await;
+        assert_eq!(sold(&report.realized), sold(&gains));
+        assert_eq!(
+            sold(&report.unrealized),
+            vec![
+                ("AAPL:2024-01-10".to_string(), 5.0),
+                ("AAPL:2024-01-10#2".to_string(), 5.0),
+                ("NVDA:2024-01-03".to_string(), 16.0)
+            ]
+        );
+
+        // nvda was split 4:1 on the 16th, so the 4 shares of the lot are 16 at the sale:
+        let gains: Vec<lots::LotGain> = post_json(
+            &app,
+            "/api/portfolio/lots/sell",
+            sell("NVDA", 16.0, "hifo", "2024-01-22"),
+        )
+        .await;
+        assert_eq!(sold(&gains), vec![("NVDA:2024-01-03".to_string(), 16.0)]);
+        let mut specific = sell("AAPL", 2.0, "specific", "2024-01-25");
+        specific["lot_ids"] = json!(["AAPL:2024-01-10#2"]);
+        let gains: Vec<lots::LotGain> = post_json(&app, "/api/portfolio/lots/sell", specific).await;
+        assert_eq!(sold(&gains), vec![("AAPL:2024-01-10#2".to_string(), 2.0)]);
+
+        let report: lots::LotReport = post_json(
+            &app,
+            "/api/portfolio/lots",
+            json!({"portfolio_id": "3", "until": "2024-01-31"}),
+        )
+        .await;
+        let nvda = report.realized.iter().find(|g| g.ticker == "NVDA").unwrap();
+        assert_eq!(nvda.volume, 16.0);
+        let realized = report.realized.iter().map(|g| g.gain).sum::<f64>();
+        assert!((report.summary.realized_short_term - realized).abs() < 1e-9);
+        assert_eq!(report.summary.realized_long_term, 0.0);
+        let unrealized = report.unrealized.iter().map(|g| g.volume).sum::<f64>();
+        assert_eq!(unrealized, 8.0);
+        assert!(report.unrealized.iter().all(|g| g.date == "2024-01-31"));
+
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/portfolio/lots")
+                .set_json(json!({"portfolio_id": "42", "until": "2024-01-31"}))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let too_many = sell("AAPL", 100.0, "lifo", "2024-01-31");
+        let fields = invalid_fields(&app, "/api/portfolio/lots/sell", too_many).await;
+        assert_eq!(fields, vec!["volume"]);
+        let mut unknown = sell("AAPL", 1.0, "specific", "2024-01-31");
+        unknown["lot_ids"] = json!(["AAPL:2024-01-02"]);
+        let fields = invalid_fields(&app, "/api/portfolio/lots/sell", unknown).await;
+        assert_eq!(fields, vec!["lot_ids[0]"]);
+        let mut twice = sell("AAPL", 4.0, "specific", "2024-01-31");
+        twice["lot_ids"] = json!(["AAPL:2024-01-10", "AAPL:2024-01-10"]);
+        let fields = invalid_fields(&app, "/api/portfolio/lots/sell", twice).await;
+        assert_eq!(fields, vec!["lot_ids[1]"]);
+        let fields = invalid_fields(
+            &app,
+            "/api/portfolio/lots/sell",
+            sell("", 1.0, "specific", "2024-01-31"),
+        )
+        .await;
+        assert_eq!(fields, vec!["ticker", "lot_ids"]);
+    }
+
+    #[actix_web::test]
+    async fn portfolio_ledger() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let aapl = json!({"ticker": "AAPL", "security_type": "stock"});
+        let book = |transaction: Value| json!({"portfolio_id": "1", "transaction": transaction});
+        let uri = "/api/portfolio/ledger/transaction";
+        for transaction in [
+            json!({"kind": "deposit", "date": "2024-01-02", "amount": 2000.0}),
+            json!({"kind": "buy", "date": "2024-01-02", "ticker": aapl, "volume": 10.0, "price": 150.0, "fee": 1.0}),
+            json!({"kind": "sell", "date": "2024-01-10", "ticker": aapl, "volume": 4.0, "price": 160.0, "fee": 1.0}),
+            json!({"kind": "dividend", "date": "2024-01-12", "ticker": aapl, "amount": 2.5}),
+            json!({"kind": "fee", "date": "2024-01-31", "amount": 3.0, "note": "custody"}),
+        ] {
+            let _: ledger::Ledger = post_json(&app, uri, book(transaction)).await;
+        }
+        let ledger: ledger::Ledger = get_json(&app, "/api/portfolio/ledger?id=1").await;
+        let balances = ledger.entries.iter().map(|e| e.balance).collect::<Vec<_>>();
+        assert_eq!(balances, vec![2000.0, 499.0, 1138.0, 1140.5, 1137.5]);
+        assert_eq!(ledger.balance, 1137.5);
+
+        // the buy exceeds the cash, until margin is enabled:
+        let expensive = json!({"kind": "buy", "date": "2024-01-15", "ticker": aapl, "volume": 10.0, "price": 150.0});
+        let fields = invalid_fields(&app, uri, book(expensive.clone())).await;
+        assert_eq!(fields, vec!["transaction"]);
+        let margin = json!({"portfolio_id": "1", "margin": true});
+        let ledger: ledger::Ledger = post_json(&app, "/api/portfolio/ledger/margin", margin).await;
+        assert!(ledger.margin);
+        let ledger: ledger::Ledger = post_json(&app, uri, book(expensive)).await;
+        assert_eq!(ledger.balance, 1137.5 - 1500.0);
+        let no_margin = json!({"portfolio_id": "1", "margin": false});
+        let fields = invalid_fields(&app, "/api/portfolio/ledger/margin", no_margin).await;
+        assert_eq!(fields, vec!["margin"]);
+
+        let profits: ledger::LedgerProfits = post_json(
+            &app,
+            "/api/portfolio/ledger/profits",
+            json!({"portfolio_id": "1", "until": "2024-01-31"}),
+        )
+        .await;
+        assert_eq!(profits.deposits, 2000.0);
+        assert_eq!(profits.fees, 5.0);
+        assert_eq!(profits.dividends, 2.5);
+        assert_eq!(profits.positions[0].volume, 16.0);
+        assert!((profits.equity - (profits.cash + profits.market_value)).abs() < 1e-9);
+        let positions = profits.positions.iter().map(|p| p.profit).sum::<f64>();
+        assert!((profits.profit - (positions - 3.0)).abs() < 1e-9);
+
+        let delete = json!({"portfolio_id": "1", "id": 42});
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/portfolio/ledger/delete")
+                .set_json(delete)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get()
+                .uri("/api/portfolio/ledger?id=42")
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let invalid = json!({"kind": "buy", "date": "2024-01-15", "volume": 1.0});
+        let fields = invalid_fields(&app, uri, book(invalid)).await;
+        assert_eq!(fields, vec!["transaction.ticker", "transaction.price"]);
+    }
+
+    #[actix_web::test]
+    async fn portfolio_fx() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let currencies: fx::PortfolioCurrencies =
+            get_json(&app, "/api/portfolio/currencies?id=1").await;
+        assert_eq!(currencies.base_currency, "USD");
+        assert!(currencies.currencies.is_empty());
+        // siemens trades in EUR by the custom fields of its ticker:
+        let sie = json!({
+            "portfolio_id": "1", "security_type": 0, "ticker": "SIE",
+            "volume": 10.0, "purchase_date": "2024-01-03", "sell_date": "",
+        });
+        let _: Value = post_json(&app, "/api/portfolio/buy", sie).await;
+
+        let req = json!({"portfolio_id": "1", "until": "2024-01-31"});
+        let valuation: fx::FxValuation = post_json(&app, "/api/portfolio/fx", &req).await;
+        assert_eq!(valuation.base_currency, "USD");
+        let position = |valuation: &fx::FxValuation, ticker: &str| {
+            let p = valuation.positions.iter().find(|p| p.ticker == ticker);
+            p.unwrap().clone()
+        };
+        let compounded = |local: f64, currency: f64, total: f64| {
+            ((1.0 + local) * (1.0 + currency) - 1.0 - total).abs() < 1e-12
+        };
+        let aapl = position(&valuation, "AAPL");
+        assert_eq!((aapl.currency.as_str(), aapl.rate), ("USD", 1.0));
+        assert_eq!(aapl.currency_return, 0.0);
+        let msft = position(&valuation, "MSFT");
+        assert_eq!(msft.date, "2024-01-25");
+        let siemens = position(&valuation, "SIE");
+        assert_eq!(siemens.currency, "EUR");
+        assert!(siemens.purchase_rate > 1.0 && siemens.rate > 1.0);
+        assert!((siemens.base_cost - siemens.local_cost * siemens.purchase_rate).abs() < 1e-9);
+        assert!(siemens.currency_return != 0.0);
+        for p in valuation.positions.iter() {
+            assert!(compounded(
+                p.local_return,
+                p.currency_return,
+                p.total_return
+            ));
+        }
+        assert!(compounded(
+            valuation.local_return,
+            valuation.currency_return,
+            valuation.total_return
+        ));
+        let profit = valuation.positions.iter().map(|p| p.profit).sum::<f64>();
+        assert!((valuation.profit - profit).abs() < 1e-9);
+
+        // in EUR, the rates of the USD securities are the inverted EURUSD closes:
+        let eur =
+            json!({"portfolio_id": "1", "base_currency": "eur", "currencies": {"MSFT": "usd"}});
+        let currencies: fx::PortfolioCurrencies =
+            post_json(&app, "/api/portfolio/currencies", &eur).await;
+        assert_eq!(currencies.base_currency, "EUR");
+        assert_eq!(currencies.currencies["MSFT"], "USD");
+        let in_eur: fx::FxValuation = post_json(&app, "/api/portfolio/fx", &req).await;
+        assert_eq!(in_eur.base_currency, "EUR");
+        assert!((position(&in_eur, "AAPL").rate * siemens.rate - 1.0).abs() < 1e-12);
+        let siemens_in_eur = position(&in_eur, "SIE");
+        assert_eq!(siemens_in_eur.rate, 1.0);
+        assert_eq!(siemens_in_eur.total_return, siemens.local_return);
+        let ledger: ledger::Ledger = get_json(&app, "/api/portfolio/ledger?id=1").await;
+        assert_eq!(ledger.base_currency, "EUR");
+
+        let jpy = json!({"portfolio_id": "1", "base_currency": "JPY"});
+        let _: fx::PortfolioCurrencies = post_json(&app, "/api/portfolio/currencies", jpy).await;
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::post()
+                .uri("/api/portfolio/fx")
+                .set_json(&req)
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let _: fx::PortfolioCurrencies = post_json(&app, "/api/portfolio/currencies", &eur).await;
+
+        // the ledger values its positions in the base currency too:
+        let aapl = json!({"ticker": "AAPL", "security_type": "stock"});
+        for transaction in [
+            json!({"kind": "deposit", "date": "2024-01-02", "amount": 1000.0}),
+            json!({"kind": "buy", "date": "2024-01-02", "ticker": aapl, "volume": 2.0, "price": 170.0}),
+        ] {
+            let book = json!({"portfolio_id": "1", "transaction": transaction});
+            let _: ledger::Ledger =
+                post_json(&app, "/api/portfolio/ledger/transaction", book).await;
+        }
+        let profits: ledger::LedgerProfits = post_json(
+            &app,
+            "/api/portfolio/ledger/profits",
+            json!({"portfolio_id": "1", "until": "2024-01-31"}),
+        )
+        .await;
+        let aapl_in_eur = position(&in_eur, "AAPL");
+        let price = aapl_in_eur.local_value / aapl_in_eur.volume * aapl_in_eur.rate;
+        assert!((profits.positions[0].price - price).abs() < 1e-9);
+        // the transactions are booked in EUR now:
+        let usd = json!({"portfolio_id": "1", "base_currency": "USD"});
+        let fields = invalid_fields(&app, "/api/portfolio/currencies", usd).await;
+        assert_eq!(fields, vec!["base_currency"]);
+
+        let invalid =
+            json!({"portfolio_id": "1", "base_currency": "EURO", "currencies": {"SIE": "1"}});
+        let fields = invalid_fields(&app, "/api/portfolio/currencies", invalid).await;
+        assert_eq!(fields, vec!["base_currency", "currencies.SIE"]);
+    }
+
+    #[actix_web::test]
+    async fn tickers() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
         let req_body = trading::TickerFilter {
-            ttype: 0,
+            ttype: SecurityType::Stock,
             filter: Some("aapl".to_string()),
             limit: Some(10),
             traded_within_past_n_days: None,
         };
-        let ts: Vec<trading::Ticker> = post_json("/tickers", req_body).await?;
-        println!("streamed {} tickers", ts.len());
-        assert!(ts.len() > 0);
-        Ok(())
+        let ts: Vec<Value> = post_json(&app, "/api/tickers", req_body).await;
+        assert_eq!(ts.len(), 1);
+        assert_eq!(ts[0]["ticker"], "AAPL");
+        assert_eq!(ts[0]["name"], "Apple Inc.");
+
+        let req_body = trading::TickerFilter {
+            ttype: SecurityType::Etf,
+            filter: None,
+            limit: None,
+            traded_within_past_n_days: None,
+        };
+        let ts: Vec<Value> = post_json(&app, "/api/tickers", req_body).await;
+        assert_eq!(ts.len(), 2);
     }
 
-    #[tokio::test]
-    async fn security_data() -> Result<()> {
-        let start = Instant::now();
+    #[actix_web::test]
+    async fn security_data() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
         let req_body = trading::TimeSeriesReq {
             ticker: trading::BasicTicker {
                 ticker: "AAPL".to_string(),
-                security_type: 0,
+                security_type: SecurityType::Stock,
             },
-            from: "2024-01-01".to_string(),
-            until: "2024-01-31".to_string(),
+            from: "2024-01-02".to_string(),
+            until: "2024-01-03".to_string(),
+            adjusted: None,
+            resolution: None,
+            extended_hours: None,
         };
-        let ts: Vec<trading::TimeSeriesData> = post_json("/securityData", req_body).await?;
-        println!(
-            "streamed {} security data in {:?}",
-            ts.len(),
-            start.elapsed()
+        let ts: Vec<Value> = post_json(&app, "/api/securityData", req_body).await;
+        // 13 half hourly bars per trading day:
+        assert_eq!(ts.len(), 26);
+        assert_eq!(ts[0]["date"], "2024-01-02T09:30:00");
+        assert_eq!(ts[25]["date"], "2024-01-03T15:30:00");
+        assert!(ts[0]["values"]["close"].is_number());
+    }
+
+    #[actix_web::test]
+    async fn resampled_security_data() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let mut req_body = json!({
+            "ticker": {"ticker": "AAPL", "security_type": 0},
+            "from": "2024-01-02",
+            "until": "2024-01-02",
+            "resolution": "1h",
+        });
+        let raw: Vec<Value> = post_json(
+            &app,
+            "/api/securityData",
+            json!({
+                "ticker": {"ticker": "AAPL", "security_type": 0},
+                "from": "2024-01-02",
+                "until": "2024-01-02",
+            }),
+        )
+        .await;
+        let value = |ts: &[Value], i: usize, key: &str| ts[i]["values"][key].as_f64().unwrap();
+
+        // 13 half hourly bars from 9:30 make 7 hourly bars, the last one only half full:
+        let bars: Vec<Value> = post_json(&app, "/api/securityData", &req_body).await;
+        assert_eq!(bars.len(), 7);
+        assert_eq!(bars[0]["date"], "2024-01-02T09:30:00");
+        assert_eq!(bars[6]["date"], "2024-01-02T15:30:00");
+        assert_eq!(value(&bars, 0, "open"), value(&raw, 0, "open"));
+        assert_eq!(value(&bars, 0, "close"), value(&raw, 1, "close"));
+        assert_eq!(
+            value(&bars, 0, "volume"),
+            value(&raw, 0, "volume") + value(&raw, 1, "volume")
+        );
+        assert_eq!(value(&bars, 6, "close"), value(&raw, 12, "close"));
+
+        req_body["until"] = json!("2024-01-31");
+        for (resolution, n) in [("1d", 21), ("1w", 5), ("1M", 1)] {
+            req_body["resolution"] = json!(resolution);
+            let bars: Vec<Value> = post_json(&app, "/api/securityData", &req_body).await;
+            assert_eq!(bars.len(), n, "{}", resolution);
+        }
+        let bars: Vec<Value> = post_json(&app, "/api/securityData", &req_body).await;
+        assert_eq!(bars[0]["date"], "2024-01-01");
+
+        req_body["resolution"] = json!("2h");
+        let req = test::TestRequest::post()
+            .uri("/api/securityData")
+            .set_json(&req_body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn indicators() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let mut req_body = json!({
+            "ticker": {"ticker": "AAPL", "security_type": 0},
+            "from": "2024-01-22",
+            "until": "2024-01-31",
+            "indicators": [{"name": "sma", "period": 5}, {"name": "rsi", "period": 3}],
+        });
+        let daily: Vec<Value> = post_json(
+            &app,
+            "/api/securityData",
+            json!({
+                "ticker": {"ticker": "AAPL", "security_type": 0},
+                "from": "2024-01-01",
+                "until": "2024-01-31",
+                "resolution": "1d",
+            }),
+        )
+        .await;
+        let closes = daily
+            .iter()
+            .map(|d| d["values"]["close"].as_f64().unwrap())
+            .collect::<Vec<_>>();
+
+        // the warm-up bars before from are not part of the output:
+        let values: Vec<Value> = post_json(&app, "/api/indicators", &req_body).await;
+        assert_eq!(values.len(), 8);
+        assert_eq!(values[0]["date"], "2024-01-22");
+        let i = daily
+            .iter()
+            .position(|d| d["date"] == "2024-01-22")
+            .unwrap();
+        let sma = closes[i - 4..=i].iter().sum::<f64>() / 5.0;
+        assert!((values[0]["values"]["sma_5"].as_f64().unwrap() - sma).abs() < 1e-9);
+        assert_eq!(values[0]["values"]["close"].as_f64().unwrap(), closes[i]);
+        assert!(values.iter().all(|v| v["values"]["rsi_3"].is_number()));
+
+        req_body["indicators"] = json!([{"name": "sma", "period": 0}]);
+        let req = test::TestRequest::post()
+            .uri("/api/indicators")
+            .set_json(&req_body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let body: ErrorBody = test::read_body_json(resp).await;
+        assert_eq!(body.fields[0].field, "indicators[0]");
+    }
+
+    #[actix_web::test]
+    async fn split_adjusted_security_data() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        // NVDA split 4:1 on 2024-01-16, the last bar before is the 13th:
+        let mut req_body = json!({
+            "ticker": {"ticker": "NVDA", "security_type": 0},
+            "from": "2024-01-12",
+            "until": "2024-01-16",
+        });
+        let value = |ts: &[Value], i: usize, key: &str| ts[i]["values"][key].as_f64().unwrap();
+        let raw: Vec<Value> = post_json(&app, "/api/securityData", &req_body).await;
+        assert_eq!(raw.len(), 26);
+        assert!(value(&raw, 12, "close") / value(&raw, 13, "close") > 3.0);
+
+        req_body["adjusted"] = json!(true);
+        let adjusted: Vec<Value> = post_json(&app, "/api/securityData", &req_body).await;
+        assert_eq!(adjusted.len(), 26);
+        let jump = value(&adjusted, 12, "close") / value(&adjusted, 13, "close");
+        assert!((0.8..1.25).contains(&jump), "jump: {}", jump);
+        assert_eq!(value(&adjusted, 0, "close"), value(&raw, 0, "close") / 4.0);
+        assert_eq!(
+            value(&adjusted, 0, "volume"),
+            value(&raw, 0, "volume") * 4.0
         );
-        assert!(ts.len() > 100);
-        Ok(())
+        // entries after the split are untouched:
+        assert_eq!(adjusted[13], raw[13]);
+    }
+
+    #[actix_web::test]
+    async fn movements() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let mut req_body = json!({
+            "security_type": 0,
+            "sort_by": 0,
+            "until": "2024-01-31",
+            "period": 3,
+            "limit": 10,
+            "min_volume": 0,
+            "min_variance": 0.0,
+            "max_variance": 0.0,
+        });
+        let ms: Vec<Value> = post_json(&app, "/api/movements", &req_body).await;
+        let tickers: Vec<&str> = ms
+            .iter()
+            .map(|m| m["ticker"]["ticker"].as_str().unwrap())
+            .collect();
+        assert_eq!(tickers, vec!["MSFT", "AAPL", "NVDA"]);
+
+        // NVDA had a stock split in january:
+        req_body["without_stock_splits"] = json!(true);
+        let ms: Vec<Value> = post_json(&app, "/api/movements", &req_body).await;
+        let tickers: Vec<&str> = ms
+            .iter()
+            .map(|m| m["ticker"]["ticker"].as_str().unwrap())
+            .collect();
+        assert_eq!(tickers, vec!["MSFT", "AAPL"]);
+
+        // or keep it, with the performance corrected for the split:
+        req_body["without_stock_splits"] = json!(false);
+        req_body["adjusted"] = json!(true);
+        let ms: Vec<Value> = post_json(&app, "/api/movements", &req_body).await;
+        let nvda = ms.iter().find(|m| m["ticker"]["ticker"] == "NVDA").unwrap();
+        let performance = nvda["performance"].as_f64().unwrap();
+        assert!((performance - 0.12).abs() < 1e-9);
+        // and ranked by it:
+        let tickers: Vec<&str> = ms
+            .iter()
+            .map(|m| m["ticker"]["ticker"].as_str().unwrap())
+            .collect();
+        assert_eq!(tickers, vec!["NVDA", "MSFT", "AAPL"]);
+        req_body["sort_by"] = json!("loser");
+        let ms: Vec<Value> = post_json(&app, "/api/movements", &req_body).await;
+        let tickers: Vec<&str> = ms
+            .iter()
+            .map(|m| m["ticker"]["ticker"].as_str().unwrap())
+            .collect();
+        assert_eq!(tickers, vec!["AAPL", "MSFT", "NVDA"]);
+
+        // the enums are also accepted by name, unknown values are rejected:
+        let req_body = json!({
+            "security_type": "stock",
+            "sort_by": "winner",
+            "until": "2024-01-31",
+            "period": "month",
+            "limit": 10,
+            "min_volume": 0,
+            "min_variance": 0.0,
+            "max_variance": 0.0,
+        });
+        let ms: Vec<Value> = post_json(&app, "/api/movements", &req_body).await;
+        assert_eq!(ms.len(), 3);
+        for (key, value) in [("period", json!("fortnight")), ("sort_by", json!(9))] {
+            let mut invalid = req_body.clone();
+            invalid[key] = value;
+            let req = test::TestRequest::post()
+                .uri("/api/movements")
+                .set_json(invalid)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+            let body: ErrorBody = test::read_body_json(resp).await;
+            assert!(body.message.contains("unknown"), "{}", body.message);
+        }
+    }
+
+    #[actix_web::test]
+    async fn correlating_tickers() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req_body = json!({"until": "2024-01-31", "period": 3, "limit": 2, "sign": 2});
+        let cs: Vec<Value> = post_json(&app, "/api/correlatingTickers", req_body).await;
+        assert_eq!(cs.len(), 2);
+        let req_body =
+            json!({"until": "2024-01-31", "period": "quarter", "limit": 2, "sign": "negative"});
+        let named: Vec<Value> = post_json(&app, "/api/correlatingTickers", req_body).await;
+        assert_eq!(named.len(), 2);
+        assert_eq!(cs[0]["tickers"][0]["ticker"], "AAPL");
+        assert_eq!(cs[0]["tickers"][1]["ticker"], "SPY");
+        assert_eq!(cs[0]["correlation"], -0.35);
+    }
+
+    #[actix_web::test]
+    async fn mutual_correlations() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req_body = json!({
+            "tickers": [
+                {"ticker": "AAPL", "security_type": 0},
+                {"ticker": "MSFT", "security_type": 0},
+                {"ticker": "NVDA", "security_type": 0},
+            ],
+            "until": "2024-01-31",
+            "period": 3,
+        });
+        let cs: Vec<Value> = post_json(&app, "/api/mutualCorrelations", req_body).await;
+        assert_eq!(cs.len(), 3);
+        assert_eq!(cs[0]["ticker"]["ticker"], "AAPL");
+        assert_eq!(cs[0]["correlations"].as_array().unwrap().len(), 2);
+        assert_eq!(cs[0]["correlations"][0]["correlation"], 0.82);
+    }
+
+    #[actix_web::test]
+    async fn stream_formats() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/tickers?format=csv")
+            .set_json(json!({"security_type": 0, "filter": "a"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), "text/csv");
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "ticker,name,security_type,custom_fields\n\
+             AAPL,Apple Inc.,0,\"{\"\"exchange\"\":\"\"NASDAQ\"\"}\"\n\
+             MSFT,Microsoft Corporation,0,\"{\"\"exchange\"\":\"\"NASDAQ\"\"}\"\n\
+             NVDA,NVIDIA Corporation,0,\"{\"\"exchange\"\":\"\"NASDAQ\"\"}\"\n"
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/api/securityData")
+            .insert_header(("Accept", "application/x-ndjson"))
+            .set_json(json!({
+                "ticker": {"ticker": "AAPL", "security_type": 0},
+                "from": "2024-01-02",
+                "until": "2024-01-02",
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = test::read_body(resp).await;
+        let lines = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0]["date"], "2024-01-02T09:30:00");
+
+        let req = test::TestRequest::post()
+            .uri("/api/correlatingTickers")
+            .insert_header(("Accept", "text/event-stream"))
+            .set_json(json!({"until": "2024-01-31", "period": 3, "limit": 3}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+        let body = test::read_body(resp).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(body.matches("data: {\"tickers\"").count(), 3);
+        assert!(body.ends_with("event: end\ndata: {\"count\":3}\n\n"));
+
+        let req = test::TestRequest::post()
+            .uri("/api/tickers?format=xml")
+            .set_json(json!({"security_type": 0}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn columnar_security_data() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req_body = json!({
+            "ticker": {"ticker": "MSFT", "security_type": 0},
+            "from": "2024-01-02",
+            "until": "2024-01-05",
+        });
+        let req = test::TestRequest::post()
+            .uri("/api/securityData/arrow")
+            .set_json(&req_body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/vnd.apache.arrow.stream"
+        );
+        let body = test::read_body(resp).await;
+        let reader = arrow_ipc::reader::StreamReader::try_new(&body[..], None).unwrap();
+        assert_eq!(reader.schema().fields().len(), 6);
+        let rows: usize = reader.map(|b| b.unwrap().num_rows()).sum();
+        assert_eq!(rows, 4 * 13);
+
+        let req = test::TestRequest::post()
+            .uri("/api/securityData/parquet")
+            .set_json(&req_body)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap(),
+            "attachment; filename=\"MSFT_2024-01-02_2024-01-05.parquet\""
+        );
+        let body = test::read_body(resp).await;
+        let builder =
+            parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder::try_new(body).unwrap();
+        assert_eq!(builder.metadata().file_metadata().num_rows(), 4 * 13);
+    }
+
+    #[actix_web::test]
+    async fn ticker_details() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let t: Value = get_json(&app, "/api/ticker?ticker=SPY&security_type=etf").await;
+        assert_eq!(t["ticker"], "SPY");
+        assert_eq!(t["name"], "SPDR S&P 500 ETF Trust");
+        assert_eq!(t["exchange"], "NASDAQ");
+
+        let req = test::TestRequest::get()
+            .uri("/api/ticker?ticker=XYZ&security_type=0")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn latest_security_data_date() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let d: trading::LatestDate = get_json(
+            &app,
+            "/api/securityData/latestDate?ticker=AAPL&security_type=0",
+        )
+        .await;
+        assert_eq!(d.date, "2024-01-31T15:30:00");
+    }
+
+    #[actix_web::test]
+    async fn single_and_average_movements() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req_body =
+            json!({"ticker": "MSFT", "security_type": 0, "until": "2024-01-31", "period": 3});
+        let m: Value = post_json(&app, "/api/movement", &req_body).await;
+        assert_eq!(m["ticker"]["ticker"], "MSFT");
+        assert_eq!(m["performance"], 0.06);
+        let m: Value = post_json(&app, "/api/avgMovement", &req_body).await;
+        assert_eq!(m["ticker"]["ticker"], "MSFT");
+
+        let ms: Vec<Value> = post_json(
+            &app,
+            "/api/avgMovements",
+            json!({
+                "security_type": 1,
+                "sort_by": 1,
+                "until": "2024-01-31",
+                "period": 3,
+                "limit": 10,
+                "min_volume": 0,
+                "min_variance": 0.0,
+                "max_variance": 0.0,
+            }),
+        )
+        .await;
+        let tickers: Vec<&str> = ms
+            .iter()
+            .map(|m| m["ticker"]["ticker"].as_str().unwrap())
+            .collect();
+        assert_eq!(tickers, vec!["SPY", "QQQ"]);
+    }
+
+    #[actix_web::test]
+    async fn correlations() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let req_body = json!({
+            "tickers": [
+                {"ticker": "AAPL", "security_type": 0},
+                {"ticker": "MSFT", "security_type": 0},
+                {"ticker": "NVDA", "security_type": 0},
+            ],
+            "period": 3,
+        });
+        let cs: Vec<Value> = post_json(&app, "/api/correlations", &req_body).await;
+        assert_eq!(cs.len(), 3);
+        assert_eq!(cs[0]["correlation"], 0.82);
+
+        let req = test::TestRequest::post()
+            .uri("/api/correlations?format=ndjson")
+            .set_json(&req_body)
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        assert_eq!(std::str::from_utf8(&body).unwrap().lines().count(), 3);
+    }
+
+    #[actix_web::test]
+    async fn delete_portfolios() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let resp: Value = post_json(
+            &app,
+            "/api/portfolio/security/delete",
+            json!({
+                "portfolio_id": "1",
+                "security_type": 0,
+                "ticker": "MSFT",
+                "volume": 5.0,
+                "purchase_date": "2024-01-08",
+                "sell_date": "2024-01-25",
+            }),
+        )
+        .await;
+        assert_eq!(resp["success"], true);
+        let securities: Vec<Value> = get_json(&app, "/api/portfolio/securities?id=1").await;
+        assert_eq!(securities.len(), 1);
+
+        // the ledger and the sales of lots go with the portfolio, a valuation job keeps it:
+        let deposit = json!({"kind": "deposit", "date": "2024-01-02", "amount": 1000.0});
+        let book = json!({"portfolio_id": "1", "transaction": deposit});
+        let _: ledger::Ledger = post_json(&app, "/api/portfolio/ledger/transaction", book).await;
+        let sell = json!({
+            "portfolio_id": "1", "ticker": "AAPL", "security_type": "stock",
+            "volume": 4.0, "method": "fifo", "date": "2024-01-19",
+        });
+        let _: Vec<lots::LotGain> = post_json(&app, "/api/portfolio/lots/sell", sell).await;
+        let job = json!({
+            "name": "valuation",
+            "trigger": {"type": "cron", "expr": "0 22 * * MON-FRI"},
+            "action": {"type": "portfolio_valuation", "portfolio_id": "1"},
+        });
+        let _: Value = post_json(&app, "/api/jobs", job).await;
+        let req = test::TestRequest::post()
+            .uri("/api/portfolio/delete")
+            .set_json(json!({"id": "1"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let _: Value = post_json(&app, "/api/jobs/delete", json!({"name": "valuation"})).await;
+
+        let resp: Value = post_json(&app, "/api/portfolio/delete", json!({"id": "1"})).await;
+        assert_eq!(resp["success"], true);
+        let ps: Vec<trading::Portfolio> = get_json(&app, "/api/portfolios?filter=").await;
+        assert_eq!(ps.len(), 1);
+        let envs = server.envs();
+        for file in [envs.ledger_file, envs.lots_file] {
+            let store: Value =
+                serde_json::from_str(&std::fs::read_to_string(file).unwrap()).unwrap();
+            // the accounts of the ledger and the sales of the lots by portfolio:
+            let by_portfolio = store
+                .as_object()
+                .unwrap()
+                .values()
+                .filter_map(|v| v.as_object());
+            assert!(by_portfolio.flat_map(|p| p.keys()).all(|id| id != "1"));
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/api/portfolio/delete")
+            .set_json(json!({"id": "1"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn stock_splits() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let splits: Vec<trading::StockSplit> =
+            get_json(&app, "/api/stockSplits?from=2024-01-01&until=2024-01-31").await;
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].ticker, "NVDA");
+        assert_eq!(splits[0].numerator, 4.0);
+
+        let splits: Vec<trading::StockSplit> = get_json(
+            &app,
+            "/api/stockSplits?from=2024-02-01&until=2024-02-28&limit=5",
+        )
+        .await;
+        assert!(splits.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn market_status() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let statuses: Vec<Value> = get_json(&app, "/api/market/status").await;
+        assert_eq!(statuses.len(), 6);
+        assert_eq!(statuses[0]["exchange"], "NYSE");
+        assert_eq!(statuses[0]["timezone"], "America/New_York");
+        let statuses: Vec<Value> = get_json(&app, "/api/market/status?exchange=crypto").await;
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0]["state"], "open");
+
+        let req = test::TestRequest::get()
+            .uri("/api/market/status?exchange=moon")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // the feed starts with the current status of each exchange:
+        let req = test::TestRequest::get()
+            .uri("/api/market/feed?exchange=crypto")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let mut body = resp.into_body();
+        let chunk = futures::future::poll_fn(|cx| {
+            actix_web::body::MessageBody::poll_next(std::pin::Pin::new(&mut body), cx)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        let status: Value = serde_json::from_str(chunk.strip_prefix("data: ").unwrap()).unwrap();
+        assert_eq!(status["exchange"], "CRYPTO");
+        assert_eq!(status["state"], "open");
+    }
+
+    #[actix_web::test]
+    async fn jobs() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+
+        let job = json!({
+            "name": "nightly-movements",
+            "trigger": {"type": "cron", "expr": "0 22 * * MON-FRI", "exchange": "NYSE"},
+            "action": {"type": "movements", "req": {
+                "security_type": 0,
+                "sort_by": 0,
+                "period": 3,
+                "limit": 10,
+                "min_volume": 0,
+                "min_variance": 0.0,
+                "max_variance": 1000.0,
+                "without_stock_splits": null,
+            }},
+        });
+        let info: Value = post_json(&app, "/api/jobs", job.clone()).await;
+        assert_eq!(info["name"], "nightly-movements");
+        assert_eq!(info["paused"], false);
+        assert!(info["next_run"].is_string());
+
+        let info: Value = post_json(
+            &app,
+            "/api/jobs/pause",
+            json!({"name": "nightly-movements"}),
+        )
+        .await;
+        assert_eq!(info["paused"], true);
+        assert!(info["next_run"].is_null());
+
+        let run: Value = post_json(
+            &app,
+            "/api/jobs/trigger",
+            json!({"name": "nightly-movements"}),
+        )
+        .await;
+        assert_eq!(run["manual"], true);
+        let mut runs: Vec<Value> = vec![];
+        for _ in 0..100 {
+            runs = get_json(&app, "/api/jobs/runs?name=nightly-movements").await;
+            if runs[0]["status"] != "running" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0]["status"], "succeeded");
+
+        let jobs: Vec<Value> = get_json(&app, "/api/jobs").await;
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0]["last_run"]["id"], runs[0]["id"]);
+
+        let mut invalid = job.clone();
+        invalid["trigger"]["expr"] = json!("every day");
+        let req = test::TestRequest::post()
+            .uri("/api/jobs")
+            .set_json(invalid)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/api/jobs/trigger")
+            .set_json(json!({"name": "unknown"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp: Value = post_json(
+            &app,
+            "/api/jobs/delete",
+            json!({"name": "nightly-movements"}),
+        )
+        .await;
+        assert_eq!(resp["success"], true);
+    }
+
+    #[actix_web::test]
+    async fn routes_documented() {
+        let server = MockServer::start().await.unwrap();
+        let app = app(&server).await;
+        let doc: Value = get_json(&app, "/api/openapi.json").await;
+        let paths = doc["paths"].as_object().unwrap();
+
+        // every route registered with an actix attribute is part of the document:
+        let route = regex::Regex::new(r#"#\[(get|post)\("([^"]+)"\)\]"#).unwrap();
+        let routes = route
+            .captures_iter(include_str!("main.rs"))
+            .map(|c| (c[1].to_string(), format!("/api{}", &c[2])))
+            .collect::<Vec<_>>();
+        for (method, path) in routes.iter() {
+            assert!(
+                paths.get(path).and_then(|p| p.get(method)).is_some(),
+                "{} {} is not documented",
+                method,
+                path
+            );
+        }
+        // and nothing else is:
+        let operations = paths
+            .values()
+            .flat_map(|p| p.as_object().unwrap().keys())
+            .filter(|method| ["get", "post"].contains(&method.as_str()))
+            .count();
+        assert_eq!(operations, routes.len());
+
+        let req = test::TestRequest::get().uri("/api/docs/").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    // serve runs the routes on a free port, for tests of the http client:
+    fn serve(server: &MockServer) -> String {
+        let trading = Data::new(Trading::new(server.envs()).unwrap());
+        let scheduler = Scheduler::new(trading.clone().into_inner(), &server.envs()).unwrap();
+        let scheduler = Data::new(scheduler);
+        let http = HttpServer::new(move || {
+            App::new()
+                .app_data(trading.clone())
+                .app_data(scheduler.clone())
+                .configure(routes)
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = http.addrs()[0];
+        actix_web::rt::spawn(http.run());
+        format!("http://{}", addr)
+    }
+
+    #[actix_web::test]
+    async fn client() {
+        let server = MockServer::start().await.unwrap();
+        let client = Client::with_base_url(&serve(&server)).unwrap();
+
+        let index = client.portfolio("2").await.unwrap();
+        assert_eq!(index.name, "index");
+        let err = client.portfolio("42").await.err().unwrap();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.status, Some(404));
+        assert_eq!(err.body.message, "unknown portfolio 42");
+
+        // streams are decoded entry by entry:
+        let filter = trading::TickerFilter {
+            ttype: SecurityType::Etf,
+            filter: None,
+            limit: None,
+            traded_within_past_n_days: None,
+        };
+        let tickers = client.tickers(&filter).await.unwrap();
+        assert_eq!(tickers.try_collect::<Vec<_>>().await.unwrap().len(), 2);
+        let series = trading::TimeSeriesReq {
+            ticker: trading::BasicTicker {
+                ticker: "AAPL".to_string(),
+                security_type: SecurityType::Stock,
+            },
+            from: "2024-01-02".to_string(),
+            until: "2024-01-02".to_string(),
+            adjusted: None,
+            resolution: None,
+            extended_hours: None,
+        };
+        let series = client.security_data(&series).await.unwrap();
+        let series = series.try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(series.len(), 13);
+        assert_eq!(series[0].date, "2024-01-02T09:30:00");
+
+        let mut req = trading::MovementsReq {
+            security_type: SecurityType::Stock,
+            sort_by: SortBy::Winner,
+            until: "2024-01-31".to_string(),
+            period: Period::Quarter,
+            limit: 10,
+            min_volume: 0,
+            min_variance: 0.0,
+            max_variance: 0.0,
+            without_stock_splits: None,
+            adjusted: None,
+            trading_days: None,
+        };
+        assert_eq!(client.movements(&req).await.unwrap().len(), 3);
+        req.limit = 0;
+        let err = client.movements(&req).await.err().unwrap();
+        let err = err.downcast_ref::<ApiError>().unwrap();
+        assert_eq!(err.status, Some(400));
+        assert_eq!(err.body.fields[0].field, "limit");
+
+        let created = client
+            .create_portfolio("dividends", "income")
+            .await
+            .unwrap();
+        let security = trading::PortfolioSecurity {
+            portfolio_id: created.id.to_string(),
+            security_type: 0,
+            ticker: "AAPL".to_string(),
+            volume: 3.0,
+            purchase_date: "2024-01-03".to_string(),
+            sell_date: "".to_string(),
+        };
+        client.buy_security(&security).await.unwrap();
+        let securities = client.portfolio_securities(&created.id).await.unwrap();
+        assert_eq!(securities[0].ticker, "AAPL");
+
+        let splits = client
+            .stock_splits(&trading::StockSplitsReq {
+                from: "2024-01-01".to_string(),
+                until: "2024-01-31".to_string(),
+                limit: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(splits[0].ticker, "NVDA");
+        let status = client.market_status(&MarketStatusReq::default()).await;
+        assert_eq!(status.unwrap().len(), 6);
+        assert!(client.jobs().await.unwrap().is_empty());
+
+        // requests are retried, until the retries are used up:
+        let unreachable = Client::new(ClientOptions {
+            base_url: "http://127.0.0.1:1".to_string(),
+            retries: 2,
+            retry_backoff: std::time::Duration::from_millis(1),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(unreachable.portfolios("").await.is_err());
     }
 }
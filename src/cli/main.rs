@@ -0,0 +1,565 @@
+// rustix-cli queries rustix from the command line. It talks to the rustix api, or with --direct
+// to the DataLoader (at DB_LOADER_HOST and DB_LOADER_PORT), and prints the results as table,
+// json or csv. Requests are validated before they are sent, so both ways report the same errors.
+mod output;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use futures::TryStreamExt;
+use output::Output;
+use rustix::client::{ndjson_stream, Client};
+use rustix::enums::{Period, SecurityType, Sign, SortBy};
+use rustix::envs::Envs;
+use rustix::lots::Lots;
+use rustix::resample::Resolution;
+use rustix::stream::StreamFormat;
+use rustix::time::{new_york_now, parse_date};
+use rustix::trading::{
+    BasicTicker, CorrelReq, CorrelatingTickers, CorrelatingTickersReq, Movement, MovementsReq,
+    Portfolio, PortfolioSecurity, SecurityProfit, SecurityProfitReq, Ticker, TickerFilter,
+    TimeSeriesData, TimeSeriesReq, Trading,
+};
+use rustix::validate::Validate;
+use std::io::Write;
+use std::sync::Arc;
+
+#[derive(Parser)]
+#[command(
+    name = "rustix-cli",
+    about = "Query tickers, time series, movements, correlations and portfolios of rustix"
+)]
+struct Cli {
+    /// Address of the rustix api
+    #[arg(
+        long,
+        env = "RUSTIX_URL",
+        default_value = "http://127.0.0.1:8000",
+        global = true
+    )]
+    url: String,
+    /// Query the DataLoader at DB_LOADER_HOST and DB_LOADER_PORT instead of the api
+    #[arg(long, global = true)]
+    direct: bool,
+    #[arg(long, short, value_enum, default_value_t = Output::Table, global = true)]
+    output: Output,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the tickers of a security type
+    Tickers {
+        #[arg(long = "type", default_value = "stock", value_parser = SecurityType::parse)]
+        security_type: SecurityType,
+        /// Part of the ticker or name
+        #[arg(long)]
+        filter: Option<String>,
+        #[arg(long)]
+        limit: Option<u32>,
+    },
+    /// Time series of a ticker, e.g. daily bars with --resolution 1d
+    Series {
+        ticker: String,
+        #[arg(long = "type", default_value = "stock", value_parser = SecurityType::parse)]
+        security_type: SecurityType,
+        #[arg(long, value_parser = date)]
+        from: NaiveDate,
+        /// Today, if not set
+        #[arg(long, value_parser = date)]
+        until: Option<NaiveDate>,
+        /// 1m, 5m, 15m, 1h, 1d, 1w or 1M
+        #[arg(long, value_parser = Resolution::parse)]
+        resolution: Option<Resolution>,
+        /// Adjust prices and volumes for stock splits
+        #[arg(long)]
+        adjusted: bool,
+    },
+    /// Best or worst performing tickers of a period
+    Movements {
+        #[arg(long = "type", default_value = "stock", value_parser = SecurityType::parse)]
+        security_type: SecurityType,
+        #[arg(long, default_value = "winner", value_parser = SortBy::parse)]
+        sort_by: SortBy,
+        #[arg(long, default_value = "month", value_parser = Period::parse)]
+        period: Period,
+        /// Today, if not set
+        #[arg(long, value_parser = date)]
+        until: Option<NaiveDate>,
+        #[arg(long, default_value_t = 10)]
+        limit: u32,
+        #[arg(long, default_value_t = 0)]
+        min_volume: u64,
+        /// 0 for no lower bound
+        #[arg(long, default_value_t = 0.0)]
+        min_variance: f64,
+        /// 0 for no upper bound
+        #[arg(long, default_value_t = 0.0)]
+        max_variance: f64,
+        /// Correct the performance of tickers with stock splits
+        #[arg(long)]
+        adjusted: bool,
+    },
+    /// Most correlating tickers of a period, or the correlations of the given tickers
+    Correl {
+        /// Tickers to correlate with each other, e.g. --ticker AAPL --ticker MSFT
+        #[arg(long = "ticker")]
+        tickers: Vec<String>,
+        #[arg(long = "type", default_value = "stock", value_parser = SecurityType::parse)]
+        security_type: SecurityType,
+        #[arg(long, default_value = "month", value_parser = Period::parse)]
+        period: Period,
+        /// Today, if not set
+        #[arg(long, value_parser = date)]
+        until: Option<NaiveDate>,
+        #[arg(long, default_value_t = 10)]
+        limit: u32,
+        #[arg(long, value_parser = Sign::parse)]
+        sign: Option<Sign>,
+    },
+    /// List, create and value portfolios, buy and sell their securities
+    #[command(subcommand)]
+    Portfolio(PortfolioCommand),
+}
+
+#[derive(Subcommand)]
+enum PortfolioCommand {
+    /// List the portfolios, whose name contains the filter
+    List {
+        #[arg(long, default_value = "")]
+        filter: String,
+    },
+    Create {
+        name: String,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    Buy {
+        id: String,
+        ticker: String,
+        volume: f64,
+        #[arg(long = "type", default_value = "stock", value_parser = SecurityType::parse)]
+        security_type: SecurityType,
+        /// Today, if not set
+        #[arg(long, value_parser = date)]
+        date: Option<NaiveDate>,
+    },
+    /// Sell the securities bought at the purchase date
+    Sell {
+        id: String,
+        ticker: String,
+        volume: f64,
+        #[arg(long = "type", default_value = "stock", value_parser = SecurityType::parse)]
+        security_type: SecurityType,
+        #[arg(long, value_parser = date)]
+        purchase_date: NaiveDate,
+        /// Today, if not set
+        #[arg(long, value_parser = date)]
+        date: Option<NaiveDate>,
+    },
+    /// Daily profits of the portfolio's securities
+    Profits {
+        id: String,
+        /// Today, if not set
+        #[arg(long, value_parser = date)]
+        until: Option<NaiveDate>,
+    },
+}
+
+fn date(s: &str) -> Result<NaiveDate> {
+    parse_date(s)
+}
+fn or_today(date: Option<NaiveDate>) -> String {
+    date.unwrap_or_else(|| new_york_now().date_naive())
+        .to_string()
+}
+
+// Backend is where the requests go: the rustix api, or the DataLoader, whose streams are
+// requested as ndjson and decoded like those of the api. Securities are sold through the lots,
+// which keep the sales of lots of the DataLoader.
+enum Backend {
+    Api(Client),
+    DataLoader(Arc<Trading>, Lots),
+}
+
+impl Backend {
+    fn data_loader(envs: Envs) -> Result<Backend> {
+        let trading = Arc::new(Trading::new(envs.clone())?);
+        let lots = Lots::new(trading.clone(), &envs)?;
+        Ok(Backend::DataLoader(trading, lots))
+    }
+    async fn tickers(&self, filter: TickerFilter) -> Result<Vec<Ticker>> {
+        match self {
+            Backend::Api(client) => client.tickers(&filter).await?.try_collect().await,
+            Backend::DataLoader(trading, _) => {
+                ndjson_stream(trading.tickers(filter, StreamFormat::NdJson).await?)
+                    .try_collect()
+                    .await
+            }
+        }
+    }
+    async fn security_data(&self, req: TimeSeriesReq) -> Result<Vec<TimeSeriesData>> {
+        match self {
+            Backend::Api(client) => client.security_data(&req).await?.try_collect().await,
+            Backend::DataLoader(trading, _) => {
+                ndjson_stream(trading.security_data(req, StreamFormat::NdJson).await?)
+                    .try_collect()
+                    .await
+            }
+        }
+    }
+    async fn movements(&self, req: MovementsReq) -> Result<Vec<Movement>> {
+        match self {
+            Backend::Api(client) => client.movements(&req).await,
+            Backend::DataLoader(trading, _) => trading.movements(req).await,
+        }
+    }
+    async fn correlating_tickers(
+        &self,
+        req: CorrelatingTickersReq,
+    ) -> Result<Vec<CorrelatingTickers>> {
+        match self {
+            Backend::Api(client) => client.correlating_tickers(&req).await?.try_collect().await,
+            Backend::DataLoader(trading, _) => {
+                ndjson_stream(
+                    trading
+                        .correlating_tickers(req, StreamFormat::NdJson)
+                        .await?,
+                )
+                .try_collect()
+                .await
+            }
+        }
+    }
+    async fn correlations(&self, req: CorrelReq) -> Result<Vec<CorrelatingTickers>> {
+        match self {
+            Backend::Api(client) => client.correlations(&req).await?.try_collect().await,
+            Backend::DataLoader(trading, _) => {
+                ndjson_stream(trading.correlations(req, StreamFormat::NdJson).await?)
+                    .try_collect()
+                    .await
+            }
+        }
+    }
+    async fn portfolios(&self, filter: String) -> Result<Vec<Portfolio>> {
+        match self {
+            Backend::Api(client) => client.portfolios(&filter).await,
+            Backend::DataLoader(trading, _) => trading.portfolios(filter).await,
+        }
+    }
+    async fn create_portfolio(&self, name: &str, description: &str) -> Result<Portfolio> {
+        match self {
+            Backend::Api(client) => client.create_portfolio(name, description).await,
+            Backend::DataLoader(trading, _) => trading.create_portfolio(name, description).await,
+        }
+    }
+    async fn buy_security(&self, security: PortfolioSecurity) -> Result<()> {
+        match self {
+            Backend::Api(client) => client.buy_security(&security).await,
+            Backend::DataLoader(trading, _) => trading.buy_security(security).await,
+        }
+    }
+    async fn sell_security(&self, security: PortfolioSecurity) -> Result<()> {
+        match self {
+            Backend::Api(client) => client.sell_security(&security).await,
+            Backend::DataLoader(_, lots) => lots.sell_security(security).await,
+        }
+    }
+    // portfolio_valuation values the portfolio's securities like the DataLoader valuation does:
+    async fn portfolio_valuation(&self, id: String, until: String) -> Result<Vec<SecurityProfit>> {
+        match self {
+            Backend::Api(client) => {
+                let securities = client.portfolio_securities(&id).await?;
+                let req = SecurityProfitReq {
+                    util: until,
+                    parition: Period::Day.into(),
+                    securities: securities.into_iter().map(|s| s.into()).collect(),
+                };
+                client.portfolio_profits(&req).await
+            }
+            Backend::DataLoader(trading, _) => trading.portfolio_valuation(id, until).await,
+        }
+    }
+}
+
+async fn run(cli: Cli, backend: &Backend, out: &mut impl Write) -> Result<()> {
+    match cli.command {
+        Command::Tickers {
+            security_type,
+            filter,
+            limit,
+        } => {
+            let filter = TickerFilter {
+                ttype: security_type,
+                filter,
+                limit,
+                traded_within_past_n_days: None,
+            };
+            filter.validate()?;
+            output::print(&backend.tickers(filter).await?, cli.output, out)
+        }
+        Command::Series {
+            ticker,
+            security_type,
+            from,
+            until,
+            resolution,
+            adjusted,
+        } => {
+            let req = TimeSeriesReq {
+                ticker: BasicTicker {
+                    ticker,
+                    security_type,
+                },
+                from: from.to_string(),
+                until: or_today(until),
+                adjusted: Some(adjusted),
+                resolution,
+                extended_hours: None,
+            };
+            req.validate()?;
+            output::print(&backend.security_data(req).await?, cli.output, out)
+        }
+        Command::Movements {
+            security_type,
+            sort_by,
+            period,
+            until,
+            limit,
+            min_volume,
+            min_variance,
+            max_variance,
+            adjusted,
+        } => {
+            let req = MovementsReq {
+                security_type,
+                sort_by,
+                until: or_today(until),
+                period,
+                limit,
+                min_volume,
+                min_variance,
+                max_variance,
+                without_stock_splits: None,
+                adjusted: Some(adjusted),
+                trading_days: None,
+            };
+            req.validate()?;
+            output::print(&backend.movements(req).await?, cli.output, out)
+        }
+        Command::Correl {
+            tickers,
+            security_type,
+            period,
+            until,
+            limit,
+            sign,
+        } => {
+            let correls = match tickers.is_empty() {
+                true => {
+                    let req = CorrelatingTickersReq {
+                        until: or_today(until),
+                        period,
+                        limit,
+                        min_volume: None,
+                        sign,
+                    };
+                    req.validate()?;
+                    backend.correlating_tickers(req).await?
+                }
+                false => {
+                    let req = CorrelReq {
+                        tickers: tickers
+                            .into_iter()
+                            .map(|ticker| BasicTicker {
+                                ticker,
+                                security_type,
+                            })
+                            .collect(),
+                        until: until.map(|d| d.to_string()),
+                        period,
+                    };
+                    req.validate()?;
+                    backend.correlations(req).await?
+                }
+            };
+            output::print(&correls, cli.output, out)
+        }
+        Command::Portfolio(command) => portfolio(command, backend, cli.output, out).await,
+    }
+}
+
+async fn portfolio(
+    command: PortfolioCommand,
+    backend: &Backend,
+    output: Output,
+    out: &mut impl Write,
+) -> Result<()> {
+    match command {
+        PortfolioCommand::List { filter } => {
+            output::print(&backend.portfolios(filter).await?, output, out)
+        }
+        PortfolioCommand::Create { name, description } => {
+            let portfolio = Portfolio {
+                id: "".to_string(),
+                name,
+                description,
+            };
+            portfolio.validate()?;
+            let created = backend
+                .create_portfolio(&portfolio.name, &portfolio.description)
+                .await?;
+            output::print(&[created], output, out)
+        }
+        PortfolioCommand::Buy {
+            id,
+            ticker,
+            volume,
+            security_type,
+            date,
+        } => {
+            let security = PortfolioSecurity {
+                portfolio_id: id,
+                security_type: security_type.into(),
+                ticker,
+                volume,
+                purchase_date: or_today(date),
+                sell_date: "".to_string(),
+            };
+            security.validate()?;
+            backend.buy_security(security).await
+        }
+        PortfolioCommand::Sell {
+            id,
+            ticker,
+            volume,
+            security_type,
+            purchase_date,
+            date,
+        } => {
+            let security = PortfolioSecurity {
+                portfolio_id: id,
+                security_type: security_type.into(),
+                ticker,
+                volume,
+                purchase_date: purchase_date.to_string(),
+                sell_date: or_today(date),
+            };
+            security.validate()?;
+            backend.sell_security(security).await
+        }
+        PortfolioCommand::Profits { id, until } => {
+            let profits = backend.portfolio_valuation(id, or_today(until)).await?;
+            output::print(&profits, output, out)
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let backend = match cli.direct {
+        true => Backend::data_loader(Envs::parse())?,
+        false => Backend::Api(Client::with_base_url(&cli.url)?),
+    };
+    run(cli, &backend, &mut std::io::stdout().lock()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::mock::MockServer;
+
+    async fn cli(backend: &Backend, args: &[&str]) -> Result<String> {
+        let cli = Cli::try_parse_from(["rustix-cli"].iter().chain(args))?;
+        let mut out = vec![];
+        run(cli, backend, &mut out).await?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[tokio::test]
+    async fn direct() {
+        let server = MockServer::start().await.unwrap();
+        let backend = Backend::data_loader(server.envs()).unwrap();
+
+        let tickers = cli(&backend, &["tickers", "--type", "etf", "-o", "csv"])
+            .await
+            .unwrap();
+        assert_eq!(tickers.lines().count(), 3, "{}", tickers);
+        assert!(tickers.starts_with("ticker,name,security_type,custom_fields\n"));
+
+        let args = [
+            "series",
+            "AAPL",
+            "--from",
+            "2024-01-02",
+            "--until",
+            "2024-01-02",
+            "-o",
+            "json",
+        ];
+        let series =
+            serde_json::from_str::<Vec<TimeSeriesData>>(&cli(&backend, &args).await.unwrap());
+        assert_eq!(series.unwrap().len(), 13);
+
+        let movements = cli(&backend, &["movements", "--until", "2024-01-31"])
+            .await
+            .unwrap();
+        assert!(movements.starts_with("ticker"), "{}", movements);
+        assert_eq!(movements.lines().count(), 2 + 3);
+
+        let correl = [
+            "correl", "--ticker", "AAPL", "--ticker", "MSFT", "-o", "csv",
+        ];
+        let correl = cli(&backend, &correl).await.unwrap();
+        assert!(correl.starts_with("ticker0,"), "{}", correl);
+
+        let created = cli(&backend, &["portfolio", "create", "growth", "-o", "json"])
+            .await
+            .unwrap();
+        assert!(created.contains("\"growth\""), "{}", created);
+        let buy = ["portfolio", "buy", "3", "NVDA", "2", "--date", "2024-01-03"];
+        assert_eq!(cli(&backend, &buy).await.unwrap(), "");
+        let profits = [
+            "portfolio",
+            "profits",
+            "1",
+            "--until",
+            "2024-01-31",
+            "-o",
+            "csv",
+        ];
+        let profits = cli(&backend, &profits).await.unwrap();
+        assert!(profits.contains("\nAAPL,"), "{}", profits);
+        assert!(profits.contains("\nMSFT,"), "{}", profits);
+
+        // requests are validated before they are sent:
+        let err = cli(&backend, &["portfolio", "buy", "1", "AAPL", "0"])
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("volume: must be a positive number"));
+        server.stop().await;
+    }
+
+    #[test]
+    fn arguments() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["rustix-cli"].iter().chain(args));
+        let err = parse(&["series", "AAPL", "--from", "31.01.2024"])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Invalid date format"), "{}", err);
+        assert!(parse(&["movements", "--period", "decade"]).is_err());
+        assert!(parse(&["tickers", "-o", "xml"]).is_err());
+
+        let cli = parse(&["--direct", "movements", "--sort-by", "losers", "-o", "csv"]).unwrap();
+        assert!(cli.direct);
+        assert_eq!(cli.output, Output::Csv);
+        let Command::Movements { sort_by, .. } = cli.command else {
+            panic!("expected movements");
+        };
+        assert_eq!(sort_by, SortBy::Loser);
+    }
+}
@@ -0,0 +1,203 @@
+// output prints results as aligned table, json or csv. Table and csv use the csv layout of the
+// result types, so both have the same columns as the csv streams of the api.
+use anyhow::Result;
+use clap::ValueEnum;
+use rustix::stream::{csv_line, CsvRecord};
+use serde::Serialize;
+use std::io::Write;
+
+// tables round numbers to this many decimals, json and csv keep the full precision:
+const TABLE_DECIMALS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Output {
+    Table,
+    Json,
+    Csv,
+}
+
+pub fn print<T: Serialize + CsvRecord>(
+    entries: &[T],
+    output: Output,
+    out: &mut impl Write,
+) -> Result<()> {
+    match output {
+        Output::Json => {
+            serde_json::to_writer_pretty(&mut *out, entries)?;
+            writeln!(out)?;
+        }
+        Output::Csv => {
+            let Some(header) = header(entries) else {
+                return Ok(());
+            };
+            write!(out, "{}", csv_line(&header))?;
+            for entry in entries {
+                write!(out, "{}", csv_line(&entry.csv_row(&header)))?;
+            }
+        }
+        Output::Table => {
+            let Some(header) = header(entries) else {
+                return Ok(());
+            };
+            let rows = entries
+                .iter()
+                .map(|e| e.csv_row(&header).iter().map(|c| cell(c)).collect())
+                .collect::<Vec<Vec<String>>>();
+            write!(out, "{}", table(&header, &rows))?;
+        }
+    }
+    Ok(())
+}
+
+// header is the columns of all entries, in the order they first appear. Unlike the streams of
+// the api, the cli has all entries at hand, so no column of a later entry is lost:
+fn header<T: CsvRecord>(entries: &[T]) -> Option<Vec<String>> {
+    let mut header = entries.first()?.csv_header();
+    for entry in entries.iter().skip(1) {
+        for column in entry.csv_header() {
+            if !header.contains(&column) {
+                header.push(column);
+            }
+        }
+    }
+    Some(header)
+}
+
+// cell rounds long decimals, other values are printed as they are:
+fn cell(value: &str) -> String {
+    let decimals = value.split_once('.').map(|(_, d)| d.len()).unwrap_or(0);
+    match value.parse::<f64>() {
+        Ok(n) if decimals > TABLE_DECIMALS => format!("{:.*}", TABLE_DECIMALS, n),
+        _ => value.to_string(),
+    }
+}
+
+// table pads the columns to their widest value, numeric columns are aligned to the right:
+fn table(header: &[String], rows: &[Vec<String>]) -> String {
+    let width = |i: usize| {
+        rows.iter()
+            .filter_map(|r| r.get(i))
+            .chain([&header[i]])
+            .map(|c| c.chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let numeric = |i: usize| {
+        rows.iter()
+            .filter_map(|r| r.get(i))
+            .filter(|c| !c.is_empty())
+            .all(|c| c.parse::<f64>().is_ok())
+    };
+    let columns = (0..header.len())
+        .map(|i| (width(i), numeric(i)))
+        .collect::<Vec<_>>();
+    let line = |cells: &[String]| {
+        let cells = columns
+            .iter()
+            .enumerate()
+            .map(|(i, (width, numeric))| {
+                let c = cells.get(i).map(|c| c.as_str()).unwrap_or("");
+                match numeric {
+                    true => format!("{:>width$}", c, width = width),
+                    false => format!("{:<width$}", c, width = width),
+                }
+            })
+            .collect::<Vec<_>>();
+        format!("{}\n", cells.join("  ").trim_end())
+    };
+    let separator = columns
+        .iter()
+        .map(|(width, _)| "-".repeat(*width))
+        .collect::<Vec<_>>();
+    let mut table = line(header);
+    table.push_str(&line(&separator));
+    for row in rows {
+        table.push_str(&line(row));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::trading::{Portfolio, TimeSeriesData};
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Split {
+        ticker: String,
+        ratio: f64,
+    }
+    impl CsvRecord for Split {
+        fn csv_header(&self) -> Vec<String> {
+            vec!["ticker".to_string(), "ratio".to_string()]
+        }
+        fn csv_row(&self, _: &[String]) -> Vec<String> {
+            vec![self.ticker.to_string(), self.ratio.to_string()]
+        }
+    }
+
+    fn printed<T: Serialize + CsvRecord>(entries: &[T], output: Output) -> String {
+        let mut out = vec![];
+        print(entries, output, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        let portfolios = vec![
+            Portfolio {
+                id: "1".to_string(),
+                name: "tech pack".to_string(),
+                description: "large cap, tech".to_string(),
+            },
+            Portfolio {
+                id: "12".to_string(),
+                name: "index".to_string(),
+                description: "".to_string(),
+            },
+        ];
+        assert_eq!(
+            printed(&portfolios, Output::Table),
+            "id  name       description\n\
+             --  ---------  ---------------\n \
+             1  tech pack  large cap, tech\n\
+             12  index\n"
+        );
+        assert_eq!(
+            printed(&portfolios, Output::Csv),
+            "id,name,description\n1,tech pack,\"large cap, tech\"\n12,index,\n"
+        );
+        let json = serde_json::from_str::<serde_json::Value>(&printed(&portfolios, Output::Json));
+        assert_eq!(json.unwrap()[1]["name"], "index");
+
+        assert_eq!(printed::<Portfolio>(&[], Output::Table), "");
+        assert_eq!(printed::<Portfolio>(&[], Output::Json), "[]\n");
+    }
+
+    #[test]
+    fn rounded_numbers() {
+        let splits = [("NVDA", 4.0), ("AAPL", 4.0 / 3.0)].map(|(ticker, ratio)| Split {
+            ticker: ticker.to_string(),
+            ratio,
+        });
+        assert_eq!(
+            printed(&splits, Output::Table),
+            "ticker   ratio\n------  ------\nNVDA         4\nAAPL    1.3333\n"
+        );
+        assert!(printed(&splits, Output::Csv).contains("AAPL,1.3333333333333333\n"));
+    }
+
+    #[test]
+    fn columns_of_all_entries() {
+        let series =
+            [("2024-01-02", "price"), ("2024-01-03", "close")].map(|(date, key)| TimeSeriesData {
+                date: date.to_string(),
+                values: HashMap::from([(key.to_string(), 1.5)]),
+            });
+        assert_eq!(
+            printed(&series, Output::Csv),
+            "date,price,close\n2024-01-02,1.5,\n2024-01-03,,1.5\n"
+        );
+    }
+}
@@ -0,0 +1,636 @@
+// analytics measures the performance of a portfolio: its daily equity curve is reconstructed from
+// the holdings and daily closes, and reported with time- and money-weighted returns, the maximum
+// drawdown, volatility and risk adjusted ratios. It does no io, Trading fetches the data.
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use utoipa::ToSchema;
+
+// volatility and risk adjusted ratios are annualized by trading days, the money-weighted return
+// by calendar days:
+pub const TRADING_DAYS: f64 = 252.0;
+const CALENDAR_DAYS: f64 = 365.0;
+
+// Holding is a position of a portfolio, bought and sold at the close of the given dates. The
+// volume is the number of shares at the purchase, before any later stock splits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Holding {
+    pub ticker: String,
+    pub volume: f64,
+    pub purchase_date: NaiveDate,
+    pub sell_date: Option<NaiveDate>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Split {
+    pub ticker: String,
+    pub date: NaiveDate,
+    pub ratio: f64,
+}
+
+// Closes are the daily (unadjusted) close prices of a ticker:
+pub type Closes = BTreeMap<NaiveDate, f64>;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EquityPoint {
+    pub date: String,
+    // market value of the holdings at the close:
+    pub value: f64,
+    // value bought (positive) or sold (negative) at the close. The first point's value is
+    // its flow, as if the holdings had been bought then:
+    pub flow: f64,
+    // growth of 1 invested at the first point, i.e. the compounded time-weighted return:
+    pub index: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Drawdown {
+    // the largest decline of the index from a previous peak, e.g. -0.12 for 12%:
+    pub depth: f64,
+    pub peak: Option<String>,
+    pub trough: Option<String>,
+    // the first date the index regained the peak, none if it has not yet:
+    pub recovery: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Performance {
+    pub time_weighted_return: f64,
+    // the annualized internal rate of return of the flows (xirr), none if there is none:
+    pub money_weighted_return: Option<f64>,
+    pub max_drawdown: Drawdown,
+    // annualized standard deviation of the daily returns:
+    pub volatility: f64,
+    // annualized ratios of the excess returns, none without any variation of the returns:
+    pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    pub equity_curve: Vec<EquityPoint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<Benchmark>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BenchmarkPoint {
+    pub date: String,
+    // growth of 1 invested in the benchmark at the first point of the equity curve:
+    pub index: f64,
+}
+
+// Benchmark compares the daily returns of the portfolio with those of a benchmark ticker. Ratios
+// are none, if the returns do not vary enough to compute them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Benchmark {
+    pub ticker: String,
+    pub benchmark_return: f64,
+    // the time-weighted return of the portfolio minus the benchmark's return:
+    pub relative_return: f64,
+    pub beta: Option<f64>,
+    // jensen's alpha, the annualized return not explained by beta and the risk free rate:
+    pub alpha: Option<f64>,
+    // annualized standard deviation of the differences of the daily returns:
+    pub tracking_error: f64,
+    pub information_ratio: Option<f64>,
+    // mean portfolio return on days the benchmark rose (fell), relative to the benchmark's:
+    pub up_capture: Option<f64>,
+    pub down_capture: Option<f64>,
+    // the benchmark rebased to the dates of the equity curve:
+    pub curve: Vec<BenchmarkPoint>,
+}
+
+// shares returns the holding's number of shares at date, after the splits since its purchase:
+pub fn shares(holding: &Holding, splits: &[Split], date: NaiveDate) -> f64 {
+    splits
+        .iter()
+        .filter(|s| s.ticker == holding.ticker)
+        .filter(|s| holding.purchase_date < s.date && s.date <= date)
+        .fold(holding.volume, |volume, s| volume * s.ratio)
+}
+
+fn held(holding: &Holding, date: NaiveDate) -> bool {
+    holding.purchase_date <= date && holding.sell_date.is_none_or(|s| date < s)
+}
+
+// close_at returns the latest close at or before date:
+fn close_at(closes: &HashMap<String, Closes>, ticker: &str, date: NaiveDate) -> Result<f64> {
+    closes
+        .get(ticker)
+        .and_then(|c| latest_close(c, date))
+        .ok_or_else(|| anyhow!("no close price of {} on or before {}", ticker, date))
+}
+pub fn latest_close(closes: &Closes, date: NaiveDate) -> Option<f64> {
+    closes.range(..=date).next_back().map(|(_, close)| *close)
+}
+
+// equity_curve values the holdings at each date, on which any of the tickers has a close between
+// from and until. Buys and sells in between are the flows, which the index is corrected for.
+pub fn equity_curve(
+    holdings: &[Holding],
+    closes: &HashMap<String, Closes>,
+    splits: &[Split],
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Result<Vec<EquityPoint>> {
+    let dates = closes
+        .values()
+        .flat_map(|c| c.range(from..=until).map(|(date, _)| *date))
+        .collect::<BTreeSet<_>>();
+    let mut curve = Vec::<EquityPoint>::with_capacity(dates.len());
+    let mut prev: Option<NaiveDate> = None;
+    for date in dates {
+        let mut value = 0.0;
+        let mut flow = 0.0;
+        for h in holdings {
+            let (now, before) = (held(h, date), prev.is_some_and(|p| held(h, p)));
+            if !now && !before {
+                continue;
+            }
+            let position = shares(h, splits, date) * close_at(closes, &h.ticker, date)?;
+            if now {
+                value += position;
+            }
+            match (now, before) {
+                (true, false) => flow += position,
+                (false, true) => flow -= position,
+                _ => {}
+            }
+        }
+        let index = match curve.last() {
+            Some(last) => last.index * (1.0 + daily_return(last.value, value, flow)),
+            None => 1.0,
+        };
+        curve.push(EquityPoint {
+            date: date.to_string(),
+            value,
+            flow: if prev.is_none() { value } else { flow },
+            index,
+        });
+        prev = Some(date);
+    }
+    Ok(curve)
+}
+
+// daily_return is the return of the holdings, that were held at the previous close:
+fn daily_return(prev_value: f64, value: f64, flow: f64) -> f64 {
+    match prev_value > 0.0 {
+        true => (value - flow) / prev_value - 1.0,
+        false => 0.0,
+    }
+}
+
+// returns are the daily returns of the curve, leaving out days without holdings:
+pub fn returns(curve: &[EquityPoint]) -> Vec<f64> {
+    curve
+        .windows(2)
+        .filter(|w| w[0].value > 0.0)
+        .map(|w| daily_return(w[0].value, w[1].value, w[1].flow))
+        .collect()
+}
+
+pub fn time_weighted_return(curve: &[EquityPoint]) -> f64 {
+    curve.last().map(|p| p.index - 1.0).unwrap_or(0.0)
+}
+
+// money_weighted_return is the xirr of the investor's flows: buys are paid in, sells paid out,
+// and the holdings are valued at the last point.
+pub fn money_weighted_return(curve: &[EquityPoint]) -> Option<f64> {
+    let last = curve.last()?;
+    let mut flows = curve
+        .iter()
+        .map(|p| (p.date.as_str(), -p.flow))
+        .collect::<Vec<_>>();
+    flows.push((&last.date, last.value));
+    let flows = flows
+        .into_iter()
+        .map(|(date, amount)| Ok((date.parse::<NaiveDate>()?, amount)))
+        .collect::<Result<Vec<_>>>()
+        .ok()?;
+    xirr(&flows)
+}
+
+// xirr finds the annual rate, at which the flows have a net present value of 0, by bisection.
+// There is none, unless there are both positive and negative flows.
+pub fn xirr(flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let start = flows.iter().map(|(date, _)| *date).min()?;
+    let npv = |rate: f64| {
+        flows
+            .iter()
+            .map(|(date, amount)| {
+                let years = (*date - start).num_days() as f64 / CALENDAR_DAYS;
+                amount / (1.0 + rate).powf(years)
+            })
+            .sum::<f64>()
+    };
+    let (mut low, mut high) = (-0.999_999, 1.0);
+    while npv(low).signum() == npv(high).signum() {
+        if high > 1e6 {
+            return None;
+        }
+        high *= 2.0;
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if npv(mid).signum() == npv(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+        if high - low < 1e-12 {
+            break;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+pub fn max_drawdown(curve: &[EquityPoint]) -> Drawdown {
+    let mut drawdown = Drawdown {
+        depth: 0.0,
+        peak: None,
+        trough: None,
+        recovery: None,
+    };
+    let Some(first) = curve.first() else {
+        return drawdown;
+    };
+    let (mut peak, mut peak_date) = (first.index, &first.date);
+    let mut max_peak = peak;
+    let mut trough = 0;
+    for (i, point) in curve.iter().enumerate() {
+        if point.index > peak {
+            (peak, peak_date) = (point.index, &point.date);
+        }
+        let depth = point.index / peak - 1.0;
+        if depth < drawdown.depth {
+            drawdown.depth = depth;
+            drawdown.peak = Some(peak_date.to_string());
+            drawdown.trough = Some(point.date.to_string());
+            (max_peak, trough) = (peak, i);
+        }
+    }
+    if drawdown.trough.is_some() {
+        drawdown.recovery = curve[trough..]
+            .iter()
+            .find(|p| p.index >= max_peak)
+            .map(|p| p.date.to_string());
+    }
+    drawdown
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+// covariance is the sample covariance, 0 for less than two values:
+fn covariance(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < 2 {
+        return 0.0;
+    }
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - mean_a) * (b - mean_b))
+        .sum::<f64>()
+        / (a.len() - 1) as f64
+}
+
+// volatility is the annualized sample standard deviation of the daily returns:
+pub fn volatility(returns: &[f64]) -> f64 {
+    (covariance(returns, returns) * TRADING_DAYS).sqrt()
+}
+
+// sharpe_ratio is the annualized mean excess return per volatility, the risk free rate is annual:
+pub fn sharpe_ratio(returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    let volatility = volatility(returns);
+    if volatility == 0.0 {
+        return None;
+    }
+    let excess = mean(returns) - risk_free_rate / TRADING_DAYS;
+    Some(excess * TRADING_DAYS / volatility)
+}
+
+// sortino_ratio is like the sharpe ratio, but only penalizes returns below the risk free rate:
+pub fn sortino_ratio(returns: &[f64], risk_free_rate: f64) -> Option<f64> {
+    if returns.is_empty() {
+        return None;
+    }
+    let daily_rate = risk_free_rate / TRADING_DAYS;
+    let downside = returns
+        .iter()
+        .map(|r| (r - daily_rate).min(0.0).powi(2))
+        .sum::<f64>()
+        / returns.len() as f64;
+    let downside = (downside * TRADING_DAYS).sqrt();
+    if downside == 0.0 {
+        return None;
+    }
+    Some((mean(returns) - daily_rate) * TRADING_DAYS / downside)
+}
+
+pub fn performance(equity_curve: Vec<EquityPoint>, risk_free_rate: f64) -> Performance {
+    let returns = returns(&equity_curve);
+    Performance {
+        time_weighted_return: time_weighted_return(&equity_curve),
+        money_weighted_return: money_weighted_return(&equity_curve),
+        max_drawdown: max_drawdown(&equity_curve),
+        volatility: volatility(&returns),
+        sharpe_ratio: sharpe_ratio(&returns, risk_free_rate),
+        sortino_ratio: sortino_ratio(&returns, risk_free_rate),
+        equity_curve,
+        benchmark: None,
+    }
+}
+
+// benchmark compares the equity curve with the closes of a benchmark ticker. The benchmark's
+// returns are taken between the same dates as the portfolio's, closes are carried forward.
+pub fn benchmark(
+    ticker: &str,
+    curve: &[EquityPoint],
+    closes: &Closes,
+    risk_free_rate: f64,
+) -> Result<Benchmark> {
+    let mut rebased = Vec::<BenchmarkPoint>::with_capacity(curve.len());
+    let mut first_close = None;
+    for point in curve {
+        let date = point.date.parse::<NaiveDate>()?;
+        let close = latest_close(closes, date)
+            .filter(|c| *c > 0.0)
+            .ok_or_else(|| anyhow!("no close price of {} on or before {}", ticker, date))?;
+        let first = *first_close.get_or_insert(close);
+        rebased.push(BenchmarkPoint {
+            date: point.date.to_string(),
+            index: close / first,
+        });
+    }
+    let (portfolio, benchmark): (Vec<f64>, Vec<f64>) = curve
+        .windows(2)
+        .zip(rebased.windows(2))
+        .filter(|(p, _)| p[0].value > 0.0)
+        .map(|(p, b)| {
+            let portfolio = daily_return(p[0].value, p[1].value, p[1].flow);
+            (portfolio, b[1].index / b[0].index - 1.0)
+        })
+        .unzip();
+    let active = portfolio
+        .iter()
+        .zip(&benchmark)
+        .map(|(p, b)| p - b)
+        .collect::<Vec<_>>();
+
+    let benchmark_return = rebased.last().map(|b| b.index - 1.0).unwrap_or(0.0);
+    let variance = covariance(&benchmark, &benchmark);
+    let beta = (variance > 0.0).then(|| covariance(&portfolio, &benchmark) / variance);
+    let daily_rate = risk_free_rate / TRADING_DAYS;
+    let alpha = beta.map(|beta| {
+        let expected = daily_rate + beta * (mean(&benchmark) - daily_rate);
+        (mean(&portfolio) - expected) * TRADING_DAYS
+    });
+    let tracking_error = volatility(&active);
+    let information_ratio =
+        (tracking_error > 0.0).then(|| mean(&active) * TRADING_DAYS / tracking_error);
+    let capture = |up: bool| {
+        let (p, b): (Vec<f64>, Vec<f64>) = portfolio
+            .iter()
+            .zip(&benchmark)
+            .filter(|(_, b)| if up { **b > 0.0 } else { **b < 0.0 })
+            .unzip();
+        (!b.is_empty()).then(|| mean(&p) / mean(&b))
+    };
+    Ok(Benchmark {
+        ticker: ticker.to_string(),
+        benchmark_return,
+        relative_return: time_weighted_return(curve) - benchmark_return,
+        beta,
+        alpha,
+        tracking_error,
+        information_ratio,
+        up_capture: capture(true),
+        down_capture: capture(false),
+        curve: rebased,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(d: &str) -> NaiveDate {
+        d.parse().unwrap()
+    }
+    fn closes(ticker: &str, closes: &[(&str, f64)]) -> (String, Closes) {
+        let closes = closes.iter().map(|(d, c)| (date(d), *c)).collect();
+        (ticker.to_string(), closes)
+    }
+    fn point(d: &str, value: f64, flow: f64, index: f64) -> EquityPoint {
+        EquityPoint {
+            date: d.to_string(),
+            value,
+            flow,
+            index,
+        }
+    }
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn curve_with_flows_and_splits() {
+        let closes = HashMap::from([
+            closes(
+                "AAA",
+                &[
+                    ("2024-01-02", 10.0),
+                    ("2024-01-03", 11.0),
+                    ("2024-01-04", 12.0),
+                    ("2024-01-05", 12.0),
+                ],
+            ),
+            // a 2:1 split on the 4th, the feed is not adjusted:
+            closes(
+                "BBB",
+                &[
+                    ("2024-01-02", 20.0),
+                    ("2024-01-03", 22.0),
+                    ("2024-01-04", 11.0),
+                ],
+            ),
+        ]);
+        let splits = vec![Split {
+            ticker: "BBB".to_string(),
+            date: date("2024-01-04"),
+            ratio: 2.0,
+        }];
+        let holdings = vec![
+            // bought before the range, so it's the initial investment:
+            Holding {
+                ticker: "AAA".to_string(),
+                volume: 10.0,
+                purchase_date: date("2023-12-29"),
+                sell_date: Some(date("2024-01-04")),
+            },
+            Holding {
+                ticker: "BBB".to_string(),
+                volume: 5.0,
+                purchase_date: date("2024-01-03"),
+                sell_date: None,
+            },
+        ];
+        let curve = equity_curve(
+            &holdings,
+            &closes,
+            &splits,
+            date("2024-01-01"),
+            date("2024-01-31"),
+        )
+        .unwrap();
+        let expected = [
+            ("2024-01-02", 100.0, 100.0),
+            // AAA gained 10%, BBB was bought:
+            ("2024-01-03", 220.0, 110.0),
+            // AAA was sold at 120, the 10 shares of BBB kept their value:
+            ("2024-01-04", 110.0, -120.0),
+            // the last close of BBB is carried forward:
+            ("2024-01-05", 110.0, 0.0),
+        ];
+        assert_eq!(curve.len(), expected.len());
+        for (point, (d, value, flow)) in curve.iter().zip(expected) {
+            assert_eq!(point.date, d);
+            assert_close(point.value, value);
+            assert_close(point.flow, flow);
+        }
+        // +10% on the 3rd, then (110 + 120) / 220 on the 4th:
+        assert_close(curve[3].index, 1.1 * 230.0 / 220.0);
+        assert_close(time_weighted_return(&curve), 1.1 * 230.0 / 220.0 - 1.0);
+        assert_eq!(returns(&curve).len(), 3);
+
+        let missing = vec![Holding {
+            ticker: "CCC".to_string(),
+            ..holdings[1].clone()
+        }];
+        let err = equity_curve(
+            &missing,
+            &closes,
+            &splits,
+            date("2024-01-01"),
+            date("2024-01-31"),
+        );
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .contains("no close price of CCC"));
+    }
+
+    #[test]
+    fn money_weighted() {
+        let flows = [(date("2023-01-01"), -1000.0), (date("2024-01-01"), 1100.0)];
+        assert_close(xirr(&flows).unwrap(), 0.1);
+        assert!(xirr(&flows[..1]).is_none());
+
+        // money added before a loss weighs more than the time-weighted return:
+        let curve = vec![
+            point("2023-01-01", 100.0, 100.0, 1.0),
+            point("2023-07-02", 1100.0, 1000.0, 1.0),
+            point("2024-01-01", 550.0, 0.0, 0.5),
+        ];
+        assert_close(time_weighted_return(&curve), -0.5);
+        let mwr = money_weighted_return(&curve).unwrap();
+        assert!(mwr < -0.5, "{}", mwr);
+        let npv = -100.0 - 1000.0 / (1.0 + mwr).powf(182.0 / 365.0) + 550.0 / (1.0 + mwr);
+        assert!(npv.abs() < 1e-6, "{}", npv);
+    }
+
+    #[test]
+    fn drawdown() {
+        let curve = [1.0, 1.2, 0.9, 1.1, 1.3, 1.25]
+            .iter()
+            .enumerate()
+            .map(|(i, index)| point(&format!("2024-01-0{}", i + 1), 1.0, 0.0, *index))
+            .collect::<Vec<_>>();
+        let drawdown = max_drawdown(&curve);
+        assert_close(drawdown.depth, 0.9 / 1.2 - 1.0);
+        assert_eq!(drawdown.peak.as_deref(), Some("2024-01-02"));
+        assert_eq!(drawdown.trough.as_deref(), Some("2024-01-03"));
+        assert_eq!(drawdown.recovery.as_deref(), Some("2024-01-05"));
+
+        let unrecovered = max_drawdown(&curve[..4]);
+        assert_eq!(unrecovered.recovery, None);
+        assert_eq!(max_drawdown(&curve[..2]).depth, 0.0);
+        assert_eq!(max_drawdown(&curve[..2]).peak, None);
+    }
+
+    #[test]
+    fn benchmark_comparison() {
+        let benchmark_closes = [100.0, 102.0, 99.96, 101.9592, 100.939608];
+        let days = [
+            "2024-01-02",
+            "2024-01-03",
+            "2024-01-04",
+            "2024-01-05",
+            "2024-01-08",
+        ];
+        let mut closes = days
+            .iter()
+            .zip(benchmark_closes)
+            .map(|(d, c)| (date(d), c))
+            .collect::<Closes>();
+        // the portfolio moves twice as much as the benchmark (+2%, -2%, +2%, -1%), and got a
+        // deposit on the 4th, which must not count as return:
+        let values = [1000.0, 1040.0, 1998.4, 2078.336, 2036.76928];
+        let flows = [1000.0, 0.0, 1000.0, 0.0, 0.0];
+        let mut curve = vec![point(days[0], values[0], flows[0], 1.0)];
+        for i in 1..days.len() {
+            let index = curve[i - 1].index * (values[i] - flows[i]) / values[i - 1];
+            curve.push(point(days[i], values[i], flows[i], index));
+        }
+        let b = benchmark("SPY", &curve, &closes, 0.0).unwrap();
+        assert_close(b.beta.unwrap(), 2.0);
+        assert!(b.alpha.unwrap().abs() < 1e-9);
+        assert_close(b.up_capture.unwrap(), 2.0);
+        assert_close(b.down_capture.unwrap(), 2.0);
+        assert_close(b.benchmark_return, 0.00939608);
+        assert_close(
+            b.relative_return,
+            time_weighted_return(&curve) - b.benchmark_return,
+        );
+        // the active returns equal the benchmark's:
+        let benchmark_returns = [0.02, -0.02, 0.02, -0.01];
+        assert_close(b.tracking_error, volatility(&benchmark_returns));
+        assert_close(
+            b.information_ratio.unwrap(),
+            0.0025 * TRADING_DAYS / b.tracking_error,
+        );
+        assert_eq!(b.curve.len(), 5);
+        assert_eq!(b.curve[4].date, "2024-01-08");
+        assert_close(b.curve[1].index, 1.02);
+
+        // with a beta of 2, the portfolio would have been expected to lose the risk free rate:
+        let with_rate = benchmark("SPY", &curve, &closes, 0.0252).unwrap();
+        assert_close(with_rate.alpha.unwrap(), 0.0252);
+
+        let later = closes.split_off(&date("2024-01-03"));
+        assert!(benchmark("SPY", &curve, &later, 0.0).is_err());
+    }
+
+    #[test]
+    fn risk_ratios() {
+        let returns = [0.01, -0.02, 0.015, 0.005];
+        let mean = 0.0025;
+        let std = ((0.0075f64.powi(2) + 0.0225f64.powi(2) + 0.0125f64.powi(2) + 0.0025f64.powi(2))
+            / 3.0)
+            .sqrt();
+        assert_close(volatility(&returns), std * TRADING_DAYS.sqrt());
+        assert_close(
+            sharpe_ratio(&returns, 0.0).unwrap(),
+            mean / std * TRADING_DAYS.sqrt(),
+        );
+        let downside = (0.02f64.powi(2) / 4.0).sqrt();
+        assert_close(
+            sortino_ratio(&returns, 0.0).unwrap(),
+            mean / downside * TRADING_DAYS.sqrt(),
+        );
+        // the risk free rate lowers the ratios:
+        assert!(sharpe_ratio(&returns, 0.05).unwrap() < sharpe_ratio(&returns, 0.0).unwrap());
+
+        assert_eq!(volatility(&[0.01]), 0.0);
+        assert_eq!(sharpe_ratio(&[0.01, 0.01], 0.0), None);
+        assert_eq!(sortino_ratio(&[0.01, 0.02], 0.0), None);
+    }
+}
@@ -0,0 +1,663 @@
+// calendar knows when an exchange is open: its regular hours, weekends, holidays and early
+// closes. Holidays are computed from rules, so the calendars work for any year. Sessions are
+// defined in exchange local time, the conversion to utc accounts for daylight saving time.
+use crate::proto::dataloader::TickerType;
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::America::New_York;
+use chrono_tz::Europe::{Berlin, London};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Exchange {
+    Nyse,
+    Nasdaq,
+    Lse,
+    Xetra,
+    Fx,
+    Crypto,
+}
+
+impl Exchange {
+    pub const ALL: [Exchange; 6] = [
+        Exchange::Nyse,
+        Exchange::Nasdaq,
+        Exchange::Lse,
+        Exchange::Xetra,
+        Exchange::Fx,
+        Exchange::Crypto,
+    ];
+    // parse accepts the exchange names and their market identifier codes, ignoring case:
+    pub fn parse(s: &str) -> Option<Exchange> {
+        match s.trim().to_uppercase().as_str() {
+            "NYSE" | "XNYS" | "ARCA" | "NYSEARCA" | "AMEX" => Some(Exchange::Nyse),
+            "NASDAQ" | "XNAS" => Some(Exchange::Nasdaq),
+            "LSE" | "XLON" => Some(Exchange::Lse),
+            "XETRA" | "XETR" => Some(Exchange::Xetra),
+            "FX" | "FOREX" => Some(Exchange::Fx),
+            "CRYPTO" => Some(Exchange::Crypto),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exchange::Nyse => "NYSE",
+            Exchange::Nasdaq => "NASDAQ",
+            Exchange::Lse => "LSE",
+            Exchange::Xetra => "XETRA",
+            Exchange::Fx => "FX",
+            Exchange::Crypto => "CRYPTO",
+        }
+    }
+}
+
+// Session is a trading day with its open and close in exchange local time:
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub date: NaiveDate,
+    pub open: DateTime<Tz>,
+    pub close: DateTime<Tz>,
+    pub early_close: bool,
+    // extended hours before the open and after the close, which are the open and close for
+    // exchanges without pre- or post-market trading:
+    pub pre_market: DateTime<Tz>,
+    pub post_market: DateTime<Tz>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TradingCalendar {
+    exchange: Exchange,
+    tz: Tz,
+    // open and close are offsets from the local midnight of the session's date, so sessions may
+    // start the day before (fx) or end at midnight (crypto):
+    open: Duration,
+    close: Duration,
+    early_close: Duration,
+    pre_market: Duration,
+    post_market: Duration,
+    early_post_market: Duration,
+    weekends: bool,
+}
+
+// sessions are searched for this many days ahead/back, which covers any sequence of holidays:
+const MAX_SEARCH_DAYS: i64 = 14;
+
+impl TradingCalendar {
+    pub fn new(exchange: Exchange) -> TradingCalendar {
+        let hm = |h, m| Duration::hours(h) + Duration::minutes(m);
+        let (tz, open, close, early_close, weekends) = match exchange {
+            Exchange::Nyse | Exchange::Nasdaq => (New_York, hm(9, 30), hm(16, 0), hm(13, 0), false),
+            Exchange::Lse => (London, hm(8, 0), hm(16, 30), hm(12, 30), false),
+            Exchange::Xetra => (Berlin, hm(9, 0), hm(17, 30), hm(17, 30), false),
+            // fx trades from sunday 17:00 until friday 17:00 new york time, each session is
+            // named after the day it closes:
+            Exchange::Fx => (New_York, hm(-7, 0), hm(17, 0), hm(17, 0), false),
+            Exchange::Crypto => (Tz::UTC, hm(0, 0), hm(24, 0), hm(24, 0), true),
+        };
+        // only the us exchanges trade in extended hours, which end at 17:00 on early close days:
+        let (pre_market, post_market, early_post_market) = match exchange {
+            Exchange::Nyse | Exchange::Nasdaq => (hm(4, 0), hm(20, 0), hm(17, 0)),
+            _ => (open, close, early_close),
+        };
+        TradingCalendar {
+            exchange,
+            tz,
+            open,
+            close,
+            early_close,
+            pre_market,
+            post_market,
+            early_post_market,
+            weekends,
+        }
+    }
+    pub fn nyse() -> TradingCalendar {
+        TradingCalendar::new(Exchange::Nyse)
+    }
+    // for_security_type returns the calendar of securities, that don't trade on an exchange:
+    pub fn for_security_type(security_type: i32) -> Option<TradingCalendar> {
+        match security_type {
+            t if t == TickerType::Currency as i32 => Some(TradingCalendar::new(Exchange::Fx)),
+            t if t == TickerType::Crypto as i32 => Some(TradingCalendar::new(Exchange::Crypto)),
+            _ => None,
+        }
+    }
+    // for_ticker selects the calendar by the security type, or else by the ticker's "exchange"
+    // field. Tickers of unknown exchanges get the nyse calendar.
+    pub fn for_ticker(
+        security_type: i32,
+        custom_fields: &HashMap<String, String>,
+    ) -> TradingCalendar {
+        TradingCalendar::for_security_type(security_type).unwrap_or_else(|| {
+            let exchange = custom_fields
+                .get("exchange")
+                .and_then(|e| Exchange::parse(e))
+                .unwrap_or(Exchange::Nyse);
+            TradingCalendar::new(exchange)
+        })
+    }
+    pub fn exchange(&self) -> Exchange {
+        self.exchange
+    }
+    pub fn timezone(&self) -> Tz {
+        self.tz
+    }
+    // open_time is the local time of day, at which sessions open:
+    pub fn open_time(&self) -> NaiveTime {
+        NaiveTime::MIN + self.open
+    }
+    // session_minutes is the length of a regular session:
+    pub fn session_minutes(&self) -> i64 {
+        (self.close - self.open).num_minutes()
+    }
+
+    // holidays returns the full day closures of the year, at the dates they are observed:
+    pub fn holidays(&self, year: i32) -> Vec<NaiveDate> {
+        let mut holidays = match self.exchange {
+            Exchange::Nyse | Exchange::Nasdaq => nyse_holidays(year),
+            Exchange::Lse => lse_holidays(year),
+            Exchange::Xetra => xetra_holidays(year),
+            Exchange::Fx | Exchange::Crypto => vec![],
+        };
+        holidays.sort();
+        holidays
+    }
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.holidays(date.year()).contains(&date)
+    }
+    // is_early_close tells whether the exchange closes early: nyse on the day after thanksgiving,
+    // and on the days before independence day and christmas, lse on christmas and new year's eve
+    // (unless those are closed anyway).
+    pub fn is_early_close(&self, date: NaiveDate) -> bool {
+        let year = date.year();
+        let candidates = match self.exchange {
+            Exchange::Nyse | Exchange::Nasdaq => vec![
+                nth_weekday(year, 11, Weekday::Thu, 4) + Duration::days(1),
+                self::date(year, 7, 3),
+                self::date(year, 12, 24),
+            ],
+            Exchange::Lse => vec![self::date(year, 12, 24), self::date(year, 12, 31)],
+            _ => vec![],
+        };
+        candidates.contains(&date) && self.is_trading_day(date)
+    }
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        (self.weekends || !weekend) && !self.is_holiday(date)
+    }
+
+    pub fn session(&self, date: NaiveDate) -> Option<Session> {
+        if !self.is_trading_day(date) {
+            return None;
+        }
+        let early_close = self.is_early_close(date);
+        let (close, post_market) = match early_close {
+            true => (self.early_close, self.early_post_market),
+            false => (self.close, self.post_market),
+        };
+        Some(Session {
+            date,
+            open: self.local(date, self.open),
+            close: self.local(date, close),
+            early_close,
+            pre_market: self.local(date, self.pre_market),
+            post_market: self.local(date, post_market),
+        })
+    }
+    // session_at returns the session, that the exchange local time is part of:
+    pub fn session_at(&self, local: NaiveDateTime) -> Option<Session> {
+        let date = local.date();
+        [date, date + Duration::days(1)]
+            .into_iter()
+            .filter_map(|d| self.session(d))
+            .find(|s| s.open.naive_local() <= local && local < s.close.naive_local())
+    }
+    // session_date returns the date of the session, whose (extended) hours contain the local
+    // time, e.g. the monday for fx quotes of sunday evening. Other times keep their date.
+    pub fn session_date(&self, local: NaiveDateTime) -> NaiveDate {
+        let date = local.date();
+        [date, date + Duration::days(1)]
+            .into_iter()
+            .filter_map(|d| self.session(d))
+            .find(|s| s.pre_market.naive_local() <= local && local < s.post_market.naive_local())
+            .map(|s| s.date)
+            .unwrap_or(date)
+    }
+    // sessions_between returns the sessions from and until the given dates (both inclusive):
+    pub fn sessions_between(&self, from: NaiveDate, until: NaiveDate) -> Vec<Session> {
+        from.iter_days()
+            .take_while(|d| *d <= until)
+            .filter_map(|d| self.session(d))
+            .collect()
+    }
+    // sessions_back returns the date of the n-th session before date:
+    pub fn sessions_back(&self, date: NaiveDate, n: usize) -> NaiveDate {
+        if n == 0 {
+            return date;
+        }
+        date.iter_days()
+            .rev()
+            .skip(1)
+            .filter(|d| self.is_trading_day(*d))
+            .nth(n - 1)
+            .unwrap_or(date)
+    }
+    pub fn trading_day_on_or_before(&self, date: NaiveDate) -> NaiveDate {
+        date.iter_days()
+            .rev()
+            .take(MAX_SEARCH_DAYS as usize)
+            .find(|d| self.is_trading_day(*d))
+            .unwrap_or(date)
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        self.session_at(at.with_timezone(&self.tz).naive_local())
+            .is_some()
+    }
+    // next_open returns the first session open after at:
+    pub fn next_open(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        self.sessions_from(at)
+            .map(|s| s.open.with_timezone(&Utc))
+            .find(|open| *open > at)
+            .unwrap_or(at)
+    }
+    // next_close returns the first session close after at:
+    pub fn next_close(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        self.sessions_from(at)
+            .map(|s| s.close.with_timezone(&Utc))
+            .find(|close| *close > at)
+            .unwrap_or(at)
+    }
+    // previous_close returns the last session close at or before at:
+    pub fn previous_close(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        self.sessions_until(at)
+            .map(|s| s.close.with_timezone(&Utc))
+            .find(|close| *close <= at)
+            .unwrap_or(at)
+    }
+    // start_of_day returns the local midnight of date:
+    pub fn start_of_day(&self, date: NaiveDate) -> DateTime<Tz> {
+        self.local(date, Duration::zero())
+    }
+    // last_trading_day returns the date of the latest session, that has already started:
+    pub fn last_trading_day(&self, at: DateTime<Utc>) -> NaiveDate {
+        self.sessions_until(at)
+            .find(|s| s.open <= at)
+            .map(|s| s.date)
+            .unwrap_or(self.local_date(at))
+    }
+
+    // sessions_from iterates the sessions forward, starting with yesterday's (which may still be
+    // open at midnight):
+    fn sessions_from(&self, at: DateTime<Utc>) -> impl Iterator<Item = Session> + '_ {
+        let yesterday = self.local_date(at) - Duration::days(1);
+        (0..=MAX_SEARCH_DAYS).filter_map(move |n| self.session(yesterday + Duration::days(n)))
+    }
+    // sessions_until iterates the sessions backwards, starting with tomorrow's (which may have
+    // opened already):
+    fn sessions_until(&self, at: DateTime<Utc>) -> impl Iterator<Item = Session> + '_ {
+        let tomorrow = self.local_date(at) + Duration::days(1);
+        (0..=MAX_SEARCH_DAYS).filter_map(move |n| self.session(tomorrow - Duration::days(n)))
+    }
+    fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&self.tz).date_naive()
+    }
+    fn local(&self, date: NaiveDate, offset: Duration) -> DateTime<Tz> {
+        let dt = date.and_time(NaiveTime::MIN) + offset;
+        // a time skipped by the switch to daylight saving time is moved an hour ahead:
+        self.tz
+            .from_local_datetime(&dt)
+            .earliest()
+            .or_else(|| {
+                self.tz
+                    .from_local_datetime(&(dt + Duration::hours(1)))
+                    .earliest()
+            })
+            .unwrap_or_else(|| self.tz.from_utc_datetime(&dt))
+    }
+}
+
+fn nyse_holidays(year: i32) -> Vec<NaiveDate> {
+    let mut holidays = vec![
+        nth_weekday(year, 1, Weekday::Mon, 3), // martin luther king jr. day
+        nth_weekday(year, 2, Weekday::Mon, 3), // washington's birthday
+        easter_sunday(year) - Duration::days(2), // good friday
+        last_weekday(year, 5, Weekday::Mon),   // memorial day
+        observed(date(year, 7, 4)),            // independence day
+        nth_weekday(year, 9, Weekday::Mon, 1), // labor day
+        nth_weekday(year, 11, Weekday::Thu, 4), // thanksgiving
+        observed(date(year, 12, 25)),          // christmas
+    ];
+    // new year's day on a saturday is not observed on the friday before:
+    let new_year = date(year, 1, 1);
+    if new_year.weekday() != Weekday::Sat {
+        holidays.push(observed(new_year));
+    }
+    if year >= 2022 {
+        holidays.push(observed(date(year, 6, 19))); // juneteenth
+    }
+    holidays
+}
+// lse_holidays are the english bank holidays. One-off bank holidays (e.g. for coronations) are
+// not covered by the rules.
+fn lse_holidays(year: i32) -> Vec<NaiveDate> {
+    let easter = easter_sunday(year);
+    // christmas and boxing day on a weekend are substituted by the following weekdays:
+    let (christmas, boxing_day) = match date(year, 12, 25).weekday() {
+        Weekday::Fri => (25, 28),
+        Weekday::Sat => (27, 28),
+        Weekday::Sun => (27, 26),
+        _ => (25, 26),
+    };
+    vec![
+        next_weekday(date(year, 1, 1)),
+        easter - Duration::days(2),
+        easter + Duration::days(1),
+        nth_weekday(year, 5, Weekday::Mon, 1),
+        last_weekday(year, 5, Weekday::Mon),
+        last_weekday(year, 8, Weekday::Mon),
+        date(year, 12, christmas),
+        date(year, 12, boxing_day),
+    ]
+}
+fn xetra_holidays(year: i32) -> Vec<NaiveDate> {
+    let easter = easter_sunday(year);
+    vec![
+        date(year, 1, 1),
+        easter - Duration::days(2),
+        easter + Duration::days(1),
+        date(year, 5, 1),
+        date(year, 12, 24),
+        date(year, 12, 25),
+        date(year, 12, 26),
+        date(year, 12, 31),
+    ]
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+// observed moves holidays on a saturday to the friday before, and on a sunday to the monday after:
+fn observed(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date - Duration::days(1),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+// next_weekday moves dates on a weekend to the monday after:
+fn next_weekday(date: NaiveDate) -> NaiveDate {
+    match date.weekday() {
+        Weekday::Sat => date + Duration::days(2),
+        Weekday::Sun => date + Duration::days(1),
+        _ => date,
+    }
+}
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+// easter_sunday uses the anonymous gregorian algorithm (meeus/jones/butcher):
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    date(year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ny(date: &str, time: &str) -> DateTime<Utc> {
+        let dt = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        New_York
+            .from_local_datetime(&dt)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn easter() {
+        let cases = [(2019, 4, 21), (2024, 3, 31), (2025, 4, 20), (2038, 4, 25)];
+        for (year, month, day) in cases {
+            assert_eq!(easter_sunday(year), date(year, month, day));
+        }
+    }
+
+    #[test]
+    fn holidays() {
+        let nyse = TradingCalendar::nyse();
+        let holidays = nyse
+            .holidays(2024)
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            holidays,
+            [
+                "2024-01-01",
+                "2024-01-15",
+                "2024-02-19",
+                "2024-03-29",
+                "2024-05-27",
+                "2024-06-19",
+                "2024-07-04",
+                "2024-09-02",
+                "2024-11-28",
+                "2024-12-25",
+            ]
+        );
+        // observed on the monday after:
+        assert!(nyse.is_holiday(date(2022, 12, 26)));
+        assert!(nyse.is_holiday(date(2022, 6, 20)));
+        // observed on the friday before:
+        assert!(nyse.is_holiday(date(2026, 7, 3)));
+        // but not for new year's day:
+        assert!(!nyse.is_holiday(date(2021, 12, 31)));
+        assert!(nyse.is_trading_day(date(2021, 12, 31)));
+    }
+
+    #[test]
+    fn good_friday() {
+        let nyse = TradingCalendar::nyse();
+        assert!(!nyse.is_trading_day(date(2024, 3, 29)));
+        assert!(!nyse.is_trading_day(date(2025, 4, 18)));
+        assert!(!nyse.is_open(ny("2025-04-18", "11:00")));
+        // the thursday before is a regular session, the next open is on monday:
+        assert!(!nyse.is_early_close(date(2025, 4, 17)));
+        assert_eq!(
+            nyse.next_open(ny("2025-04-17", "16:30")),
+            ny("2025-04-21", "09:30")
+        );
+        assert_eq!(
+            nyse.previous_close(ny("2025-04-21", "08:00")),
+            ny("2025-04-17", "16:00")
+        );
+    }
+
+    #[test]
+    fn thanksgiving() {
+        let nyse = TradingCalendar::nyse();
+        assert!(nyse.is_holiday(date(2024, 11, 28)));
+        let session = nyse.session(date(2024, 11, 29)).unwrap();
+        assert!(session.early_close);
+        assert_eq!(session.close.with_timezone(&Utc), ny("2024-11-29", "13:00"));
+        assert!(nyse.is_open(ny("2024-11-29", "12:59")));
+        assert!(!nyse.is_open(ny("2024-11-29", "13:00")));
+        assert_eq!(
+            nyse.next_close(ny("2024-11-27", "17:00")),
+            ny("2024-11-29", "13:00")
+        );
+        // christmas eve and the day before independence day close early as well:
+        assert!(nyse.is_early_close(date(2024, 12, 24)));
+        assert!(nyse.is_early_close(date(2024, 7, 3)));
+        // unless they are closed anyway:
+        assert!(!nyse.is_early_close(date(2026, 7, 3)));
+        assert!(!nyse.is_early_close(date(2022, 12, 24)));
+    }
+
+    #[test]
+    fn daylight_saving_time() {
+        let nyse = TradingCalendar::nyse();
+        // est (utc-5) before, edt (utc-4) after 2024-03-10:
+        let friday = nyse.session(date(2024, 3, 8)).unwrap();
+        assert_eq!(friday.open.with_timezone(&Utc), utc("2024-03-08T14:30:00Z"));
+        assert_eq!(
+            nyse.next_open(utc("2024-03-08T21:00:00Z")),
+            utc("2024-03-11T13:30:00Z")
+        );
+        assert!(nyse.is_open(utc("2024-03-11T13:30:00Z")));
+        assert!(!nyse.is_open(utc("2024-03-08T14:00:00Z")));
+        // and back on 2024-11-03:
+        assert_eq!(
+            nyse.next_close(utc("2024-11-01T18:00:00Z")),
+            utc("2024-11-01T20:00:00Z")
+        );
+        assert_eq!(
+            nyse.next_close(utc("2024-11-01T21:00:00Z")),
+            utc("2024-11-04T21:00:00Z")
+        );
+    }
+
+    #[test]
+    fn sessions() {
+        let nyse = TradingCalendar::nyse();
+        let sessions = nyse.sessions_between(date(2024, 1, 1), date(2024, 1, 31));
+        assert_eq!(sessions.len(), 21);
+        assert_eq!(sessions[0].date, date(2024, 1, 2));
+        assert_eq!(sessions[20].date, date(2024, 1, 31));
+        assert!(nyse
+            .sessions_between(date(2024, 12, 25), date(2024, 12, 25))
+            .is_empty());
+
+        // saturday, the last trading day was friday:
+        assert_eq!(
+            nyse.last_trading_day(ny("2024-01-06", "12:00")),
+            date(2024, 1, 5)
+        );
+        assert_eq!(
+            nyse.last_trading_day(ny("2024-01-08", "09:00")),
+            date(2024, 1, 5)
+        );
+        assert_eq!(
+            nyse.last_trading_day(ny("2024-01-08", "09:30")),
+            date(2024, 1, 8)
+        );
+    }
+
+    #[test]
+    fn lse_and_xetra() {
+        let lse = TradingCalendar::new(Exchange::Lse);
+        let holidays = |cal: &TradingCalendar, year| {
+            cal.holidays(year)
+                .iter()
+                .map(|d| d.format("%m-%d").to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            holidays(&lse, 2024),
+            ["01-01", "03-29", "04-01", "05-06", "05-27", "08-26", "12-25", "12-26"]
+        );
+        // christmas on a saturday (2021) and on a sunday (2022):
+        assert_eq!(holidays(&lse, 2021)[6..], ["12-27", "12-28"]);
+        assert_eq!(holidays(&lse, 2022)[6..], ["12-26", "12-27"]);
+        // 8:00 london time is 7:00 utc in summer:
+        let session = lse.session(date(2024, 7, 1)).unwrap();
+        assert_eq!(
+            session.open.with_timezone(&Utc),
+            utc("2024-07-01T07:00:00Z")
+        );
+        assert!(lse.is_early_close(date(2024, 12, 31)));
+
+        let xetra = TradingCalendar::new(Exchange::Xetra);
+        assert_eq!(
+            holidays(&xetra, 2024),
+            ["01-01", "03-29", "04-01", "05-01", "12-24", "12-25", "12-26", "12-31"]
+        );
+        assert!(xetra.is_open(utc("2024-01-02T16:29:00Z")));
+        assert!(!xetra.is_open(utc("2024-01-02T16:30:00Z")));
+    }
+
+    #[test]
+    fn fx_and_crypto() {
+        let fx = TradingCalendar::new(Exchange::Fx);
+        assert!(!fx.is_open(ny("2024-01-06", "12:00")));
+        assert!(!fx.is_open(ny("2024-01-07", "16:59")));
+        assert!(fx.is_open(ny("2024-01-07", "17:00")));
+        assert!(fx.is_open(ny("2024-01-10", "03:00")));
+        assert!(fx.is_open(ny("2024-01-12", "16:59")));
+        assert!(!fx.is_open(ny("2024-01-12", "17:00")));
+        assert_eq!(
+            fx.next_open(ny("2024-01-06", "12:00")),
+            ny("2024-01-07", "17:00")
+        );
+        assert_eq!(
+            fx.previous_close(ny("2024-01-07", "12:00")),
+            ny("2024-01-05", "17:00")
+        );
+        // the session opening on sunday evening is monday's:
+        assert_eq!(
+            fx.last_trading_day(ny("2024-01-07", "18:00")),
+            date(2024, 1, 8)
+        );
+        assert_eq!(fx.open_time(), NaiveTime::from_hms_opt(17, 0, 0).unwrap());
+
+        let crypto = TradingCalendar::new(Exchange::Crypto);
+        assert!(crypto.is_open(utc("2024-12-25T12:00:00Z")));
+        assert!(crypto.is_open(utc("2024-01-06T00:00:00Z")));
+        assert_eq!(crypto.session_minutes(), 24 * 60);
+        assert_eq!(
+            crypto.next_close(utc("2024-01-06T12:00:00Z")),
+            utc("2024-01-07T00:00:00Z")
+        );
+        assert_eq!(crypto.sessions_back(date(2024, 1, 8), 7), date(2024, 1, 1));
+    }
+
+    #[test]
+    fn selection() {
+        let fields =
+            |exchange: &str| HashMap::from([("exchange".to_string(), exchange.to_string())]);
+        let exchange = |security_type, exchange| {
+            TradingCalendar::for_ticker(security_type, &fields(exchange)).exchange()
+        };
+        assert_eq!(exchange(0, "NASDAQ"), Exchange::Nasdaq);
+        assert_eq!(exchange(1, "xlon"), Exchange::Lse);
+        assert_eq!(exchange(0, "XETRA"), Exchange::Xetra);
+        assert_eq!(exchange(0, "unknown"), Exchange::Nyse);
+        assert_eq!(exchange(3, "NASDAQ"), Exchange::Fx);
+        assert_eq!(exchange(4, "NASDAQ"), Exchange::Crypto);
+        assert_eq!(
+            TradingCalendar::for_ticker(0, &HashMap::new()).exchange(),
+            Exchange::Nyse
+        );
+        // 20 sessions before march 1st 2024, skipping presidents' day:
+        let nyse = TradingCalendar::nyse();
+        assert_eq!(nyse.sessions_back(date(2024, 3, 1), 20), date(2024, 2, 1));
+        assert_eq!(nyse.sessions_back(date(2024, 3, 1), 0), date(2024, 3, 1));
+    }
+}
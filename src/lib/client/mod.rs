@@ -0,0 +1,523 @@
+// client is a typed http client of the rustix api for other rust services. It uses the request
+// and response types of the api, and requests streaming endpoints as ndjson, which is decoded
+// entry by entry. Read requests are retried on connection errors and unavailable upstreams,
+// requests that change state (portfolios, jobs) only if they could not be sent at all.
+use crate::analytics::Performance;
+use crate::error::ErrorBody;
+use crate::fx::{FxValuation, FxValuationReq, PortfolioCurrencies};
+use crate::jobs::{JobDef, JobInfo, JobRun};
+use crate::ledger::{
+    Ledger, LedgerProfits, LedgerProfitsReq, MarginReq, TransactionId, TransactionReq,
+};
+use crate::lots::{LotGain, LotReport};
+use crate::market::{MarketStatus, MarketStatusReq};
+use crate::trading::{
+    BasicTicker, CorrelReq, CorrelatingTickers, CorrelatingTickersReq, DateReq, IndicatorsReq,
+    LatestDate, LotReportReq, LotSellReq, Movement, MovementReq, MovementsReq, MutualCorrel,
+    Portfolio, PortfolioAnalyticsReq, PortfolioSecurity, SecurityProfit, SecurityProfitReq,
+    StockSplit, StockSplitsReq, Ticker, TickerFilter, TimeSeriesData, TimeSeriesReq,
+};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::{header, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin::Pin;
+use std::time::Duration;
+
+pub type ClientStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send>>;
+
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    // the address of rustix, without the /api prefix:
+    pub base_url: String,
+    // applies to whole requests, except for streams, which may run for any time:
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    // retries of failed requests, waiting retry_backoff before the first and doubling it:
+    pub retries: u32,
+    pub retry_backoff: Duration,
+}
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            base_url: "http://127.0.0.1:8000".to_string(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            retries: 3,
+            retry_backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+// ApiError is an error response of the api. Streams, which fail after the response started,
+// report the error as their last entry, which has no http status.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: Option<u16>,
+    pub body: ErrorBody,
+}
+impl std::error::Error for ApiError {}
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(f, "status {} - {}", status, self.body.code)?,
+            None => write!(f, "stream failed - {}", self.body.code)?,
+        }
+        write!(f, ": {}", self.body.message)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Retry {
+    // reads are retried on connection errors, timeouts and 502, 503 and 504 responses:
+    Read,
+    // writes are retried only if the connection could not be established:
+    Write,
+}
+
+#[derive(Deserialize)]
+struct Success {
+    success: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Id<'a> {
+    id: &'a str,
+}
+#[derive(Serialize)]
+struct Name<'a> {
+    name: &'a str,
+}
+#[derive(Serialize)]
+struct Filter<'a> {
+    filter: &'a str,
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    options: ClientOptions,
+}
+
+impl Client {
+    pub fn new(options: ClientOptions) -> Result<Client> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .build()?;
+        Ok(Client { http, options })
+    }
+    pub fn with_base_url(base_url: &str) -> Result<Client> {
+        Client::new(ClientOptions {
+            base_url: base_url.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/api{}",
+            self.options.base_url.trim_end_matches('/'),
+            path
+        )
+    }
+
+    async fn send(&self, build: impl Fn() -> RequestBuilder, retry: Retry) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let result = build().send().await;
+            let retryable = match &result {
+                Ok(resp) => retry == Retry::Read && matches!(resp.status().as_u16(), 502..=504),
+                Err(err) => err.is_connect() || (retry == Retry::Read && err.is_timeout()),
+            };
+            if retryable && attempt < self.options.retries {
+                tokio::time::sleep(self.options.retry_backoff * 2u32.pow(attempt)).await;
+                attempt += 1;
+                continue;
+            }
+            return error_for_status(result?).await;
+        }
+    }
+
+    async fn get<Q: Serialize, T: DeserializeOwned>(&self, path: &str, query: &Q) -> Result<T> {
+        let url = self.url(path);
+        let build = || {
+            self.http
+                .get(&url)
+                .query(query)
+                .timeout(self.options.timeout)
+        };
+        Ok(self.send(build, Retry::Read).await?.json().await?)
+    }
+    async fn post<B: Serialize, T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &B,
+        retry: Retry,
+    ) -> Result<T> {
+        let url = self.url(path);
+        let build = || {
+            self.http
+                .post(&url)
+                .json(body)
+                .timeout(self.options.timeout)
+        };
+        Ok(self.send(build, retry).await?.json().await?)
+    }
+    async fn post_success<B: Serialize>(&self, path: &str, body: &B) -> Result<()> {
+        let resp: Success = self.post(path, body, Retry::Write).await?;
+        match resp.success {
+            true => Ok(()),
+            false => Err(anyhow!(resp.error.unwrap_or_default())),
+        }
+    }
+    async fn post_bytes<B: Serialize>(&self, path: &str, body: &B) -> Result<Bytes> {
+        let url = self.url(path);
+        let build = || {
+            self.http
+                .post(&url)
+                .json(body)
+                .timeout(self.options.timeout)
+        };
+        Ok(self.send(build, Retry::Read).await?.bytes().await?)
+    }
+    async fn stream<B: Serialize, T: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<ClientStream<T>> {
+        let url = self.url(path);
+        let build = || {
+            self.http
+                .post(&url)
+                .query(&[("format", "ndjson")])
+                .header(header::ACCEPT, "application/x-ndjson")
+                .json(body)
+        };
+        let resp = self.send(build, Retry::Read).await?;
+        Ok(ndjson_stream(resp.bytes_stream()))
+    }
+
+    pub async fn ticker_details(&self, ticker: &BasicTicker) -> Result<Ticker> {
+        self.get("/ticker", ticker).await
+    }
+    pub async fn tickers(&self, filter: &TickerFilter) -> Result<ClientStream<Ticker>> {
+        self.stream("/tickers", filter).await
+    }
+
+    pub async fn security_data(&self, req: &TimeSeriesReq) -> Result<ClientStream<TimeSeriesData>> {
+        self.stream("/securityData", req).await
+    }
+    pub async fn indicators(&self, req: &IndicatorsReq) -> Result<ClientStream<TimeSeriesData>> {
+        self.stream("/indicators", req).await
+    }
+    // security_data_arrow returns the time series as arrow ipc stream:
+    pub async fn security_data_arrow(&self, req: &TimeSeriesReq) -> Result<Bytes> {
+        self.post_bytes("/securityData/arrow", req).await
+    }
+    pub async fn security_data_parquet(&self, req: &TimeSeriesReq) -> Result<Bytes> {
+        self.post_bytes("/securityData/parquet", req).await
+    }
+    pub async fn latest_security_data_date(&self, req: &DateReq) -> Result<LatestDate> {
+        self.get("/securityData/latestDate", req).await
+    }
+    pub async fn stock_splits(&self, req: &StockSplitsReq) -> Result<Vec<StockSplit>> {
+        self.get("/stockSplits", req).await
+    }
+
+    pub async fn portfolio(&self, id: &str) -> Result<Portfolio> {
+        self.get("/portfolio", &Id { id }).await
+    }
+    pub async fn portfolios(&self, filter: &str) -> Result<Vec<Portfolio>> {
+        self.get("/portfolios", &Filter { filter }).await
+    }
+    pub async fn portfolio_securities(&self, id: &str) -> Result<Vec<PortfolioSecurity>> {
+        self.get("/portfolio/securities", &Id { id }).await
+    }
+    pub async fn portfolio_profits(&self, req: &SecurityProfitReq) -> Result<Vec<SecurityProfit>> {
+        self.post("/portfolio/profits", req, Retry::Read).await
+    }
+    pub async fn portfolio_analytics(&self, req: &PortfolioAnalyticsReq) -> Result<Performance> {
+        self.post("/portfolio/analytics", req, Retry::Read).await
+    }
+    pub async fn portfolio_lots(&self, req: &LotReportReq) -> Result<LotReport> {
+        self.post("/portfolio/lots", req, Retry::Read).await
+    }
+    pub async fn sell_lots(&self, req: &LotSellReq) -> Result<Vec<LotGain>> {
+        self.post("/portfolio/lots/sell", req, Retry::Write).await
+    }
+    pub async fn portfolio_ledger(&self, id: &str) -> Result<Ledger> {
+        self.get("/portfolio/ledger", &Id { id }).await
+    }
+    pub async fn add_transaction(&self, req: &TransactionReq) -> Result<Ledger> {
+        self.post("/portfolio/ledger/transaction", req, Retry::Write)
+            .await
+    }
+    pub async fn delete_transaction(&self, req: &TransactionId) -> Result<Ledger> {
+        self.post("/portfolio/ledger/delete", req, Retry::Write)
+            .await
+    }
+    pub async fn set_ledger_margin(&self, req: &MarginReq) -> Result<Ledger> {
+        self.post("/portfolio/ledger/margin", req, Retry::Write)
+            .await
+    }
+    pub async fn ledger_profits(&self, req: &LedgerProfitsReq) -> Result<LedgerProfits> {
+        self.post("/portfolio/ledger/profits", req, Retry::Read)
+            .await
+    }
+    pub async fn portfolio_currencies(&self, id: &str) -> Result<PortfolioCurrencies> {
+        self.get("/portfolio/currencies", &Id { id }).await
+    }
+    pub async fn set_portfolio_currencies(
+        &self,
+        req: &PortfolioCurrencies,
+    ) -> Result<PortfolioCurrencies> {
+        self.post("/portfolio/currencies", req, Retry::Write).await
+    }
+    pub async fn portfolio_fx(&self, req: &FxValuationReq) -> Result<FxValuation> {
+        self.post("/portfolio/fx", req, Retry::Read).await
+    }
+    pub async fn create_portfolio(&self, name: &str, description: &str) -> Result<Portfolio> {
+        let portfolio = Portfolio {
+            id: "".to_string(),
+            name: name.to_string(),
+            description: description.to_string(),
+        };
+        self.post("/portfolio/create", &portfolio, Retry::Write)
+            .await
+    }
+    pub async fn delete_portfolio(&self, id: &str) -> Result<()> {
+        self.post_success("/portfolio/delete", &Id { id }).await
+    }
+    pub async fn buy_security(&self, security: &PortfolioSecurity) -> Result<()> {
+        self.post_success("/portfolio/buy", security).await
+    }
+    pub async fn sell_security(&self, security: &PortfolioSecurity) -> Result<()> {
+        self.post_success("/portfolio/sell", security).await
+    }
+    pub async fn delete_portfolio_security(&self, security: &PortfolioSecurity) -> Result<()> {
+        self.post_success("/portfolio/security/delete", security)
+            .await
+    }
+
+    pub async fn movement(&self, req: &MovementReq) -> Result<Movement> {
+        self.post("/movement", req, Retry::Read).await
+    }
+    pub async fn avg_movement(&self, req: &MovementReq) -> Result<Movement> {
+        self.post("/avgMovement", req, Retry::Read).await
+    }
+    pub async fn movements(&self, req: &MovementsReq) -> Result<Vec<Movement>> {
+        self.post("/movements", req, Retry::Read).await
+    }
+    pub async fn avg_movements(&self, req: &MovementsReq) -> Result<Vec<Movement>> {
+        self.post("/avgMovements", req, Retry::Read).await
+    }
+
+    pub async fn correlating_tickers(
+        &self,
+        req: &CorrelatingTickersReq,
+    ) -> Result<ClientStream<CorrelatingTickers>> {
+        self.stream("/correlatingTickers", req).await
+    }
+    pub async fn correlations(&self, req: &CorrelReq) -> Result<ClientStream<CorrelatingTickers>> {
+        self.stream("/correlations", req).await
+    }
+    pub async fn mutual_correlations(&self, req: &CorrelReq) -> Result<Vec<MutualCorrel>> {
+        self.post("/mutualCorrelations", req, Retry::Read).await
+    }
+
+    pub async fn market_status(&self, req: &MarketStatusReq) -> Result<Vec<MarketStatus>> {
+        self.get("/market/status", req).await
+    }
+    // market_feed streams the status of the exchanges on every state change, until dropped:
+    pub async fn market_feed(&self, req: &MarketStatusReq) -> Result<ClientStream<MarketStatus>> {
+        let url = self.url("/market/feed");
+        let build = || self.http.get(&url).query(req);
+        let resp = self.send(build, Retry::Read).await?;
+        Ok(server_sent_events(lines(resp.bytes_stream())).boxed())
+    }
+
+    pub async fn jobs(&self) -> Result<Vec<JobInfo>> {
+        self.get("/jobs", &()).await
+    }
+    pub async fn upsert_job(&self, job: &JobDef) -> Result<JobInfo> {
+        self.post("/jobs", job, Retry::Write).await
+    }
+    pub async fn delete_job(&self, name: &str) -> Result<()> {
+        self.post_success("/jobs/delete", &Name { name }).await
+    }
+    pub async fn trigger_job(&self, name: &str) -> Result<JobRun> {
+        self.post("/jobs/trigger", &Name { name }, Retry::Write)
+            .await
+    }
+    pub async fn pause_job(&self, name: &str) -> Result<JobInfo> {
+        self.post("/jobs/pause", &Name { name }, Retry::Write).await
+    }
+    pub async fn resume_job(&self, name: &str) -> Result<JobInfo> {
+        self.post("/jobs/resume", &Name { name }, Retry::Write)
+            .await
+    }
+    pub async fn job_runs(&self, name: &str) -> Result<Vec<JobRun>> {
+        self.get("/jobs/runs", &Name { name }).await
+    }
+}
+
+async fn error_for_status(resp: Response) -> Result<Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let text = resp.text().await.unwrap_or_default();
+    // errors of proxies in front of rustix are not json:
+    let body = serde_json::from_str::<ErrorBody>(&text).unwrap_or_else(|_| ErrorBody {
+        code: status.as_str().to_string(),
+        message: text,
+        details: vec![],
+        fields: vec![],
+        request_id: None,
+    });
+    Err(ApiError {
+        status: Some(status.as_u16()),
+        body,
+    }
+    .into())
+}
+
+// lines splits a byte stream into lines, without the line breaks:
+fn lines<S, E>(bytes: S) -> impl Stream<Item = Result<String>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    stream::try_unfold(
+        (bytes, Vec::<u8>::new(), false),
+        |(mut bytes, mut buf, mut done)| async move {
+            loop {
+                if let Some(i) = buf.iter().position(|b| *b == b'\n') {
+                    let line = buf.drain(..=i).collect::<Vec<_>>();
+                    let line = String::from_utf8(line)?.trim_end().to_string();
+                    return Ok(Some((line, (bytes, buf, done))));
+                }
+                if done {
+                    if buf.is_empty() {
+                        return Ok(None);
+                    }
+                    let line = String::from_utf8(std::mem::take(&mut buf))?;
+                    return Ok(Some((line, (bytes, buf, done))));
+                }
+                match bytes.next().await {
+                    Some(chunk) => buf.extend_from_slice(&chunk?),
+                    None => done = true,
+                }
+            }
+        },
+    )
+}
+
+// decode parses a streamed entry, or the error, that ended the stream:
+fn decode<T: DeserializeOwned>(entry: &str) -> Result<T> {
+    let value = serde_json::from_str::<serde_json::Value>(entry)?;
+    if let Some(error) = value
+        .as_object()
+        .filter(|o| o.len() == 1)
+        .and_then(|o| o.get("error"))
+    {
+        let body = serde_json::from_value::<ErrorBody>(error.clone())?;
+        return Err(ApiError { status: None, body }.into());
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+// ndjson_stream decodes an ndjson byte stream, e.g. a stream of Trading requested as ndjson:
+pub fn ndjson_stream<T, S, E>(bytes: S) -> ClientStream<T>
+where
+    T: DeserializeOwned + Send + 'static,
+    S: Stream<Item = Result<Bytes, E>> + Unpin + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    ndjson(lines(bytes)).boxed()
+}
+
+fn ndjson<T: DeserializeOwned>(
+    lines: impl Stream<Item = Result<String>>,
+) -> impl Stream<Item = Result<T>> {
+    lines
+        .try_filter(|line| futures::future::ready(!line.is_empty()))
+        .and_then(|line| futures::future::ready(decode(&line)))
+}
+
+// server_sent_events decodes the data of the events, comments (keep-alives) are skipped:
+fn server_sent_events<T: DeserializeOwned>(
+    lines: impl Stream<Item = Result<String>>,
+) -> impl Stream<Item = Result<T>> {
+    lines
+        .try_filter_map(|line| {
+            let data = line
+                .strip_prefix("data:")
+                .map(|data| data.trim_start().to_string());
+            futures::future::ready(Ok(data))
+        })
+        .and_then(|data| futures::future::ready(decode(&data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(chunks: &[&'static str]) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+        stream::iter(
+            chunks
+                .iter()
+                .map(|c| Ok(Bytes::from_static(c.as_bytes())))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[tokio::test]
+    async fn ndjson_entries() {
+        // entries may be split across chunks, the last line may miss its line break:
+        let entries = ndjson::<StockSplit>(lines(chunks(&[
+            "{\"ticker\":\"NVDA\",\"date\":\"2024-01-16\",",
+            "\"numerator\":4.0,\"denominator\":1.0}\n\n{\"ticker\":\"AAPL\",",
+            "\"date\":\"2020-08-31\",\"numerator\":4.0,\"denominator\":1.0}",
+        ])))
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].ticker, "NVDA");
+        assert_eq!(entries[1].date, "2020-08-31");
+
+        // a failed stream ends with the error:
+        let entries = ndjson::<StockSplit>(lines(chunks(&[
+            "{\"ticker\":\"NVDA\",\"date\":\"2024-01-16\",\"numerator\":4.0,\"denominator\":1.0}\n",
+            "{\"error\":{\"code\":\"unavailable\",\"message\":\"down\",\"details\":[],\"request_id\":null}}\n",
+        ])))
+        .collect::<Vec<_>>()
+        .await;
+        assert!(entries[0].is_ok());
+        let err = entries[1]
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<ApiError>())
+            .unwrap();
+        assert_eq!(err.status, None);
+        assert_eq!(err.body.code, "unavailable");
+    }
+
+    #[tokio::test]
+    async fn events() {
+        let events = server_sent_events::<serde_json::Value>(lines(chunks(&[
+            "data: {\"state\":\"open\"}\n\n: keep-alive\n\n",
+            "data: {\"state\":\"closed\"}\n\n",
+        ])))
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1]["state"], "closed");
+    }
+}
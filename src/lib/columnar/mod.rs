@@ -0,0 +1,409 @@
+// columnar converts streamed time series into Arrow IPC streams or Parquet files. The schema is
+// inferred from the first batch: a `date` timestamp column plus one float column per value key of
+// any of its entries, which is null where an entry lacks the key. The dates are exchange local
+// times, dates with an offset are converted into the timezone of the ticker's exchange.
+use crate::proto::dataloader as db_proto;
+use crate::stream::{ActixStream, ActixStreamItem, StreamError};
+use crate::time::{parse_date, parse_exchange_date_time};
+use anyhow::{anyhow, Result};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, TimestampMillisecondArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use bytes::Bytes;
+use chrono_tz::Tz;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+
+pub const BATCH_SIZE: usize = 8192;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColumnarFormat {
+    ArrowIpc,
+    Parquet,
+}
+
+impl ColumnarFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ColumnarFormat::ArrowIpc => "application/vnd.apache.arrow.stream",
+            ColumnarFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ColumnarFormat::ArrowIpc => "arrows",
+            ColumnarFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// time_series_schema derives the schema from the value keys of all entries, in sorted order:
+pub fn time_series_schema(entries: &[db_proto::TimeSeriesData]) -> SchemaRef {
+    let keys = entries
+        .iter()
+        .flat_map(|entry| entry.values.keys())
+        .collect::<BTreeSet<_>>();
+    let mut fields = vec![Field::new(
+        "date",
+        DataType::Timestamp(TimeUnit::Millisecond, None),
+        false,
+    )];
+    fields.extend(
+        keys.into_iter()
+            .map(|key| Field::new(key, DataType::Float64, true)),
+    );
+    Arc::new(Schema::new(fields))
+}
+
+// the dates are exchange local times, so they are stored as timestamps without timezone:
+fn timestamp(date: &str, tz: Tz) -> Result<i64> {
+    let dt = match date.len() {
+        10 => parse_date(date)?.and_hms_opt(0, 0, 0).unwrap_or_default(),
+        _ => parse_exchange_date_time(date, tz)?,
+    };
+    Ok(dt.and_utc().timestamp_millis())
+}
+
+// record_batch fails for value keys, which are not in the schema, rather than dropping them. A
+// stream has one schema, so keys cannot be added after the first batch.
+fn record_batch(
+    schema: &SchemaRef,
+    rows: &[db_proto::TimeSeriesData],
+    tz: Tz,
+) -> Result<RecordBatch> {
+    let unknown = rows
+        .iter()
+        .flat_map(|row| row.values.keys().map(move |key| (row, key)))
+        .find(|(_, key)| schema.field_with_name(key).is_err());
+    if let Some((row, key)) = unknown {
+        return Err(anyhow!(
+            "value {} of {} is not in the schema, which is taken from the first batch",
+            key,
+            row.date
+        ));
+    }
+    let dates = rows
+        .iter()
+        .map(|row| timestamp(&row.date, tz))
+        .collect::<Result<Vec<_>>>()?;
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(TimestampMillisecondArray::from(dates))];
+    for field in schema.fields().iter().skip(1) {
+        let values = rows
+            .iter()
+            .map(|row| row.values.get(field.name()).copied())
+            .collect::<Float64Array>();
+        columns.push(Arc::new(values));
+    }
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+// BatchWriter writes into an in-memory buffer, which is drained after every batch:
+enum BatchWriter {
+    ArrowIpc(StreamWriter<Vec<u8>>),
+    Parquet(ArrowWriter<Vec<u8>>),
+}
+
+impl BatchWriter {
+    fn try_new(format: ColumnarFormat, schema: &SchemaRef) -> Result<BatchWriter> {
+        Ok(match format {
+            ColumnarFormat::ArrowIpc => {
+                BatchWriter::ArrowIpc(StreamWriter::try_new(vec![], schema)?)
+            }
+            ColumnarFormat::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                BatchWriter::Parquet(ArrowWriter::try_new(vec![], schema.clone(), Some(props))?)
+            }
+        })
+    }
+    // write writes the batch as one ipc message, or as one parquet row group respectively:
+    fn write(&mut self, batch: &RecordBatch) -> Result<Bytes> {
+        match self {
+            BatchWriter::ArrowIpc(w) => {
+                w.write(batch)?;
+                Ok(Bytes::from(std::mem::take(w.get_mut())))
+            }
+            BatchWriter::Parquet(w) => {
+                w.write(batch)?;
+                w.flush()?;
+                Ok(Bytes::from(std::mem::take(w.inner_mut())))
+            }
+        }
+    }
+    fn finish(self) -> Result<Bytes> {
+        Ok(Bytes::from(match self {
+            BatchWriter::ArrowIpc(mut w) => {
+                w.finish()?;
+                w.into_inner()?
+            }
+            BatchWriter::Parquet(w) => w.into_inner()?,
+        }))
+    }
+}
+
+async fn write_batches<S>(
+    mut stream: S,
+    format: ColumnarFormat,
+    batch_size: usize,
+    tz: Tz,
+    tx: &mpsc::Sender<ActixStreamItem>,
+) -> Result<()>
+where
+    S: Stream<Item = Result<db_proto::TimeSeriesData, tonic::Status>> + Unpin,
+{
+    let mut rows = Vec::with_capacity(batch_size);
+    let mut schema = None;
+    let mut writer = None;
+    loop {
+        let entry = tokio::select! {
+            _ = tx.closed() => return Ok(()),
+            entry = stream.next() => entry,
+        };
+        let done = entry.is_none();
+        if let Some(entry) = entry {
+            rows.push(entry?);
+        }
+        if rows.len() >= batch_size || (done && !rows.is_empty()) {
+            let schema = schema.get_or_insert_with(|| time_series_schema(&rows));
+            let batch = record_batch(schema, &rows, tz)?;
+            rows.clear();
+            let writer = match &mut writer {
+                Some(writer) => writer,
+                None => writer.insert(BatchWriter::try_new(format, schema)?),
+            };
+            tx.send(Ok(writer.write(&batch)?)).await?;
+        }
+        if done {
+            break;
+        }
+    }
+    // an empty stream still results in a valid file, with nothing but the date column:
+    let writer = match writer {
+        Some(writer) => writer,
+        None => BatchWriter::try_new(
+            format,
+            &Arc::new(Schema::new(vec![Field::new(
+                "date",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            )])),
+        )?,
+    };
+    tx.send(Ok(writer.finish()?)).await?;
+    Ok(())
+}
+
+// time_series_to_columnar writes the stream incrementally, in record batches of batch_size rows.
+// Binary formats cannot carry a trailing error, so a failing gRPC stream (or a value key, which
+// is not in the schema) aborts the response, which leaves the client with an incomplete (and
+// thereby invalid) file.
+pub async fn time_series_to_columnar<S>(
+    stream: S,
+    format: ColumnarFormat,
+    batch_size: usize,
+    tz: Tz,
+) -> ActixStream
+where
+    S: Stream<Item = Result<db_proto::TimeSeriesData, tonic::Status>> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel::<ActixStreamItem>(16);
+    tokio::spawn(async move {
+        if let Err(err) = write_batches(stream, format, batch_size, tz, &tx).await {
+            println!("columnar-error: {:?}", err);
+            let _ = tx.send(Err(StreamError::new(err))).await;
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::StreamReader;
+    use chrono_tz::America::New_York;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::collections::HashMap;
+    use tonic::Status;
+
+    fn fixture(n: usize) -> Vec<Result<db_proto::TimeSeriesData, Status>> {
+        entries(n).into_iter().map(Ok).collect()
+    }
+    fn entries(n: usize) -> Vec<db_proto::TimeSeriesData> {
+        (0..n)
+            .map(|i| {
+                let mut values = HashMap::from([
+                    ("close".to_string(), 100.0 + i as f64),
+                    ("volume".to_string(), 1000.0 * i as f64),
+                ]);
+                // a key missing from the first entry is still part of the schema:
+                if i > 0 {
+                    values.insert("extra".to_string(), 1.0);
+                }
+                db_proto::TimeSeriesData {
+                    date: format!("2024-01-02T{:02}:{:02}:00", 9 + i / 60, i % 60),
+                    values,
+                }
+            })
+            .collect()
+    }
+
+    async fn collect(stream: ActixStream) -> (Bytes, usize) {
+        let chunks = stream.map(|c| c.unwrap()).collect::<Vec<_>>().await;
+        (Bytes::from(chunks.concat()), chunks.len())
+    }
+
+    #[tokio::test]
+    async fn arrow_round_trip() {
+        let upstream = futures::stream::iter(fixture(250));
+        let stream =
+            time_series_to_columnar(upstream, ColumnarFormat::ArrowIpc, 100, New_York).await;
+        let (body, chunks) = collect(stream).await;
+        // three batches and the end of stream marker:
+        assert_eq!(chunks, 4);
+
+        let reader = StreamReader::try_new(&body[..], None).unwrap();
+        let schema = reader.schema();
+        let names = schema
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["date", "close", "extra", "volume"]);
+        assert_eq!(
+            schema.field(0).data_type(),
+            &DataType::Timestamp(TimeUnit::Millisecond, None)
+        );
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 250);
+        assert_eq!(batches[0].num_rows(), 100);
+
+        let close = batches[2]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(close.value(49), 349.0);
+        let dates = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert_eq!(
+            dates.value(0),
+            timestamp("2024-01-02T09:00:00", New_York).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn parquet_round_trip() {
+        let upstream = futures::stream::iter(fixture(250));
+        let stream =
+            time_series_to_columnar(upstream, ColumnarFormat::Parquet, 100, New_York).await;
+        let (body, _) = collect(stream).await;
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(body).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        assert_eq!(builder.metadata().file_metadata().num_rows(), 250);
+        let names = builder
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["date", "close", "extra", "volume"]);
+
+        let batches = builder
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 250);
+        let volume = batches[0]
+            .column(3)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(volume.value(3), 3000.0);
+        assert_eq!(volume.null_count(), 0);
+        let extra = batches[0]
+            .column(2)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert!(extra.is_null(0));
+        assert_eq!(extra.value(1), 1.0);
+    }
+
+    #[tokio::test]
+    async fn empty_and_failing_streams() {
+        let upstream = futures::stream::iter(fixture(0));
+        let stream =
+            time_series_to_columnar(upstream, ColumnarFormat::Parquet, 100, New_York).await;
+        let (body, _) = collect(stream).await;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(body).unwrap();
+        assert_eq!(builder.metadata().file_metadata().num_rows(), 0);
+
+        let mut entries = fixture(150);
+        entries.push(Err(Status::unavailable("DataLoader went away")));
+        let upstream = futures::stream::iter(entries);
+        let stream =
+            time_series_to_columnar(upstream, ColumnarFormat::ArrowIpc, 100, New_York).await;
+        let chunks = stream.collect::<Vec<_>>().await;
+        assert!(chunks[0].is_ok());
+        assert!(chunks.last().unwrap().is_err());
+
+        // a key that first appears after the first batch fails the stream, instead of being lost:
+        let mut late = fixture(150);
+        late[120]
+            .as_mut()
+            .unwrap()
+            .values
+            .insert("late".to_string(), 1.0);
+        let upstream = futures::stream::iter(late);
+        let stream =
+            time_series_to_columnar(upstream, ColumnarFormat::Parquet, 100, New_York).await;
+        let chunks = stream.collect::<Vec<_>>().await;
+        assert_eq!(chunks.len(), 2);
+        let err = chunks[1].as_ref().unwrap_err().to_string();
+        assert!(err.contains("value late of 2024-01-02T11:00:00"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn offset_dates() {
+        // the same instant in utc, with an offset and in exchange local time:
+        let dates = [
+            "2024-01-02T14:30:00Z",
+            "2024-01-02T15:30:00+01:00",
+            "2024-01-02T09:30:00",
+        ];
+        let entries = dates.map(|date| db_proto::TimeSeriesData {
+            date: date.to_string(),
+            values: HashMap::from([("close".to_string(), 1.0)]),
+        });
+        let upstream = futures::stream::iter(entries.into_iter().map(Ok));
+        let stream =
+            time_series_to_columnar(upstream, ColumnarFormat::Parquet, 100, New_York).await;
+        let (body, _) = collect(stream).await;
+        let batches = ParquetRecordBatchReaderBuilder::try_new(body)
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let dates = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        let local = timestamp("2024-01-02T09:30:00", New_York).unwrap();
+        assert_eq!(dates.values().to_vec(), vec![local; 3]);
+    }
+}
@@ -0,0 +1,275 @@
+// enums are the typed parameters of the json api. They are sent as names ("stock", "quarter"),
+// but the integers of the corresponding prost enums are still accepted for older clients.
+// Unknown names and integers are rejected with an error instead of falling back to a default.
+use crate::proto::dataloader::{self as db_proto, correl_tickers_req};
+use anyhow::{anyhow, Result};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::RefOr;
+use utoipa::{PartialSchema, ToSchema};
+
+// api_enum defines an enum, whose variants map to the equally named variants of a prost enum.
+// Names are matched ignoring case, with '-' and ' ' being equal to '_'.
+macro_rules! api_enum {
+    ($(#[$doc:meta])* $name:ident => $proto:ty, $what:literal {
+        $($variant:ident = $label:literal $(| $alias:literal)*,)+
+    }) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant,)+];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $label,)+
+                }
+            }
+            // parse accepts the names, their aliases and the legacy integers:
+            pub fn parse(s: &str) -> Result<$name> {
+                if let Ok(n) = s.trim().parse::<i64>() {
+                    return $name::from_legacy(n);
+                }
+                let normalized = s.trim().to_lowercase().replace(['-', ' '], "_");
+                match normalized.as_str() {
+                    $($label $(| $alias)* => Ok($name::$variant),)+
+                    _ => Err(anyhow!(
+                        "unknown {} '{}' - expected one of {}",
+                        $what,
+                        s,
+                        $name::expected()
+                    )),
+                }
+            }
+            pub fn from_legacy(n: i64) -> Result<$name> {
+                $name::ALL
+                    .iter()
+                    .find(|v| i64::from(i32::from(**v)) == n)
+                    .copied()
+                    .ok_or_else(|| anyhow!(
+                        "unknown {} {} - expected one of {}",
+                        $what,
+                        n,
+                        $name::expected()
+                    ))
+            }
+            fn expected() -> String {
+                let names = $name::ALL.iter().map(|v| v.name()).collect::<Vec<_>>();
+                format!("{} (or 0-{})", names.join(", "), $name::ALL.len() - 1)
+            }
+        }
+
+        impl From<$name> for $proto {
+            fn from(v: $name) -> Self {
+                match v {
+                    $($name::$variant => <$proto>::$variant,)+
+                }
+            }
+        }
+        impl From<$name> for i32 {
+            fn from(v: $name) -> Self {
+                <$proto>::from(v) as i32
+            }
+        }
+        impl TryFrom<i32> for $name {
+            type Error = anyhow::Error;
+            fn try_from(n: i32) -> Result<Self> {
+                $name::from_legacy(n as i64)
+            }
+        }
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.name())
+            }
+        }
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.name())
+            }
+        }
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(ApiEnumVisitor::<$name>::new($what))
+            }
+        }
+        // the schema documents the names, the legacy integers are left out:
+        impl PartialSchema for $name {
+            fn schema() -> RefOr<Schema> {
+                ObjectBuilder::new()
+                    .schema_type(Type::String)
+                    .enum_values(Some($name::ALL.iter().map(|v| v.name())))
+                    .description(Some(format!("{} (or 0-{})", $what, $name::ALL.len() - 1)))
+                    .into()
+            }
+        }
+        impl ToSchema for $name {}
+        impl ApiEnum for $name {
+            fn parse(s: &str) -> Result<Self> {
+                $name::parse(s)
+            }
+            fn from_legacy(n: i64) -> Result<Self> {
+                $name::from_legacy(n)
+            }
+        }
+    };
+}
+
+trait ApiEnum: Sized {
+    fn parse(s: &str) -> Result<Self>;
+    fn from_legacy(n: i64) -> Result<Self>;
+}
+
+// ApiEnumVisitor accepts names and integers, the latter also as strings (e.g. in query strings):
+struct ApiEnumVisitor<T> {
+    what: &'static str,
+    marker: std::marker::PhantomData<T>,
+}
+impl<T> ApiEnumVisitor<T> {
+    fn new(what: &'static str) -> Self {
+        Self {
+            what,
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+impl<'de, T: ApiEnum> Visitor<'de> for ApiEnumVisitor<T> {
+    type Value = T;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a {} name or integer", self.what)
+    }
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        T::parse(v).map_err(E::custom)
+    }
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        T::from_legacy(v).map_err(E::custom)
+    }
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        let v = i64::try_from(v).map_err(E::custom)?;
+        T::from_legacy(v).map_err(E::custom)
+    }
+}
+
+api_enum! {
+    SecurityType => db_proto::TickerType, "security type" {
+        Stock = "stock" | "stocks",
+        Etf = "etf" | "etfs",
+        Commodity = "commodity" | "commodities",
+        Currency = "currency" | "currencies" | "fx",
+        Crypto = "crypto",
+    }
+}
+
+api_enum! {
+    Period => db_proto::Period, "period" {
+        Year = "year",
+        SemiAnnual = "semi_annual" | "semiannual" | "half_year",
+        Quarter = "quarter",
+        Month = "month",
+        Week = "week",
+        Day = "day",
+        Hour = "hour",
+        Minute = "minute",
+    }
+}
+
+api_enum! {
+    // SortBy orders movements:
+    SortBy => db_proto::MovementType, "sort order" {
+        Winner = "winner" | "winners",
+        Loser = "loser" | "losers",
+        Volume = "volume",
+        Volatility = "volatility",
+        AbsPerformance = "abs_performance",
+    }
+}
+
+api_enum! {
+    // Sign selects correlating tickers by the sign of their correlation:
+    Sign => correl_tickers_req::Sign, "sign" {
+        Abs = "abs" | "absolute",
+        Positive = "positive",
+        Negative = "negative",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn names_and_legacy_integers() {
+        let parse = |v| serde_json::from_value::<SecurityType>(v);
+        assert_eq!(parse(json!("stock")).unwrap(), SecurityType::Stock);
+        assert_eq!(parse(json!("ETF")).unwrap(), SecurityType::Etf);
+        assert_eq!(parse(json!(3)).unwrap(), SecurityType::Currency);
+        // query strings deliver integers as strings:
+        assert_eq!(parse(json!("4")).unwrap(), SecurityType::Crypto);
+
+        let err = parse(json!("bond")).unwrap_err().to_string();
+        assert!(err.contains("unknown security type 'bond'"), "{}", err);
+        assert!(err.contains("stock, etf, commodity, currency, crypto (or 0-4)"));
+        assert!(parse(json!(5)).is_err());
+        assert!(parse(json!(-1)).is_err());
+        assert!(parse(json!(1.5)).is_err());
+
+        assert_eq!(
+            serde_json::from_value::<Period>(json!("Semi-Annual")).unwrap(),
+            Period::SemiAnnual
+        );
+        assert!(serde_json::from_value::<Period>(json!(8)).is_err());
+        assert_eq!(
+            serde_json::from_value::<SortBy>(json!("winner")).unwrap(),
+            SortBy::Winner
+        );
+        assert_eq!(
+            serde_json::from_value::<Sign>(json!(2)).unwrap(),
+            Sign::Negative
+        );
+    }
+
+    #[test]
+    fn prost_mapping() {
+        for (i, period) in Period::ALL.iter().enumerate() {
+            assert_eq!(i32::from(*period), i as i32);
+            assert_eq!(Period::try_from(i as i32).unwrap(), *period);
+            // the names are those of the proto definition:
+            let proto = db_proto::Period::from(*period).as_str_name();
+            assert_eq!(proto.to_lowercase(), period.name());
+        }
+        assert_eq!(
+            db_proto::MovementType::from(SortBy::AbsPerformance),
+            db_proto::MovementType::AbsPerformance
+        );
+        assert_eq!(
+            correl_tickers_req::Sign::from(Sign::Positive),
+            correl_tickers_req::Sign::Positive
+        );
+        assert_eq!(i32::from(SecurityType::Crypto), 4);
+    }
+
+    #[test]
+    fn serialized_as_names() {
+        assert_eq!(
+            serde_json::to_value(SortBy::AbsPerformance).unwrap(),
+            json!("abs_performance")
+        );
+        assert_eq!(
+            serde_json::to_value(Period::Quarter).unwrap(),
+            json!("quarter")
+        );
+        let roundtrip = serde_json::to_value(SecurityType::Commodity).unwrap();
+        assert_eq!(
+            serde_json::from_value::<SecurityType>(roundtrip).unwrap(),
+            SecurityType::Commodity
+        );
+        // the openapi schema lists the names:
+        let schema = serde_json::to_value(Sign::schema()).unwrap();
+        assert_eq!(schema["enum"], json!(["abs", "positive", "negative"]));
+    }
+}
@@ -1,9 +1,21 @@
+#[derive(Clone)]
 pub struct Envs {
     pub host: String,
     pub port: u16,
     pub db_loader_host: String,
     pub db_loader_port: u16,
+    pub db_loader_connect_timeout_ms: u64,
+    pub db_loader_request_timeout_ms: u64,
+    pub db_loader_keep_alive_secs: u64,
+    pub db_loader_concurrency_limit: usize,
     pub mode: String,
+    // jobs_file persists the scheduled jobs and their runs, jobs_output_dir receives their results:
+    pub jobs_file: String,
+    pub jobs_output_dir: String,
+    // ledger_file persists the cash ledgers of the portfolios:
+    pub ledger_file: String,
+    // lots_file persists the sales of the lots of the portfolios:
+    pub lots_file: String,
 }
 impl Envs {
     pub fn parse() -> Envs {
@@ -12,7 +24,23 @@ impl Envs {
             port: envmnt::get_or("PORT", "8000").parse().unwrap(),
             db_loader_host: envmnt::get_or("DB_LOADER_HOST", "[::]"),
             db_loader_port: envmnt::get_or("DB_LOADER_PORT", "8002").parse().unwrap(),
+            db_loader_connect_timeout_ms: envmnt::get_or("DB_LOADER_CONNECT_TIMEOUT_MS", "5000")
+                .parse()
+                .unwrap(),
+            db_loader_request_timeout_ms: envmnt::get_or("DB_LOADER_REQUEST_TIMEOUT_MS", "60000")
+                .parse()
+                .unwrap(),
+            db_loader_keep_alive_secs: envmnt::get_or("DB_LOADER_KEEP_ALIVE_SECS", "30")
+                .parse()
+                .unwrap(),
+            db_loader_concurrency_limit: envmnt::get_or("DB_LOADER_CONCURRENCY_LIMIT", "256")
+                .parse()
+                .unwrap(),
             mode: envmnt::get_or("MODE", "info"),
+            jobs_file: envmnt::get_or("JOBS_FILE", "jobs.json"),
+            jobs_output_dir: envmnt::get_or("JOBS_OUTPUT_DIR", "jobs"),
+            ledger_file: envmnt::get_or("LEDGER_FILE", "ledger.json"),
+            lots_file: envmnt::get_or("LOTS_FILE", "lots.json"),
         }
     }
 }
@@ -1,19 +1,90 @@
+use crate::fx::FxError;
+use crate::jobs::JobError;
+use crate::ledger::LedgerError;
+use crate::time::InvalidDateError;
+use crate::validate::{FieldError, ValidationError};
 use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
     error::ResponseError,
-    http::{header::ContentType, StatusCode},
+    http::{
+        header::{ContentType, HeaderName, HeaderValue},
+        StatusCode,
+    },
     HttpResponse,
 };
-// use anyhow;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use utoipa::ToSchema;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// new_request_id generates an id, which is unique for the lifetime of the process:
+pub fn new_request_id() -> String {
+    let started = crate::time::utc_now().timestamp_micros();
+    let n = REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:04x}", started, n)
+}
+// with_request_id makes the id available to all errors created while running fut:
+pub async fn with_request_id<F: Future>(id: String, fut: F) -> F::Output {
+    REQUEST_ID.scope(id, fut).await
+}
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.to_string()).ok()
+}
+
+// track_request is a middleware (to be used with `wrap_fn`), which takes the request id from the
+// x-request-id header or generates one, and returns it in the response's x-request-id header.
+pub fn track_request<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .map(|id| id.to_string())
+        .unwrap_or_else(new_request_id);
+    let fut = srv.call(req);
+    async move {
+        let mut resp = with_request_id(id.to_string(), fut).await?;
+        if let Ok(id) = HeaderValue::from_str(&id) {
+            resp.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), id);
+        }
+        Ok(resp)
+    }
+}
+
+// ErrorBody is the json envelope of every error response:
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub details: Vec<String>,
+    // the failed checks of an invalid request:
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    pub request_id: Option<String>,
+}
 
 #[derive(Debug)]
 pub struct RustixErr {
     pub err: anyhow::Error,
     pub status: u16,
+    pub code: String,
 }
 impl fmt::Display for RustixErr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // Use `self.number` to refer to each positional data point.
         write!(f, "status {} - err {:?})", self.status, self.err)
     }
 }
@@ -24,17 +95,180 @@ impl ResponseError for RustixErr {
     }
     fn error_response(&self) -> HttpResponse {
         HttpResponse::build(self.status_code())
-            .insert_header(ContentType::html())
-            .body(self.to_string())
+            .insert_header(ContentType::json())
+            .json(self.body())
     }
 }
+// the status is derived from the first error in the chain, that tells what went wrong:
 impl From<anyhow::Error> for RustixErr {
     fn from(err: anyhow::Error) -> Self {
-        Self { err, status: 500 }
+        let (status, code) = err
+            .chain()
+            .find_map(|cause| {
+                if let Some(status) = cause.downcast_ref::<tonic::Status>() {
+                    Some(grpc_status(status.code()))
+                } else if cause.is::<InvalidDateError>() {
+                    Some((400, "invalid_date"))
+                } else if cause.is::<ValidationError>() {
+                    Some((400, "invalid_request"))
+                } else if let Some(err) = cause.downcast_ref::<JobError>() {
+                    Some(match err {
+                        JobError::NotFound(_) => (404, "not_found"),
+                        JobError::AlreadyRunning(_) | JobError::PortfolioInUse(..) => {
+                            (409, "conflict")
+                        }
+                    })
+                } else if cause.is::<LedgerError>() || cause.is::<FxError>() {
+                    Some((404, "not_found"))
+                } else if cause.is::<tonic::transport::Error>() {
+                    Some((503, "unavailable"))
+                } else {
+                    None
+                }
+            })
+            .unwrap_or((500, "internal"));
+        Self {
+            err,
+            status,
+            code: code.to_string(),
+        }
     }
 }
 impl RustixErr {
     pub fn new(err: anyhow::Error, status: u16) -> Self {
-        Self { err, status }
+        let code = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("error")
+            .to_lowercase()
+            .replace(' ', "_");
+        Self { err, status, code }
+    }
+    pub fn bad_request(err: anyhow::Error) -> Self {
+        Self::new(err, 400)
+    }
+    pub fn body(&self) -> ErrorBody {
+        let message = match self.err.downcast_ref::<tonic::Status>() {
+            Some(status) => status.message().to_string(),
+            None => self.err.to_string(),
+        };
+        ErrorBody {
+            code: self.code.to_string(),
+            message,
+            details: self.err.chain().skip(1).map(|c| c.to_string()).collect(),
+            fields: self
+                .err
+                .chain()
+                .find_map(|c| c.downcast_ref::<ValidationError>())
+                .map(|v| v.errors.clone())
+                .unwrap_or_default(),
+            request_id: request_id(),
+        }
+    }
+}
+
+// grpc_status maps a DataLoader status code to an http status and an error code:
+fn grpc_status(code: tonic::Code) -> (u16, &'static str) {
+    use tonic::Code;
+    match code {
+        Code::Ok | Code::Unknown | Code::Internal | Code::DataLoss => (500, "internal"),
+        Code::Cancelled => (499, "cancelled"),
+        Code::InvalidArgument => (400, "invalid_argument"),
+        Code::OutOfRange => (400, "out_of_range"),
+        Code::FailedPrecondition => (400, "failed_precondition"),
+        Code::Unauthenticated => (401, "unauthenticated"),
+        Code::PermissionDenied => (403, "permission_denied"),
+        Code::NotFound => (404, "not_found"),
+        Code::AlreadyExists => (409, "already_exists"),
+        Code::Aborted => (409, "aborted"),
+        Code::ResourceExhausted => (429, "resource_exhausted"),
+        Code::Unimplemented => (501, "unimplemented"),
+        Code::Unavailable => (503, "unavailable"),
+        Code::DeadlineExceeded => (504, "deadline_exceeded"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::parse_date;
+    use actix_web::body::to_bytes;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn grpc_status_mapping() {
+        let cases = [
+            (tonic::Status::not_found("unknown ticker"), 404, "not_found"),
+            (
+                tonic::Status::invalid_argument("bad"),
+                400,
+                "invalid_argument",
+            ),
+            (tonic::Status::unavailable("down"), 503, "unavailable"),
+            (
+                tonic::Status::deadline_exceeded("slow"),
+                504,
+                "deadline_exceeded",
+            ),
+            (tonic::Status::internal("oops"), 500, "internal"),
+        ];
+        for (status, http, code) in cases {
+            let err = RustixErr::from(anyhow::Error::new(status));
+            assert_eq!(err.status, http);
+            assert_eq!(err.code, code);
+        }
+    }
+
+    #[test]
+    fn wrapped_causes() {
+        // the status is found even if it was wrapped with context:
+        let err = Err::<(), _>(tonic::Status::not_found("unknown ticker XYZ"))
+            .context("requesting ticker details")
+            .unwrap_err();
+        let err = RustixErr::from(err);
+        assert_eq!(err.status, 404);
+
+        let err = RustixErr::from(parse_date("2024-13-01").unwrap_err());
+        assert_eq!(err.status, 400);
+        assert_eq!(err.code, "invalid_date");
+
+        let err = RustixErr::from(
+            Err::<(), _>(ValidationError {
+                errors: vec![FieldError {
+                    field: "limit".to_string(),
+                    message: "must be between 1 and 10000".to_string(),
+                }],
+            })
+            .context("checking movements request")
+            .unwrap_err(),
+        );
+        assert_eq!(err.status, 400);
+        assert_eq!(err.code, "invalid_request");
+        assert_eq!(err.body().fields[0].field, "limit");
+
+        let err = RustixErr::from(anyhow!("something else"));
+        assert_eq!(err.status, 500);
+        assert_eq!(err.code, "internal");
+
+        let err = RustixErr::new(anyhow!("nope"), 400);
+        assert_eq!(err.code, "bad_request");
+    }
+
+    #[actix_web::test]
+    async fn json_body() {
+        let err = RustixErr::from(anyhow::Error::new(tonic::Status::not_found(
+            "unknown ticker",
+        )));
+        let resp = with_request_id("abc".to_string(), async { err.error_response() }).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/json"
+        );
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let body: ErrorBody = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.code, "not_found");
+        assert_eq!(body.message, "unknown ticker");
+        assert_eq!(body.request_id, Some("abc".to_string()));
     }
 }
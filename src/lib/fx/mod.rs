@@ -0,0 +1,312 @@
+// fx converts the values of portfolios into their base currency with the daily closes of
+// CURRENCY tickers. A pair like EURUSD is the price of one EUR in USD, pairs which are only
+// quoted the other way round are inverted. The return of a position in the base currency is
+// split into its local return (in the trading currency) and its currency return, so that
+// (1 + total) = (1 + local) * (1 + currency).
+use crate::analytics::{latest_close, Closes};
+use crate::validate::{Validate, Validator};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use utoipa::ToSchema;
+
+// securities without a currency (explicit or in their ticker's custom fields) trade in:
+pub const DEFAULT_CURRENCY: &str = "USD";
+// the custom field of a ticker with its trading currency:
+pub const CURRENCY_FIELD: &str = "currency";
+
+// currency normalizes a currency code, e.g. " eur" to EUR:
+pub fn currency(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+pub fn is_currency(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())
+}
+// currency_of returns the trading currency in the custom fields of a ticker:
+pub fn currency_of(custom_fields: &HashMap<String, String>) -> Option<String> {
+    custom_fields
+        .get(CURRENCY_FIELD)
+        .map(|c| currency(c))
+        .filter(|c| is_currency(c))
+}
+// pair is the ticker of the price of from in to:
+pub fn pair(from: &str, to: &str) -> String {
+    format!("{}{}", from, to)
+}
+
+// FxRates are the daily rates of a currency in the base currency:
+#[derive(Clone, Debug, Default)]
+pub struct FxRates {
+    // the closes of the quoted pair, none if the currencies are the same:
+    closes: Option<Closes>,
+    inverted: bool,
+}
+
+impl FxRates {
+    pub fn same_currency() -> Self {
+        Self::default()
+    }
+    pub fn quoted(closes: Closes, inverted: bool) -> Self {
+        Self {
+            closes: Some(closes),
+            inverted,
+        }
+    }
+    // at returns the latest rate on or before the date:
+    pub fn at(&self, date: NaiveDate) -> Option<f64> {
+        let Some(closes) = &self.closes else {
+            return Some(1.0);
+        };
+        let close = latest_close(closes, date).filter(|c| *c > 0.0)?;
+        match self.inverted {
+            true => Some(1.0 / close),
+            false => Some(close),
+        }
+    }
+}
+
+// PortfolioCurrencies are the base currency of a portfolio and the trading currencies of its
+// securities, which are not (or wrongly) set in the custom fields of their tickers:
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PortfolioCurrencies {
+    pub portfolio_id: String,
+    #[schema(example = "USD")]
+    pub base_currency: String,
+    // the currencies by ticker, e.g. {"SAP": "EUR"}:
+    #[serde(default)]
+    pub currencies: BTreeMap<String, String>,
+}
+impl Validate for PortfolioCurrencies {
+    fn check(&self, v: &mut Validator) {
+        let message = "must be a currency code like USD";
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.check("base_currency", is_currency(&self.base_currency), message);
+        for (ticker, code) in self.currencies.iter() {
+            v.check(
+                &format!("currencies.{}", ticker),
+                is_currency(code),
+                message,
+            );
+        }
+    }
+}
+impl PortfolioCurrencies {
+    // normalized upper cases the codes:
+    pub fn normalized(self) -> Self {
+        Self {
+            base_currency: currency(&self.base_currency),
+            currencies: self
+                .currencies
+                .into_iter()
+                .map(|(ticker, code)| (ticker, currency(&code)))
+                .collect(),
+            ..self
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FxValuationReq {
+    pub portfolio_id: String,
+    #[schema(example = "2024-01-31")]
+    pub until: String,
+}
+impl Validate for FxValuationReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.date("until", &self.until);
+    }
+}
+
+#[derive(Debug)]
+pub enum FxError {
+    MissingRates { from: String, to: String },
+}
+impl Error for FxError {}
+impl fmt::Display for FxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FxError::MissingRates { from, to } => write!(
+                f,
+                "no fx rates of {} in {} - neither {} nor {} is quoted",
+                from,
+                to,
+                pair(from, to),
+                pair(to, from)
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FxPosition {
+    pub ticker: String,
+    // the trading currency:
+    pub currency: String,
+    // the shares at the date, after splits:
+    pub volume: f64,
+    pub purchase_date: String,
+    // the date of the sale, or of the valuation:
+    pub date: String,
+    pub local_cost: f64,
+    pub local_value: f64,
+    // the rates of the trading currency in the base currency:
+    pub purchase_rate: f64,
+    pub rate: f64,
+    pub base_cost: f64,
+    pub base_value: f64,
+    pub profit: f64,
+    pub local_return: f64,
+    pub currency_return: f64,
+    pub total_return: f64,
+}
+
+// Conversion is what a position costs and is worth, locally and at which rates:
+pub struct Conversion {
+    pub local_cost: f64,
+    pub local_value: f64,
+    pub purchase_rate: f64,
+    pub rate: f64,
+}
+
+impl Conversion {
+    pub fn position(
+        &self,
+        ticker: &str,
+        currency: &str,
+        volume: f64,
+        purchase_date: NaiveDate,
+        date: NaiveDate,
+    ) -> FxPosition {
+        let base_cost = self.local_cost * self.purchase_rate;
+        let base_value = self.local_value * self.rate;
+        FxPosition {
+            ticker: ticker.to_string(),
+            currency: currency.to_string(),
+            volume,
+            purchase_date: purchase_date.to_string(),
+            date: date.to_string(),
+            local_cost: self.local_cost,
+            local_value: self.local_value,
+            purchase_rate: self.purchase_rate,
+            rate: self.rate,
+            base_cost,
+            base_value,
+            profit: base_value - base_cost,
+            local_return: ratio(self.local_value, self.local_cost) - 1.0,
+            currency_return: ratio(self.rate, self.purchase_rate) - 1.0,
+            total_return: ratio(base_value, base_cost) - 1.0,
+        }
+    }
+}
+
+fn ratio(a: f64, b: f64) -> f64 {
+    match b == 0.0 {
+        true => 1.0,
+        false => a / b,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FxValuation {
+    pub portfolio_id: String,
+    pub base_currency: String,
+    pub until: String,
+    pub base_cost: f64,
+    pub base_value: f64,
+    pub profit: f64,
+    // the return at the purchase rates, i.e. without the currency moves:
+    pub local_return: f64,
+    // what the currency moves added: (1 + total) / (1 + local) - 1
+    pub currency_return: f64,
+    pub total_return: f64,
+    pub positions: Vec<FxPosition>,
+}
+
+pub fn valuation(
+    portfolio_id: &str,
+    base_currency: &str,
+    until: NaiveDate,
+    positions: Vec<FxPosition>,
+) -> FxValuation {
+    let base_cost = positions.iter().map(|p| p.base_cost).sum::<f64>();
+    let base_value = positions.iter().map(|p| p.base_value).sum::<f64>();
+    let at_purchase_rates = positions
+        .iter()
+        .map(|p| p.local_value * p.purchase_rate)
+        .sum::<f64>();
+    let local = ratio(at_purchase_rates, base_cost);
+    let total = ratio(base_value, base_cost);
+    FxValuation {
+        portfolio_id: portfolio_id.to_string(),
+        base_currency: base_currency.to_string(),
+        until: until.to_string(),
+        base_cost,
+        base_value,
+        profit: base_value - base_cost,
+        local_return: local - 1.0,
+        currency_return: ratio(total, local) - 1.0,
+        total_return: total - 1.0,
+        positions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(d: &str) -> NaiveDate {
+        d.parse().unwrap()
+    }
+
+    #[test]
+    fn rates() {
+        let closes = Closes::from([(date("2024-01-02"), 1.1), (date("2024-01-05"), 1.25)]);
+        let eur_usd = FxRates::quoted(closes.clone(), false);
+        assert_eq!(eur_usd.at(date("2024-01-04")), Some(1.1));
+        assert_eq!(eur_usd.at(date("2024-01-06")), Some(1.25));
+        assert_eq!(eur_usd.at(date("2024-01-01")), None);
+        let usd_eur = FxRates::quoted(closes, true);
+        assert_eq!(usd_eur.at(date("2024-01-05")), Some(0.8));
+        assert_eq!(FxRates::same_currency().at(date("2024-01-01")), Some(1.0));
+
+        let fields = HashMap::from([(CURRENCY_FIELD.to_string(), " eur".to_string())]);
+        assert_eq!(currency_of(&fields).as_deref(), Some("EUR"));
+        assert_eq!(currency_of(&HashMap::new()), None);
+        assert_eq!(pair("EUR", "USD"), "EURUSD");
+    }
+
+    #[test]
+    fn local_and_currency_returns() {
+        // 10 shares bought at 100 EUR (1.10 USD), worth 120 EUR (1.21 USD):
+        let sap = Conversion {
+            local_cost: 1000.0,
+            local_value: 1200.0,
+            purchase_rate: 1.1,
+            rate: 1.21,
+        }
+        .position("SAP", "EUR", 10.0, date("2024-01-02"), date("2024-01-31"));
+        assert!((sap.local_return - 0.2).abs() < 1e-12);
+        assert!((sap.currency_return - 0.1).abs() < 1e-12);
+        assert!((sap.total_return - 0.32).abs() < 1e-12);
+        assert!((sap.profit - 352.0).abs() < 1e-9);
+
+        let aapl = Conversion {
+            local_cost: 900.0,
+            local_value: 900.0,
+            purchase_rate: 1.0,
+            rate: 1.0,
+        }
+        .position("AAPL", "USD", 5.0, date("2024-01-02"), date("2024-01-31"));
+        let valuation = valuation("1", "USD", date("2024-01-31"), vec![sap, aapl]);
+        assert!((valuation.base_cost - 2000.0).abs() < 1e-9);
+        assert!((valuation.base_value - 2352.0).abs() < 1e-9);
+        // locally, only sap gained 20% of its 1100 USD:
+        assert!((valuation.local_return - 0.11).abs() < 1e-12);
+        let total = (1.0 + valuation.local_return) * (1.0 + valuation.currency_return);
+        assert!((total - 1.0 - valuation.total_return).abs() < 1e-12);
+        assert!((valuation.total_return - 0.176).abs() < 1e-12);
+    }
+}
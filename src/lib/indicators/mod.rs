@@ -0,0 +1,710 @@
+// indicators computes technical indicators incrementally, one bar at a time, so they can be
+// applied to the streamed security data. Indicators are warmed up with bars before the requested
+// range, which are not part of the output.
+use crate::calendar::TradingCalendar;
+use crate::proto::dataloader as db_proto;
+use crate::resample::Resolution;
+use crate::time::parse_date;
+use anyhow::{anyhow, Result};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use utoipa::ToSchema;
+
+pub struct Bar<'a> {
+    pub date: &'a str,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl<'a> Bar<'a> {
+    // from_entry returns None for entries without a price:
+    pub fn from_entry(entry: &'a db_proto::TimeSeriesData) -> Option<Bar<'a>> {
+        let value = |key: &str| entry.values.get(key).copied();
+        let close = value("close").or_else(|| value("price"))?;
+        Some(Bar {
+            date: &entry.date,
+            open: value("open").unwrap_or(close),
+            high: value("high").unwrap_or(close),
+            low: value("low").unwrap_or(close),
+            close,
+            volume: value("volume").unwrap_or_default(),
+        })
+    }
+}
+
+pub trait Indicator: Send {
+    // update feeds the next bar, and returns the indicator's values once it is warmed up:
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>>;
+}
+
+// IndicatorSpec is the requested indicator with its parameters, e.g. {"name": "sma", "period": 20}:
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "name", rename_all = "lowercase")]
+pub enum IndicatorSpec {
+    Sma {
+        period: usize,
+    },
+    Ema {
+        period: usize,
+    },
+    Rsi {
+        #[serde(default = "default_rsi_period")]
+        period: usize,
+    },
+    Macd {
+        #[serde(default = "default_macd_fast")]
+        fast: usize,
+        #[serde(default = "default_macd_slow")]
+        slow: usize,
+        #[serde(default = "default_macd_signal")]
+        signal: usize,
+    },
+    Bollinger {
+        #[serde(default = "default_bollinger_period")]
+        period: usize,
+        #[serde(default = "default_bollinger_stddevs")]
+        stddevs: f64,
+    },
+    Atr {
+        #[serde(default = "default_atr_period")]
+        period: usize,
+    },
+    Vwap,
+    Obv,
+}
+fn default_rsi_period() -> usize {
+    14
+}
+fn default_macd_fast() -> usize {
+    12
+}
+fn default_macd_slow() -> usize {
+    26
+}
+fn default_macd_signal() -> usize {
+    9
+}
+fn default_bollinger_period() -> usize {
+    20
+}
+fn default_bollinger_stddevs() -> f64 {
+    2.0
+}
+fn default_atr_period() -> usize {
+    14
+}
+
+impl IndicatorSpec {
+    pub fn validate(&self) -> Result<()> {
+        let periods = match self {
+            IndicatorSpec::Sma { period }
+            | IndicatorSpec::Ema { period }
+            | IndicatorSpec::Rsi { period }
+            | IndicatorSpec::Bollinger { period, .. }
+            | IndicatorSpec::Atr { period } => vec![*period],
+            IndicatorSpec::Macd { fast, slow, signal } => {
+                if fast >= slow {
+                    return Err(anyhow!(
+                        "macd: fast period must be shorter than slow period"
+                    ));
+                }
+                vec![*fast, *slow, *signal]
+            }
+            IndicatorSpec::Vwap | IndicatorSpec::Obv => vec![],
+        };
+        if periods.contains(&0) {
+            return Err(anyhow!("{}: periods must be positive", self.label()));
+        }
+        Ok(())
+    }
+    // label is the name of the indicator's value, and the prefix of multi-valued indicators:
+    pub fn label(&self) -> String {
+        match self {
+            IndicatorSpec::Sma { period } => format!("sma_{}", period),
+            IndicatorSpec::Ema { period } => format!("ema_{}", period),
+            IndicatorSpec::Rsi { period } => format!("rsi_{}", period),
+            IndicatorSpec::Macd { fast, slow, signal } => {
+                format!("macd_{}_{}_{}", fast, slow, signal)
+            }
+            IndicatorSpec::Bollinger { period, stddevs } => {
+                format!("bollinger_{}_{}", period, stddevs)
+            }
+            IndicatorSpec::Atr { period } => format!("atr_{}", period),
+            IndicatorSpec::Vwap => "vwap".to_string(),
+            IndicatorSpec::Obv => "obv".to_string(),
+        }
+    }
+    pub fn keys(&self) -> Vec<String> {
+        let label = self.label();
+        match self {
+            IndicatorSpec::Macd { .. } => vec![
+                label.to_string(),
+                format!("{}_signal", label),
+                format!("{}_histogram", label),
+            ],
+            IndicatorSpec::Bollinger { .. } => vec![
+                format!("{}_middle", label),
+                format!("{}_upper", label),
+                format!("{}_lower", label),
+            ],
+            _ => vec![label],
+        }
+    }
+    // warm_up is the number of bars needed before the first value. Smoothed indicators depend on
+    // all previous bars, they get thrice their period to come close to values computed from the
+    // full history.
+    pub fn warm_up(&self) -> usize {
+        match self {
+            IndicatorSpec::Sma { period } | IndicatorSpec::Bollinger { period, .. } => *period,
+            IndicatorSpec::Ema { period } => 3 * period,
+            IndicatorSpec::Rsi { period } | IndicatorSpec::Atr { period } => 3 * period + 1,
+            IndicatorSpec::Macd { slow, signal, .. } => 3 * slow + signal,
+            IndicatorSpec::Vwap | IndicatorSpec::Obv => 0,
+        }
+    }
+    // build creates the indicator. Cumulative indicators (vwap, obv) start at anchor, intraday
+    // vwaps are reset with every session.
+    pub fn build(&self, anchor: &str, intraday: bool) -> Box<dyn Indicator> {
+        match self {
+            IndicatorSpec::Sma { period } => Box::new(Sma::new(*period)),
+            IndicatorSpec::Ema { period } => Box::new(Ema::new(*period)),
+            IndicatorSpec::Rsi { period } => Box::new(Rsi::new(*period)),
+            IndicatorSpec::Macd { fast, slow, signal } => {
+                Box::new(Macd::new(*fast, *slow, *signal))
+            }
+            IndicatorSpec::Bollinger { period, stddevs } => {
+                Box::new(Bollinger::new(*period, *stddevs))
+            }
+            IndicatorSpec::Atr { period } => Box::new(Atr::new(*period)),
+            IndicatorSpec::Vwap => Box::new(Vwap::new(anchor, intraday)),
+            IndicatorSpec::Obv => Box::new(Obv::new(anchor)),
+        }
+    }
+}
+
+// warm_up_start returns the date to request data from, so there are at least `bars` bars before
+// from. Daily and intraday bars are counted in sessions of the security's trading calendar.
+pub fn warm_up_start(
+    from: &str,
+    resolution: Resolution,
+    bars: usize,
+    calendar: &TradingCalendar,
+) -> Result<String> {
+    let from = parse_date(from)?;
+    let start = match resolution {
+        Resolution::Day => calendar.sessions_back(from, bars),
+        Resolution::Week => from - Duration::weeks(bars as i64 + 1),
+        Resolution::Month => from - Duration::days(31 * (bars as i64 + 1)),
+        intraday => {
+            // the last bar of a session may be a partial one:
+            let minutes = intraday.minutes().unwrap_or(1) as usize;
+            let session_minutes = calendar.session_minutes() as usize;
+            let per_session = session_minutes.div_ceil(minutes);
+            calendar.sessions_back(from, bars.div_ceil(per_session))
+        }
+    };
+    Ok(start.to_string())
+}
+
+// IndicatorSet applies all requested indicators to the bars of a time series.
+pub struct IndicatorSet {
+    from: String,
+    indicators: Vec<(Vec<String>, Box<dyn Indicator>)>,
+}
+
+impl IndicatorSet {
+    pub fn new(specs: &[IndicatorSpec], from: &str, intraday: bool) -> IndicatorSet {
+        IndicatorSet {
+            from: from.to_string(),
+            indicators: specs
+                .iter()
+                .map(|spec| (spec.keys(), spec.build(from, intraday)))
+                .collect(),
+        }
+    }
+    // update returns the entry with the close price and all warmed up indicator values, or None
+    // for bars before from:
+    pub fn update(&mut self, entry: &db_proto::TimeSeriesData) -> Option<db_proto::TimeSeriesData> {
+        let bar = Bar::from_entry(entry)?;
+        let mut values = HashMap::from([("close".to_string(), bar.close)]);
+        for (keys, indicator) in self.indicators.iter_mut() {
+            if let Some(vals) = indicator.update(&bar) {
+                values.extend(keys.iter().cloned().zip(vals));
+            }
+        }
+        if entry.date < self.from {
+            return None;
+        }
+        Some(db_proto::TimeSeriesData {
+            date: entry.date.to_string(),
+            values,
+        })
+    }
+}
+
+pub struct Sma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+impl Sma {
+    pub fn new(period: usize) -> Sma {
+        Sma {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            sum: 0.0,
+        }
+    }
+    pub fn next(&mut self, value: f64) -> Option<f64> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        (self.window.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+}
+impl Indicator for Sma {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        self.next(bar.close).map(|v| vec![v])
+    }
+}
+
+// Ema is seeded with the simple average of the first period values:
+pub struct Ema {
+    alpha: f64,
+    seed: Sma,
+    value: Option<f64>,
+}
+impl Ema {
+    pub fn new(period: usize) -> Ema {
+        Ema {
+            alpha: 2.0 / (period as f64 + 1.0),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+    pub fn next(&mut self, value: f64) -> Option<f64> {
+        self.value = match self.value {
+            Some(ema) => Some(ema + self.alpha * (value - ema)),
+            None => self.seed.next(value),
+        };
+        self.value
+    }
+}
+impl Indicator for Ema {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        self.next(bar.close).map(|v| vec![v])
+    }
+}
+
+// Wilder is wilder's smoothing, seeded with the simple average of the first period values:
+struct Wilder {
+    period: usize,
+    seed: Sma,
+    value: Option<f64>,
+}
+impl Wilder {
+    fn new(period: usize) -> Wilder {
+        Wilder {
+            period,
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+    fn next(&mut self, value: f64) -> Option<f64> {
+        let n = self.period as f64;
+        self.value = match self.value {
+            Some(avg) => Some((avg * (n - 1.0) + value) / n),
+            None => self.seed.next(value),
+        };
+        self.value
+    }
+}
+
+pub struct Rsi {
+    prev_close: Option<f64>,
+    gains: Wilder,
+    losses: Wilder,
+}
+impl Rsi {
+    pub fn new(period: usize) -> Rsi {
+        Rsi {
+            prev_close: None,
+            gains: Wilder::new(period),
+            losses: Wilder::new(period),
+        }
+    }
+}
+impl Indicator for Rsi {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        let change = bar.close - self.prev_close.replace(bar.close)?;
+        let gain = self.gains.next(change.max(0.0));
+        let loss = self.losses.next((-change).max(0.0));
+        let (gain, loss) = (gain?, loss?);
+        match loss {
+            0.0 => Some(vec![100.0]),
+            _ => Some(vec![100.0 - 100.0 / (1.0 + gain / loss)]),
+        }
+    }
+}
+
+// Macd returns the macd line, the signal line and the histogram:
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Macd {
+        Macd {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+        }
+    }
+}
+impl Indicator for Macd {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        let fast = self.fast.next(bar.close);
+        let slow = self.slow.next(bar.close);
+        let macd = fast? - slow?;
+        let signal = self.signal.next(macd)?;
+        Some(vec![macd, signal, macd - signal])
+    }
+}
+
+// Bollinger returns the middle, upper and lower band, using the population standard deviation:
+pub struct Bollinger {
+    sma: Sma,
+    stddevs: f64,
+}
+impl Bollinger {
+    pub fn new(period: usize, stddevs: f64) -> Bollinger {
+        Bollinger {
+            sma: Sma::new(period),
+            stddevs,
+        }
+    }
+}
+impl Indicator for Bollinger {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        let mean = self.sma.next(bar.close)?;
+        let window = &self.sma.window;
+        let variance = window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / window.len() as f64;
+        let band = self.stddevs * variance.sqrt();
+        Some(vec![mean, mean + band, mean - band])
+    }
+}
+
+// Atr is the wilder smoothed true range, the first bar's true range being its high - low:
+pub struct Atr {
+    prev_close: Option<f64>,
+    atr: Wilder,
+}
+impl Atr {
+    pub fn new(period: usize) -> Atr {
+        Atr {
+            prev_close: None,
+            atr: Wilder::new(period),
+        }
+    }
+}
+impl Indicator for Atr {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        let range = match self.prev_close.replace(bar.close) {
+            Some(prev) => (bar.high - bar.low)
+                .max((bar.high - prev).abs())
+                .max((bar.low - prev).abs()),
+            None => bar.high - bar.low,
+        };
+        self.atr.next(range).map(|v| vec![v])
+    }
+}
+
+// Vwap is the volume weighted typical price since the anchor, or since the session start:
+pub struct Vwap {
+    anchor: String,
+    intraday: bool,
+    session: String,
+    price_volume: f64,
+    volume: f64,
+}
+impl Vwap {
+    pub fn new(anchor: &str, intraday: bool) -> Vwap {
+        Vwap {
+            anchor: anchor.to_string(),
+            intraday,
+            session: String::new(),
+            price_volume: 0.0,
+            volume: 0.0,
+        }
+    }
+}
+impl Indicator for Vwap {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        if bar.date < self.anchor.as_str() {
+            return None;
+        }
+        let session = bar.date.get(..10).unwrap_or(bar.date);
+        if self.intraday && session != self.session {
+            self.session = session.to_string();
+            self.price_volume = 0.0;
+            self.volume = 0.0;
+        }
+        let typical = (bar.high + bar.low + bar.close) / 3.0;
+        self.price_volume += typical * bar.volume;
+        self.volume += bar.volume;
+        (self.volume > 0.0).then(|| vec![self.price_volume / self.volume])
+    }
+}
+
+// Obv is the on-balance volume since the anchor:
+pub struct Obv {
+    anchor: String,
+    prev_close: Option<f64>,
+    obv: f64,
+}
+impl Obv {
+    pub fn new(anchor: &str) -> Obv {
+        Obv {
+            anchor: anchor.to_string(),
+            prev_close: None,
+            obv: 0.0,
+        }
+    }
+}
+impl Indicator for Obv {
+    fn update(&mut self, bar: &Bar) -> Option<Vec<f64>> {
+        if bar.date < self.anchor.as_str() {
+            return None;
+        }
+        if let Some(prev) = self.prev_close.replace(bar.close) {
+            if bar.close > prev {
+                self.obv += bar.volume;
+            } else if bar.close < prev {
+                self.obv -= bar.volume;
+            }
+        }
+        Some(vec![self.obv])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // closes of wilder's rsi example, as used by most ta libraries:
+    const CLOSES: [f64; 33] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03, 45.61,
+        46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45, 45.78, 45.35,
+        44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13,
+    ];
+
+    fn bars() -> Vec<Bar<'static>> {
+        CLOSES
+            .iter()
+            .enumerate()
+            .map(|(i, close)| Bar {
+                date: "2024-01-02",
+                open: *close,
+                high: close + 0.5 + (i % 3) as f64 * 0.1,
+                low: close - 0.4 - (i % 2) as f64 * 0.1,
+                close: *close,
+                volume: 1000.0,
+            })
+            .collect()
+    }
+    fn run(indicator: &mut dyn Indicator, bars: &[Bar]) -> Vec<Option<Vec<f64>>> {
+        bars.iter().map(|bar| indicator.update(bar)).collect()
+    }
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sma() {
+        let mut sma = Sma::new(3);
+        let values = [1.0, 2.0, 3.0, 4.0, 8.0].map(|v| sma.next(v));
+        assert_eq!(values, [None, None, Some(2.0), Some(3.0), Some(5.0)]);
+    }
+
+    #[test]
+    fn ema() {
+        let values = run(&mut Ema::new(10), &bars());
+        assert!(values[8].is_none());
+        assert_close(values[9].as_ref().unwrap()[0], 44.779);
+        assert_close(values[10].as_ref().unwrap()[0], 44.981);
+        assert_close(values[11].as_ref().unwrap()[0], 45.1717);
+        assert_close(values[32].as_ref().unwrap()[0], 44.1193);
+    }
+
+    #[test]
+    fn rsi() {
+        let expected = [
+            70.46, 66.25, 66.48, 69.35, 66.29, 57.92, 62.88, 63.21, 56.01, 62.34, 54.67, 50.39,
+            40.02, 41.49, 41.90, 45.50, 37.32, 33.09, 37.79,
+        ];
+        let values = run(&mut Rsi::new(14), &bars())
+            .into_iter()
+            .flatten()
+            .map(|v| (v[0] * 100.0).round() / 100.0)
+            .collect::<Vec<_>>();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn macd() {
+        let values = run(&mut Macd::new(12, 26, 5), &bars());
+        // the signal line needs 5 macd values, the first of which is at the 26th bar:
+        assert_eq!(values.iter().flatten().count(), 4);
+        let last = values[32].as_ref().unwrap();
+        assert_close(last[0], -0.4747);
+        assert_close(last[1], -0.2652);
+        assert_close(last[2], -0.2094);
+    }
+
+    #[test]
+    fn bollinger() {
+        let values = run(&mut Bollinger::new(20, 2.0), &bars());
+        assert!(values[18].is_none());
+        let last = values[32].as_ref().unwrap();
+        assert_close(last[0], 45.241);
+        assert_close(last[1], 47.6202);
+        assert_close(last[2], 42.8618);
+    }
+
+    #[test]
+    fn atr() {
+        let values = run(&mut Atr::new(14), &bars());
+        assert!(values[12].is_none());
+        assert_close(values[13].as_ref().unwrap()[0], 1.0821);
+        assert_close(values[32].as_ref().unwrap()[0], 1.1656);
+    }
+
+    #[test]
+    fn vwap_and_obv() {
+        let bar = |date, close, volume| Bar {
+            date,
+            open: close,
+            high: close + 1.0,
+            low: close - 1.0,
+            close,
+            volume,
+        };
+        let bars = [
+            bar("2024-01-01T15:30:00", 5.0, 100.0),
+            bar("2024-01-02T09:30:00", 10.0, 100.0),
+            bar("2024-01-02T10:00:00", 13.0, 200.0),
+            bar("2024-01-02T10:30:00", 11.0, 100.0),
+            bar("2024-01-03T09:30:00", 11.0, 300.0),
+        ];
+        let vwaps = run(&mut Vwap::new("2024-01-02", true), &bars);
+        assert_eq!(vwaps[0], None);
+        assert_eq!(vwaps[1], Some(vec![10.0]));
+        assert_eq!(vwaps[3], Some(vec![11.75]));
+        // reset with the next session:
+        assert_eq!(vwaps[4], Some(vec![11.0]));
+        let vwaps = run(&mut Vwap::new("2024-01-02", false), &bars);
+        assert_eq!(
+            vwaps[4],
+            Some(vec![(1000.0 + 2600.0 + 1100.0 + 3300.0) / 700.0])
+        );
+
+        let obvs = run(&mut Obv::new("2024-01-02"), &bars);
+        let obvs = obvs
+            .into_iter()
+            .map(|v| v.map(|v| v[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            obvs,
+            [None, Some(0.0), Some(200.0), Some(100.0), Some(100.0)]
+        );
+    }
+
+    #[test]
+    fn specs() {
+        let specs: Vec<IndicatorSpec> = serde_json::from_str(
+            r#"[{"name": "sma", "period": 20}, {"name": "macd"}, {"name": "bollinger"}, {"name": "obv"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            specs[1],
+            IndicatorSpec::Macd {
+                fast: 12,
+                slow: 26,
+                signal: 9
+            }
+        );
+        let keys = specs.iter().flat_map(|s| s.keys()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                "sma_20",
+                "macd_12_26_9",
+                "macd_12_26_9_signal",
+                "macd_12_26_9_histogram",
+                "bollinger_20_2_middle",
+                "bollinger_20_2_upper",
+                "bollinger_20_2_lower",
+                "obv",
+            ]
+        );
+        assert!(serde_json::from_str::<IndicatorSpec>(r#"{"name": "sma"}"#).is_err());
+        assert!(IndicatorSpec::Sma { period: 0 }.validate().is_err());
+        assert!(IndicatorSpec::Macd {
+            fast: 26,
+            slow: 12,
+            signal: 9
+        }
+        .validate()
+        .is_err());
+        assert_eq!(specs[1].warm_up(), 87);
+    }
+
+    #[test]
+    fn warm_up() {
+        use crate::calendar::Exchange;
+        let nyse = TradingCalendar::nyse();
+        let start = |resolution, bars, calendar| {
+            warm_up_start("2024-03-01", resolution, bars, calendar).unwrap()
+        };
+        // presidents' day is skipped:
+        assert_eq!(start(Resolution::Day, 20, &nyse), "2024-02-01");
+        assert_eq!(start(Resolution::Day, 0, &nyse), "2024-03-01");
+        // 26 hourly bars (7 per session) are spread over 4 sessions:
+        assert_eq!(start(Resolution::Hour, 26, &nyse), "2024-02-26");
+        // crypto trades every day, in sessions of 24 hours:
+        let crypto = TradingCalendar::new(Exchange::Crypto);
+        assert_eq!(start(Resolution::Day, 20, &crypto), "2024-02-10");
+        assert_eq!(start(Resolution::Hour, 26, &crypto), "2024-02-28");
+        assert_eq!(start(Resolution::Week, 2, &crypto), "2024-02-09");
+    }
+
+    #[test]
+    fn indicator_set() {
+        let specs = [IndicatorSpec::Sma { period: 2 }, IndicatorSpec::Obv];
+        let mut set = IndicatorSet::new(&specs, "2024-01-03", false);
+        let entry = |date: &str, close: f64| db_proto::TimeSeriesData {
+            date: date.to_string(),
+            values: HashMap::from([("close".to_string(), close), ("volume".to_string(), 10.0)]),
+        };
+        assert!(set.update(&entry("2024-01-02", 1.0)).is_none());
+        let out = set.update(&entry("2024-01-03", 3.0)).unwrap();
+        assert_eq!(out.values["close"], 3.0);
+        assert_eq!(out.values["sma_2"], 2.0);
+        assert_eq!(out.values["obv"], 0.0);
+        let out = set.update(&entry("2024-01-04", 2.0)).unwrap();
+        assert_eq!(out.values["sma_2"], 2.5);
+        assert_eq!(out.values["obv"], -10.0);
+    }
+}
@@ -0,0 +1,914 @@
+// jobs runs scheduled work inside rustix, e.g. nightly movement snapshots or end of day portfolio
+// valuations. Jobs are triggered by cron expressions or relative to the sessions of an exchange.
+// The job definitions and their run history are persisted to a json file, so they survive
+// restarts, and the results of each run are written to a json file of their own.
+use crate::calendar::{Exchange, Session, TradingCalendar};
+use crate::envs::Envs;
+use crate::persist;
+use crate::time::utc_now;
+use crate::trading::{MovementsReq, Trading};
+use crate::validate::{Validate, Validator};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+use utoipa::ToSchema;
+
+// the run history keeps this many runs per job:
+const MAX_RUNS: usize = 100;
+// without any scheduled job, the scheduler still wakes up this often:
+const IDLE: std::time::Duration = std::time::Duration::from_secs(3600);
+// market relative triggers may be offset by up to half a day from the open or close:
+const MAX_OFFSET_MINUTES: i64 = 12 * 60;
+// sessions are searched for this many days ahead:
+const MAX_SEARCH_DAYS: i64 = 14;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEvent {
+    Open,
+    Close,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    // a cron expression with five fields (minute hour day month weekday) or six (with leading
+    // seconds) in the exchange's timezone, or else in utc. Weekdays are names (MON-FRI) or
+    // numbers as in crontab, where 0 and 7 are sunday and 1 is monday.
+    Cron {
+        expr: String,
+        #[serde(default)]
+        exchange: Option<Exchange>,
+    },
+    // relative to each session's open or close, e.g. 30 minutes after the open:
+    Market {
+        exchange: Exchange,
+        at: SessionEvent,
+        #[serde(default)]
+        offset_minutes: i64,
+    },
+    // every few minutes while the exchange is open, starting at the open:
+    WhileOpen {
+        exchange: Exchange,
+        every_minutes: u32,
+    },
+}
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+// crontab_weekdays replaces the numeric weekdays of crontab by their names, as the cron crate
+// counts from 1 = sunday. E.g. 1-5 becomes MON,TUE,WED,THU,FRI. Names and * are kept.
+fn crontab_weekdays(field: &str) -> Result<String> {
+    let day = |d: &str| d.parse::<usize>().ok().filter(|d| *d <= 7);
+    let items = field.split(',').map(|item| {
+        if !item.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(item.to_string());
+        }
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|s| *s > 0)),
+            None => (item, Some(1)),
+        };
+        let (from, until) = match range.split_once('-') {
+            Some((from, until)) => (day(from), day(until)),
+            // a start with a step runs until the end of the week:
+            None if item.contains('/') => (day(range), Some(7)),
+            None => (day(range), day(range)),
+        };
+        match (from, until, step) {
+            (Some(from), Some(until), Some(step)) if from <= until => Ok((from..=until)
+                .step_by(step)
+                .map(|d| WEEKDAYS[d % 7])
+                .collect::<Vec<_>>()
+                .join(",")),
+            _ => Err(anyhow!(
+                "invalid weekday '{}' - expected 0-7 (0 and 7 are sunday) or names like MON-FRI",
+                item
+            )),
+        }
+    });
+    Ok(items.collect::<Result<Vec<_>>>()?.join(","))
+}
+
+fn schedule(expr: &str) -> Result<cron::Schedule> {
+    let mut fields = expr
+        .split_whitespace()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if fields.len() == 5 {
+        fields.insert(0, "0".to_string());
+    }
+    if fields.len() == 6 {
+        fields[5] = crontab_weekdays(&fields[5])
+            .map_err(|err| anyhow!("invalid cron expression '{}': {}", expr, err))?;
+    }
+    cron::Schedule::from_str(&fields.join(" "))
+        .map_err(|err| anyhow!("invalid cron expression '{}': {}", expr, err))
+}
+
+impl Trigger {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            Trigger::Cron { expr, .. } => schedule(expr).map(|_| ()),
+            Trigger::Market { offset_minutes, .. } if offset_minutes.abs() > MAX_OFFSET_MINUTES => {
+                Err(anyhow!(
+                    "offset_minutes must be within ±{}",
+                    MAX_OFFSET_MINUTES
+                ))
+            }
+            Trigger::WhileOpen { every_minutes, .. } if *every_minutes == 0 => {
+                Err(anyhow!("every_minutes must be positive"))
+            }
+            _ => Ok(()),
+        }
+    }
+    pub fn timezone(&self) -> Tz {
+        match self {
+            Trigger::Cron { exchange: None, .. } => Tz::UTC,
+            Trigger::Cron {
+                exchange: Some(exchange),
+                ..
+            }
+            | Trigger::Market { exchange, .. }
+            | Trigger::WhileOpen { exchange, .. } => TradingCalendar::new(*exchange).timezone(),
+        }
+    }
+    // next_after returns the first time after at, at which the trigger fires:
+    pub fn next_after(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Trigger::Cron { expr, .. } => schedule(expr)
+                .ok()?
+                .after(&at.with_timezone(&self.timezone()))
+                .next()
+                .map(|t| t.with_timezone(&Utc)),
+            Trigger::Market {
+                exchange,
+                at: event,
+                offset_minutes,
+            } => sessions(*exchange, at)
+                .map(|s| match event {
+                    SessionEvent::Open => s.open,
+                    SessionEvent::Close => s.close,
+                })
+                .map(|t| t.with_timezone(&Utc) + Duration::minutes(*offset_minutes))
+                .find(|t| *t > at),
+            Trigger::WhileOpen {
+                exchange,
+                every_minutes,
+            } => {
+                let every = Duration::minutes(*every_minutes as i64);
+                sessions(*exchange, at).find_map(|s| {
+                    let (open, close) = (s.open.with_timezone(&Utc), s.close.with_timezone(&Utc));
+                    if at < open {
+                        return Some(open);
+                    }
+                    let n = (at - open).num_seconds() / every.num_seconds() + 1;
+                    let next = open + every * n as i32;
+                    (next < close).then_some(next)
+                })
+            }
+        }
+    }
+}
+
+// sessions iterates the sessions of the exchange, starting with yesterday's:
+fn sessions(exchange: Exchange, at: DateTime<Utc>) -> impl Iterator<Item = Session> {
+    let calendar = TradingCalendar::new(exchange);
+    let yesterday = at.with_timezone(&calendar.timezone()).date_naive() - Duration::days(1);
+    (0..=MAX_SEARCH_DAYS).filter_map(move |n| calendar.session(yesterday + Duration::days(n)))
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    // a snapshot of the movements until the day of the run (the request's until is ignored):
+    Movements { req: MovementsReq },
+    // the profits of a portfolio's securities until the day of the run:
+    PortfolioValuation { portfolio_id: String },
+}
+
+impl Action {
+    async fn run(&self, trading: &Trading, date: NaiveDate) -> Result<(serde_json::Value, usize)> {
+        let (result, count) = match self {
+            Action::Movements { req } => {
+                let mut req = req.clone();
+                req.until = date.to_string();
+                let movements = trading.movements(req).await?;
+                let count = movements.len();
+                (serde_json::to_value(movements)?, count)
+            }
+            Action::PortfolioValuation { portfolio_id } => {
+                let profits = trading
+                    .portfolio_valuation(portfolio_id.to_string(), date.to_string())
+                    .await?;
+                let count = profits.len();
+                (serde_json::to_value(profits)?, count)
+            }
+        };
+        Ok((result, count))
+    }
+}
+impl Validate for Action {
+    fn check(&self, v: &mut Validator) {
+        match self {
+            Action::Movements { req } => {
+                // until is set, when the job runs:
+                let mut req = req.clone();
+                req.until = utc_now().date_naive().to_string();
+                v.nested("req", &req);
+            }
+            Action::PortfolioValuation { portfolio_id } => {
+                v.not_blank("portfolio_id", portfolio_id)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobDef {
+    pub name: String,
+    pub trigger: Trigger,
+    pub action: Action,
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl Validate for JobDef {
+    fn check(&self, v: &mut Validator) {
+        // the name is part of the output path:
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        v.check(
+            "name",
+            !self.name.is_empty() && self.name.chars().all(valid),
+            "must consist of letters, digits, '-' and '_'",
+        );
+        if let Err(err) = self.trigger.validate() {
+            v.error("trigger", err.to_string());
+        }
+        v.nested("action", &self.action);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+    // a scheduled run is skipped, while the previous run of the job is still running:
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct JobRun {
+    pub id: u64,
+    pub job: String,
+    pub manual: bool,
+    pub status: RunStatus,
+    pub started: String,
+    #[serde(default)]
+    pub finished: Option<String>,
+    // the error, or a summary of the result:
+    #[serde(default)]
+    pub message: Option<String>,
+    // the file the result was written to:
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct JobInfo {
+    #[serde(flatten)]
+    pub job: JobDef,
+    pub running: bool,
+    pub next_run: Option<String>,
+    pub last_run: Option<JobRun>,
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    NotFound(String),
+    AlreadyRunning(String),
+    // a portfolio, which a job values, with the name of the job:
+    PortfolioInUse(String, String),
+}
+impl Error for JobError {}
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::NotFound(name) => write!(f, "unknown job '{}'", name),
+            JobError::AlreadyRunning(name) => write!(f, "job '{}' is already running", name),
+            JobError::PortfolioInUse(portfolio_id, name) => {
+                write!(
+                    f,
+                    "portfolio '{}' is valued by job '{}'",
+                    portfolio_id, name
+                )
+            }
+        }
+    }
+}
+
+// Store is the persisted state of the scheduler:
+#[derive(Default, Serialize, Deserialize)]
+struct Store {
+    jobs: Vec<JobDef>,
+    // the runs of all jobs, oldest first:
+    runs: Vec<JobRun>,
+    last_run_id: u64,
+}
+
+#[derive(Default)]
+struct State {
+    store: Store,
+    // counts the changes of the store, so that older snapshots never overwrite newer ones:
+    version: u64,
+    running: HashSet<String>,
+    // the next scheduled run of each job, that isn't paused:
+    next_runs: HashMap<String, DateTime<Utc>>,
+}
+
+impl State {
+    fn job(&self, name: &str) -> Result<&JobDef> {
+        self.store
+            .jobs
+            .iter()
+            .find(|j| j.name == name)
+            .ok_or_else(|| JobError::NotFound(name.to_string()).into())
+    }
+    fn info(&self, job: &JobDef) -> JobInfo {
+        JobInfo {
+            job: job.clone(),
+            running: self.running.contains(&job.name),
+            next_run: self.next_runs.get(&job.name).map(|t| timestamp(*t)),
+            last_run: self
+                .store
+                .runs
+                .iter()
+                .rev()
+                .find(|r| r.job == job.name)
+                .cloned(),
+        }
+    }
+    fn schedule(&mut self, job: &JobDef, now: DateTime<Utc>) {
+        match job.trigger.next_after(now).filter(|_| !job.paused) {
+            Some(next) => self.next_runs.insert(job.name.to_string(), next),
+            None => self.next_runs.remove(&job.name),
+        };
+    }
+    // snapshot serializes the store while the state is locked, it's saved after the lock is
+    // released:
+    fn snapshot(&mut self) -> Result<Snapshot> {
+        self.version += 1;
+        Ok(Snapshot {
+            version: self.version,
+            js: serde_json::to_vec_pretty(&self.store)?,
+        })
+    }
+    fn push_run(&mut self, run: JobRun) {
+        self.store.runs.push(run.clone());
+        let count = self.store.runs.iter().filter(|r| r.job == run.job).count();
+        if count > MAX_RUNS {
+            let oldest = self.store.runs.iter().position(|r| r.job == run.job);
+            if let Some(oldest) = oldest {
+                self.store.runs.remove(oldest);
+            }
+        }
+    }
+}
+
+struct Snapshot {
+    version: u64,
+    js: Vec<u8>,
+}
+
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub struct Scheduler {
+    trading: Arc<Trading>,
+    path: PathBuf,
+    output_dir: PathBuf,
+    state: Mutex<State>,
+    // the version of the last saved snapshot, which serializes the writes of the jobs file:
+    saved: tokio::sync::Mutex<u64>,
+    changed: Notify,
+}
+
+impl Scheduler {
+    pub fn new(trading: Arc<Trading>, envs: &Envs) -> Result<Scheduler> {
+        let path = PathBuf::from(&envs.jobs_file);
+        let mut store = persist::load::<Store>(&path, "jobs")?;
+        // runs, which were running when rustix stopped, never finished:
+        for run in store
+            .runs
+            .iter_mut()
+            .filter(|r| r.status == RunStatus::Running)
+        {
+            run.status = RunStatus::Failed;
+            run.message = Some("interrupted by a restart".to_string());
+        }
+        let mut state = State {
+            store,
+            ..Default::default()
+        };
+        let now = utc_now();
+        for job in state.store.jobs.clone().iter() {
+            state.schedule(job, now);
+        }
+        Ok(Scheduler {
+            trading,
+            path,
+            output_dir: PathBuf::from(&envs.jobs_output_dir),
+            state: Mutex::new(state),
+            saved: tokio::sync::Mutex::new(0),
+            changed: Notify::new(),
+        })
+    }
+
+    // start runs the jobs in the background, whenever they are due. Runs missed while rustix
+    // was down are not made up for.
+    pub fn start(self: &Arc<Self>) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = utc_now();
+                let (due, next) = {
+                    let mut state = scheduler.lock();
+                    let due = state
+                        .next_runs
+                        .iter()
+                        .filter(|(_, t)| **t <= now)
+                        .map(|(name, _)| name.to_string())
+                        .collect::<Vec<_>>();
+                    for name in due.iter() {
+                        if let Ok(job) = state.job(name).cloned() {
+                            state.schedule(&job, now);
+                        }
+                    }
+                    (due, state.next_runs.values().min().copied())
+                };
+                for name in due {
+                    if let Err(err) = scheduler.run(&name, false).await {
+                        println!("job {}: cannot start run: {:?}", name, err);
+                    }
+                }
+                let wait = next
+                    .map(|t| (t - now).to_std().unwrap_or_default().min(IDLE))
+                    .unwrap_or(IDLE);
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = scheduler.changed.notified() => {}
+                }
+            }
+        });
+    }
+
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let state = self.lock();
+        state.store.jobs.iter().map(|j| state.info(j)).collect()
+    }
+    pub fn job(&self, name: &str) -> Result<JobInfo> {
+        let state = self.lock();
+        Ok(state.info(state.job(name)?))
+    }
+    // upsert creates the job, or replaces the definition of the job with the same name:
+    pub async fn upsert(&self, job: JobDef) -> Result<JobInfo> {
+        job.validate()?;
+        let (info, snapshot) = {
+            let mut state = self.lock();
+            match state.store.jobs.iter_mut().find(|j| j.name == job.name) {
+                Some(existing) => *existing = job.clone(),
+                None => state.store.jobs.push(job.clone()),
+            }
+            state.schedule(&job, utc_now());
+            (state.info(&job), state.snapshot()?)
+        };
+        self.changed.notify_one();
+        self.save(snapshot).await?;
+        Ok(info)
+    }
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let snapshot = {
+            let mut state = self.lock();
+            state.job(name)?;
+            state.store.jobs.retain(|j| j.name != name);
+            state.store.runs.retain(|r| r.job != name);
+            state.next_runs.remove(name);
+            state.snapshot()?
+        };
+        self.save(snapshot).await
+    }
+    pub async fn set_paused(&self, name: &str, paused: bool) -> Result<JobInfo> {
+        let (info, snapshot) = {
+            let mut state = self.lock();
+            let mut job = state.job(name)?.clone();
+            job.paused = paused;
+            state.schedule(&job, utc_now());
+            if let Some(existing) = state.store.jobs.iter_mut().find(|j| j.name == name) {
+                *existing = job.clone();
+            }
+            (state.info(&job), state.snapshot()?)
+        };
+        self.changed.notify_one();
+        self.save(snapshot).await?;
+        Ok(info)
+    }
+    // check_unused fails, if a job values the portfolio, so it isn't deleted under the job:
+    pub fn check_unused(&self, portfolio_id: &str) -> Result<()> {
+        let state = self.lock();
+        let job = state.store.jobs.iter().find(|j| {
+            matches!(&j.action, Action::PortfolioValuation { portfolio_id: id } if id == portfolio_id)
+        });
+        match job {
+            Some(job) => {
+                Err(JobError::PortfolioInUse(portfolio_id.to_string(), job.name.to_string()).into())
+            }
+            None => Ok(()),
+        }
+    }
+    // runs returns the run history of the job, latest first:
+    pub fn runs(&self, name: &str) -> Result<Vec<JobRun>> {
+        let state = self.lock();
+        state.job(name)?;
+        Ok(state
+            .store
+            .runs
+            .iter()
+            .rev()
+            .filter(|r| r.job == name)
+            .cloned()
+            .collect())
+    }
+    // trigger runs the job now, even if it is paused. The run continues in the background.
+    pub async fn trigger(self: &Arc<Self>, name: &str) -> Result<JobRun> {
+        self.run(name, true).await
+    }
+
+    // run starts a run of the job. Runs of a job never overlap: triggering a running job fails,
+    // while a scheduled run is recorded as skipped.
+    async fn run(self: &Arc<Self>, name: &str, manual: bool) -> Result<JobRun> {
+        let run = self.begin(name, manual).await?;
+        if run.status == RunStatus::Running {
+            let scheduler = self.clone();
+            let started = run.clone();
+            tokio::spawn(async move { scheduler.execute(started).await });
+        }
+        Ok(run)
+    }
+    async fn begin(&self, name: &str, manual: bool) -> Result<JobRun> {
+        let (run, snapshot) = {
+            let mut state = self.lock();
+            state.job(name)?;
+            let running = state.running.contains(name);
+            if running && manual {
+                return Err(JobError::AlreadyRunning(name.to_string()).into());
+            }
+            state.store.last_run_id += 1;
+            let now = timestamp(utc_now());
+            let run = JobRun {
+                id: state.store.last_run_id,
+                job: name.to_string(),
+                manual,
+                status: match running {
+                    true => RunStatus::Skipped,
+                    false => RunStatus::Running,
+                },
+                started: now.to_string(),
+                finished: running.then_some(now),
+                message: running.then(|| "the previous run is still running".to_string()),
+                output: None,
+            };
+            if !running {
+                state.running.insert(name.to_string());
+            }
+            state.push_run(run.clone());
+            (run, state.snapshot())
+        };
+        // the run has started, it's saved (at the latest) with its result:
+        self.save_or_log(snapshot).await;
+        Ok(run)
+    }
+    async fn execute(&self, mut run: JobRun) {
+        let job = self.lock().job(&run.job).cloned();
+        let result = match job {
+            Ok(job) => self.output(&job, run.id).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok((output, count)) => {
+                run.status = RunStatus::Succeeded;
+                run.message = Some(format!("{} entries", count));
+                run.output = Some(output.to_string_lossy().to_string());
+            }
+            Err(err) => {
+                println!("job {}: run {} failed: {:?}", run.job, run.id, err);
+                run.status = RunStatus::Failed;
+                run.message = Some(format!("{:#}", err));
+            }
+        }
+        run.finished = Some(timestamp(utc_now()));
+
+        let snapshot = {
+            let mut state = self.lock();
+            state.running.remove(&run.job);
+            if let Some(existing) = state.store.runs.iter_mut().find(|r| r.id == run.id) {
+                *existing = run;
+            }
+            state.snapshot()
+        };
+        self.save_or_log(snapshot).await;
+    }
+    // output runs the job's action and writes its result to <output dir>/<job>/<date>_<run>.json:
+    async fn output(&self, job: &JobDef, run_id: u64) -> Result<(PathBuf, usize)> {
+        let date = utc_now()
+            .with_timezone(&job.trigger.timezone())
+            .date_naive();
+        let (result, count) = job.action.run(&self.trading, date).await?;
+        let dir = self.output_dir.join(&job.name);
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(format!("{}_{}.json", date, run_id));
+        tokio::fs::write(&path, serde_json::to_vec(&result)?).await?;
+        Ok((path, count))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // a panic while holding the lock leaves the state consistent, as it's only ever
+        // modified by assignments:
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+    // save writes the snapshot without holding the state's lock. Snapshots, which are older
+    // than the saved one, are skipped: the newer one contains their changes.
+    async fn save(&self, snapshot: Snapshot) -> Result<()> {
+        let mut saved = self.saved.lock().await;
+        if snapshot.version <= *saved {
+            return Ok(());
+        }
+        persist::save(&self.path, snapshot.js, "jobs").await?;
+        *saved = snapshot.version;
+        Ok(())
+    }
+    async fn save_or_log(&self, snapshot: Result<Snapshot>) {
+        if let Err(err) = async { self.save(snapshot?).await }.await {
+            println!("jobs: cannot save {}: {:?}", self.path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::{Period, SecurityType, SortBy};
+    use crate::mock::MockServer;
+    use crate::validate::ValidationError;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+    fn next(trigger: &Trigger, at: &str) -> String {
+        timestamp(trigger.next_after(utc(at)).unwrap())
+    }
+    fn market(at: SessionEvent, offset_minutes: i64) -> Trigger {
+        Trigger::Market {
+            exchange: Exchange::Nyse,
+            at,
+            offset_minutes,
+        }
+    }
+
+    #[test]
+    fn cron_triggers() {
+        let nightly = Trigger::Cron {
+            expr: "0 22 * * MON-FRI".to_string(),
+            exchange: Some(Exchange::Nyse),
+        };
+        assert!(nightly.validate().is_ok());
+        // 22:00 new york time, on weekdays:
+        assert_eq!(
+            next(&nightly, "2024-03-05T12:00:00Z"),
+            "2024-03-06T03:00:00Z"
+        );
+        assert_eq!(
+            next(&nightly, "2024-03-09T12:00:00Z"),
+            "2024-03-12T02:00:00Z"
+        );
+
+        // numeric weekdays count as in crontab, from 0 = sunday:
+        let numeric = Trigger::Cron {
+            expr: "0 22 * * 1-5".to_string(),
+            exchange: Some(Exchange::Nyse),
+        };
+        for at in ["2024-03-05T12:00:00Z", "2024-03-09T12:00:00Z"] {
+            assert_eq!(next(&numeric, at), next(&nightly, at));
+        }
+        let sundays = ["0", "7", "SUN"].map(|weekday| Trigger::Cron {
+            expr: format!("0 12 * * {}", weekday),
+            exchange: None,
+        });
+        for sunday in sundays.iter() {
+            assert_eq!(next(sunday, "2024-03-05T12:00:00Z"), "2024-03-10T12:00:00Z");
+        }
+        assert_eq!(
+            crontab_weekdays("*/2,5-7,1/3").unwrap(),
+            "*/2,FRI,SAT,SUN,MON,THU,SUN"
+        );
+        for invalid in ["8", "5-1", "1-5/0", "1-x"] {
+            assert!(crontab_weekdays(invalid).is_err(), "{}", invalid);
+        }
+
+        let utc_cron = Trigger::Cron {
+            expr: "30 0 */6 * * *".to_string(),
+            exchange: None,
+        };
+        assert_eq!(
+            next(&utc_cron, "2024-03-05T12:01:00Z"),
+            "2024-03-05T18:00:30Z"
+        );
+
+        let invalid = Trigger::Cron {
+            expr: "0 25 * * *".to_string(),
+            exchange: None,
+        };
+        assert!(invalid.validate().is_err());
+        assert!(invalid.next_after(utc("2024-03-05T12:00:00Z")).is_none());
+    }
+
+    #[test]
+    fn market_triggers() {
+        let after_open = market(SessionEvent::Open, 30);
+        assert_eq!(
+            next(&after_open, "2024-03-05T12:00:00Z"),
+            "2024-03-05T15:00:00Z"
+        );
+        assert_eq!(
+            next(&after_open, "2024-03-05T15:00:00Z"),
+            "2024-03-06T15:00:00Z"
+        );
+        // friday -> monday, daylight saving time starts on sunday:
+        assert_eq!(
+            next(&after_open, "2024-03-08T16:00:00Z"),
+            "2024-03-11T14:00:00Z"
+        );
+
+        let at_close = market(SessionEvent::Close, 0);
+        assert_eq!(
+            next(&at_close, "2024-03-05T12:00:00Z"),
+            "2024-03-05T21:00:00Z"
+        );
+        // thanksgiving is skipped, the day after closes early:
+        assert_eq!(
+            next(&at_close, "2024-11-27T22:00:00Z"),
+            "2024-11-29T18:00:00Z"
+        );
+
+        let before_open = market(SessionEvent::Open, -60);
+        assert_eq!(
+            next(&before_open, "2024-03-05T12:00:00Z"),
+            "2024-03-05T13:30:00Z"
+        );
+        assert!(market(SessionEvent::Open, 24 * 60).validate().is_err());
+    }
+
+    #[test]
+    fn while_open_triggers() {
+        let every = Trigger::WhileOpen {
+            exchange: Exchange::Nyse,
+            every_minutes: 5,
+        };
+        assert_eq!(next(&every, "2024-03-05T12:00:00Z"), "2024-03-05T14:30:00Z");
+        assert_eq!(next(&every, "2024-03-05T14:30:00Z"), "2024-03-05T14:35:00Z");
+        assert_eq!(next(&every, "2024-03-05T14:37:12Z"), "2024-03-05T14:40:00Z");
+        // the last run is before the close:
+        assert_eq!(next(&every, "2024-03-05T20:54:00Z"), "2024-03-05T20:55:00Z");
+        assert_eq!(next(&every, "2024-03-05T20:55:00Z"), "2024-03-06T14:30:00Z");
+        assert!(Trigger::WhileOpen {
+            exchange: Exchange::Nyse,
+            every_minutes: 0
+        }
+        .validate()
+        .is_err());
+    }
+
+    fn movements_job(name: &str) -> JobDef {
+        JobDef {
+            name: name.to_string(),
+            trigger: market(SessionEvent::Close, 60),
+            action: Action::Movements {
+                req: MovementsReq {
+                    security_type: SecurityType::Stock,
+                    sort_by: SortBy::Winner,
+                    until: "".to_string(),
+                    period: Period::Month,
+                    limit: 10,
+                    min_volume: 0,
+                    min_variance: 0.0,
+                    max_variance: 1000.0,
+                    without_stock_splits: None,
+                    adjusted: None,
+                    trading_days: None,
+                },
+            },
+            paused: false,
+        }
+    }
+
+    async fn finished(scheduler: &Scheduler, name: &str) -> JobRun {
+        for _ in 0..100 {
+            let run = scheduler.runs(name).unwrap().remove(0);
+            if run.status != RunStatus::Running {
+                return run;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("job {} did not finish", name);
+    }
+
+    #[tokio::test]
+    async fn runs() {
+        let server = MockServer::start().await.unwrap();
+        let envs = server.envs();
+        let trading = Arc::new(Trading::new(server.envs()).unwrap());
+        let scheduler = Arc::new(Scheduler::new(trading.clone(), &envs).unwrap());
+
+        let info = scheduler.upsert(movements_job("snapshot")).await.unwrap();
+        assert!(info.next_run.is_some());
+        let err = scheduler
+            .upsert(movements_job("no/slashes"))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast::<ValidationError>().unwrap().errors[0].field,
+            "name"
+        );
+
+        let run = scheduler.trigger("snapshot").await.unwrap();
+        assert_eq!(run.status, RunStatus::Running);
+        let run = finished(&scheduler, "snapshot").await;
+        assert_eq!(run.status, RunStatus::Succeeded, "{:?}", run.message);
+        assert_eq!(run.message.as_deref(), Some("3 entries"));
+        let output = std::fs::read_to_string(run.output.unwrap()).unwrap();
+        let output = serde_json::from_str::<serde_json::Value>(&output).unwrap();
+        assert_eq!(output.as_array().unwrap().len(), 3);
+
+        // runs don't overlap:
+        let first = scheduler.begin("snapshot", false).await.unwrap();
+        assert_eq!(first.status, RunStatus::Running);
+        let err = scheduler.trigger("snapshot").await.unwrap_err();
+        assert!(err.downcast_ref::<JobError>().is_some());
+        let second = scheduler.begin("snapshot", false).await.unwrap();
+        assert_eq!(second.status, RunStatus::Skipped);
+
+        let paused = scheduler.set_paused("snapshot", true).await.unwrap();
+        assert!(paused.job.paused);
+        assert!(paused.next_run.is_none());
+        assert!(scheduler.set_paused("unknown", true).await.is_err());
+
+        // the definitions and runs are persisted, unfinished runs failed:
+        let reloaded = Scheduler::new(trading, &envs).unwrap();
+        let jobs = reloaded.jobs();
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs[0].job.paused);
+        let runs = reloaded.runs("snapshot").unwrap();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[0].status, RunStatus::Skipped);
+        assert_eq!(runs[1].status, RunStatus::Failed);
+        assert_eq!(runs[2].status, RunStatus::Succeeded);
+
+        reloaded.delete("snapshot").await.unwrap();
+        assert!(reloaded.jobs().is_empty());
+        assert!(reloaded.runs("snapshot").is_err());
+    }
+
+    #[tokio::test]
+    async fn portfolio_valuation() {
+        let server = MockServer::start().await.unwrap();
+        let envs = server.envs();
+        let trading = Arc::new(Trading::new(server.envs()).unwrap());
+        let scheduler = Arc::new(Scheduler::new(trading, &envs).unwrap());
+        scheduler
+            .upsert(JobDef {
+                name: "valuation".to_string(),
+                trigger: market(SessionEvent::Close, 0),
+                action: Action::PortfolioValuation {
+                    portfolio_id: "unknown".to_string(),
+                },
+                paused: true,
+            })
+            .await
+            .unwrap();
+        scheduler.trigger("valuation").await.unwrap();
+        let run = finished(&scheduler, "valuation").await;
+        // an unknown portfolio has no securities:
+        assert_eq!(run.status, RunStatus::Succeeded, "{:?}", run.message);
+        assert_eq!(run.message.as_deref(), Some("0 entries"));
+    }
+}
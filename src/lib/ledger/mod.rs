@@ -0,0 +1,872 @@
+// ledger keeps the cash of portfolios: a ledger of deposits, withdrawals, buys, sells, fees,
+// dividends, interest and splits with a running balance. Buys must not exceed the cash, unless
+// the portfolio trades on margin. Profits are computed from the ledger, so they include the
+// commissions, fees and income, which the positions of the DataLoader know nothing about.
+// Amounts and prices are in the base currency of the portfolio, which is kept with its ledger, as
+// are the trading currencies of its securities. The ledgers are persisted to a json file, like
+// the jobs.
+use crate::envs::Envs;
+use crate::fx::{self, PortfolioCurrencies};
+use crate::persist;
+use crate::time::parse_date;
+use crate::trading::{BasicTicker, Trading};
+use crate::validate::{FieldError, Validate, ValidationError, Validator};
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use utoipa::ToSchema;
+
+// balances and share counts within this tolerance of zero are zero:
+const EPSILON: f64 = 1e-9;
+// fx rates are carried forward for up to this many days, e.g. over holidays:
+const FX_CARRIED_FORWARD_DAYS: i64 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    Deposit,
+    Withdrawal,
+    Buy,
+    Sell,
+    Fee,
+    Dividend,
+    Interest,
+    Split,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Transaction {
+    // the id is assigned by the ledger:
+    #[serde(default)]
+    pub id: u64,
+    pub kind: TransactionKind,
+    #[schema(example = "2024-01-31")]
+    pub date: String,
+    // the security of buys, sells, dividends and splits:
+    #[serde(default)]
+    pub ticker: Option<BasicTicker>,
+    // the shares of buys and sells:
+    #[serde(default)]
+    pub volume: Option<f64>,
+    // the price per share of buys and sells:
+    #[serde(default)]
+    pub price: Option<f64>,
+    // the cash of deposits, withdrawals, fees, dividends and interest:
+    #[serde(default)]
+    pub amount: Option<f64>,
+    // the commission of a buy or sell:
+    #[serde(default)]
+    pub fee: f64,
+    // the new shares per old share of a split, e.g. 4 for a 4:1 split:
+    #[serde(default)]
+    pub ratio: Option<f64>,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl Validate for Transaction {
+    fn check(&self, v: &mut Validator) {
+        v.date("date", &self.date);
+        v.non_negative("fee", self.fee);
+        let required = |v: &mut Validator, field: &str, value: Option<f64>| match value {
+            Some(value) => v.positive(field, value),
+            None => v.error(field, format!("is required for a {:?}", self.kind)),
+        };
+        let security = matches!(
+            self.kind,
+            TransactionKind::Buy
+                | TransactionKind::Sell
+                | TransactionKind::Dividend
+                | TransactionKind::Split
+        );
+        match &self.ticker {
+            Some(ticker) => v.nested("ticker", ticker),
+            None if security => v.error("ticker", format!("is required for a {:?}", self.kind)),
+            None => {}
+        }
+        match self.kind {
+            TransactionKind::Buy | TransactionKind::Sell => {
+                required(v, "volume", self.volume);
+                required(v, "price", self.price);
+            }
+            TransactionKind::Split => required(v, "ratio", self.ratio),
+            _ => required(v, "amount", self.amount),
+        }
+        // only trades deduct their commission from the cash:
+        let trade = matches!(self.kind, TransactionKind::Buy | TransactionKind::Sell);
+        if !trade && self.fee > 0.0 {
+            v.error(
+                "fee",
+                format!(
+                    "is only charged on buys and sells, not on a {:?} - book a fee instead",
+                    self.kind
+                ),
+            );
+        }
+    }
+}
+
+impl Transaction {
+    // cash_flow is the change of the cash balance:
+    pub fn cash_flow(&self) -> f64 {
+        let amount = self.amount.unwrap_or_default();
+        let value = self.volume.unwrap_or_default() * self.price.unwrap_or_default();
+        match self.kind {
+            TransactionKind::Deposit | TransactionKind::Dividend | TransactionKind::Interest => {
+                amount
+            }
+            TransactionKind::Withdrawal | TransactionKind::Fee => -amount,
+            TransactionKind::Buy => -value - self.fee,
+            TransactionKind::Sell => value - self.fee,
+            TransactionKind::Split => 0.0,
+        }
+    }
+    fn ticker(&self) -> &str {
+        self.ticker
+            .as_ref()
+            .map(|t| t.ticker.as_str())
+            .unwrap_or("")
+    }
+    fn describe(&self) -> String {
+        match self.kind {
+            TransactionKind::Buy | TransactionKind::Sell => format!(
+                "the {:?} of {} {} on {}",
+                self.kind,
+                self.volume.unwrap_or_default(),
+                self.ticker(),
+                self.date
+            ),
+            _ => format!("the {:?} on {}", self.kind, self.date),
+        }
+        .to_lowercase()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct LedgerEntry {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    pub cash_flow: f64,
+    // the cash after the transaction:
+    pub balance: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Ledger {
+    pub portfolio_id: String,
+    // with margin, buys may exceed the cash:
+    pub margin: bool,
+    pub base_currency: String,
+    pub balance: f64,
+    // the transactions by date, and in the order they were added on the same day:
+    pub entries: Vec<LedgerEntry>,
+}
+
+// sorted orders the transactions by date, those of the same day as they were added:
+fn sorted(transactions: &[Transaction]) -> Result<Vec<(NaiveDate, Transaction)>> {
+    let mut sorted = transactions
+        .iter()
+        .map(|t| Ok((parse_date(&t.date)?, t.clone())))
+        .collect::<Result<Vec<_>>>()?;
+    sorted.sort_by_key(|(date, t)| (*date, t.id));
+    Ok(sorted)
+}
+
+// entries replays the transactions with the running balance. It returns which transaction
+// overdraws the cash (without margin) or sells more shares than are held.
+pub fn entries(transactions: &[Transaction], margin: bool) -> Result<Vec<LedgerEntry>, String> {
+    let mut balance = 0.0;
+    let mut shares: HashMap<&str, f64> = HashMap::new();
+    let mut entries = vec![];
+    let sorted = sorted(transactions).map_err(|err| err.to_string())?;
+    for (_, t) in sorted.iter() {
+        balance += t.cash_flow();
+        let held = shares.entry(t.ticker()).or_default();
+        match t.kind {
+            TransactionKind::Buy => *held += t.volume.unwrap_or_default(),
+            TransactionKind::Sell => *held -= t.volume.unwrap_or_default(),
+            TransactionKind::Split => *held *= t.ratio.unwrap_or(1.0),
+            _ => {}
+        }
+        if *held < -EPSILON {
+            return Err(format!(
+                "{} exceeds the {} shares held",
+                t.describe(),
+                *held + t.volume.unwrap_or_default()
+            ));
+        }
+        let withdraws = matches!(t.kind, TransactionKind::Buy | TransactionKind::Withdrawal);
+        if withdraws && !margin && balance < -EPSILON {
+            return Err(format!(
+                "{} exceeds the cash of {} - only portfolios with margin may borrow",
+                t.describe(),
+                balance - t.cash_flow()
+            ));
+        }
+        entries.push(LedgerEntry {
+            transaction: t.clone(),
+            cash_flow: t.cash_flow(),
+            balance,
+        });
+    }
+    Ok(entries)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PositionProfit {
+    pub ticker: String,
+    pub volume: f64,
+    // the cost of the held shares at their average price, with commissions:
+    pub cost: f64,
+    pub price: f64,
+    pub market_value: f64,
+    // the gains of sales over the average cost, after commissions:
+    pub realized: f64,
+    pub unrealized: f64,
+    pub dividends: f64,
+    pub commissions: f64,
+    // realized + unrealized + dividends:
+    pub profit: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LedgerProfits {
+    pub until: String,
+    pub deposits: f64,
+    pub withdrawals: f64,
+    // the fee transactions and the commissions of buys and sells:
+    pub fees: f64,
+    pub dividends: f64,
+    pub interest: f64,
+    pub cash: f64,
+    pub market_value: f64,
+    // cash + market value:
+    pub equity: f64,
+    // equity - deposits + withdrawals, i.e. after fees and with income:
+    pub profit: f64,
+    pub positions: Vec<PositionProfit>,
+}
+
+// held returns the shares of the tickers, which are held at the date:
+pub fn held(transactions: &[Transaction], date: NaiveDate) -> Result<HashMap<String, f64>> {
+    let mut shares: HashMap<String, f64> = HashMap::new();
+    for (_, t) in sorted(transactions)?.iter().take_while(|(d, _)| *d <= date) {
+        let held = shares.entry(t.ticker().to_string()).or_default();
+        match t.kind {
+            TransactionKind::Buy => *held += t.volume.unwrap_or_default(),
+            TransactionKind::Sell => *held -= t.volume.unwrap_or_default(),
+            TransactionKind::Split => *held *= t.ratio.unwrap_or(1.0),
+            _ => {}
+        }
+    }
+    shares.retain(|ticker, volume| !ticker.is_empty() && volume.abs() > EPSILON);
+    Ok(shares)
+}
+
+// profits values the ledger until the date with the prices of the held tickers. Positions are
+// valued at their average cost, so partial sales realize the gain over the average price.
+pub fn profits(
+    transactions: &[Transaction],
+    prices: &HashMap<String, f64>,
+    until: NaiveDate,
+) -> Result<LedgerProfits> {
+    let mut profits = LedgerProfits {
+        until: until.to_string(),
+        ..Default::default()
+    };
+    let mut positions: BTreeMap<String, PositionProfit> = BTreeMap::new();
+    for (date, t) in sorted(transactions)? {
+        if date > until {
+            break;
+        }
+        profits.cash += t.cash_flow();
+        let amount = t.amount.unwrap_or_default();
+        match t.kind {
+            TransactionKind::Deposit => profits.deposits += amount,
+            TransactionKind::Withdrawal => profits.withdrawals += amount,
+            TransactionKind::Fee => profits.fees += amount,
+            TransactionKind::Interest => profits.interest += amount,
+            _ => {}
+        }
+        if t.ticker.is_none() {
+            continue;
+        }
+        let position = positions
+            .entry(t.ticker().to_string())
+            .or_insert_with(|| PositionProfit {
+                ticker: t.ticker().to_string(),
+                ..Default::default()
+            });
+        let volume = t.volume.unwrap_or_default();
+        let value = volume * t.price.unwrap_or_default();
+        position.commissions += t.fee;
+        match t.kind {
+            TransactionKind::Buy => {
+                position.volume += volume;
+                position.cost += value + t.fee;
+            }
+            TransactionKind::Sell if position.volume > EPSILON => {
+                let cost = position.cost * volume.min(position.volume) / position.volume;
+                position.realized += value - t.fee - cost;
+                position.cost -= cost;
+                position.volume -= volume;
+            }
+            TransactionKind::Dividend => position.dividends += amount,
+            TransactionKind::Split => position.volume *= t.ratio.unwrap_or(1.0),
+            _ => {}
+        }
+    }
+    for position in positions.values_mut() {
+        profits.fees += position.commissions;
+        profits.dividends += position.dividends;
+        if position.volume.abs() <= EPSILON {
+            position.volume = 0.0;
+        }
+        if position.volume != 0.0 {
+            position.price = *prices
+                .get(&position.ticker)
+                .with_context(|| format!("no price of {}", position.ticker))?;
+        }
+        position.market_value = position.volume * position.price;
+        position.unrealized = position.market_value - position.cost;
+        position.profit = position.realized + position.unrealized + position.dividends;
+        profits.market_value += position.market_value;
+    }
+    profits.equity = profits.cash + profits.market_value;
+    profits.profit = profits.equity - profits.deposits + profits.withdrawals;
+    profits.positions = positions.into_values().collect();
+    Ok(profits)
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TransactionReq {
+    pub portfolio_id: String,
+    pub transaction: Transaction,
+}
+impl Validate for TransactionReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.nested("transaction", &self.transaction);
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct TransactionId {
+    pub portfolio_id: String,
+    pub id: u64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MarginReq {
+    pub portfolio_id: String,
+    pub margin: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LedgerProfitsReq {
+    pub portfolio_id: String,
+    #[schema(example = "2024-01-31")]
+    pub until: String,
+}
+impl Validate for LedgerProfitsReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.date("until", &self.until);
+    }
+}
+
+#[derive(Debug)]
+pub enum LedgerError {
+    UnknownTransaction(u64),
+}
+impl Error for LedgerError {}
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::UnknownTransaction(id) => write!(f, "unknown transaction {}", id),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Account {
+    margin: bool,
+    transactions: Vec<Transaction>,
+    // empty for the default currency:
+    #[serde(default)]
+    base_currency: String,
+    #[serde(default)]
+    currencies: BTreeMap<String, String>,
+}
+impl Account {
+    fn base_currency(&self) -> String {
+        match self.base_currency.is_empty() {
+            true => fx::DEFAULT_CURRENCY.to_string(),
+            false => self.base_currency.to_string(),
+        }
+    }
+}
+
+// Store is the persisted state of the ledgers:
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Store {
+    accounts: BTreeMap<String, Account>,
+    last_transaction_id: u64,
+}
+
+pub struct Ledgers {
+    trading: Arc<Trading>,
+    path: PathBuf,
+    // the saved store, which is replaced after an update is saved:
+    store: Mutex<Store>,
+    // serializes the updates, so none of them is lost:
+    updating: tokio::sync::Mutex<()>,
+}
+
+impl Ledgers {
+    pub fn new(trading: Arc<Trading>, envs: &Envs) -> Result<Ledgers> {
+        let path = PathBuf::from(&envs.ledger_file);
+        let store = persist::load::<Store>(&path, "ledger")?;
+        Ok(Ledgers {
+            trading,
+            path,
+            store: Mutex::new(store),
+            updating: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub async fn ledger(&self, portfolio_id: &str) -> Result<Ledger> {
+        // unknown portfolios have no transactions, but should not look like empty ones:
+        self.trading.portfolio(portfolio_id.to_string()).await?;
+        let account = self.account(portfolio_id);
+        let entries = entries(&account.transactions, account.margin).unwrap_or_default();
+        Ok(ledger(portfolio_id, &account, entries))
+    }
+    // add books a transaction, if the ledger stays covered with it:
+    pub async fn add(&self, req: TransactionReq) -> Result<Ledger> {
+        self.trading.portfolio(req.portfolio_id.to_string()).await?;
+        self.update(&req.portfolio_id, "transaction", |store, account| {
+            store.last_transaction_id += 1;
+            let mut transaction = req.transaction;
+            transaction.id = store.last_transaction_id;
+            account.transactions.push(transaction);
+            Ok(())
+        })
+        .await
+    }
+    // delete removes a transaction, unless later transactions depend on it:
+    pub async fn delete(&self, req: TransactionId) -> Result<Ledger> {
+        self.trading.portfolio(req.portfolio_id.to_string()).await?;
+        self.update(&req.portfolio_id, "id", |_, account| {
+            let i = account.transactions.iter().position(|t| t.id == req.id);
+            let i = i.ok_or(LedgerError::UnknownTransaction(req.id))?;
+            account.transactions.remove(i);
+            Ok(())
+        })
+        .await
+    }
+    pub async fn set_margin(&self, req: MarginReq) -> Result<Ledger> {
+        self.trading.portfolio(req.portfolio_id.to_string()).await?;
+        self.update(&req.portfolio_id, "margin", |_, account| {
+            account.margin = req.margin;
+            Ok(())
+        })
+        .await
+    }
+    pub async fn currencies(&self, portfolio_id: &str) -> Result<PortfolioCurrencies> {
+        self.trading.portfolio(portfolio_id.to_string()).await?;
+        let account = self.account(portfolio_id);
+        Ok(PortfolioCurrencies {
+            portfolio_id: portfolio_id.to_string(),
+            base_currency: account.base_currency(),
+            currencies: account.currencies,
+        })
+    }
+    // set_currencies changes the currencies of a portfolio. The booked amounts are in the base
+    // currency, so it cannot change, once the ledger has transactions.
+    pub async fn set_currencies(&self, req: PortfolioCurrencies) -> Result<PortfolioCurrencies> {
+        self.trading.portfolio(req.portfolio_id.to_string()).await?;
+        let req = req.normalized();
+        self.update(&req.portfolio_id, "base_currency", |_, account| {
+            let base = account.base_currency();
+            if base != req.base_currency && !account.transactions.is_empty() {
+                return Err(ValidationError {
+                    errors: vec![FieldError {
+                        field: "base_currency".to_string(),
+                        message: format!(
+                            "cannot change from {}, while the ledger has transactions in it",
+                            base
+                        ),
+                    }],
+                }
+                .into());
+            }
+            account.base_currency = req.base_currency.to_string();
+            account.currencies = req.currencies.clone();
+            Ok(())
+        })
+        .await?;
+        Ok(req)
+    }
+    // profits values the ledger with the closes on or before until, converted into the base
+    // currency. Only the tickers still held need a price, sold ones may be delisted by now.
+    pub async fn profits(&self, req: LedgerProfitsReq) -> Result<LedgerProfits> {
+        let until = parse_date(&req.until)?;
+        self.trading.portfolio(req.portfolio_id.to_string()).await?;
+        let account = self.account(&req.portfolio_id);
+        let held = held(&account.transactions, until)?;
+        let tickers = account
+            .transactions
+            .iter()
+            .filter_map(|t| t.ticker.as_ref())
+            .filter(|t| held.contains_key(&t.ticker))
+            .map(|t| (t.ticker.to_string(), t.clone()))
+            .collect::<HashMap<_, _>>();
+        let base = account.base_currency();
+        let start = until - chrono::Duration::days(FX_CARRIED_FORWARD_DAYS);
+        let mut prices = HashMap::new();
+        for (name, ticker) in tickers {
+            let currency = self.trading.currency(&ticker, &account.currencies).await?;
+            let rate = self
+                .trading
+                .fx_rates(&currency, &base, start, until)
+                .await?
+                .at(until)
+                .with_context(|| format!("no fx rate of {} in {} on {}", currency, base, until))?;
+            prices.insert(name, self.trading.close(ticker, until).await? * rate);
+        }
+        profits(&account.transactions, &prices, until)
+    }
+    // remove drops the account of a deleted portfolio:
+    pub async fn remove(&self, portfolio_id: &str) -> Result<()> {
+        let _updating = self.updating.lock().await;
+        let mut store = self.lock().clone();
+        if store.accounts.remove(portfolio_id).is_none() {
+            return Ok(());
+        }
+        persist::save(&self.path, serde_json::to_vec_pretty(&store)?, "ledger").await?;
+        *self.lock() = store;
+        Ok(())
+    }
+
+    fn account(&self, portfolio_id: &str) -> Account {
+        self.lock()
+            .accounts
+            .get(portfolio_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+    // update changes a copy of the store, which replaces the store once it's saved, if the
+    // account's ledger is valid. Otherwise the change is rejected as an invalid value of the field.
+    async fn update(
+        &self,
+        portfolio_id: &str,
+        field: &str,
+        change: impl FnOnce(&mut Store, &mut Account) -> Result<()>,
+    ) -> Result<Ledger> {
+        let _updating = self.updating.lock().await;
+        let mut store = self.lock().clone();
+        let mut account = store
+            .accounts
+            .get(portfolio_id)
+            .cloned()
+            .unwrap_or_default();
+        change(&mut store, &mut account)?;
+        let entries = match entries(&account.transactions, account.margin) {
+            Ok(entries) => entries,
+            Err(message) => {
+                return Err(ValidationError {
+                    errors: vec![FieldError {
+                        field: field.to_string(),
+                        message,
+                    }],
+                }
+                .into());
+            }
+        };
+        let ledger = ledger(portfolio_id, &account, entries);
+        store.accounts.insert(portfolio_id.to_string(), account);
+        persist::save(&self.path, serde_json::to_vec_pretty(&store)?, "ledger").await?;
+        *self.lock() = store;
+        Ok(ledger)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Store> {
+        self.store.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+fn ledger(portfolio_id: &str, account: &Account, entries: Vec<LedgerEntry>) -> Ledger {
+    Ledger {
+        portfolio_id: portfolio_id.to_string(),
+        margin: account.margin,
+        base_currency: account.base_currency(),
+        balance: entries.last().map(|e| e.balance).unwrap_or_default(),
+        entries,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::SecurityType;
+    use crate::mock::MockServer;
+
+    fn cash(id: u64, kind: TransactionKind, date: &str, amount: f64) -> Transaction {
+        Transaction {
+            id,
+            kind,
+            date: date.to_string(),
+            ticker: None,
+            volume: None,
+            price: None,
+            amount: Some(amount),
+            fee: 0.0,
+            ratio: None,
+            note: "".to_string(),
+        }
+    }
+    fn trade(id: u64, kind: TransactionKind, date: &str, volume: f64, price: f64) -> Transaction {
+        Transaction {
+            ticker: Some(BasicTicker {
+                ticker: "AAPL".to_string(),
+                security_type: SecurityType::Stock,
+            }),
+            volume: Some(volume),
+            price: Some(price),
+            amount: None,
+            fee: 1.0,
+            ..cash(id, kind, date, 0.0)
+        }
+    }
+
+    #[test]
+    fn balances() {
+        use TransactionKind::*;
+        let mut transactions = vec![
+            cash(1, Deposit, "2024-01-02", 1000.0),
+            trade(2, Buy, "2024-01-03", 5.0, 150.0),
+            cash(3, Fee, "2024-01-31", 10.0),
+        ];
+        let balances = |transactions: &[Transaction], margin| {
+            entries(transactions, margin).map(|e| e.iter().map(|e| e.balance).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            balances(&transactions, false).unwrap(),
+            vec![1000.0, 249.0, 239.0]
+        );
+
+        // a backdated buy is booked before the fee:
+        transactions.push(trade(4, Buy, "2024-01-10", 2.0, 150.0));
+        let err = balances(&transactions, false).unwrap_err();
+        assert_eq!(
+            err,
+            "the buy of 2 aapl on 2024-01-10 exceeds the cash of 249 - only portfolios with margin may borrow"
+        );
+        assert_eq!(
+            balances(&transactions, true).unwrap(),
+            vec![1000.0, 249.0, -52.0, -62.0]
+        );
+
+        // the split doubles the shares, so 10 can be sold:
+        transactions.pop();
+        transactions.push(Transaction {
+            ratio: Some(2.0),
+            ..trade(5, Split, "2024-01-15", 0.0, 0.0)
+        });
+        transactions.push(trade(6, Sell, "2024-01-20", 10.0, 80.0));
+        assert_eq!(
+            balances(&transactions, false).unwrap(),
+            vec![1000.0, 249.0, 249.0, 1048.0, 1038.0]
+        );
+        transactions.push(trade(7, Sell, "2024-01-21", 1.0, 80.0));
+        assert!(balances(&transactions, false)
+            .unwrap_err()
+            .contains("exceeds the 0 shares held"));
+        transactions.pop();
+        let withdrawal = cash(8, Withdrawal, "2024-01-04", 300.0);
+        assert!(balances(&[transactions.clone(), vec![withdrawal]].concat(), false).is_err());
+    }
+
+    #[test]
+    fn profits_with_fees_and_income() {
+        use TransactionKind::*;
+        let transactions = vec![
+            cash(1, Deposit, "2024-01-02", 2000.0),
+            trade(2, Buy, "2024-01-03", 10.0, 100.0),
+            trade(3, Buy, "2024-01-04", 10.0, 120.0),
+            trade(4, Sell, "2024-01-10", 5.0, 130.0),
+            Transaction {
+                amount: Some(4.0),
+                volume: None,
+                price: None,
+                fee: 0.0,
+                ..trade(5, Dividend, "2024-01-12", 0.0, 0.0)
+            },
+            cash(6, Interest, "2024-01-31", 3.0),
+            cash(7, Fee, "2024-01-31", 5.0),
+            cash(8, Withdrawal, "2024-02-01", 100.0),
+        ];
+        let prices = HashMap::from([("AAPL".to_string(), 125.0)]);
+        let profits = profits(&transactions, &prices, profits_until()).unwrap();
+        let aapl = &profits.positions[0];
+        // the average cost is 110.1 per share with the commissions:
+        assert_eq!(aapl.volume, 15.0);
+        assert!((aapl.cost - 1651.5).abs() < 1e-9);
+        assert!((aapl.realized - (650.0 - 1.0 - 550.5)).abs() < 1e-9);
+        assert!((aapl.unrealized - (1875.0 - 1651.5)).abs() < 1e-9);
+        assert_eq!(aapl.dividends, 4.0);
+        assert_eq!(aapl.commissions, 3.0);
+
+        assert_eq!(profits.fees, 8.0);
+        assert_eq!(profits.withdrawals, 0.0);
+        assert!((profits.cash - (2000.0 - 1001.0 - 1201.0 + 649.0 + 4.0 + 3.0 - 5.0)).abs() < 1e-9);
+        assert_eq!(profits.equity, profits.cash + profits.market_value);
+        // the profit of the positions, with interest and after fees, is the gain of equity:
+        let positions = aapl.profit + profits.interest - 5.0;
+        assert!((profits.profit - positions).abs() < 1e-9);
+
+        let missing = super::profits(&transactions, &HashMap::new(), profits_until());
+        assert_eq!(missing.unwrap_err().to_string(), "no price of AAPL");
+
+        // sold positions need no price:
+        let held = |until: &str| held(&transactions, until.parse().unwrap()).unwrap();
+        assert_eq!(
+            held("2024-01-31"),
+            HashMap::from([("AAPL".to_string(), 15.0)])
+        );
+        assert!(held("2024-01-02").is_empty());
+        let sold = [
+            transactions.clone(),
+            vec![trade(9, Sell, "2024-01-20", 15.0, 90.0)],
+        ]
+        .concat();
+        let profits = super::profits(&sold, &HashMap::new(), profits_until()).unwrap();
+        assert_eq!(profits.market_value, 0.0);
+        assert!(super::held(&sold, profits_until()).unwrap().is_empty());
+
+        // a commission is only deducted from trades:
+        let dividend = Transaction {
+            fee: 2.0,
+            ..transactions[4].clone()
+        };
+        let err = dividend.validate().unwrap_err();
+        assert_eq!(
+            err.downcast::<ValidationError>().unwrap().errors[0].field,
+            "fee"
+        );
+    }
+    fn profits_until() -> NaiveDate {
+        "2024-01-31".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn persisted() {
+        use TransactionKind::*;
+        let server = MockServer::start().await.unwrap();
+        let envs = server.envs();
+        let trading = Arc::new(Trading::new(server.envs()).unwrap());
+        let ledgers = Ledgers::new(trading.clone(), &envs).unwrap();
+
+        let req = |transaction| TransactionReq {
+            portfolio_id: "1".to_string(),
+            transaction,
+        };
+        ledgers
+            .add(req(cash(0, Deposit, "2024-01-02", 1000.0)))
+            .await
+            .unwrap();
+        let ledger = ledgers
+            .add(req(trade(0, Buy, "2024-01-03", 5.0, 150.0)))
+            .await
+            .unwrap();
+        assert_eq!(ledger.balance, 249.0);
+        let ids = ledger
+            .entries
+            .iter()
+            .map(|e| e.transaction.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2]);
+
+        // deleting the deposit would overdraw the buy:
+        let err = ledgers
+            .delete(TransactionId {
+                portfolio_id: "1".to_string(),
+                id: 1,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ValidationError>().unwrap().errors[0].field,
+            "id"
+        );
+        let err = ledgers
+            .delete(TransactionId {
+                portfolio_id: "1".to_string(),
+                id: 7,
+            })
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<LedgerError>().is_some());
+        let unknown = ledgers.ledger("42").await;
+        assert!(unknown.is_err());
+
+        let reloaded = Ledgers::new(trading, &envs).unwrap();
+        let ledger = reloaded.ledger("1").await.unwrap();
+        assert_eq!(ledger.entries.len(), 2);
+        assert!(!ledger.margin);
+        let ledger = reloaded
+            .add(req(cash(0, Fee, "2024-01-04", 1.0)))
+            .await
+            .unwrap();
+        assert_eq!(ledger.entries[2].transaction.id, 3);
+
+        // the amounts are booked in usd, only the currencies of the securities may change:
+        let currencies = |base: &str| PortfolioCurrencies {
+            portfolio_id: "1".to_string(),
+            base_currency: base.to_string(),
+            currencies: BTreeMap::from([("SAP".to_string(), "eur".to_string())]),
+        };
+        let err = reloaded
+            .set_currencies(currencies("EUR"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ValidationError>().unwrap().errors[0].field,
+            "base_currency"
+        );
+        assert_eq!(reloaded.currencies("1").await.unwrap().base_currency, "USD");
+        let changed = reloaded.set_currencies(currencies("usd")).await.unwrap();
+        assert_eq!(changed.currencies["SAP"], "EUR");
+    }
+
+    #[tokio::test]
+    async fn unsaved_changes_are_dropped() {
+        use TransactionKind::*;
+        let server = MockServer::start().await.unwrap();
+        let mut envs = server.envs();
+        // the ledger file cannot be written, while a file is in the place of its directory:
+        let dir = PathBuf::from(&envs.ledger_file).with_extension("dir");
+        envs.ledger_file = dir.join("ledger.json").to_string_lossy().to_string();
+        let trading = Arc::new(Trading::new(server.envs()).unwrap());
+        let ledgers = Ledgers::new(trading, &envs).unwrap();
+        std::fs::create_dir_all(dir.parent().unwrap()).unwrap();
+        std::fs::write(&dir, "").unwrap();
+
+        let deposit = || TransactionReq {
+            portfolio_id: "1".to_string(),
+            transaction: cash(0, Deposit, "2024-01-02", 1000.0),
+        };
+        assert!(ledgers.add(deposit()).await.is_err());
+        assert!(ledgers.ledger("1").await.unwrap().entries.is_empty());
+        std::fs::remove_file(&dir).unwrap();
+        let ledger = ledgers.add(deposit()).await.unwrap();
+        assert_eq!(ledger.entries.len(), 1);
+        assert_eq!(ledger.entries[0].transaction.id, 1);
+    }
+}
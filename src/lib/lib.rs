@@ -1,5 +1,25 @@
+pub mod analytics;
+pub mod calendar;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod columnar;
+pub mod enums;
 pub mod envs;
 pub mod error;
+pub mod fx;
+pub mod indicators;
+pub mod jobs;
+pub mod ledger;
+pub mod lots;
+pub mod market;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod period;
+pub mod persist;
 pub mod proto;
+pub mod resample;
+pub mod splits;
+pub mod stream;
 pub mod time;
 pub mod trading;
+pub mod validate;
@@ -0,0 +1,547 @@
+// lots does the tax-lot accounting of portfolios. Every purchase is a lot, sells are matched to
+// the open lots of a ticker by a method (fifo, lifo, hifo or specific identification), and the
+// gains are reported as realized or unrealized, short- or long-term. Stock splits change the
+// shares and the cost basis per share of a lot, but not its total cost.
+// The DataLoader tells the securities of a day only apart by their order, so the sales of lots
+// are kept by rustix instead of splitting its securities. They are persisted to a json file,
+// like the ledgers.
+use crate::analytics::Split;
+use crate::envs::Envs;
+use crate::persist;
+use crate::trading::{LotReportReq, LotSellReq, PortfolioSecurity, Trading};
+use crate::validate::{FieldError, ValidationError};
+use anyhow::Result;
+use chrono::{Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+// sold volumes within this tolerance of a lot's volume close the lot:
+pub const VOLUME_EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LotMethod {
+    // first in, first out:
+    #[default]
+    Fifo,
+    // last in, first out:
+    Lifo,
+    // highest cost first, which realizes the smallest gains:
+    Hifo,
+    // the lots are given by their ids:
+    Specific,
+}
+
+// Term is the holding period of a lot, which is long-term if it was held for more than a year:
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Term {
+    ShortTerm,
+    LongTerm,
+}
+
+pub fn term(purchase_date: NaiveDate, date: NaiveDate) -> Term {
+    match purchase_date
+        .checked_add_months(Months::new(12))
+        .is_some_and(|anniversary| date > anniversary)
+    {
+        true => Term::LongTerm,
+        false => Term::ShortTerm,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lot {
+    pub id: String,
+    pub ticker: String,
+    pub purchase_date: NaiveDate,
+    pub volume: f64,
+    // the purchase price per share:
+    pub cost_basis: f64,
+}
+
+impl Lot {
+    // as_of returns the lot after the splits between its purchase and date:
+    pub fn as_of(&self, splits: &[Split], date: NaiveDate) -> Lot {
+        let ratio = splits
+            .iter()
+            .filter(|s| s.ticker == self.ticker)
+            .filter(|s| self.purchase_date < s.date && s.date <= date)
+            .map(|s| s.ratio)
+            .product::<f64>();
+        Lot {
+            volume: self.volume * ratio,
+            cost_basis: self.cost_basis / ratio,
+            ..self.clone()
+        }
+    }
+}
+
+// Allocation is the part of a lot, that is sold:
+#[derive(Clone, Debug, PartialEq)]
+pub struct Allocation {
+    pub lot_id: String,
+    pub volume: f64,
+    // whether the lot is sold completely:
+    pub closes: bool,
+}
+
+fn invalid(field: &str, message: String) -> anyhow::Error {
+    ValidationError {
+        errors: vec![FieldError {
+            field: field.to_string(),
+            message,
+        }],
+    }
+    .into()
+}
+
+// allocate matches the sold volume to the open lots of a ticker. The lots are as of the sale,
+// so their volumes are in the same (split adjusted) shares as the sold volume.
+pub fn allocate(
+    lots: &[Lot],
+    volume: f64,
+    method: LotMethod,
+    lot_ids: &[String],
+) -> Result<Vec<Allocation>> {
+    let mut ordered = lots.iter().collect::<Vec<_>>();
+    match method {
+        LotMethod::Fifo => ordered.sort_by_key(|l| l.purchase_date),
+        LotMethod::Lifo => ordered.sort_by_key(|l| std::cmp::Reverse(l.purchase_date)),
+        LotMethod::Hifo => ordered.sort_by(|a, b| {
+            b.cost_basis
+                .total_cmp(&a.cost_basis)
+                .then(a.purchase_date.cmp(&b.purchase_date))
+        }),
+        LotMethod::Specific => {
+            ordered = lot_ids
+                .iter()
+                .enumerate()
+                .map(|(i, id)| {
+                    let field = format!("lot_ids[{}]", i);
+                    // a lot, which is listed twice, would be sold twice:
+                    if lot_ids[..i].contains(id) {
+                        return Err(invalid(&field, "is listed twice".to_string()));
+                    }
+                    lots.iter()
+                        .find(|l| &l.id == id)
+                        .ok_or_else(|| invalid(&field, format!("no open lot {}", id)))
+                })
+                .collect::<Result<_>>()?;
+        }
+    }
+    let held = ordered.iter().map(|l| l.volume).sum::<f64>();
+    if volume > held + VOLUME_EPSILON {
+        return Err(invalid(
+            "volume",
+            format!("cannot sell {} - only {} held in the lots", volume, held),
+        ));
+    }
+    let mut remaining = volume;
+    let mut allocations = vec![];
+    for lot in ordered {
+        if remaining <= VOLUME_EPSILON {
+            break;
+        }
+        let sold = remaining.min(lot.volume);
+        remaining -= sold;
+        allocations.push(Allocation {
+            lot_id: lot.id.to_string(),
+            volume: sold,
+            closes: lot.volume - sold <= VOLUME_EPSILON,
+        });
+    }
+    Ok(allocations)
+}
+
+// LotGain is the gain of (a part of) a lot, realized by a sale or unrealized at a valuation:
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LotGain {
+    pub lot_id: String,
+    pub ticker: String,
+    pub purchase_date: String,
+    // the date of the sale or valuation:
+    pub date: String,
+    pub volume: f64,
+    pub cost_basis: f64,
+    pub price: f64,
+    pub gain: f64,
+    pub term: Term,
+}
+
+// gain values a volume of a lot (as of the date) at the price:
+pub fn gain(lot: &Lot, volume: f64, date: NaiveDate, price: f64) -> LotGain {
+    LotGain {
+        lot_id: lot.id.to_string(),
+        ticker: lot.ticker.to_string(),
+        purchase_date: lot.purchase_date.to_string(),
+        date: date.to_string(),
+        volume,
+        cost_basis: lot.cost_basis,
+        price,
+        gain: (price - lot.cost_basis) * volume,
+        term: term(lot.purchase_date, date),
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GainSummary {
+    pub realized_short_term: f64,
+    pub realized_long_term: f64,
+    pub unrealized_short_term: f64,
+    pub unrealized_long_term: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LotReport {
+    pub realized: Vec<LotGain>,
+    pub unrealized: Vec<LotGain>,
+    pub summary: GainSummary,
+}
+
+pub fn report(realized: Vec<LotGain>, unrealized: Vec<LotGain>) -> LotReport {
+    let mut summary = GainSummary::default();
+    for g in realized.iter() {
+        match g.term {
+            Term::ShortTerm => summary.realized_short_term += g.gain,
+            Term::LongTerm => summary.realized_long_term += g.gain,
+        }
+    }
+    for g in unrealized.iter() {
+        match g.term {
+            Term::ShortTerm => summary.unrealized_short_term += g.gain,
+            Term::LongTerm => summary.unrealized_long_term += g.gain,
+        }
+    }
+    LotReport {
+        realized,
+        unrealized,
+        summary,
+    }
+}
+
+// lot_id names a lot by its ticker and purchase date, the n-th lot of the same day gets a suffix:
+pub fn lot_id(ticker: &str, purchase_date: &str, n: usize) -> String {
+    match n {
+        0 => format!("{}:{}", ticker, purchase_date),
+        n => format!("{}:{}#{}", ticker, purchase_date, n + 1),
+    }
+}
+
+// LotSale is the sale of (a part of) an open security, the volume is in the shares of its
+// purchase. The security is the n-th open one with its type, ticker, purchase date and volume, so
+// the sale stays with it, when other securities are bought or sold:
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LotSale {
+    pub security_type: i32,
+    pub ticker: String,
+    pub purchase_date: String,
+    pub security_volume: f64,
+    pub n: usize,
+    pub volume: f64,
+    pub date: String,
+}
+
+impl LotSale {
+    // is_of tells if the sale is of the security, the n-th one of its kind:
+    pub fn is_of(&self, security: &PortfolioSecurity, n: usize) -> bool {
+        self.n == n
+            && self.security_type == security.security_type
+            && self.ticker == security.ticker
+            && self.purchase_date == security.purchase_date
+            && self.security_volume == security.volume
+    }
+    // may_be_of tells if the DataLoader, which finds a security by its ticker and purchase date,
+    // may take the security for the one of the sale:
+    fn may_be_of(&self, security: &PortfolioSecurity) -> bool {
+        self.ticker == security.ticker && self.purchase_date == security.purchase_date
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Store {
+    // the sales of the lots by portfolio:
+    sales: BTreeMap<String, Vec<LotSale>>,
+}
+
+pub struct Lots {
+    trading: Arc<Trading>,
+    path: PathBuf,
+    // a sale holds the lock while it prices the lots, so no lot is sold twice:
+    store: Mutex<Store>,
+}
+
+impl Lots {
+    pub fn new(trading: Arc<Trading>, envs: &Envs) -> Result<Lots> {
+        let path = PathBuf::from(&envs.lots_file);
+        let store = persist::load::<Store>(&path, "lots")?;
+        Ok(Lots {
+            trading,
+            path,
+            store: Mutex::new(store),
+        })
+    }
+
+    pub async fn sales(&self, portfolio_id: &str) -> Vec<LotSale> {
+        let store = self.store.lock().await;
+        store.sales.get(portfolio_id).cloned().unwrap_or_default()
+    }
+    pub async fn report(&self, req: LotReportReq) -> Result<LotReport> {
+        let sales = self.sales(&req.portfolio_id).await;
+        self.trading.portfolio_lots(req, &sales).await
+    }
+    // sell sells shares from the open lots and returns the realized gains. The sales are only
+    // kept, once they are saved.
+    pub async fn sell(&self, req: LotSellReq) -> Result<Vec<LotGain>> {
+        let mut store = self.store.lock().await;
+        let portfolio_id = req.portfolio_id.to_string();
+        let sales = store.sales.get(&portfolio_id).cloned().unwrap_or_default();
+        let (gains, sold) = self.trading.sell_lots(req, &sales).await?;
+        let mut updated = store.clone();
+        updated.sales.entry(portfolio_id).or_default().extend(sold);
+        persist::save(&self.path, serde_json::to_vec_pretty(&updated)?, "lots").await?;
+        *store = updated;
+        Ok(gains)
+    }
+    // remove drops the sales of a deleted portfolio:
+    pub async fn remove(&self, portfolio_id: &str) -> Result<()> {
+        let mut store = self.store.lock().await;
+        let mut updated = store.clone();
+        if updated.sales.remove(portfolio_id).is_none() {
+            return Ok(());
+        }
+        persist::save(&self.path, serde_json::to_vec_pretty(&updated)?, "lots").await?;
+        *store = updated;
+        Ok(())
+    }
+    // sell_security and delete_security change a security directly, they are refused for the
+    // securities of a day with sales of lots, as the sales would move to another security:
+    pub async fn sell_security(&self, security: PortfolioSecurity) -> Result<()> {
+        let store = self.store.lock().await;
+        unsold(&store, &security)?;
+        self.trading.sell_security(security).await
+    }
+    pub async fn delete_security(&self, security: PortfolioSecurity) -> Result<()> {
+        let store = self.store.lock().await;
+        unsold(&store, &security)?;
+        self.trading.delete_portfolio_security(security).await
+    }
+}
+
+fn unsold(store: &Store, security: &PortfolioSecurity) -> Result<()> {
+    let sales = store.sales.get(&security.portfolio_id);
+    match sales.into_iter().flatten().any(|s| s.may_be_of(security)) {
+        true => Err(invalid(
+            "purchase_date",
+            format!(
+                "{} of {} has sales of lots, sell its lots instead",
+                security.ticker, security.purchase_date
+            ),
+        )),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockServer;
+
+    fn date(d: &str) -> NaiveDate {
+        d.parse().unwrap()
+    }
+    fn lot(purchase_date: &str, volume: f64, cost_basis: f64) -> Lot {
+        Lot {
+            id: lot_id("AAPL", purchase_date, 0),
+            ticker: "AAPL".to_string(),
+            purchase_date: date(purchase_date),
+            volume,
+            cost_basis,
+        }
+    }
+    fn allocated(allocations: Vec<Allocation>) -> Vec<(String, f64, bool)> {
+        allocations
+            .into_iter()
+            .map(|a| (a.lot_id, a.volume, a.closes))
+            .collect()
+    }
+    fn field(err: anyhow::Error) -> String {
+        err.downcast::<ValidationError>().unwrap().errors[0].to_string()
+    }
+
+    #[test]
+    fn methods() {
+        let lots = vec![
+            lot("2023-01-03", 10.0, 120.0),
+            lot("2023-06-01", 10.0, 180.0),
+            lot("2024-01-02", 10.0, 150.0),
+        ];
+        let sell = |method, ids: &[&str]| {
+            let ids = ids.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+            allocate(&lots, 15.0, method, &ids).map(allocated)
+        };
+        let id = |d: &str| lot_id("AAPL", d, 0);
+        assert_eq!(
+            sell(LotMethod::Fifo, &[]).unwrap(),
+            vec![
+                (id("2023-01-03"), 10.0, true),
+                (id("2023-06-01"), 5.0, false)
+            ]
+        );
+        assert_eq!(
+            sell(LotMethod::Lifo, &[]).unwrap(),
+            vec![
+                (id("2024-01-02"), 10.0, true),
+                (id("2023-06-01"), 5.0, false)
+            ]
+        );
+        assert_eq!(
+            sell(LotMethod::Hifo, &[]).unwrap(),
+            vec![
+                (id("2023-06-01"), 10.0, true),
+                (id("2024-01-02"), 5.0, false)
+            ]
+        );
+        assert_eq!(
+            sell(LotMethod::Specific, &["AAPL:2024-01-02", "AAPL:2023-01-03"]).unwrap(),
+            vec![
+                (id("2024-01-02"), 10.0, true),
+                (id("2023-01-03"), 5.0, false)
+            ]
+        );
+
+        let err = sell(LotMethod::Specific, &["AAPL:2024-01-02", "AAPL:2020-01-01"]);
+        assert_eq!(
+            field(err.unwrap_err()),
+            "lot_ids[1]: no open lot AAPL:2020-01-01"
+        );
+        let err = sell(LotMethod::Specific, &["AAPL:2024-01-02", "AAPL:2024-01-02"]);
+        assert_eq!(field(err.unwrap_err()), "lot_ids[1]: is listed twice");
+        // the specified lots have to cover the volume:
+        let err = sell(LotMethod::Specific, &["AAPL:2024-01-02"]).unwrap_err();
+        assert_eq!(
+            field(err),
+            "volume: cannot sell 15 - only 10 held in the lots"
+        );
+        assert!(allocate(&lots, 30.0, LotMethod::Fifo, &[]).is_ok());
+        assert!(allocate(&lots, 30.1, LotMethod::Fifo, &[]).is_err());
+    }
+
+    #[test]
+    fn splits_and_terms() {
+        let splits = vec![Split {
+            ticker: "AAPL".to_string(),
+            date: date("2024-01-16"),
+            ratio: 4.0,
+        }];
+        let bought = lot("2023-01-12", 10.0, 480.0);
+        assert_eq!(bought.as_of(&splits, date("2024-01-15")), bought);
+        let split = bought.as_of(&splits, date("2024-01-16"));
+        assert_eq!((split.volume, split.cost_basis), (40.0, 120.0));
+        // lots bought on the day of the split are in split shares already:
+        let after = lot("2024-01-16", 10.0, 120.0).as_of(&splits, date("2024-02-01"));
+        assert_eq!(after.volume, 10.0);
+
+        assert_eq!(
+            term(date("2023-01-12"), date("2024-01-12")),
+            Term::ShortTerm
+        );
+        assert_eq!(term(date("2023-01-12"), date("2024-01-13")), Term::LongTerm);
+        // a year after the 29th of february is the 28th:
+        assert_eq!(term(date("2024-02-29"), date("2025-03-01")), Term::LongTerm);
+
+        let realized = gain(&split, 30.0, date("2024-01-16"), 125.0);
+        assert_eq!(realized.gain, 150.0);
+        assert_eq!(realized.term, Term::LongTerm);
+        let unrealized = gain(&split, 10.0, date("2024-01-16"), 110.0);
+        let short = gain(
+            &lot("2024-01-02", 5.0, 100.0),
+            5.0,
+            date("2024-01-16"),
+            110.0,
+        );
+        let report = report(vec![realized], vec![unrealized, short]);
+        assert_eq!(
+            report.summary,
+            GainSummary {
+                realized_short_term: 0.0,
+                realized_long_term: 150.0,
+                unrealized_short_term: 50.0,
+                unrealized_long_term: -100.0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn persisted_sales() {
+        let server = MockServer::start().await.unwrap();
+        let envs = server.envs();
+        let trading = Arc::new(Trading::new(server.envs()).unwrap());
+        let lots = Lots::new(trading.clone(), &envs).unwrap();
+        let gains = lots
+            .sell(LotSellReq {
+                portfolio_id: "1".to_string(),
+                ticker: "AAPL".to_string(),
+                security_type: crate::enums::SecurityType::Stock,
+                volume: 4.0,
+                date: "2024-01-19".to_string(),
+                method: LotMethod::Fifo,
+                lot_ids: vec![],
+            })
+            .await
+            .unwrap();
+        assert_eq!(gains.len(), 1);
+        assert_eq!(gains[0].volume, 4.0);
+
+        // the security of the DataLoader is left open, the sale is kept with the lots:
+        let securities = trading.portfolio_securities("1".to_string()).await.unwrap();
+        let aapl = securities.iter().find(|s| s.ticker == "AAPL").unwrap();
+        assert_eq!((aapl.volume, aapl.sell_date.as_str()), (10.0, ""));
+        let lots = Lots::new(trading.clone(), &envs).unwrap();
+        let sales = lots.sales("1").await;
+        assert_eq!(sales.len(), 1);
+        assert_eq!(
+            (sales[0].ticker.as_str(), sales[0].purchase_date.as_str()),
+            ("AAPL", "2024-01-02")
+        );
+        assert_eq!((sales[0].security_volume, sales[0].n), (10.0, 0));
+
+        // the sale stays with its security, when another one of the day is bought:
+        let mut bought = aapl.clone();
+        bought.volume = 5.0;
+        trading.buy_security(bought).await.unwrap();
+        let report = lots
+            .report(LotReportReq {
+                portfolio_id: "1".to_string(),
+                until: "2024-01-31".to_string(),
+            })
+            .await
+            .unwrap();
+        let aapl_volumes = |gains: &[LotGain]| {
+            gains
+                .iter()
+                .filter(|g| g.ticker == "AAPL")
+                .map(|g| g.volume)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(aapl_volumes(&report.realized), vec![4.0]);
+        assert_eq!(aapl_volumes(&report.unrealized), vec![6.0, 5.0]);
+
+        // the securities of the day can't be sold or deleted directly anymore:
+        let sold = PortfolioSecurity {
+            sell_date: "2024-01-22".to_string(),
+            ..aapl.clone()
+        };
+        let err = lots.sell_security(sold).await.unwrap_err();
+        let err = err.downcast_ref::<ValidationError>().unwrap();
+        assert_eq!(err.errors[0].field, "purchase_date");
+        let err = lots.delete_security(aapl.clone()).await.unwrap_err();
+        assert!(err.downcast_ref::<ValidationError>().is_some());
+        let msft = securities.iter().find(|s| s.ticker == "MSFT").unwrap();
+        lots.delete_security(msft.clone()).await.unwrap();
+    }
+}
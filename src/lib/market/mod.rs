@@ -0,0 +1,287 @@
+// market reports the state of the exchanges (pre-market, open, post-market, closed or holiday)
+// from their trading calendars, as a snapshot and as a feed of state transitions.
+use crate::calendar::{Exchange, TradingCalendar};
+use crate::stream::{ActixStream, ActixStreamItem};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Duration, SecondsFormat, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use utoipa::{IntoParams, ToSchema};
+
+// the feed sends a comment line this often, so proxies keep idle connections open:
+const KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketState {
+    PreMarket,
+    Open,
+    PostMarket,
+    Closed,
+    Holiday,
+}
+
+// MarketStatus is the state of an exchange at a point in time. Times are rfc3339 in exchange
+// local time, the last trading day is the date of the latest session that has started.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MarketStatus {
+    pub exchange: Exchange,
+    pub state: MarketState,
+    pub timezone: String,
+    pub local_time: String,
+    pub next_open: String,
+    pub next_close: String,
+    pub last_trading_day: String,
+}
+
+#[derive(Serialize, Deserialize, Default, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MarketStatusReq {
+    // comma separated exchange names or mics, all exchanges if missing:
+    #[serde(default)]
+    pub exchange: Option<String>,
+}
+
+impl MarketStatusReq {
+    pub fn calendars(&self) -> Result<Vec<TradingCalendar>> {
+        let Some(exchanges) = &self.exchange else {
+            return Ok(Exchange::ALL.map(TradingCalendar::new).to_vec());
+        };
+        exchanges
+            .split(',')
+            .map(|e| {
+                Exchange::parse(e)
+                    .map(TradingCalendar::new)
+                    .ok_or_else(|| anyhow!("unknown exchange '{}'", e.trim()))
+            })
+            .collect()
+    }
+}
+
+pub fn state(calendar: &TradingCalendar, at: DateTime<Utc>) -> MarketState {
+    let local = at.with_timezone(&calendar.timezone());
+    if calendar.session_at(local.naive_local()).is_some() {
+        return MarketState::Open;
+    }
+    let date = local.date_naive();
+    // fx sessions are named after the day they close, so tomorrow's may have started:
+    for session in [date, date + Duration::days(1)]
+        .into_iter()
+        .filter_map(|d| calendar.session(d))
+    {
+        if session.pre_market <= at && at < session.open {
+            return MarketState::PreMarket;
+        }
+        if session.close <= at && at < session.post_market {
+            return MarketState::PostMarket;
+        }
+    }
+    let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+    if calendar.is_holiday(date) && !weekend {
+        return MarketState::Holiday;
+    }
+    MarketState::Closed
+}
+
+pub fn status(calendar: &TradingCalendar, at: DateTime<Utc>) -> MarketStatus {
+    let tz = calendar.timezone();
+    let local = |t: DateTime<Utc>| {
+        t.with_timezone(&tz)
+            .to_rfc3339_opts(SecondsFormat::Secs, false)
+    };
+    MarketStatus {
+        exchange: calendar.exchange(),
+        state: state(calendar, at),
+        timezone: tz.name().to_string(),
+        local_time: local(at),
+        next_open: local(calendar.next_open(at)),
+        next_close: local(calendar.next_close(at)),
+        last_trading_day: calendar.last_trading_day(at).to_string(),
+    }
+}
+
+// next_change returns the next time after at, at which the state may change. That's one of the
+// session's boundaries, or else the next local midnight (when a holiday starts or ends).
+pub fn next_change(calendar: &TradingCalendar, at: DateTime<Utc>) -> DateTime<Utc> {
+    let date = at.with_timezone(&calendar.timezone()).date_naive();
+    let midnight = calendar
+        .start_of_day(date + Duration::days(1))
+        .with_timezone(&Utc);
+    (-1..=1)
+        .filter_map(|n| calendar.session(date + Duration::days(n)))
+        .flat_map(|s| [s.pre_market, s.open, s.close, s.post_market])
+        .map(|t| t.with_timezone(&Utc))
+        .filter(|t| *t > at)
+        .fold(midnight, |next, t| next.min(t))
+}
+
+fn event(status: &MarketStatus) -> Result<Bytes> {
+    Ok(Bytes::from(format!(
+        "data: {}\n\n",
+        serde_json::to_string(status)?
+    )))
+}
+
+// feed streams the status of the exchanges as server-sent events: first the current status of
+// each, then an event whenever an exchange's state changes. The task sleeps until the next
+// possible change and stops, once the client disconnects.
+pub fn feed(calendars: Vec<TradingCalendar>) -> ActixStream {
+    let (tx, rx) = mpsc::channel::<ActixStreamItem>(16);
+    tokio::spawn(send_changes(calendars, tx));
+    ReceiverStream::new(rx)
+}
+
+async fn send_changes(
+    calendars: Vec<TradingCalendar>,
+    tx: mpsc::Sender<ActixStreamItem>,
+) -> Result<()> {
+    let mut states = HashMap::<Exchange, MarketState>::new();
+    loop {
+        let now = Utc::now();
+        for calendar in calendars.iter() {
+            let status = status(calendar, now);
+            if states.insert(status.exchange, status.state) != Some(status.state) {
+                tx.send(Ok(event(&status)?)).await?;
+            }
+        }
+        let next = calendars
+            .iter()
+            .map(|c| next_change(c, now))
+            .min()
+            .unwrap_or(now);
+        let wait = (next - now).to_std().unwrap_or_default().min(KEEP_ALIVE);
+        tokio::select! {
+            _ = tx.closed() => return Ok(()),
+            _ = tokio::time::sleep(wait) => {}
+        }
+        if Utc::now() < next {
+            tx.send(Ok(Bytes::from(": keep-alive\n\n"))).await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn nyse_states() {
+        let nyse = TradingCalendar::nyse();
+        let state = |s| state(&nyse, utc(s));
+        // tuesday 2024-03-05, new york is at utc-5:
+        assert_eq!(state("2024-03-05T08:59:00Z"), MarketState::Closed);
+        assert_eq!(state("2024-03-05T09:00:00Z"), MarketState::PreMarket);
+        assert_eq!(state("2024-03-05T14:30:00Z"), MarketState::Open);
+        assert_eq!(state("2024-03-05T21:00:00Z"), MarketState::PostMarket);
+        assert_eq!(state("2024-03-06T01:00:00Z"), MarketState::Closed);
+        // saturday:
+        assert_eq!(state("2024-03-09T15:00:00Z"), MarketState::Closed);
+        // thanksgiving, and the early close after it with post-market until 17:00:
+        assert_eq!(state("2024-11-28T15:00:00Z"), MarketState::Holiday);
+        assert_eq!(state("2024-11-29T17:59:00Z"), MarketState::Open);
+        assert_eq!(state("2024-11-29T18:00:00Z"), MarketState::PostMarket);
+        assert_eq!(state("2024-11-29T22:00:00Z"), MarketState::Closed);
+        // new year's day 2022 fell on a saturday and was not observed:
+        assert_eq!(state("2022-01-01T15:00:00Z"), MarketState::Closed);
+    }
+
+    #[test]
+    fn other_exchanges() {
+        let state = |e, s| state(&TradingCalendar::new(e), utc(s));
+        // lse has no extended hours, london is at utc+1 in summer:
+        assert_eq!(
+            state(Exchange::Lse, "2024-07-01T06:30:00Z"),
+            MarketState::Closed
+        );
+        assert_eq!(
+            state(Exchange::Lse, "2024-07-01T07:00:00Z"),
+            MarketState::Open
+        );
+        assert_eq!(
+            state(Exchange::Lse, "2024-12-25T12:00:00Z"),
+            MarketState::Holiday
+        );
+        assert_eq!(
+            state(Exchange::Xetra, "2024-12-24T10:00:00Z"),
+            MarketState::Holiday
+        );
+        // fx opens on sunday 17:00 new york time:
+        assert_eq!(
+            state(Exchange::Fx, "2024-03-09T12:00:00Z"),
+            MarketState::Closed
+        );
+        assert_eq!(
+            state(Exchange::Fx, "2024-03-10T21:00:00Z"),
+            MarketState::Open
+        );
+        assert_eq!(
+            state(Exchange::Crypto, "2024-12-25T00:00:00Z"),
+            MarketState::Open
+        );
+    }
+
+    #[test]
+    fn snapshot() {
+        let status = status(&TradingCalendar::nyse(), utc("2024-03-09T15:00:00Z"));
+        assert_eq!(
+            status,
+            MarketStatus {
+                exchange: Exchange::Nyse,
+                state: MarketState::Closed,
+                timezone: "America/New_York".to_string(),
+                local_time: "2024-03-09T10:00:00-05:00".to_string(),
+                // daylight saving time starts on sunday:
+                next_open: "2024-03-11T09:30:00-04:00".to_string(),
+                next_close: "2024-03-11T16:00:00-04:00".to_string(),
+                last_trading_day: "2024-03-08".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let nyse = TradingCalendar::nyse();
+        let next = |s| next_change(&nyse, utc(s));
+        assert_eq!(next("2024-03-05T08:00:00Z"), utc("2024-03-05T09:00:00Z"));
+        assert_eq!(next("2024-03-05T09:00:00Z"), utc("2024-03-05T14:30:00Z"));
+        assert_eq!(next("2024-03-05T15:00:00Z"), utc("2024-03-05T21:00:00Z"));
+        assert_eq!(next("2024-03-05T21:00:00Z"), utc("2024-03-06T01:00:00Z"));
+        // after the post-market, the next change may be midnight:
+        assert_eq!(next("2024-03-06T01:00:00Z"), utc("2024-03-06T05:00:00Z"));
+        assert_eq!(next("2024-11-28T02:00:00Z"), utc("2024-11-28T05:00:00Z"));
+        assert_eq!(next("2024-11-28T06:00:00Z"), utc("2024-11-29T05:00:00Z"));
+
+        let crypto = TradingCalendar::new(Exchange::Crypto);
+        assert_eq!(
+            next_change(&crypto, utc("2024-03-05T12:00:00Z")),
+            utc("2024-03-06T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn requested_exchanges() {
+        let req = |e: Option<&str>| MarketStatusReq {
+            exchange: e.map(|e| e.to_string()),
+        };
+        assert_eq!(req(None).calendars().unwrap().len(), Exchange::ALL.len());
+        let exchanges = req(Some("xnys, LSE"))
+            .calendars()
+            .unwrap()
+            .iter()
+            .map(|c| c.exchange())
+            .collect::<Vec<_>>();
+        assert_eq!(exchanges, vec![Exchange::Nyse, Exchange::Lse]);
+        assert!(req(Some("NYSE,moon"))
+            .calendars()
+            .unwrap_err()
+            .to_string()
+            .contains("'moon'"));
+    }
+}
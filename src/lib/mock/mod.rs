@@ -0,0 +1,773 @@
+// mock provides an in-process DataLoader gRPC server over deterministic fixture data, so the
+// trading layer and the http api can be tested without a running DataLoader.
+use crate::envs::Envs;
+use crate::proto::dataloader::{
+    self as db_proto,
+    correl_tickers_req::Sign,
+    data_loader_server::{DataLoader, DataLoaderServer},
+    MovementType,
+};
+use anyhow::Result;
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+pub type MockStream<T> = ReceiverStream<Result<T, Status>>;
+
+pub struct Fixtures {
+    pub tickers: Vec<db_proto::Ticker>,
+    pub time_series: HashMap<String, Vec<db_proto::TimeSeriesData>>,
+    pub movements: Vec<db_proto::Movement>,
+    pub correlations: Vec<db_proto::Correl>,
+    pub portfolios: Vec<db_proto::PortfolioMeta>,
+    pub portfolio_securities: Vec<db_proto::PortfolioSecurity>,
+    pub stock_splits: Vec<db_proto::StockSplit>,
+}
+
+fn ticker(ticker: &str, name: &str, security_type: db_proto::TickerType) -> db_proto::Ticker {
+    db_proto::Ticker {
+        name: name.to_string(),
+        ticker: ticker.to_string(),
+        security_type: security_type as i32,
+        custom_fields: HashMap::from([("exchange".to_string(), "NASDAQ".to_string())]),
+    }
+}
+
+// trading_days returns all weekdays of january 2024, except for new year and mlk day:
+fn trading_days() -> Vec<NaiveDate> {
+    let holidays = [
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+    ];
+    NaiveDate::from_ymd_opt(2024, 1, 1)
+        .unwrap()
+        .iter_days()
+        .take_while(|d| d.month() == 1)
+        .filter(|d| !matches!(d.weekday(), Weekday::Sat | Weekday::Sun))
+        .filter(|d| !holidays.contains(d))
+        .collect()
+}
+
+// series builds 13 half hourly bars per trading day, starting at 9:30 new york time.
+// split scales prices down (and volumes up) from the given date on, like an unadjusted feed does.
+fn series(base: f64, split: Option<(NaiveDate, f64)>) -> Vec<db_proto::TimeSeriesData> {
+    let mut data = vec![];
+    let mut prev_close = base;
+    let mut i = 0;
+    for day in trading_days() {
+        let factor = match split {
+            Some((split_date, ratio)) if day >= split_date => ratio,
+            _ => 1.0,
+        };
+        for bar in 0..13 {
+            let time = NaiveTime::from_hms_opt(9, 30, 0).unwrap() + Duration::minutes(30 * bar);
+            let close = base + ((i * 7) % 11) as f64 * 0.5 - 2.5 + i as f64 * 0.05;
+            let open = prev_close;
+            let volume = 1000.0 + ((i * 37) % 500) as f64 * 10.0;
+            prev_close = close;
+            i += 1;
+            data.push(db_proto::TimeSeriesData {
+                date: format!("{}T{}", day, time),
+                values: HashMap::from([
+                    ("open".to_string(), open / factor),
+                    ("high".to_string(), open.max(close) / factor + 0.5),
+                    ("low".to_string(), open.min(close) / factor - 0.5),
+                    ("close".to_string(), close / factor),
+                    ("volume".to_string(), volume * factor),
+                ]),
+            });
+        }
+    }
+    data
+}
+
+fn scaled(mut data: Vec<db_proto::TimeSeriesData>, factor: f64) -> Vec<db_proto::TimeSeriesData> {
+    for entry in data.iter_mut() {
+        for (key, value) in entry.values.iter_mut() {
+            if key != "volume" {
+                *value *= factor;
+            }
+        }
+    }
+    data
+}
+
+fn movement(
+    ticker: &str,
+    security_type: db_proto::TickerType,
+    performance: f64,
+    volume: f64,
+    variance: f64,
+) -> db_proto::Movement {
+    db_proto::Movement {
+        ticker: ticker.to_string(),
+        security_type: security_type as i32,
+        date: "2024-01-31".to_string(),
+        period: db_proto::Period::Month as i32,
+        performance,
+        average: 1.0 + performance / 2.0,
+        volume,
+        variance,
+        stddev: variance.sqrt(),
+        movement_exists: true,
+    }
+}
+
+fn correl(
+    ticker0: &str,
+    ticker1: &str,
+    correl: f64,
+    volume0: f64,
+    volume1: f64,
+) -> db_proto::Correl {
+    db_proto::Correl {
+        ticker0: Some(db_proto::BasicTicker {
+            ticker: ticker0.to_string(),
+            security_type: 0,
+        }),
+        ticker1: Some(db_proto::BasicTicker {
+            ticker: ticker1.to_string(),
+            security_type: 0,
+        }),
+        correl,
+        date: "2024-01-31".to_string(),
+        period: db_proto::Period::Month as i32,
+        correl_exists: true,
+        volume0,
+        volume1,
+    }
+}
+
+impl Default for Fixtures {
+    fn default() -> Self {
+        use db_proto::TickerType::*;
+        let nvda_split = NaiveDate::from_ymd_opt(2024, 1, 16).unwrap();
+        Self {
+            tickers: vec![
+                ticker("AAPL", "Apple Inc.", Stock),
+                ticker("MSFT", "Microsoft Corporation", Stock),
+                ticker("NVDA", "NVIDIA Corporation", Stock),
+                ticker("SPY", "SPDR S&P 500 ETF Trust", Etf),
+                ticker("QQQ", "Invesco QQQ Trust", Etf),
+                db_proto::Ticker {
+                    custom_fields: HashMap::from([
+                        ("exchange".to_string(), "XETRA".to_string()),
+                        ("currency".to_string(), "EUR".to_string()),
+                    ]),
+                    ..ticker("SIE", "Siemens", Stock)
+                },
+                ticker("EURUSD", "Euro / US Dollar", Currency),
+                ticker("BTCUSD", "Bitcoin / US Dollar", Crypto),
+            ],
+            time_series: HashMap::from([
+                ("AAPL".to_string(), series(185.0, None)),
+                ("MSFT".to_string(), series(370.0, None)),
+                ("NVDA".to_string(), series(480.0, Some((nvda_split, 4.0)))),
+                ("SPY".to_string(), series(470.0, None)),
+                ("QQQ".to_string(), series(400.0, None)),
+                ("SIE".to_string(), series(170.0, None)),
+                // rates move in small steps, so the series around 110 is scaled to 1.1:
+                ("EURUSD".to_string(), scaled(series(110.0, None), 0.01)),
+                ("BTCUSD".to_string(), series(42000.0, None)),
+            ]),
+            movements: vec![
+                movement("AAPL", Stock, -0.04, 5.0e7, 0.02),
+                movement("MSFT", Stock, 0.06, 2.5e7, 0.03),
+                movement("NVDA", Stock, -0.72, 4.0e7, 0.4),
+                movement("SPY", Etf, 0.02, 8.0e7, 0.01),
+                movement("QQQ", Etf, 0.03, 4.5e7, 0.015),
+            ],
+            correlations: vec![
+                correl("AAPL", "MSFT", 0.82, 5.0e7, 2.5e7),
+                correl("AAPL", "NVDA", 0.64, 5.0e7, 4.0e7),
+                correl("MSFT", "NVDA", 0.71, 2.5e7, 4.0e7),
+                correl("AAPL", "SPY", -0.35, 5.0e7, 8.0e7),
+                correl("NVDA", "QQQ", -0.12, 4.0e7, 4.5e7),
+            ],
+            portfolios: vec![
+                db_proto::PortfolioMeta {
+                    id: "1".to_string(),
+                    name: "tech pack".to_string(),
+                    description: "large cap tech".to_string(),
+                },
+                db_proto::PortfolioMeta {
+                    id: "2".to_string(),
+                    name: "index".to_string(),
+                    description: "broad market etfs".to_string(),
+                },
+            ],
+            portfolio_securities: vec![
+                db_proto::PortfolioSecurity {
+                    portfolio_id: "1".to_string(),
+                    security_type: Stock as i32,
+                    ticker: "AAPL".to_string(),
+                    volume: 10.0,
+                    purchase_date: "2024-01-02".to_string(),
+                    sell_date: "".to_string(),
+                },
+                db_proto::PortfolioSecurity {
+                    portfolio_id: "1".to_string(),
+                    security_type: Stock as i32,
+                    ticker: "MSFT".to_string(),
+                    volume: 5.0,
+                    purchase_date: "2024-01-08".to_string(),
+                    sell_date: "2024-01-25".to_string(),
+                },
+                db_proto::PortfolioSecurity {
+                    portfolio_id: "2".to_string(),
+                    security_type: Etf as i32,
+                    ticker: "SPY".to_string(),
+                    volume: 20.0,
+                    purchase_date: "2024-01-02".to_string(),
+                    sell_date: "".to_string(),
+                },
+            ],
+            stock_splits: vec![db_proto::StockSplit {
+                ticker: "NVDA".to_string(),
+                date: nvda_split.to_string(),
+                numerator: 4.0,
+                denominator: 1.0,
+            }],
+        }
+    }
+}
+
+// MockLoader answers DataLoader requests from its fixtures. Portfolio mutations are applied
+// to the fixtures, so they are visible to subsequent requests.
+#[derive(Clone)]
+pub struct MockLoader {
+    fixtures: Arc<Mutex<Fixtures>>,
+}
+
+impl MockLoader {
+    pub fn new(fixtures: Fixtures) -> Self {
+        Self {
+            fixtures: Arc::new(Mutex::new(fixtures)),
+        }
+    }
+    fn with<T>(&self, f: impl FnOnce(&mut Fixtures) -> T) -> T {
+        f(&mut self.fixtures.lock().unwrap())
+    }
+    fn close_price(&self, ticker: &str, at: &str, after: bool) -> Option<f64> {
+        self.with(|fx| {
+            let series = fx.time_series.get(ticker)?;
+            let entry = if after {
+                series.iter().find(|d| &d.date[..10] >= at)
+            } else {
+                series.iter().rev().find(|d| &d.date[..10] <= at)
+            };
+            entry.and_then(|d| d.values.get("close").copied())
+        })
+    }
+}
+
+fn stream<T: Send + 'static>(items: Vec<T>) -> MockStream<T> {
+    let (tx, rx) = mpsc::channel(items.len().max(1));
+    for item in items {
+        tx.try_send(Ok(item)).unwrap();
+    }
+    ReceiverStream::new(rx)
+}
+
+fn in_range(date: &str, from: &str, until: &str) -> bool {
+    let day = date.get(..10).unwrap_or(date);
+    (from.is_empty() || day >= from) && (until.is_empty() || day <= until)
+}
+
+fn sort_movements(movements: &mut [db_proto::Movement], sort_by: i32) {
+    let key = |m: &db_proto::Movement| -> f64 {
+        match MovementType::try_from(sort_by).unwrap_or(MovementType::Winner) {
+            MovementType::Winner => -m.performance,
+            MovementType::Loser => m.performance,
+            MovementType::Volume => -m.volume,
+            MovementType::Volatility => -m.variance,
+            MovementType::AbsPerformance => -m.performance.abs(),
+        }
+    };
+    movements.sort_by(|a, b| key(a).total_cmp(&key(b)));
+}
+
+#[tonic::async_trait]
+impl DataLoader for MockLoader {
+    async fn get_ticker_details(
+        &self,
+        req: Request<db_proto::BasicTicker>,
+    ) -> Result<Response<db_proto::Ticker>, Status> {
+        let req = req.into_inner();
+        self.with(|fx| fx.tickers.iter().find(|t| t.ticker == req.ticker).cloned())
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("unknown ticker {}", req.ticker)))
+    }
+    type GetTickersStream = MockStream<db_proto::Ticker>;
+    async fn get_tickers(
+        &self,
+        req: Request<db_proto::TickerFilter>,
+    ) -> Result<Response<Self::GetTickersStream>, Status> {
+        let req = req.into_inner();
+        let filter = req.filter.to_lowercase();
+        let tickers = self.with(|fx| {
+            fx.tickers
+                .iter()
+                .filter(|t| t.security_type == req.ticker_type)
+                .filter(|t| {
+                    t.ticker.to_lowercase().contains(&filter)
+                        || t.name.to_lowercase().contains(&filter)
+                })
+                .take(req.limit as usize)
+                .cloned()
+                .collect()
+        });
+        Ok(Response::new(stream(tickers)))
+    }
+    type GetSecurityDataStream = MockStream<db_proto::TimeSeriesData>;
+    async fn get_security_data(
+        &self,
+        req: Request<db_proto::TimeSeriesReq>,
+    ) -> Result<Response<Self::GetSecurityDataStream>, Status> {
+        let req = req.into_inner();
+        let ticker = req
+            .ticker
+            .ok_or_else(|| Status::invalid_argument("missing ticker"))?;
+        let data = self
+            .with(|fx| {
+                fx.time_series.get(&ticker.ticker).map(|series| {
+                    series
+                        .iter()
+                        .filter(|d| in_range(&d.date, &req.from_date, &req.until_date))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+            })
+            .ok_or_else(|| Status::not_found(format!("unknown ticker {}", ticker.ticker)))?;
+        Ok(Response::new(stream(data)))
+    }
+    async fn get_latest_security_data_date(
+        &self,
+        req: Request<db_proto::DateReq>,
+    ) -> Result<Response<db_proto::Date>, Status> {
+        let req = req.into_inner();
+        self.with(|fx| {
+            fx.time_series
+                .get(&req.ticker)
+                .and_then(|series| series.last())
+                .map(|d| db_proto::Date {
+                    date: d.date.to_string(),
+                })
+        })
+        .map(Response::new)
+        .ok_or_else(|| Status::not_found(format!("unknown ticker {}", req.ticker)))
+    }
+    async fn get_movement(
+        &self,
+        req: Request<db_proto::MovementReq>,
+    ) -> Result<Response<db_proto::Movement>, Status> {
+        let req = req.into_inner();
+        self.with(|fx| {
+            fx.movements
+                .iter()
+                .find(|m| m.ticker == req.ticker)
+                .cloned()
+        })
+        .map(Response::new)
+        .ok_or_else(|| Status::not_found(format!("no movement for {}", req.ticker)))
+    }
+    async fn get_movements(
+        &self,
+        req: Request<db_proto::MovementsReq>,
+    ) -> Result<Response<db_proto::Movements>, Status> {
+        let req = req.into_inner();
+        let mut movements = self.with(|fx| {
+            fx.movements
+                .iter()
+                .filter(|m| m.security_type == req.security_type)
+                .filter(|m| m.volume >= req.min_volume as f64)
+                .filter(|m| req.min_variance == 0.0 || m.variance >= req.min_variance)
+                .filter(|m| req.max_variance == 0.0 || m.variance <= req.max_variance)
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        sort_movements(&mut movements, req.sort_by);
+        movements.truncate(req.limit as usize);
+        Ok(Response::new(db_proto::Movements { movements }))
+    }
+    async fn get_avg_movement(
+        &self,
+        req: Request<db_proto::MovementReq>,
+    ) -> Result<Response<db_proto::Movement>, Status> {
+        self.get_movement(req).await
+    }
+    async fn get_avg_movements(
+        &self,
+        req: Request<db_proto::MovementsReq>,
+    ) -> Result<Response<db_proto::Movements>, Status> {
+        self.get_movements(req).await
+    }
+    type GetCorrelationsStream = MockStream<db_proto::Correl>;
+    async fn get_correlations(
+        &self,
+        req: Request<db_proto::CorrelReq>,
+    ) -> Result<Response<Self::GetCorrelationsStream>, Status> {
+        let req = req.into_inner();
+        let requested = |t: &Option<db_proto::BasicTicker>| {
+            t.as_ref()
+                .map(|t| req.tickers.iter().any(|r| r.ticker == t.ticker))
+                .unwrap_or(false)
+        };
+        let correls = self.with(|fx| {
+            fx.correlations
+                .iter()
+                .filter(|c| requested(&c.ticker0) && requested(&c.ticker1))
+                .cloned()
+                .collect()
+        });
+        Ok(Response::new(stream(correls)))
+    }
+    type GetCorrelatingTickersStream = MockStream<db_proto::Correl>;
+    async fn get_correlating_tickers(
+        &self,
+        req: Request<db_proto::CorrelTickersReq>,
+    ) -> Result<Response<Self::GetCorrelatingTickersStream>, Status> {
+        let req = req.into_inner();
+        let sign = Sign::try_from(req.sign).unwrap_or(Sign::Abs);
+        let mut correls = self.with(|fx| {
+            fx.correlations
+                .iter()
+                .filter(|c| c.volume0.min(c.volume1) >= req.min_volume as f64)
+                .filter(|c| match sign {
+                    Sign::Abs => true,
+                    Sign::Positive => c.correl > 0.0,
+                    Sign::Negative => c.correl < 0.0,
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        correls.sort_by(|a, b| match sign {
+            Sign::Negative => a.correl.total_cmp(&b.correl),
+            Sign::Positive => b.correl.total_cmp(&a.correl),
+            Sign::Abs => b.correl.abs().total_cmp(&a.correl.abs()),
+        });
+        correls.truncate(req.limit as usize);
+        Ok(Response::new(stream(correls)))
+    }
+    async fn get_mutual_correlations(
+        &self,
+        req: Request<db_proto::CorrelReq>,
+    ) -> Result<Response<db_proto::MutualCorrels>, Status> {
+        let req = req.into_inner();
+        let correls = self.with(|fx| {
+            let details = |t: &str| {
+                fx.tickers
+                    .iter()
+                    .find(|d| d.ticker == t)
+                    .cloned()
+                    .unwrap_or_else(|| ticker(t, "", db_proto::TickerType::Stock))
+            };
+            req.tickers
+                .iter()
+                .map(|t| {
+                    let movement = fx.movements.iter().find(|m| m.ticker == t.ticker);
+                    db_proto::MutualCorrel {
+                        ticker: Some(details(&t.ticker)),
+                        correlations: fx
+                            .correlations
+                            .iter()
+                            .filter_map(|c| {
+                                let (t0, t1) = (c.ticker0.as_ref()?, c.ticker1.as_ref()?);
+                                let other = match (&t0.ticker, &t1.ticker) {
+                                    (t0, t1) if *t0 == t.ticker => t1,
+                                    (t0, t1) if *t1 == t.ticker => t0,
+                                    _ => return None,
+                                };
+                                req.tickers.iter().find(|r| r.ticker == *other)?;
+                                Some(db_proto::DetailedCorrel {
+                                    ticker0: Some(details(&t0.ticker)),
+                                    ticker1: Some(details(&t1.ticker)),
+                                    correl: c.correl,
+                                    date: c.date.to_string(),
+                                    period: req.period,
+                                })
+                            })
+                            .collect(),
+                        volatility: movement.map(|m| m.variance).unwrap_or_default(),
+                        stddev: movement.map(|m| m.stddev).unwrap_or_default(),
+                        performance: movement.map(|m| m.performance).unwrap_or_default(),
+                        volume: movement.map(|m| m.volume).unwrap_or_default(),
+                    }
+                })
+                .collect()
+        });
+        Ok(Response::new(db_proto::MutualCorrels { correls }))
+    }
+    async fn get_portfolios(
+        &self,
+        req: Request<db_proto::PortfolioReq>,
+    ) -> Result<Response<db_proto::PortfolioMetas>, Status> {
+        let filter = req.into_inner().filter.to_lowercase();
+        let portfolios = self.with(|fx| {
+            fx.portfolios
+                .iter()
+                .filter(|p| p.name.to_lowercase().contains(&filter))
+                .cloned()
+                .collect()
+        });
+        Ok(Response::new(db_proto::PortfolioMetas { portfolios }))
+    }
+    async fn get_portfolio(
+        &self,
+        req: Request<db_proto::Id>,
+    ) -> Result<Response<db_proto::PortfolioMeta>, Status> {
+        let id = req.into_inner().id;
+        self.with(|fx| fx.portfolios.iter().find(|p| p.id == id).cloned())
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("unknown portfolio {}", id)))
+    }
+    async fn get_portfolio_securities(
+        &self,
+        req: Request<db_proto::Id>,
+    ) -> Result<Response<db_proto::PortfolioSecurities>, Status> {
+        let id = req.into_inner().id;
+        let securities = self.with(|fx| {
+            fx.portfolio_securities
+                .iter()
+                .filter(|s| s.portfolio_id == id)
+                .cloned()
+                .collect()
+        });
+        Ok(Response::new(db_proto::PortfolioSecurities { securities }))
+    }
+    async fn get_portfolio_profits(
+        &self,
+        req: Request<db_proto::SecurityProfitReq>,
+    ) -> Result<Response<db_proto::SecurityProfits>, Status> {
+        let req = req.into_inner();
+        let mut profits = vec![];
+        for s in req.securities {
+            let until = s.sell_date.clone().unwrap_or_else(|| req.until.to_string());
+            let (purchase_price, until_price) = match (
+                self.close_price(&s.ticker, &s.purchase_date, true),
+                self.close_price(&s.ticker, &until, false),
+            ) {
+                (Some(p), Some(u)) => (p, u),
+                _ => return Err(Status::not_found(format!("no prices for {}", s.ticker))),
+            };
+            profits.push(db_proto::SecurityProfit {
+                ticker: s.ticker,
+                security_type: s.security_type,
+                volume: s.volume,
+                purchase_date: s.purchase_date,
+                until,
+                purchase_price,
+                until_price,
+                profit_per_share: until_price - purchase_price,
+                total_profit: (until_price - purchase_price) * s.volume,
+            });
+        }
+        Ok(Response::new(db_proto::SecurityProfits { profits }))
+    }
+    async fn create_portfolio(
+        &self,
+        req: Request<db_proto::CreatePortfolioReq>,
+    ) -> Result<Response<db_proto::PortfolioMeta>, Status> {
+        let req = req.into_inner();
+        let portfolio = self.with(|fx| {
+            let portfolio = db_proto::PortfolioMeta {
+                id: (fx.portfolios.len() + 1).to_string(),
+                name: req.name,
+                description: req.description,
+            };
+            fx.portfolios.push(portfolio.clone());
+            portfolio
+        });
+        Ok(Response::new(portfolio))
+    }
+    async fn delete_portfolio(
+        &self,
+        req: Request<db_proto::Id>,
+    ) -> Result<Response<db_proto::SuccessResp>, Status> {
+        let id = req.into_inner().id;
+        let found = self.with(|fx| {
+            let count = fx.portfolios.len();
+            fx.portfolios.retain(|p| p.id != id);
+            fx.portfolio_securities.retain(|s| s.portfolio_id != id);
+            count != fx.portfolios.len()
+        });
+        match found {
+            true => Ok(Response::new(db_proto::SuccessResp {})),
+            false => Err(Status::not_found(format!("unknown portfolio {}", id))),
+        }
+    }
+    async fn buy_security(
+        &self,
+        req: Request<db_proto::PortfolioSecurity>,
+    ) -> Result<Response<db_proto::SuccessResp>, Status> {
+        let security = req.into_inner();
+        let portfolio_id = security.portfolio_id.to_string();
+        let found = self.with(|fx| {
+            let found = fx.portfolios.iter().any(|p| p.id == security.portfolio_id);
+            if found {
+                fx.portfolio_securities.push(security);
+            }
+            found
+        });
+        match found {
+            true => Ok(Response::new(db_proto::SuccessResp {})),
+            false => Err(Status::not_found(format!(
+                "unknown portfolio {}",
+                portfolio_id
+            ))),
+        }
+    }
+    async fn sell_security(
+        &self,
+        req: Request<db_proto::PortfolioSecurity>,
+    ) -> Result<Response<db_proto::SuccessResp>, Status> {
+        let sold = req.into_inner();
+        let found = self.with(|fx| {
+            let position = fx.portfolio_securities.iter_mut().find(|s| {
+                s.portfolio_id == sold.portfolio_id
+                    && s.ticker == sold.ticker
+                    && s.purchase_date == sold.purchase_date
+                    && s.sell_date.is_empty()
+            });
+            position
+                .map(|position| position.sell_date = sold.sell_date.to_string())
+                .is_some()
+        });
+        match found {
+            true => Ok(Response::new(db_proto::SuccessResp {})),
+            false => Err(Status::not_found(format!(
+                "no open position in {}",
+                sold.ticker
+            ))),
+        }
+    }
+    async fn delete_portfolio_security(
+        &self,
+        req: Request<db_proto::PortfolioSecurity>,
+    ) -> Result<Response<db_proto::SuccessResp>, Status> {
+        let security = req.into_inner();
+        // the request names one security, so only one of the same day is deleted:
+        self.with(|fx| {
+            let position = fx.portfolio_securities.iter().position(|s| {
+                s.portfolio_id == security.portfolio_id
+                    && s.ticker == security.ticker
+                    && s.purchase_date == security.purchase_date
+            });
+            if let Some(i) = position {
+                fx.portfolio_securities.remove(i);
+            }
+        });
+        Ok(Response::new(db_proto::SuccessResp {}))
+    }
+    async fn get_stock_splits(
+        &self,
+        req: Request<db_proto::StockSplitReq>,
+    ) -> Result<Response<db_proto::StockSplits>, Status> {
+        let req = req.into_inner();
+        let mut splits = self.with(|fx| {
+            fx.stock_splits
+                .iter()
+                .filter(|s| in_range(&s.date, &req.from, &req.until))
+                .cloned()
+                .collect::<Vec<_>>()
+        });
+        if req.limit > 0 {
+            splits.truncate(req.limit as usize);
+        }
+        Ok(Response::new(db_proto::StockSplits { splits }))
+    }
+}
+
+// MockServer runs a MockLoader on a local port and counts the tcp connections it accepted.
+pub struct MockServer {
+    addr: SocketAddr,
+    connections: Arc<AtomicUsize>,
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    // start serves the default fixtures on an ephemeral port:
+    pub async fn start() -> Result<MockServer> {
+        Self::serve(MockLoader::new(Fixtures::default()), "127.0.0.1:0".parse()?).await
+    }
+    pub async fn serve(loader: MockLoader, addr: SocketAddr) -> Result<MockServer> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        // files of an earlier server on the same port are stale:
+        let _ = std::fs::remove_dir_all(temp_dir(addr));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let incoming = futures::stream::unfold(listener, move |listener| {
+            let counter = counter.clone();
+            async move {
+                let conn = listener.accept().await.map(|(stream, _)| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    stream
+                });
+                Some((conn, listener))
+            }
+        });
+        let (shutdown, rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            if let Err(err) = tonic::transport::Server::builder()
+                .add_service(DataLoaderServer::new(loader))
+                .serve_with_incoming_shutdown(incoming, async {
+                    rx.await.ok();
+                })
+                .await
+            {
+                println!("mock DataLoader failed: {:?}", err);
+            }
+        });
+        Ok(MockServer {
+            addr,
+            connections,
+            shutdown,
+            handle,
+        })
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+    // envs returns an environment pointing rustix at this server:
+    pub fn envs(&self) -> Envs {
+        Envs {
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            db_loader_host: self.addr.ip().to_string(),
+            db_loader_port: self.addr.port(),
+            db_loader_connect_timeout_ms: 1000,
+            db_loader_request_timeout_ms: 5000,
+            db_loader_keep_alive_secs: 30,
+            db_loader_concurrency_limit: 8,
+            mode: "info".to_string(),
+            jobs_file: self.temp_path("jobs.json"),
+            jobs_output_dir: self.temp_path("jobs"),
+            ledger_file: self.temp_path("ledger.json"),
+            lots_file: self.temp_path("lots.json"),
+        }
+    }
+    fn temp_path(&self, name: &str) -> String {
+        temp_dir(self.addr).join(name).to_string_lossy().to_string()
+    }
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.handle.await;
+    }
+}
+
+// temp_dir is unique per server, so tests running in parallel don't share files:
+fn temp_dir(addr: SocketAddr) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("rustix-mock-{}", addr.port()))
+}
@@ -0,0 +1,234 @@
+// period does calendar arithmetic for the lookback periods of the data loader. Months, quarters
+// and years are true calendar months, clamped to the end of shorter months (Mar 31 minus a month
+// is Feb 29 in leap years and Feb 28 otherwise). Optionally days are counted in trading sessions.
+use crate::calendar::TradingCalendar;
+use crate::proto::dataloader::Period;
+use chrono::{Duration, Months, NaiveDate};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalendarPeriod {
+    Months(u32),
+    Weeks(u32),
+    Days(u32),
+    // periods shorter than a day, which stay within the day on date arithmetic:
+    Time(Duration),
+}
+
+impl From<Period> for CalendarPeriod {
+    fn from(p: Period) -> Self {
+        match p {
+            Period::Year => CalendarPeriod::Months(12),
+            Period::SemiAnnual => CalendarPeriod::Months(6),
+            Period::Quarter => CalendarPeriod::Months(3),
+            Period::Month => CalendarPeriod::Months(1),
+            Period::Week => CalendarPeriod::Weeks(1),
+            Period::Day => CalendarPeriod::Days(1),
+            Period::Hour => CalendarPeriod::Time(Duration::hours(1)),
+            Period::Minute => CalendarPeriod::Time(Duration::minutes(1)),
+        }
+    }
+}
+
+impl CalendarPeriod {
+    // before returns the date the period starts at, if it ends at until:
+    pub fn before(&self, until: NaiveDate) -> NaiveDate {
+        match *self {
+            CalendarPeriod::Months(n) => until
+                .checked_sub_months(Months::new(n))
+                .unwrap_or(NaiveDate::MIN),
+            CalendarPeriod::Weeks(n) => until - Duration::weeks(n as i64),
+            CalendarPeriod::Days(n) => until - Duration::days(n as i64),
+            CalendarPeriod::Time(_) => until,
+        }
+    }
+    // after returns the date the period ends at, if it starts at from:
+    pub fn after(&self, from: NaiveDate) -> NaiveDate {
+        match *self {
+            CalendarPeriod::Months(n) => from
+                .checked_add_months(Months::new(n))
+                .unwrap_or(NaiveDate::MAX),
+            CalendarPeriod::Weeks(n) => from + Duration::weeks(n as i64),
+            CalendarPeriod::Days(n) => from + Duration::days(n as i64),
+            CalendarPeriod::Time(_) => from,
+        }
+    }
+    // trading_days_before counts days as sessions of the calendar. Weeks and months are calendar
+    // periods as in before, but a start on a weekend or holiday rolls back to the trading day
+    // before it, so the period covers the full lookback:
+    pub fn trading_days_before(&self, until: NaiveDate, calendar: &TradingCalendar) -> NaiveDate {
+        match *self {
+            CalendarPeriod::Days(n) => calendar.sessions_back(until, n as usize),
+            CalendarPeriod::Time(_) => until,
+            _ => calendar.trading_day_on_or_before(self.before(until)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::Exchange;
+    use chrono::Datelike;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+    fn days_in_month(year: i32, month: u32) -> u32 {
+        let first = date(year, month, 1);
+        (first.checked_add_months(Months::new(1)).unwrap() - first).num_days() as u32
+    }
+
+    #[test]
+    fn month_ends() {
+        let month = CalendarPeriod::from(Period::Month);
+        assert_eq!(month.before(date(2024, 3, 31)), date(2024, 2, 29));
+        assert_eq!(month.before(date(2023, 3, 31)), date(2023, 2, 28));
+        assert_eq!(month.before(date(2024, 3, 30)), date(2024, 2, 29));
+        assert_eq!(month.before(date(2024, 3, 28)), date(2024, 2, 28));
+        assert_eq!(month.before(date(2024, 12, 31)), date(2024, 11, 30));
+        assert_eq!(month.before(date(2024, 1, 31)), date(2023, 12, 31));
+        // clamping does not stick to the end of the month:
+        assert_eq!(month.before(date(2024, 2, 29)), date(2024, 1, 29));
+        assert_eq!(month.after(date(2024, 1, 31)), date(2024, 2, 29));
+        assert_eq!(month.after(date(2023, 1, 31)), date(2023, 2, 28));
+
+        let quarter = CalendarPeriod::from(Period::Quarter);
+        assert_eq!(quarter.before(date(2024, 5, 31)), date(2024, 2, 29));
+        assert_eq!(quarter.before(date(2024, 6, 30)), date(2024, 3, 30));
+        assert_eq!(quarter.before(date(2024, 1, 15)), date(2023, 10, 15));
+        assert_eq!(quarter.after(date(2023, 11, 30)), date(2024, 2, 29));
+
+        let half = CalendarPeriod::from(Period::SemiAnnual);
+        assert_eq!(half.before(date(2024, 8, 31)), date(2024, 2, 29));
+        assert_eq!(half.before(date(2023, 8, 31)), date(2023, 2, 28));
+        assert_eq!(half.before(date(2024, 12, 31)), date(2024, 6, 30));
+    }
+
+    #[test]
+    fn leap_days() {
+        let year = CalendarPeriod::from(Period::Year);
+        assert_eq!(year.before(date(2024, 2, 29)), date(2023, 2, 28));
+        assert_eq!(year.before(date(2025, 2, 28)), date(2024, 2, 28));
+        assert_eq!(year.before(date(2025, 3, 1)), date(2024, 3, 1));
+        assert_eq!(year.after(date(2024, 2, 29)), date(2025, 2, 28));
+        // a year spanning Feb 29 has 366 days:
+        assert_eq!(
+            (date(2024, 12, 31) - year.before(date(2024, 12, 31))).num_days(),
+            366
+        );
+        assert_eq!(
+            (date(2023, 12, 31) - year.before(date(2023, 12, 31))).num_days(),
+            365
+        );
+        // 1900 was not a leap year, 2000 was:
+        assert_eq!(
+            CalendarPeriod::Months(1).before(date(1900, 3, 31)),
+            date(1900, 2, 28)
+        );
+        assert_eq!(
+            CalendarPeriod::Months(1).before(date(2000, 3, 31)),
+            date(2000, 2, 29)
+        );
+        assert_eq!(
+            CalendarPeriod::Months(48).before(date(2024, 2, 29)),
+            date(2020, 2, 29)
+        );
+    }
+
+    #[test]
+    fn every_day() {
+        // every day of a leap and a non-leap year against every month based period:
+        for months in [1, 3, 6, 12] {
+            let period = CalendarPeriod::Months(months);
+            let mut until = date(2023, 1, 1);
+            while until < date(2025, 1, 1) {
+                let from = period.before(until);
+                let elapsed = (until.year() * 12 + until.month0() as i32)
+                    - (from.year() * 12 + from.month0() as i32);
+                assert_eq!(elapsed, months as i32, "{until} - {months} months");
+                let day = until.day().min(days_in_month(from.year(), from.month()));
+                assert_eq!(from.day(), day, "{until} - {months} months");
+
+                let to = period.after(until);
+                let elapsed = (to.year() * 12 + to.month0() as i32)
+                    - (until.year() * 12 + until.month0() as i32);
+                assert_eq!(elapsed, months as i32, "{until} + {months} months");
+                let day = until.day().min(days_in_month(to.year(), to.month()));
+                assert_eq!(to.day(), day, "{until} + {months} months");
+
+                until = until.succ_opt().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn days_and_weeks() {
+        let week = CalendarPeriod::from(Period::Week);
+        assert_eq!(week.before(date(2024, 3, 5)), date(2024, 2, 27));
+        assert_eq!(week.after(date(2024, 2, 27)), date(2024, 3, 5));
+        let day = CalendarPeriod::from(Period::Day);
+        assert_eq!(day.before(date(2024, 3, 1)), date(2024, 2, 29));
+        assert_eq!(day.before(date(2023, 3, 1)), date(2023, 2, 28));
+        // shorter periods stay within the day:
+        let hour = CalendarPeriod::from(Period::Hour);
+        assert_eq!(hour.before(date(2024, 3, 1)), date(2024, 3, 1));
+        assert_eq!(
+            CalendarPeriod::from(Period::Minute).after(date(2024, 3, 1)),
+            date(2024, 3, 1)
+        );
+    }
+
+    #[test]
+    fn trading_days() {
+        let nyse = TradingCalendar::nyse();
+        let day = CalendarPeriod::from(Period::Day);
+        // monday -> friday:
+        assert_eq!(
+            day.trading_days_before(date(2024, 3, 4), &nyse),
+            date(2024, 3, 1)
+        );
+        // over good friday and the weekend:
+        assert_eq!(
+            day.trading_days_before(date(2024, 4, 1), &nyse),
+            date(2024, 3, 28)
+        );
+        assert_eq!(
+            CalendarPeriod::Days(5).trading_days_before(date(2024, 7, 8), &nyse),
+            date(2024, 6, 28)
+        );
+
+        let month = CalendarPeriod::from(Period::Month);
+        // Mar 31 2024 - 1 month = Feb 29, a thursday:
+        assert_eq!(
+            month.trading_days_before(date(2024, 3, 31), &nyse),
+            date(2024, 2, 29)
+        );
+        // Apr 30 2024 - 1 month = Mar 30, a saturday after good friday:
+        assert_eq!(
+            month.trading_days_before(date(2024, 4, 30), &nyse),
+            date(2024, 3, 28)
+        );
+        // Dec 25 2024 is a holiday:
+        assert_eq!(
+            CalendarPeriod::from(Period::Year).trading_days_before(date(2025, 12, 25), &nyse),
+            date(2024, 12, 24)
+        );
+        // a week ending on a trading day starts on one (or the day before a holiday):
+        let week = CalendarPeriod::from(Period::Week);
+        assert_eq!(
+            week.trading_days_before(date(2024, 7, 11), &nyse),
+            date(2024, 7, 3)
+        );
+
+        // crypto trades every day:
+        let crypto = TradingCalendar::new(Exchange::Crypto);
+        assert_eq!(
+            day.trading_days_before(date(2024, 3, 4), &crypto),
+            date(2024, 3, 3)
+        );
+        assert_eq!(
+            month.trading_days_before(date(2024, 4, 30), &crypto),
+            date(2024, 3, 30)
+        );
+    }
+}
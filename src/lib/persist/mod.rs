@@ -0,0 +1,32 @@
+// persist keeps the stores of rustix (jobs, ledgers, lots) in json files. A file is written to a
+// temporary file first, so it's never left half written, and off the async runtime.
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::Path;
+
+// load reads a store, a missing file is an empty store:
+pub fn load<T: DeserializeOwned + Default>(path: &Path, name: &str) -> Result<T> {
+    match fs::read_to_string(path) {
+        Ok(js) => serde_json::from_str::<T>(&js)
+            .with_context(|| format!("invalid {} file {}", name, path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// save writes the serialized store, callers must not save the same file concurrently:
+pub async fn save(path: &Path, js: Vec<u8>, name: &str) -> Result<()> {
+    let path = path.to_path_buf();
+    let name = name.to_string();
+    tokio::task::spawn_blocking(move || {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, js)?;
+        fs::rename(&tmp, &path)
+            .with_context(|| format!("cannot save the {} file {}", name, path.display()))
+    })
+    .await?
+}
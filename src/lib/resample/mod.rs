@@ -0,0 +1,537 @@
+// resample aggregates time series into open/high/low/close/volume bars. Intraday bars are aligned
+// to the session open of the security's exchange (e.g. 9:30 new york time for nyse), daily bars
+// to the session's date (fx sessions open the evening before), weekly bars to the monday and
+// monthly bars to the first of the month. Whether an entry is part of a session (rather than
+// pre/post-market) is decided by the trading calendar.
+use crate::calendar::TradingCalendar;
+use crate::proto::dataloader as db_proto;
+use crate::time::{parse_date, parse_exchange_date_time};
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use utoipa::ToSchema;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Resolution {
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+    #[serde(rename = "1w")]
+    Week,
+    #[serde(rename = "1M")]
+    Month,
+}
+
+impl Resolution {
+    pub fn parse(s: &str) -> Result<Resolution> {
+        Ok(match s {
+            "1m" => Resolution::Minute,
+            "5m" => Resolution::FiveMinutes,
+            "15m" => Resolution::FifteenMinutes,
+            "1h" => Resolution::Hour,
+            "1d" => Resolution::Day,
+            "1w" => Resolution::Week,
+            "1M" => Resolution::Month,
+            _ => return Err(anyhow!("unknown resolution {}", s)),
+        })
+    }
+    pub fn is_intraday(&self) -> bool {
+        self.minutes().is_some()
+    }
+    pub fn minutes(&self) -> Option<i64> {
+        match self {
+            Resolution::Minute => Some(1),
+            Resolution::FiveMinutes => Some(5),
+            Resolution::FifteenMinutes => Some(15),
+            Resolution::Hour => Some(60),
+            _ => None,
+        }
+    }
+    // bucket returns the start of the bar, that contains the given exchange local time. Intraday
+    // bars are aligned to the session open.
+    pub fn bucket(&self, dt: NaiveDateTime, session_open: NaiveTime) -> NaiveDateTime {
+        let date = dt.date();
+        let day = |d: NaiveDate| d.and_time(NaiveTime::MIN);
+        match self.minutes() {
+            Some(minutes) => {
+                let open = date.and_time(session_open);
+                let offset = (dt - open).num_minutes().div_euclid(minutes) * minutes;
+                open + Duration::minutes(offset)
+            }
+            None => match self {
+                Resolution::Week => {
+                    day(date - Duration::days(date.weekday().num_days_from_monday() as i64))
+                }
+                Resolution::Month => day(date.with_day(1).unwrap()),
+                _ => day(date),
+            },
+        }
+    }
+    fn format(&self, dt: NaiveDateTime) -> String {
+        match self.is_intraday() {
+            true => dt.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            false => dt.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+struct Bar {
+    start: NaiveDateTime,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: Option<f64>,
+}
+
+// Resampler builds bars incrementally from a time series ordered by date.
+pub struct Resampler {
+    resolution: Resolution,
+    extended_hours: bool,
+    calendar: TradingCalendar,
+    bar: Option<Bar>,
+}
+
+impl Resampler {
+    pub fn new(
+        resolution: Resolution,
+        extended_hours: bool,
+        calendar: TradingCalendar,
+    ) -> Resampler {
+        Resampler {
+            resolution,
+            extended_hours,
+            calendar,
+            bar: None,
+        }
+    }
+    // push adds an entry to the current bar, and returns the previous bar if the entry started
+    // a new one. Entries without price and pre/post-market entries (unless requested) are skipped.
+    pub fn push(
+        &mut self,
+        entry: &db_proto::TimeSeriesData,
+    ) -> Result<Option<db_proto::TimeSeriesData>> {
+        // daily entries don't have a time and belong to the regular session:
+        let (dt, intraday) = match entry.date.len() {
+            10 => (parse_date(&entry.date)?.and_time(NaiveTime::MIN), false),
+            _ => (
+                parse_exchange_date_time(&entry.date, self.calendar.timezone())?,
+                true,
+            ),
+        };
+        if intraday && !self.extended_hours && self.calendar.session_at(dt).is_none() {
+            return Ok(None);
+        }
+        let value = |key: &str| entry.values.get(key).copied();
+        let Some(close) = value("close").or_else(|| value("price")) else {
+            return Ok(None);
+        };
+        let open = value("open").unwrap_or(close);
+        let high = value("high").unwrap_or(close.max(open));
+        let low = value("low").unwrap_or(close.min(open));
+        let volume = value("volume");
+
+        let open_time = self.calendar.open_time();
+        let start = match (intraday, self.resolution.is_intraday()) {
+            (false, true) => dt,
+            (true, false) => {
+                let session = self.calendar.session_date(dt);
+                self.resolution
+                    .bucket(session.and_time(NaiveTime::MIN), open_time)
+            }
+            _ => self.resolution.bucket(dt, open_time),
+        };
+        if let Some(bar) = self.bar.as_mut().filter(|bar| bar.start == start) {
+            bar.high = bar.high.max(high);
+            bar.low = bar.low.min(low);
+            bar.close = close;
+            bar.volume = match (bar.volume, volume) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            };
+            return Ok(None);
+        }
+        let bar = Bar {
+            start,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        };
+        Ok(self.bar.replace(bar).map(|bar| self.entry(bar)))
+    }
+    // finish returns the last (incomplete) bar:
+    pub fn finish(&mut self) -> Option<db_proto::TimeSeriesData> {
+        self.bar.take().map(|bar| self.entry(bar))
+    }
+    fn entry(&self, bar: Bar) -> db_proto::TimeSeriesData {
+        let mut values = HashMap::from([
+            ("open".to_string(), bar.open),
+            ("high".to_string(), bar.high),
+            ("low".to_string(), bar.low),
+            ("close".to_string(), bar.close),
+        ]);
+        if let Some(volume) = bar.volume {
+            values.insert("volume".to_string(), volume);
+        }
+        db_proto::TimeSeriesData {
+            date: self.resolution.format(bar.start),
+            values,
+        }
+    }
+}
+
+async fn resample_into<S>(
+    mut stream: S,
+    mut resampler: Resampler,
+    tx: &mpsc::Sender<Result<db_proto::TimeSeriesData, tonic::Status>>,
+) -> Result<()>
+where
+    S: Stream<Item = Result<db_proto::TimeSeriesData, tonic::Status>> + Unpin,
+{
+    loop {
+        let entry = tokio::select! {
+            _ = tx.closed() => return Ok(()),
+            entry = stream.next() => entry,
+        };
+        match entry {
+            Some(Ok(entry)) => {
+                if let Some(bar) = resampler.push(&entry)? {
+                    tx.send(Ok(bar)).await?;
+                }
+            }
+            Some(Err(status)) => {
+                tx.send(Err(status)).await?;
+                return Ok(());
+            }
+            None => break,
+        }
+    }
+    if let Some(bar) = resampler.finish() {
+        tx.send(Ok(bar)).await?;
+    }
+    Ok(())
+}
+
+// resample aggregates the stream on the fly, a bar is sent as soon as the next one started:
+pub fn resample<S>(
+    stream: S,
+    resolution: Resolution,
+    extended_hours: bool,
+    calendar: TradingCalendar,
+) -> ReceiverStream<Result<db_proto::TimeSeriesData, tonic::Status>>
+where
+    S: Stream<Item = Result<db_proto::TimeSeriesData, tonic::Status>> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let resampler = Resampler::new(resolution, extended_hours, calendar);
+        if let Err(err) = resample_into(stream, resampler, &tx).await {
+            println!("resample-error: {:?}", err);
+            let _ = tx.send(Err(tonic::Status::internal(err.to_string()))).await;
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::parse_date_time;
+
+    fn entry(
+        date: &str,
+        open: f64,
+        high: f64,
+        low: f64,
+        close: f64,
+        volume: f64,
+    ) -> db_proto::TimeSeriesData {
+        db_proto::TimeSeriesData {
+            date: date.to_string(),
+            values: HashMap::from([
+                ("open".to_string(), open),
+                ("high".to_string(), high),
+                ("low".to_string(), low),
+                ("close".to_string(), close),
+                ("volume".to_string(), volume),
+                ("trades".to_string(), 1.0),
+            ]),
+        }
+    }
+    fn run(
+        resampler: &mut Resampler,
+        entries: &[db_proto::TimeSeriesData],
+    ) -> Vec<db_proto::TimeSeriesData> {
+        let mut bars = entries
+            .iter()
+            .filter_map(|e| resampler.push(e).unwrap())
+            .collect::<Vec<_>>();
+        bars.extend(resampler.finish());
+        bars
+    }
+
+    #[test]
+    fn buckets() {
+        let dt = |d: &str| parse_date_time(d).unwrap();
+        let open = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+        let cases = [
+            (
+                Resolution::Minute,
+                "2024-01-02T09:31:59",
+                "2024-01-02T09:31:00",
+            ),
+            (
+                Resolution::FiveMinutes,
+                "2024-01-02T09:34:00",
+                "2024-01-02T09:30:00",
+            ),
+            (
+                Resolution::FifteenMinutes,
+                "2024-01-02T09:59:00",
+                "2024-01-02T09:45:00",
+            ),
+            // hourly bars start at half past:
+            (
+                Resolution::Hour,
+                "2024-01-02T10:29:00",
+                "2024-01-02T09:30:00",
+            ),
+            (
+                Resolution::Hour,
+                "2024-01-02T15:59:00",
+                "2024-01-02T15:30:00",
+            ),
+            // pre-market is aligned to the session as well:
+            (
+                Resolution::Hour,
+                "2024-01-02T09:00:00",
+                "2024-01-02T08:30:00",
+            ),
+            (
+                Resolution::Day,
+                "2024-01-02T15:59:00",
+                "2024-01-02T00:00:00",
+            ),
+            (
+                Resolution::Week,
+                "2024-01-07T10:00:00",
+                "2024-01-01T00:00:00",
+            ),
+            (
+                Resolution::Month,
+                "2024-02-29T10:00:00",
+                "2024-02-01T00:00:00",
+            ),
+        ];
+        for (resolution, date, bucket) in cases {
+            assert_eq!(
+                resolution.bucket(dt(date), open),
+                dt(bucket),
+                "{:?} {}",
+                resolution,
+                date
+            );
+        }
+    }
+
+    #[test]
+    fn ohlcv() {
+        let entries = [
+            entry("2024-01-02T09:30:00", 10.0, 12.0, 9.0, 11.0, 100.0),
+            entry("2024-01-02T09:45:00", 11.0, 15.0, 10.0, 14.0, 200.0),
+            entry("2024-01-02T10:30:00", 14.0, 14.0, 7.0, 8.0, 50.0),
+        ];
+        let bars = run(
+            &mut Resampler::new(Resolution::Hour, false, TradingCalendar::nyse()),
+            &entries,
+        );
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].date, "2024-01-02T09:30:00");
+        let expected = [
+            ("open", 10.0),
+            ("high", 15.0),
+            ("low", 9.0),
+            ("close", 14.0),
+            ("volume", 300.0),
+        ];
+        for (key, value) in expected {
+            assert_eq!(bars[0].values[key], value, "{}", key);
+        }
+        // other values are not aggregated:
+        assert!(!bars[0].values.contains_key("trades"));
+        assert_eq!(bars[1].date, "2024-01-02T10:30:00");
+        assert_eq!(bars[1].values["close"], 8.0);
+    }
+
+    #[test]
+    fn extended_hours() {
+        let entries = [
+            entry("2024-01-02T08:00:00", 9.0, 9.0, 9.0, 9.0, 10.0),
+            entry("2024-01-02T09:30:00", 10.0, 10.0, 10.0, 10.0, 10.0),
+            entry("2024-01-02T15:59:00", 11.0, 11.0, 11.0, 11.0, 10.0),
+            entry("2024-01-02T16:00:00", 12.0, 12.0, 12.0, 12.0, 10.0),
+            entry("2024-01-02T19:00:00", 13.0, 13.0, 13.0, 13.0, 10.0),
+        ];
+        let bars = run(
+            &mut Resampler::new(Resolution::Day, false, TradingCalendar::nyse()),
+            &entries,
+        );
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].date, "2024-01-02");
+        assert_eq!(bars[0].values["open"], 10.0);
+        assert_eq!(bars[0].values["close"], 11.0);
+        assert_eq!(bars[0].values["volume"], 20.0);
+
+        let bars = run(
+            &mut Resampler::new(Resolution::Day, true, TradingCalendar::nyse()),
+            &entries,
+        );
+        assert_eq!(bars[0].values["open"], 9.0);
+        assert_eq!(bars[0].values["close"], 13.0);
+        assert_eq!(bars[0].values["volume"], 50.0);
+
+        // holidays have no session, and early closes end the session at 13:00:
+        let entries = [
+            entry("2024-11-28T10:00:00", 9.0, 9.0, 9.0, 9.0, 10.0),
+            entry("2024-11-29T12:30:00", 10.0, 10.0, 10.0, 10.0, 10.0),
+            entry("2024-11-29T13:30:00", 11.0, 11.0, 11.0, 11.0, 10.0),
+        ];
+        let bars = run(
+            &mut Resampler::new(Resolution::Hour, false, TradingCalendar::nyse()),
+            &entries,
+        );
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].date, "2024-11-29T12:30:00");
+        assert_eq!(bars[0].values["close"], 10.0);
+        let bars = run(
+            &mut Resampler::new(Resolution::Hour, true, TradingCalendar::nyse()),
+            &entries,
+        );
+        assert_eq!(bars.len(), 3);
+    }
+
+    #[test]
+    fn other_exchanges() {
+        use crate::calendar::Exchange;
+        // crypto trades on weekends, and its sessions start at midnight utc:
+        let entries = [
+            entry("2024-01-06T00:10:00", 1.0, 1.0, 1.0, 1.0, 1.0),
+            entry("2024-01-06T23:50:00", 2.0, 2.0, 2.0, 2.0, 1.0),
+            entry("2024-01-07T00:10:00", 3.0, 3.0, 3.0, 3.0, 1.0),
+        ];
+        let calendar = TradingCalendar::new(Exchange::Crypto);
+        let bars = run(
+            &mut Resampler::new(Resolution::Day, false, calendar),
+            &entries,
+        );
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].values["close"], 2.0);
+
+        // lse opens at 8:00 london time, entries with an offset are converted to it:
+        let entries = [
+            entry("2024-07-01T07:59:00+01:00", 1.0, 1.0, 1.0, 1.0, 1.0),
+            entry("2024-07-01T07:10:00Z", 2.0, 2.0, 2.0, 2.0, 1.0),
+        ];
+        let calendar = TradingCalendar::new(Exchange::Lse);
+        let bars = run(
+            &mut Resampler::new(Resolution::Hour, false, calendar),
+            &entries,
+        );
+        assert_eq!(bars.len(), 1);
+        assert_eq!(bars[0].date, "2024-07-01T08:00:00");
+        assert_eq!(bars[0].values["close"], 2.0);
+
+        // fx sessions open at 17:00 new york time the day before, sunday evening is monday:
+        let entries = [
+            entry("2024-01-05T16:59:00", 1.0, 1.0, 1.0, 1.0, 1.0),
+            entry("2024-01-07T17:00:00", 2.0, 2.0, 2.0, 2.0, 1.0),
+            entry("2024-01-08T09:00:00", 3.0, 3.0, 3.0, 3.0, 1.0),
+            entry("2024-01-08T17:30:00", 4.0, 4.0, 4.0, 4.0, 1.0),
+        ];
+        let calendar = TradingCalendar::new(Exchange::Fx);
+        let bars = run(
+            &mut Resampler::new(Resolution::Day, false, calendar.clone()),
+            &entries,
+        );
+        let days = bars.iter().map(|b| b.date.as_str()).collect::<Vec<_>>();
+        assert_eq!(days, vec!["2024-01-05", "2024-01-08", "2024-01-09"]);
+        assert_eq!(bars[1].values["open"], 2.0);
+        assert_eq!(bars[1].values["close"], 3.0);
+        // the friday session is in the week of the 1st, sunday evening in the week of the 8th:
+        let bars = run(
+            &mut Resampler::new(Resolution::Week, false, calendar),
+            &entries,
+        );
+        let weeks = bars.iter().map(|b| b.date.as_str()).collect::<Vec<_>>();
+        assert_eq!(weeks, vec!["2024-01-01", "2024-01-08"]);
+    }
+
+    #[test]
+    fn daily_entries() {
+        let entries = [
+            entry("2024-01-31", 1.0, 2.0, 1.0, 2.0, 1.0),
+            db_proto::TimeSeriesData {
+                date: "2024-02-01".to_string(),
+                values: HashMap::from([("price".to_string(), 3.0)]),
+            },
+            entry("2024-02-02", 3.0, 5.0, 0.5, 4.0, 1.0),
+        ];
+        let bars = run(
+            &mut Resampler::new(Resolution::Month, false, TradingCalendar::nyse()),
+            &entries,
+        );
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].date, "2024-01-01");
+        assert_eq!(bars[1].date, "2024-02-01");
+        assert_eq!(bars[1].values["open"], 3.0);
+        assert_eq!(bars[1].values["low"], 0.5);
+        assert_eq!(bars[1].values["close"], 4.0);
+        assert_eq!(bars[1].values["volume"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn streamed() {
+        let entries = (0..10)
+            .map(|i| {
+                entry(
+                    &format!("2024-01-02T09:{}:00", 30 + i),
+                    1.0,
+                    1.0,
+                    1.0,
+                    1.0,
+                    1.0,
+                )
+            })
+            .map(Ok)
+            .chain([Err(tonic::Status::unavailable("gone"))])
+            .collect::<Vec<_>>();
+        let bars = resample(
+            futures::stream::iter(entries),
+            Resolution::FiveMinutes,
+            false,
+            TradingCalendar::nyse(),
+        )
+        .collect::<Vec<_>>()
+        .await;
+        // the incomplete bar is dropped, the error is passed on:
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].as_ref().unwrap().values["volume"], 5.0);
+        assert_eq!(
+            bars[1].as_ref().unwrap_err().code(),
+            tonic::Code::Unavailable
+        );
+    }
+}
@@ -0,0 +1,148 @@
+// splits back-adjusts prices and volumes for stock splits, so series are continuous across them.
+use crate::proto::dataloader as db_proto;
+use crate::time::parse_date;
+use anyhow::Result;
+
+// value keys holding prices (divided by the split factor) and volumes (multiplied by it):
+const PRICE_KEYS: [&str; 6] = ["open", "high", "low", "close", "price", "vwap"];
+const VOLUME_KEYS: [&str; 1] = ["volume"];
+
+// SplitAdjuster holds the splits of a single ticker. An entry is adjusted by the cumulative
+// ratio of all splits which took effect after its date. The split dates are validated upfront
+// and kept as iso dates, which compare like the date prefix of the entries.
+pub struct SplitAdjuster {
+    splits: Vec<(String, f64)>,
+}
+
+impl SplitAdjuster {
+    pub fn new(ticker: &str, splits: Vec<db_proto::StockSplit>) -> Result<SplitAdjuster> {
+        let mut splits = splits
+            .into_iter()
+            .filter(|s| s.ticker == ticker && s.numerator > 0.0 && s.denominator > 0.0)
+            .map(|s| {
+                Ok((
+                    parse_date(&s.date)?.to_string(),
+                    s.numerator / s.denominator,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        splits.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(SplitAdjuster { splits })
+    }
+    pub fn is_empty(&self) -> bool {
+        self.splits.is_empty()
+    }
+    // factor returns the cumulative split ratio of all splits after date:
+    pub fn factor(&self, date: &str) -> f64 {
+        let date = date.get(..10).unwrap_or(date);
+        self.splits
+            .iter()
+            .filter(|(split_date, _)| split_date.as_str() > date)
+            .map(|(_, ratio)| ratio)
+            .product()
+    }
+    // total returns the cumulative ratio of all splits, e.g. for a movement spanning them all:
+    pub fn total(&self) -> f64 {
+        self.splits.iter().map(|(_, ratio)| ratio).product()
+    }
+    pub fn adjust(&self, mut entry: db_proto::TimeSeriesData) -> db_proto::TimeSeriesData {
+        let factor = self.factor(&entry.date);
+        if factor != 1.0 {
+            for (key, value) in entry.values.iter_mut() {
+                if PRICE_KEYS.contains(&&key[..]) {
+                    *value /= factor;
+                } else if VOLUME_KEYS.contains(&&key[..]) {
+                    *value *= factor;
+                }
+            }
+        }
+        entry
+    }
+}
+
+// adjust_performance corrects a relative performance (0.1 being +10%) over a period, in which
+// the given splits took effect.
+pub fn adjust_performance(performance: f64, ratio: f64) -> f64 {
+    (1.0 + performance) * ratio - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn split(ticker: &str, date: &str, numerator: f64, denominator: f64) -> db_proto::StockSplit {
+        db_proto::StockSplit {
+            ticker: ticker.to_string(),
+            date: date.to_string(),
+            numerator,
+            denominator,
+        }
+    }
+    fn entry(date: &str, close: f64, volume: f64) -> db_proto::TimeSeriesData {
+        db_proto::TimeSeriesData {
+            date: date.to_string(),
+            values: HashMap::from([
+                ("close".to_string(), close),
+                ("volume".to_string(), volume),
+                ("trades".to_string(), 7.0),
+            ]),
+        }
+    }
+
+    #[test]
+    fn cumulative_factors() {
+        let adjuster = SplitAdjuster::new(
+            "NVDA",
+            vec![
+                split("NVDA", "2024-06-10", 10.0, 1.0),
+                split("AAPL", "2024-03-01", 4.0, 1.0),
+                split("NVDA", "2024-03-01", 4.0, 1.0),
+            ],
+        )
+        .unwrap();
+        assert_eq!(adjuster.factor("2024-01-02"), 40.0);
+        assert_eq!(adjuster.factor("2024-03-01T09:30:00"), 10.0);
+        assert_eq!(adjuster.factor("2024-06-10"), 1.0);
+        assert_eq!(adjuster.total(), 40.0);
+    }
+
+    #[test]
+    fn adjusts_prices_and_volumes() {
+        let adjuster = SplitAdjuster::new(
+            "XYZ",
+            vec![
+                split("XYZ", "2024-01-16", 4.0, 1.0),
+                // reverse split:
+                split("XYZ", "2024-01-20", 1.0, 2.0),
+            ],
+        )
+        .unwrap();
+
+        let adjusted = adjuster.adjust(entry("2024-01-12T15:30:00", 400.0, 100.0));
+        assert_eq!(adjusted.values["close"], 200.0);
+        assert_eq!(adjusted.values["volume"], 200.0);
+        assert_eq!(adjusted.values["trades"], 7.0);
+
+        let adjusted = adjuster.adjust(entry("2024-01-16T09:30:00", 100.0, 400.0));
+        assert_eq!(adjusted.values["close"], 200.0);
+        assert_eq!(adjusted.values["volume"], 200.0);
+
+        let adjusted = adjuster.adjust(entry("2024-01-22", 200.0, 200.0));
+        assert_eq!(adjusted.values["close"], 200.0);
+    }
+
+    #[test]
+    fn invalid_split_dates() {
+        assert!(SplitAdjuster::new("XYZ", vec![split("XYZ", "2024-13-01", 2.0, 1.0)]).is_err());
+        // other tickers' splits are not validated:
+        assert!(SplitAdjuster::new("XYZ", vec![split("ABC", "2024-13-01", 2.0, 1.0)]).is_ok());
+    }
+
+    #[test]
+    fn performance() {
+        // 100 -> 30 after a 4:1 split is a gain of 20%:
+        let raw = 30.0 / 100.0 - 1.0;
+        assert!((adjust_performance(raw, 4.0) - 0.2).abs() < 1e-12);
+    }
+}
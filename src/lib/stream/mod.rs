@@ -0,0 +1,512 @@
+use crate::error::{request_id, RustixErr};
+use actix_web::{http::header, web, HttpRequest};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use utoipa::IntoParams;
+
+#[derive(Debug)]
+pub struct StreamError {
+    src: String,
+}
+
+impl Error for StreamError {}
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.src)
+    }
+}
+impl StreamError {
+    pub fn new<T: ToString>(s: T) -> StreamError {
+        StreamError { src: s.to_string() }
+    }
+}
+
+impl From<String> for StreamError {
+    fn from(t: String) -> Self {
+        Self { src: t }
+    }
+}
+impl From<StreamError> for String {
+    fn from(e: StreamError) -> String {
+        e.src
+    }
+}
+
+pub type ActixStreamItem = Result<Bytes, StreamError>;
+pub type ActixStream = ReceiverStream<ActixStreamItem>;
+
+// StreamFormat is the wire format of a streaming endpoint:
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    // a single json array
+    #[default]
+    Json,
+    // one json document per line
+    NdJson,
+    // server-sent events, one `data:` event per entry
+    Sse,
+    // comma separated values with a header line
+    Csv,
+}
+
+// FormatQuery is the query parameter of streaming endpoints, that overrides the Accept header:
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormatQuery {
+    // json, ndjson, sse or csv:
+    pub format: Option<String>,
+}
+
+impl StreamFormat {
+    pub fn parse(format: &str) -> Result<StreamFormat> {
+        match &format.to_lowercase()[..] {
+            "json" => Ok(StreamFormat::Json),
+            "ndjson" | "jsonl" => Ok(StreamFormat::NdJson),
+            "sse" | "event-stream" => Ok(StreamFormat::Sse),
+            "csv" => Ok(StreamFormat::Csv),
+            f => Err(anyhow!(
+                "unknown format '{}' - expected one of json, ndjson, sse, csv",
+                f
+            )),
+        }
+    }
+    // negotiate picks the format from the `format` query parameter, or else the Accept header:
+    pub fn negotiate(req: &HttpRequest) -> Result<StreamFormat> {
+        let query = web::Query::<FormatQuery>::from_query(req.query_string())
+            .map_err(|err| anyhow!("invalid query: {}", err))?;
+        if let Some(format) = &query.format {
+            return StreamFormat::parse(format);
+        }
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|a| a.to_str().ok())
+            .unwrap_or("");
+        // the known type with the highest quality wins, the first listed of equal ones:
+        let mut accepted: Option<(StreamFormat, f64)> = None;
+        for (mime, quality) in accept.split(',').map(media_range) {
+            let format = match mime {
+                "application/json" => StreamFormat::Json,
+                "application/x-ndjson" | "application/jsonl" => StreamFormat::NdJson,
+                "text/event-stream" => StreamFormat::Sse,
+                "text/csv" => StreamFormat::Csv,
+                _ => continue,
+            };
+            if quality > 0.0 && accepted.is_none_or(|(_, best)| quality > best) {
+                accepted = Some((format, quality));
+            }
+        }
+        Ok(accepted.map(|(format, _)| format).unwrap_or_default())
+    }
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Json => "application/json",
+            StreamFormat::NdJson => "application/x-ndjson",
+            StreamFormat::Sse => "text/event-stream",
+            StreamFormat::Csv => "text/csv",
+        }
+    }
+}
+
+// media_range splits a media range of an Accept header into its type and quality, e.g.
+// "text/csv;q=0.9" into ("text/csv", 0.9). Without a valid q parameter the quality is 1.
+fn media_range(range: &str) -> (&str, f64) {
+    let mut params = range.split(';').map(|p| p.trim());
+    let mime = params.next().unwrap_or("");
+    let quality = params
+        .filter_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+        .find_map(|q| q.trim().parse::<f64>().ok())
+        .filter(|q| (0.0..=1.0).contains(q))
+        .unwrap_or(1.0);
+    (mime, quality)
+}
+
+// CsvRecord defines the csv layout of a streamed type. The header is taken from the first
+// record, so types with dynamic keys (e.g. time series values) get a stable column order.
+// Records with columns, which are not in that header, fail the stream instead of being
+// truncated. Keys that differ per record (e.g. custom fields) belong into a fixed column.
+pub trait CsvRecord {
+    fn csv_header(&self) -> Vec<String>;
+    fn csv_row(&self, header: &[String]) -> Vec<String>;
+}
+
+// csv_line quotes fields with separators, quotes or line breaks:
+pub fn csv_line(fields: &[String]) -> String {
+    let fields = fields
+        .iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("{}\n", fields.join(","))
+}
+
+// Encoder frames the entries of a stream according to its format:
+struct Encoder {
+    format: StreamFormat,
+    header: Option<Vec<String>>,
+    count: usize,
+}
+
+impl Encoder {
+    fn new(format: StreamFormat) -> Self {
+        Self {
+            format,
+            header: None,
+            count: 0,
+        }
+    }
+    fn start(&self) -> Option<Bytes> {
+        match self.format {
+            StreamFormat::Json => Some(Bytes::from("[")),
+            _ => None,
+        }
+    }
+    fn entry<T: Serialize + CsvRecord>(&mut self, entry: &T) -> Result<Bytes> {
+        let bytes = match self.format {
+            StreamFormat::Json => {
+                let js = serde_json::to_string(entry)?;
+                match self.count {
+                    0 => js,
+                    _ => format!(",{}", js),
+                }
+            }
+            StreamFormat::NdJson => format!("{}\n", serde_json::to_string(entry)?),
+            StreamFormat::Sse => format!("data: {}\n\n", serde_json::to_string(entry)?),
+            StreamFormat::Csv => {
+                let columns = entry.csv_header();
+                let header = self.header.get_or_insert_with(|| columns.clone());
+                if let Some(column) = columns.iter().find(|c| !header.contains(c)) {
+                    return Err(anyhow!(
+                        "csv column '{}' is not in the header of the first entry",
+                        column
+                    ));
+                }
+                let row = csv_line(&entry.csv_row(header));
+                match self.count {
+                    0 => format!("{}{}", csv_line(header), row),
+                    _ => row,
+                }
+            }
+        };
+        self.count += 1;
+        Ok(Bytes::from(bytes))
+    }
+    fn error(&self, err: anyhow::Error, request_id: Option<String>) -> Bytes {
+        let mut body = RustixErr::from(err).body();
+        body.request_id = request_id;
+        let js = serde_json::to_string(&serde_json::json!({ "error": body }))
+            .unwrap_or_else(|_| r#"{"error":{"code":"internal"}}"#.to_string());
+        let bytes = match self.format {
+            StreamFormat::Json if self.count > 0 => format!(",{}", js),
+            StreamFormat::Json => js,
+            StreamFormat::NdJson => format!("{}\n", js),
+            StreamFormat::Sse => format!("event: error\ndata: {}\n\n", js),
+            StreamFormat::Csv => format!("# error: {}\n", js),
+        };
+        Bytes::from(bytes)
+    }
+    fn end(&self) -> Option<Bytes> {
+        match self.format {
+            StreamFormat::Json => Some(Bytes::from("]")),
+            StreamFormat::Sse => Some(Bytes::from(format!(
+                "event: end\ndata: {{\"count\":{}}}\n\n",
+                self.count
+            ))),
+            _ => None,
+        }
+    }
+}
+
+// gprc_to_stream streams the gRPC entries in the given format. The output is always well
+// formed: if the gRPC stream fails, the error is appended as `{"error": {...}}` entry (an
+// `error` event for sse, a `# error:` line for csv). If the client disconnects, the task stops
+// and the gRPC stream is dropped, which cancels the upstream call.
+pub async fn gprc_to_stream<Src, T, S, Convert>(
+    mut stream: S,
+    convert: Convert,
+    format: StreamFormat,
+) -> ActixStream
+where
+    Src: Send + 'static,
+    T: Serialize + CsvRecord,
+    S: Stream<Item = Result<Src, tonic::Status>> + Send + Unpin + 'static,
+    Convert: Send + 'static + Fn(Src) -> Result<T>,
+{
+    let (tx, rx) = mpsc::channel::<ActixStreamItem>(100);
+    let request_id = request_id();
+
+    tokio::spawn(async move {
+        let mut encoder = Encoder::new(format);
+        if let Some(start) = encoder.start() {
+            tx.send(Ok(start)).await?;
+        }
+        loop {
+            let entry = tokio::select! {
+                _ = tx.closed() => {
+                    println!("gRPC-stream: client disconnected after {} entries", encoder.count);
+                    return Ok(());
+                }
+                entry = stream.next() => entry,
+            };
+            let Some(entry) = entry else {
+                break;
+            };
+            let encoded = entry
+                .map_err(anyhow::Error::from)
+                .and_then(&convert)
+                .and_then(|entry| encoder.entry(&entry));
+            match encoded {
+                Ok(bytes) => tx.send(Ok(bytes)).await?,
+                Err(err) => {
+                    println!(
+                        "gRPC-error: stream failed after {} entries: {:?}",
+                        encoder.count, err
+                    );
+                    tx.send(Ok(encoder.error(err, request_id))).await?;
+                    break;
+                }
+            }
+        }
+        if let Some(end) = encoder.end() {
+            tx.send(Ok(end)).await?;
+        }
+        Ok::<(), mpsc::error::SendError<ActixStreamItem>>(())
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tonic::Status;
+
+    #[derive(Serialize)]
+    struct Entry {
+        id: i32,
+        name: String,
+    }
+    impl CsvRecord for Entry {
+        fn csv_header(&self) -> Vec<String> {
+            vec!["id".to_string(), "name".to_string()]
+        }
+        fn csv_row(&self, _: &[String]) -> Vec<String> {
+            vec![self.id.to_string(), self.name.to_string()]
+        }
+    }
+    fn entry(id: i32) -> Result<Entry> {
+        Ok(Entry {
+            id,
+            name: format!("entry {}", id),
+        })
+    }
+
+    async fn collect(stream: ActixStream) -> String {
+        let chunks = stream.collect::<Vec<_>>().await;
+        chunks
+            .into_iter()
+            .map(|c| String::from_utf8(c.unwrap().to_vec()).unwrap())
+            .collect::<String>()
+    }
+    async fn json_body<S>(upstream: S) -> Value
+    where
+        S: Stream<Item = Result<i32, Status>> + Send + Unpin + 'static,
+    {
+        let body = collect(gprc_to_stream(upstream, entry, StreamFormat::Json).await).await;
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_complete() {
+        let body = json_body(futures::stream::iter(vec![Ok(1), Ok(2)])).await;
+        assert_eq!(
+            body,
+            json!([{"id": 1, "name": "entry 1"}, {"id": 2, "name": "entry 2"}])
+        );
+
+        let body = json_body(futures::stream::iter(vec![])).await;
+        assert_eq!(body, json!([]));
+    }
+
+    #[tokio::test]
+    async fn json_upstream_failure() {
+        let upstream = futures::stream::iter(vec![
+            Ok(1),
+            Ok(2),
+            Err(Status::unavailable("DataLoader went away")),
+            Ok(3),
+        ]);
+        let body = json_body(upstream).await;
+        let entries = body.as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1]["id"], 2);
+        assert_eq!(entries[2]["error"]["code"], "unavailable");
+        assert_eq!(entries[2]["error"]["message"], "DataLoader went away");
+
+        // a failing first entry still yields a valid array:
+        let upstream = futures::stream::iter(vec![Err(Status::not_found("unknown ticker"))]);
+        let body = json_body(upstream).await;
+        assert_eq!(body[0]["error"]["code"], "not_found");
+
+        // failing to convert an entry ends the stream the same way:
+        let convert = |n: i32| -> Result<Entry> {
+            match n {
+                2 => Err(anyhow!("cannot convert {}", n)),
+                n => entry(n),
+            }
+        };
+        let upstream = futures::stream::iter(vec![Ok(1), Ok(2), Ok(3)]);
+        let body = collect(gprc_to_stream(upstream, convert, StreamFormat::Json).await).await;
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body[0]["id"], 1);
+        assert_eq!(body[1]["error"]["code"], "internal");
+        assert_eq!(body.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn client_disconnect() {
+        let (upstream_tx, upstream_rx) = mpsc::channel::<Result<i32, Status>>(1);
+        let mut stream =
+            gprc_to_stream(ReceiverStream::new(upstream_rx), entry, StreamFormat::Json).await;
+        upstream_tx.send(Ok(1)).await.unwrap();
+        assert_eq!(&stream.next().await.unwrap().unwrap()[..], b"[");
+        assert!(stream.next().await.is_some());
+
+        // the client goes away, while the upstream has nothing to send:
+        drop(stream);
+        tokio::time::timeout(Duration::from_secs(1), upstream_tx.closed())
+            .await
+            .expect("upstream stream was not cancelled");
+    }
+
+    #[tokio::test]
+    async fn ndjson() {
+        let upstream = futures::stream::iter(vec![Ok(1), Ok(2), Err(Status::internal("boom"))]);
+        let body = collect(gprc_to_stream(upstream, entry, StreamFormat::NdJson).await).await;
+        let lines = body
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], json!({"id": 1, "name": "entry 1"}));
+        assert_eq!(lines[2]["error"]["code"], "internal");
+    }
+
+    #[tokio::test]
+    async fn sse() {
+        let upstream = futures::stream::iter(vec![Ok(1), Ok(2)]);
+        let body = collect(gprc_to_stream(upstream, entry, StreamFormat::Sse).await).await;
+        assert_eq!(
+            body,
+            "data: {\"id\":1,\"name\":\"entry 1\"}\n\n\
+             data: {\"id\":2,\"name\":\"entry 2\"}\n\n\
+             event: end\ndata: {\"count\":2}\n\n"
+        );
+
+        let upstream = futures::stream::iter(vec![Ok(1), Err(Status::internal("boom"))]);
+        let body = collect(gprc_to_stream(upstream, entry, StreamFormat::Sse).await).await;
+        assert!(body.contains("event: error\ndata: {\"error\":"));
+    }
+
+    #[tokio::test]
+    async fn csv() {
+        let convert = |n: i32| -> Result<Entry> {
+            Ok(Entry {
+                id: n,
+                name: format!("say \"{}\", twice", n),
+            })
+        };
+        let upstream = futures::stream::iter(vec![Ok(1), Ok(2)]);
+        let body = collect(gprc_to_stream(upstream, convert, StreamFormat::Csv).await).await;
+        assert_eq!(
+            body,
+            "id,name\n1,\"say \"\"1\"\", twice\"\n2,\"say \"\"2\"\", twice\"\n"
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Keys(Vec<&'static str>);
+    impl CsvRecord for Keys {
+        fn csv_header(&self) -> Vec<String> {
+            self.0.iter().map(|k| k.to_string()).collect()
+        }
+        fn csv_row(&self, header: &[String]) -> Vec<String> {
+            header.iter().map(|h| format!("{}?", h)).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn csv_columns_of_first_entry() {
+        let convert = |n: i32| -> Result<Keys> {
+            match n {
+                1 => Ok(Keys(vec!["a", "b"])),
+                2 => Ok(Keys(vec!["b"])),
+                _ => Ok(Keys(vec!["a", "c"])),
+            }
+        };
+        let upstream = futures::stream::iter(vec![Ok(1), Ok(2), Ok(3), Ok(4)]);
+        let body = collect(gprc_to_stream(upstream, convert, StreamFormat::Csv).await).await;
+        let lines = body.lines().collect::<Vec<_>>();
+        // fewer columns are fine, an unknown column ends the stream:
+        assert_eq!(lines[..3], ["a,b", "a?,b?", "a?,b?"]);
+        assert!(lines[3].starts_with("# error: "));
+        assert!(lines[3].contains("csv column 'c'"));
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn negotiation() {
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(StreamFormat::negotiate(&req).unwrap(), StreamFormat::Json);
+
+        let req = TestRequest::default()
+            .insert_header(("Accept", "text/csv;q=0.9, application/json"))
+            .to_http_request();
+        assert_eq!(StreamFormat::negotiate(&req).unwrap(), StreamFormat::Json);
+
+        let req = TestRequest::default()
+            .insert_header(("Accept", "application/json;q=0.5, text/csv; q=0.8, */*"))
+            .to_http_request();
+        assert_eq!(StreamFormat::negotiate(&req).unwrap(), StreamFormat::Csv);
+
+        // equal qualities keep the order, q=0 is not acceptable:
+        let req = TestRequest::default()
+            .insert_header((
+                "Accept",
+                "text/csv;q=0, application/x-ndjson, text/event-stream",
+            ))
+            .to_http_request();
+        assert_eq!(StreamFormat::negotiate(&req).unwrap(), StreamFormat::NdJson);
+
+        let req = TestRequest::default()
+            .insert_header(("Accept", "text/event-stream"))
+            .to_http_request();
+        assert_eq!(StreamFormat::negotiate(&req).unwrap(), StreamFormat::Sse);
+
+        // the query parameter overrides the accept header:
+        let req = TestRequest::default()
+            .uri("/api/tickers?format=ndjson")
+            .insert_header(("Accept", "text/csv"))
+            .to_http_request();
+        assert_eq!(StreamFormat::negotiate(&req).unwrap(), StreamFormat::NdJson);
+
+        let req = TestRequest::default()
+            .uri("/api/tickers?format=xml")
+            .to_http_request();
+        assert!(StreamFormat::negotiate(&req).is_err());
+    }
+}
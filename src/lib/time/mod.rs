@@ -1,6 +1,8 @@
-use anyhow::{anyhow, Result};
+use crate::calendar::TradingCalendar;
+use anyhow::Result;
 use chrono::{DateTime, Duration, DurationRound, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
 use chrono_tz::America::New_York;
+use chrono_tz::Tz;
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -8,23 +10,50 @@ lazy_static! {
     static ref REPLACE_TIMEZONE_RGX: Regex = Regex::new("[+-][0-9]{2}:[0-9]{2}$").unwrap();
 }
 
+// InvalidDateError marks dates that could not be parsed, so they can be reported as bad requests:
+#[derive(Debug)]
+pub struct InvalidDateError {
+    msg: String,
+}
+impl std::error::Error for InvalidDateError {}
+impl std::fmt::Display for InvalidDateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+fn invalid_date(msg: String) -> anyhow::Error {
+    InvalidDateError { msg }.into()
+}
+
 // parse_date expects a date formatted as "2023-08-31"
 pub fn parse_date(d: &str) -> Result<NaiveDate> {
     if let Some(d) = d.get(..10) {
         NaiveDate::parse_from_str(d, "%Y-%m-%d")
-            .map_err(|e| anyhow!("Invalid date format {}: {:?}", d, e))
+            .map_err(|e| invalid_date(format!("Invalid date format {}: {:?}", d, e)))
     } else {
-        Err(anyhow!(
+        Err(invalid_date(format!(
             "Invalid date format (too short) - expected '2006-12-31', got '{}'",
             d
-        ))
+        )))
     }
 }
 
 // parse_date_time expects a date formatted as "2023-08-31T09:30:00"
 pub fn parse_date_time(d: &str) -> Result<NaiveDateTime> {
     NaiveDateTime::parse_from_str(d, "%Y-%m-%dT%H:%M:%S")
-        .map_err(|e| anyhow!("Invalid date format {}: {:?}", d, e))
+        .map_err(|e| invalid_date(format!("Invalid date format {}: {:?}", d, e)))
+}
+
+// parse_exchange_date_time parses a date time in the exchange's timezone. Date times without an
+// offset (as delivered by the DataLoader) are exchange local times already, others are converted.
+pub fn parse_exchange_date_time(d: &str, tz: Tz) -> Result<NaiveDateTime> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(d) {
+        return Ok(dt.with_timezone(&tz).naive_local());
+    }
+    parse_date_time(d.get(..19).unwrap_or(d))
+}
+pub fn parse_new_york_date_time(d: &str) -> Result<NaiveDateTime> {
+    parse_exchange_date_time(d, New_York)
 }
 
 pub fn utc_until_tomorrow() -> Result<Duration> {
@@ -34,31 +63,14 @@ pub fn until_tomorrow(d: DateTime<Utc>) -> Result<Duration> {
     let utc_tomorrow = d.duration_round(Duration::days(1)).unwrap();
     Ok(utc_tomorrow - d)
 }
+// until_nyse_trading_hours_end returns the time until the current (or next) session closes:
 pub fn until_nyse_trading_hours_end(utc_time: DateTime<Utc>) -> Result<Duration> {
-    let nyse_hours_end = Duration::hours(16); // nyse trading hours end at 4pm eastern time (11:00 utc)
-    let ny_time = utc_time.with_timezone(&New_York);
-    let ny_end = ny_time.duration_trunc(Duration::days(1)).unwrap() + nyse_hours_end;
-    let ny_end = match ny_end - ny_time {
-        d if d < Duration::zero() => {
-            ny_time.duration_trunc(Duration::days(1)).unwrap() + Duration::days(1) + nyse_hours_end
-        }
-        _ => ny_end,
-    };
-    Ok(ny_end - ny_time)
+    Ok(TradingCalendar::nyse().next_close(utc_time) - utc_time)
 }
+// until_nyse_trading_hours_start returns the time until the next session opens, skipping
+// weekends and holidays:
 pub fn until_nyse_trading_hours_start(utc_time: DateTime<Utc>) -> Result<Duration> {
-    let nyse_hours_start = Duration::hours(9) + Duration::minutes(30); // nyse trading hours start at 9:30am eastern time (14:30 utc (utc = eastern time + 5 Hours))
-    let ny_time = utc_time.with_timezone(&New_York);
-    let ny_start = ny_time.duration_trunc(Duration::days(1)).unwrap() + nyse_hours_start;
-    let ny_start = match ny_start - ny_time {
-        d if d < Duration::zero() => {
-            ny_time.duration_trunc(Duration::days(1)).unwrap()
-                + Duration::days(1)
-                + nyse_hours_start
-        }
-        _ => ny_start,
-    };
-    Ok(ny_start - ny_time)
+    Ok(TradingCalendar::nyse().next_open(utc_time) - utc_time)
 }
 pub fn new_york_now() -> DateTime<chrono_tz::Tz> {
     chrono::offset::Local::now().with_timezone(&New_York)
@@ -69,9 +81,8 @@ pub fn utc_now() -> DateTime<Utc> {
 
 pub async fn wait_until_trading_hours_started(lbl: &str) -> Result<()> {
     let now = utc_now();
-    let until_nyse_start = until_nyse_trading_hours_start(now)?;
-    let until_nyse_end = until_nyse_trading_hours_end(now)?;
-    if until_nyse_start < until_nyse_end {
+    if !TradingCalendar::nyse().is_open(now) {
+        let until_nyse_start = until_nyse_trading_hours_start(now)?;
         // trading hours havent started yet, wait until they do:
         println!(
             "{}: waiting until trading hours start: {:?}",
@@ -129,6 +140,28 @@ mod tests {
         assert_eq!(ut, chrono::Duration::hours(24i64 - hours as i64));
     }
     #[test]
+    fn new_york_date_times() {
+        let expected = parse_date_time("2024-01-02T09:30:00").unwrap();
+        assert_eq!(
+            parse_new_york_date_time("2024-01-02T09:30:00").unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_new_york_date_time("2024-01-02T09:30:00.000").unwrap(),
+            expected
+        );
+        assert_eq!(
+            parse_new_york_date_time("2024-01-02T14:30:00Z").unwrap(),
+            expected
+        );
+        // daylight saving time:
+        assert_eq!(
+            parse_new_york_date_time("2024-07-01T13:30:00+00:00").unwrap(),
+            parse_date_time("2024-07-01T09:30:00").unwrap()
+        );
+        assert!(parse_new_york_date_time("2024-01-02").is_err());
+    }
+    #[test]
     fn summer_time() {
         let hours = 4;
         let dt = NaiveDate::from_ymd_opt(2023, 8, 28)
@@ -196,6 +229,34 @@ mod tests {
         );
     }
     #[test]
+    fn weekends_and_holidays() {
+        let ny = |y, m, d, h| {
+            New_York
+                .from_local_datetime(
+                    &NaiveDate::from_ymd_opt(y, m, d)
+                        .unwrap()
+                        .and_hms_opt(h, 0, 0)
+                        .unwrap(),
+                )
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        // saturday noon until monday 9:30:
+        let until_start = until_nyse_trading_hours_start(ny(2024, 1, 6, 12)).unwrap();
+        assert_eq!(
+            until_start,
+            Duration::hours(12 + 24 + 9) + Duration::minutes(30)
+        );
+        // christmas eve closes at 13:00, and christmas day is a holiday:
+        let until_end = until_nyse_trading_hours_end(ny(2024, 12, 24, 12)).unwrap();
+        assert_eq!(until_end, Duration::hours(1));
+        let until_start = until_nyse_trading_hours_start(ny(2024, 12, 24, 14)).unwrap();
+        assert_eq!(
+            until_start,
+            Duration::hours(10 + 24 + 9) + Duration::minutes(30)
+        );
+    }
+    #[test]
     fn current_datetime() {
         let now = utc_now().trunc_subsecs(1);
         assert_eq!(
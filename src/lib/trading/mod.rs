@@ -1,52 +1,36 @@
+use crate::analytics::{self, Closes, Holding, Performance, Split};
+use crate::calendar::TradingCalendar;
+use crate::columnar::{time_series_to_columnar, ColumnarFormat, BATCH_SIZE};
+use crate::enums::{Period, SecurityType, Sign, SortBy};
 use crate::envs::Envs;
+use crate::fx::{self, FxError, FxRates, FxValuation, FxValuationReq, PortfolioCurrencies};
+use crate::indicators::{warm_up_start, IndicatorSet, IndicatorSpec};
+use crate::lots::{self, Lot, LotGain, LotMethod, LotReport, LotSale};
+use crate::period::CalendarPeriod;
 use crate::proto::dataloader::data_loader_client::DataLoaderClient;
-use crate::proto::dataloader::{self as db_proto, Period, StockSplitReq};
+use crate::proto::dataloader::{self as db_proto, StockSplitReq};
+use crate::resample::{resample, Resolution};
+use crate::splits::{adjust_performance, SplitAdjuster};
+use crate::stream::{gprc_to_stream, CsvRecord, StreamFormat};
 use crate::time::parse_date;
-use anyhow::Result;
-use bytes::Bytes;
+use crate::validate::{Validate, Validator};
+use anyhow::{anyhow, Result};
 use chrono::{Duration, NaiveDate};
+use futures::{future, StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
-use std::error::Error;
-use std::fmt;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
-use tokio_stream::StreamExt;
-use tonic::transport::Channel;
-use tonic::Streaming;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::pin::Pin;
+use std::time::Duration as StdDuration;
+use tokio_stream::Stream;
+use tonic::transport::{Channel, Endpoint};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug)]
-pub struct StreamError {
-    src: String,
-}
-
-impl Error for StreamError {}
-impl fmt::Display for StreamError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.src)
-    }
-}
-impl StreamError {
-    pub fn new<T: ToString>(s: T) -> StreamError {
-        StreamError { src: s.to_string() }
-    }
-}
+pub use crate::stream::{ActixStream, ActixStreamItem, StreamError};
 
-impl From<String> for StreamError {
-    fn from(t: String) -> Self {
-        Self { src: t }
-    }
-}
-impl From<StreamError> for String {
-    fn from(e: StreamError) -> String {
-        e.src
-    }
-}
-
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TickerFilter {
     #[serde(rename = "security_type")]
-    pub ttype: i32,
+    pub ttype: SecurityType,
     #[serde(default)]
     pub filter: Option<String>,
     #[serde(default)]
@@ -58,33 +42,50 @@ pub struct TickerFilter {
 impl From<TickerFilter> for db_proto::TickerFilter {
     fn from(t: TickerFilter) -> Self {
         Self {
-            ticker_type: t.ttype,
+            ticker_type: t.ttype.into(),
             filter: t.filter.unwrap_or("".to_string()),
             limit: t.limit.unwrap_or(100),
             traded_within_past_n_days: t.traded_within_past_n_days.unwrap_or(10),
         }
     }
 }
+impl Validate for TickerFilter {
+    fn check(&self, v: &mut Validator) {
+        if let Some(limit) = self.limit {
+            v.limit("limit", limit.into());
+        }
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct MovementsReq {
-    pub security_type: u32,
-    pub sort_by: u32,
+    pub security_type: SecurityType,
+    pub sort_by: SortBy,
+    // jobs take movement snapshots until the day they run, so their requests leave it out:
+    #[serde(default)]
     pub until: String,
-    pub period: u32,
+    pub period: Period,
     pub limit: u32,
     pub min_volume: u64,
     pub min_variance: f64,
     pub max_variance: f64,
     pub without_stock_splits: Option<bool>,
+    // adjusted corrects the performance of tickers with stock splits, instead of removing them.
+    // The DataLoader applies the limit before, so split tickers below it are not ranked:
+    #[serde(default)]
+    pub adjusted: Option<bool>,
+    // trading_days counts the period in trading sessions, when looking for stock splits, so the
+    // day before a monday is the friday:
+    #[serde(default)]
+    pub trading_days: Option<bool>,
 }
 impl From<MovementsReq> for db_proto::MovementsReq {
     fn from(m: MovementsReq) -> Self {
         Self {
-            security_type: m.security_type as i32,
-            sort_by: m.sort_by as i32,
+            security_type: m.security_type.into(),
+            sort_by: m.sort_by.into(),
             until: m.until,
-            period: m.period as i32,
+            period: m.period.into(),
             limit: m.limit,
             min_volume: m.min_volume,
             min_variance: m.min_variance,
@@ -92,75 +93,83 @@ impl From<MovementsReq> for db_proto::MovementsReq {
         }
     }
 }
-
-impl From<u32> for Period {
-    fn from(x: u32) -> Self {
-        match x {
-            x if x == Period::Year as u32 => Period::Year,
-            x if x == Period::SemiAnnual as u32 => Period::SemiAnnual,
-            x if x == Period::Quarter as u32 => Period::Quarter,
-            x if x == Period::Month as u32 => Period::Month,
-            x if x == Period::Week as u32 => Period::Week,
-            x if x == Period::Day as u32 => Period::Day,
-            x if x == Period::Hour as u32 => Period::Hour,
-            x if x == Period::Minute as u32 => Period::Minute,
-            _ => Period::Month,
+impl Validate for MovementsReq {
+    fn check(&self, v: &mut Validator) {
+        v.date("until", &self.until);
+        v.limit("limit", self.limit.into());
+        v.non_negative("min_variance", self.min_variance);
+        v.non_negative("max_variance", self.max_variance);
+        // a max_variance of 0 is no upper bound:
+        if self.max_variance > 0.0 && self.min_variance > self.max_variance {
+            v.error(
+                "min_variance",
+                format!("must not exceed max_variance ({})", self.max_variance),
+            );
         }
     }
 }
 
-impl From<Period> for Duration {
-    fn from(p: Period) -> Self {
-        match p {
-            Period::Year => Duration::days(365),
-            Period::SemiAnnual => Duration::days(180),
-            Period::Quarter => Duration::days(90),
-            Period::Month => Duration::days(30),
-            Period::Week => Duration::days(7),
-            Period::Day => Duration::days(1),
-            Period::Hour => Duration::hours(1),
-            Period::Minute => Duration::minutes(1),
-        }
-    }
-}
-fn eval_from_date(until: &str, period: Period) -> Result<NaiveDate> {
-    let until = parse_date(until)?;
-    let period: Duration = period.into();
-    Ok(until - period)
-}
-
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CorrelatingTickersReq {
     pub until: String,
-    pub period: u32,
+    pub period: Period,
     pub limit: u32,
     pub min_volume: Option<u64>,
-    pub sign: Option<u32>,
+    pub sign: Option<Sign>,
 }
 impl From<CorrelatingTickersReq> for db_proto::CorrelTickersReq {
     fn from(c: CorrelatingTickersReq) -> Self {
         Self {
             until: c.until,
-            period: c.period as i32,
+            period: c.period.into(),
             limit: c.limit,
             min_volume: c.min_volume.unwrap_or_default(),
-            sign: c.sign.unwrap_or(0) as i32,
+            sign: c.sign.unwrap_or(Sign::Abs).into(),
         }
     }
 }
+impl Validate for CorrelatingTickersReq {
+    fn check(&self, v: &mut Validator) {
+        v.date("until", &self.until);
+        v.limit("limit", self.limit.into());
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Ticker {
-    ticker: String,
-    name: Option<String>,
-    security_type: i32,
+    pub ticker: String,
+    pub name: Option<String>,
+    pub security_type: i32,
     #[serde(flatten)]
-    custom_fields: Option<HashMap<String, String>>,
+    pub custom_fields: Option<HashMap<String, String>>,
+}
+// the custom fields differ per ticker, so they are a single json column with sorted keys:
+impl CsvRecord for Ticker {
+    fn csv_header(&self) -> Vec<String> {
+        ["ticker", "name", "security_type", "custom_fields"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect()
+    }
+    fn csv_row(&self, _: &[String]) -> Vec<String> {
+        let custom = self
+            .custom_fields
+            .iter()
+            .flatten()
+            .collect::<BTreeMap<_, _>>();
+        vec![
+            self.ticker.to_string(),
+            self.name.clone().unwrap_or_default(),
+            self.security_type.to_string(),
+            serde_json::to_string(&custom).unwrap_or_default(),
+        ]
+    }
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct BasicTicker {
     pub ticker: String,
-    pub security_type: i32,
+    pub security_type: SecurityType,
 }
 
 impl From<db_proto::Ticker> for Ticker {
@@ -177,10 +186,15 @@ impl From<BasicTicker> for db_proto::BasicTicker {
     fn from(t: BasicTicker) -> Self {
         Self {
             ticker: t.ticker,
-            security_type: t.security_type,
+            security_type: t.security_type.into(),
         }
     }
 }
+impl Validate for BasicTicker {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("ticker", &self.ticker);
+    }
+}
 impl Clone for BasicTicker {
     fn clone(&self) -> Self {
         Self {
@@ -190,10 +204,10 @@ impl Clone for BasicTicker {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TimeSeriesData {
-    date: String,
-    values: HashMap<String, f64>,
+    pub date: String,
+    pub values: HashMap<String, f64>,
 }
 impl From<db_proto::TimeSeriesData> for TimeSeriesData {
     fn from(s: db_proto::TimeSeriesData) -> Self {
@@ -203,11 +217,39 @@ impl From<db_proto::TimeSeriesData> for TimeSeriesData {
         }
     }
 }
-#[derive(Serialize, Deserialize)]
+impl CsvRecord for TimeSeriesData {
+    fn csv_header(&self) -> Vec<String> {
+        let mut keys = self.values.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        let mut header = vec!["date".to_string()];
+        header.extend(keys);
+        header
+    }
+    fn csv_row(&self, header: &[String]) -> Vec<String> {
+        let mut row = vec![self.date.to_string()];
+        row.extend(header[1..].iter().map(|key| {
+            self.values
+                .get(key)
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        }));
+        row
+    }
+}
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct TimeSeriesReq {
     pub ticker: BasicTicker,
     pub from: String,
     pub until: String,
+    // adjusted back-adjusts prices and volumes for stock splits within from and until:
+    #[serde(default)]
+    pub adjusted: Option<bool>,
+    // resolution aggregates the raw feed into ohlcv bars:
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+    // extended_hours includes pre- and post-market data in the bars:
+    #[serde(default)]
+    pub extended_hours: Option<bool>,
 }
 impl From<TimeSeriesReq> for db_proto::TimeSeriesReq {
     fn from(t: TimeSeriesReq) -> Self {
@@ -215,12 +257,46 @@ impl From<TimeSeriesReq> for db_proto::TimeSeriesReq {
             ticker: Some(t.ticker.into()),
             from_date: t.from,
             until_date: t.until,
-            intraday: true,
+            // daily and longer bars are built from the daily feed:
+            intraday: t.resolution.is_none_or(|r| r.is_intraday()),
         }
     }
 }
+impl Validate for TimeSeriesReq {
+    fn check(&self, v: &mut Validator) {
+        v.nested("ticker", &self.ticker);
+        v.date_range(("from", &self.from), ("until", &self.until));
+    }
+}
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct IndicatorsReq {
+    pub ticker: BasicTicker,
+    pub from: String,
+    pub until: String,
+    #[serde(default)]
+    pub adjusted: Option<bool>,
+    // resolution of the bars the indicators are computed on, daily if not set:
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
+    #[serde(default)]
+    pub extended_hours: Option<bool>,
+    pub indicators: Vec<IndicatorSpec>,
+}
+impl Validate for IndicatorsReq {
+    fn check(&self, v: &mut Validator) {
+        v.nested("ticker", &self.ticker);
+        v.date_range(("from", &self.from), ("until", &self.until));
+        v.not_empty("indicators", &self.indicators);
+        for (i, indicator) in self.indicators.iter().enumerate() {
+            if let Err(err) = indicator.validate() {
+                v.error(&format!("indicators[{}]", i), err.to_string());
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Movement {
     pub ticker: Ticker,
     pub performance: f64,
@@ -251,23 +327,55 @@ impl From<db_proto::Movement> for Movement {
     }
 }
 
-#[derive(Serialize)]
+impl CsvRecord for Movement {
+    fn csv_header(&self) -> Vec<String> {
+        [
+            "ticker",
+            "security_type",
+            "performance",
+            "average",
+            "volume",
+            "variance",
+            "stddev",
+            "date",
+            "period",
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+    }
+    fn csv_row(&self, _: &[String]) -> Vec<String> {
+        vec![
+            self.ticker.ticker.to_string(),
+            self.ticker.security_type.to_string(),
+            self.performance.to_string(),
+            self.average.to_string(),
+            self.volume.to_string(),
+            self.variance.to_string(),
+            self.stddev.to_string(),
+            self.date.to_string(),
+            self.period.to_string(),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct DetailedCorrel {
-    ticker0: Ticker,
-    ticker1: Ticker,
-    date: String,
-    period: i32,
-    correlation: f64,
+    pub ticker0: Ticker,
+    pub ticker1: Ticker,
+    pub date: String,
+    pub period: i32,
+    pub correlation: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct MutualCorrel {
-    ticker: Ticker,
-    correlations: Vec<DetailedCorrel>,
-    volatility: f64,
-    stddev: f64,
-    performance: f64,
-    volume: f64,
+    pub ticker: Ticker,
+    pub correlations: Vec<DetailedCorrel>,
+    pub volatility: f64,
+    pub stddev: f64,
+    pub performance: f64,
+    pub volume: f64,
 }
 impl TryFrom<db_proto::MutualCorrel> for MutualCorrel {
     type Error = StreamError;
@@ -293,8 +401,14 @@ impl TryFrom<db_proto::DetailedCorrel> for DetailedCorrel {
     type Error = StreamError;
     fn try_from(c: db_proto::DetailedCorrel) -> Result<Self, Self::Error> {
         Ok(Self {
-            ticker0: c.ticker0.unwrap().into(),
-            ticker1: c.ticker1.unwrap().into(),
+            ticker0: c
+                .ticker0
+                .ok_or_else(|| StreamError::from("DetailedCorrel - invalid ticker0".to_string()))?
+                .into(),
+            ticker1: c
+                .ticker1
+                .ok_or_else(|| StreamError::from("DetailedCorrel - invalid ticker1".to_string()))?
+                .into(),
             date: c.date,
             period: c.period,
             correlation: c.correl,
@@ -313,13 +427,18 @@ impl Clone for Ticker {
 }
 
 pub type Portfolios = Vec<Portfolio>;
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct Portfolio {
     pub id: String,
     pub name: String,
     pub description: String,
 }
 
+impl Validate for Portfolio {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("name", &self.name);
+    }
+}
 impl From<db_proto::PortfolioMeta> for Portfolio {
     fn from(p: db_proto::PortfolioMeta) -> Self {
         Self {
@@ -329,6 +448,21 @@ impl From<db_proto::PortfolioMeta> for Portfolio {
         }
     }
 }
+impl CsvRecord for Portfolio {
+    fn csv_header(&self) -> Vec<String> {
+        ["id", "name", "description"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect()
+    }
+    fn csv_row(&self, _: &[String]) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.name.to_string(),
+            self.description.to_string(),
+        ]
+    }
+}
 impl From<db_proto::PortfolioMetas> for Vec<Portfolio> {
     fn from(p: db_proto::PortfolioMetas) -> Self {
         p.portfolios.into_iter().map(|p| p.into()).collect()
@@ -336,14 +470,14 @@ impl From<db_proto::PortfolioMetas> for Vec<Portfolio> {
 }
 
 pub type PortfolioSecurities = Vec<PortfolioSecurity>;
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
 pub struct PortfolioSecurity {
-    portfolio_id: String,
-    security_type: i32,
-    ticker: String,
-    volume: f64,
-    purchase_date: String,
-    sell_date: String,
+    pub portfolio_id: String,
+    pub security_type: i32,
+    pub ticker: String,
+    pub volume: f64,
+    pub purchase_date: String,
+    pub sell_date: String,
 }
 
 impl From<db_proto::PortfolioSecurity> for PortfolioSecurity {
@@ -370,21 +504,81 @@ impl From<PortfolioSecurity> for db_proto::PortfolioSecurity {
         }
     }
 }
+impl Validate for PortfolioSecurity {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.not_blank("ticker", &self.ticker);
+        v.positive("volume", self.volume);
+        let purchase_date = v.date("purchase_date", &self.purchase_date);
+        // securities, which have not been sold, have an empty sell date:
+        let sell_date = v.optional_date("sell_date", Some(&self.sell_date));
+        v.ordered_dates(
+            ("purchase_date", &self.purchase_date),
+            purchase_date,
+            ("sell_date", &self.sell_date),
+            sell_date,
+        );
+    }
+}
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Security {
-    security_type: i32,
-    ticker: String,
-    volume: f64,
-    purchase_date: Option<String>,
-    sell_date: Option<String>,
+    pub security_type: i32,
+    pub ticker: String,
+    pub volume: f64,
+    pub purchase_date: Option<String>,
+    pub sell_date: Option<String>,
+}
+impl Validate for Security {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("ticker", &self.ticker);
+        v.positive("volume", self.volume);
+        let purchase = self.purchase_date.as_deref().unwrap_or_default();
+        let sell = self.sell_date.as_deref().unwrap_or_default();
+        let purchase_date = v.optional_date("purchase_date", Some(purchase));
+        let sell_date = v.optional_date("sell_date", Some(sell));
+        v.ordered_dates(
+            ("purchase_date", purchase),
+            purchase_date,
+            ("sell_date", sell),
+            sell_date,
+        );
+    }
 }
-#[derive(Serialize, Deserialize)]
+// a portfolio's securities are valued from their purchase, until they were sold or until today:
+impl From<PortfolioSecurity> for Security {
+    fn from(s: PortfolioSecurity) -> Self {
+        Self {
+            security_type: s.security_type,
+            ticker: s.ticker,
+            volume: s.volume,
+            purchase_date: Some(s.purchase_date),
+            sell_date: Some(s.sell_date).filter(|d| !d.is_empty()),
+        }
+    }
+}
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SecurityProfitReq {
+    // the date until which the profits are computed:
+    #[schema(example = "2024-01-31")]
     pub util: String,
+    // the period the profits are partitioned by, as integer (e.g. 3 for months):
+    #[schema(example = 3)]
     pub parition: i32,
     pub securities: Vec<Security>,
 }
+impl Validate for SecurityProfitReq {
+    fn check(&self, v: &mut Validator) {
+        v.date("util", &self.util);
+        v.check(
+            "parition",
+            Period::try_from(self.parition).is_ok(),
+            "unknown period",
+        );
+        v.not_empty("securities", &self.securities);
+        v.each("securities", &self.securities);
+    }
+}
 
 impl From<SecurityProfitReq> for db_proto::SecurityProfitReq {
     fn from(req: SecurityProfitReq) -> Self {
@@ -406,17 +600,223 @@ impl From<SecurityProfitReq> for db_proto::SecurityProfitReq {
     }
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PortfolioAnalyticsReq {
+    pub portfolio_id: String,
+    pub from: String,
+    pub until: String,
+    // the annual rate of the sharpe and sortino ratios, e.g. 0.04 for 4%, 0 if not set:
+    #[serde(default)]
+    pub risk_free_rate: Option<f64>,
+    // a ticker to compare the portfolio with, e.g. SPY:
+    #[serde(default)]
+    pub benchmark: Option<BasicTicker>,
+}
+impl Validate for PortfolioAnalyticsReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.date_range(("from", &self.from), ("until", &self.until));
+        if let Some(rate) = self.risk_free_rate {
+            v.check(
+                "risk_free_rate",
+                rate.is_finite() && rate > -1.0,
+                "must be an annual rate above -1, e.g. 0.04 for 4%",
+            );
+        }
+        if let Some(benchmark) = &self.benchmark {
+            v.nested("benchmark", benchmark);
+        }
+    }
+}
+impl TryFrom<&PortfolioSecurity> for Holding {
+    type Error = anyhow::Error;
+    fn try_from(s: &PortfolioSecurity) -> Result<Self> {
+        Ok(Self {
+            ticker: s.ticker.to_string(),
+            volume: s.volume,
+            purchase_date: parse_date(&s.purchase_date)?,
+            sell_date: match s.sell_date.is_empty() {
+                true => None,
+                false => Some(parse_date(&s.sell_date)?),
+            },
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LotReportReq {
+    pub portfolio_id: String,
+    // lots sold until this date are realized, the others are valued at its close:
+    #[schema(example = "2024-01-31")]
+    pub until: String,
+}
+impl Validate for LotReportReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.date("until", &self.until);
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LotSellReq {
+    pub portfolio_id: String,
+    pub ticker: String,
+    pub security_type: SecurityType,
+    // the sold shares, after the splits until the date:
+    pub volume: f64,
+    #[schema(example = "2024-01-31")]
+    pub date: String,
+    #[serde(default)]
+    pub method: LotMethod,
+    // the ids of the sold lots in the order they are sold, only for the specific method:
+    #[serde(default)]
+    pub lot_ids: Vec<String>,
+}
+impl Validate for LotSellReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("portfolio_id", &self.portfolio_id);
+        v.not_blank("ticker", &self.ticker);
+        v.positive("volume", self.volume);
+        v.date("date", &self.date);
+        match self.method {
+            LotMethod::Specific => v.not_empty("lot_ids", &self.lot_ids),
+            _ => v.check(
+                "lot_ids",
+                self.lot_ids.is_empty(),
+                "lots are only chosen by id with the specific method",
+            ),
+        }
+        for (i, id) in self.lot_ids.iter().enumerate() {
+            v.check(
+                &format!("lot_ids[{}]", i),
+                !self.lot_ids[..i].contains(id),
+                "is listed twice",
+            );
+        }
+    }
+}
+// lot_ids names the lots of the securities, the open and the sold lots are counted separately,
+// so the ids of the open lots stay the same, when a lot of the same day is sold:
+fn lot_ids(securities: &[PortfolioSecurity]) -> Vec<String> {
+    let mut counts = HashMap::new();
+    securities
+        .iter()
+        .map(|s| {
+            let key = (s.sell_date.is_empty(), &s.ticker, &s.purchase_date);
+            let n = counts.entry(key).or_insert(0);
+            *n += 1;
+            lots::lot_id(&s.ticker, &s.purchase_date, *n - 1)
+        })
+        .collect()
+}
+// kind_ordinals numbers the securities among the open ones of the same type, ticker, purchase
+// date and volume, which the sales of lots are kept by:
+fn kind_ordinals(securities: &[PortfolioSecurity]) -> Vec<usize> {
+    let mut counts = HashMap::new();
+    securities
+        .iter()
+        .map(|s| {
+            let key = (
+                s.sell_date.is_empty(),
+                s.security_type,
+                &s.ticker,
+                &s.purchase_date,
+                s.volume.to_bits(),
+            );
+            let n = counts.entry(key).or_insert(0);
+            *n += 1;
+            *n - 1
+        })
+        .collect()
+}
+// sort_movements orders the movements like the DataLoader does, the best first:
+fn sort_movements(movements: &mut [Movement], sort_by: SortBy) {
+    let key = |m: &Movement| match sort_by {
+        SortBy::Winner => -m.performance,
+        SortBy::Loser => m.performance,
+        SortBy::Volume => -m.volume,
+        SortBy::Volatility => -m.variance,
+        SortBy::AbsPerformance => -m.performance.abs(),
+    };
+    movements.sort_by(|a, b| key(a).total_cmp(&key(b)));
+}
+// holdings are the securities with their lot ids, after the sales of lots: an open security is a
+// sold holding per sale and an open holding with the rest.
+fn holdings<'a>(
+    securities: &'a [PortfolioSecurity],
+    sales: &[LotSale],
+) -> Result<Vec<(&'a PortfolioSecurity, Holding, String)>> {
+    let mut holdings = vec![];
+    let ordinals = kind_ordinals(securities);
+    for ((security, id), n) in securities.iter().zip(lot_ids(securities)).zip(ordinals) {
+        let holding = Holding::try_from(security)?;
+        if holding.sell_date.is_some() {
+            holdings.push((security, holding, id));
+            continue;
+        }
+        let mut open = holding.volume;
+        for sale in sales.iter().filter(|s| s.is_of(security, n)) {
+            open -= sale.volume;
+            let sold = Holding {
+                volume: sale.volume,
+                sell_date: Some(parse_date(&sale.date)?),
+                ..holding.clone()
+            };
+            holdings.push((security, sold, id.to_string()));
+        }
+        if open > lots::VOLUME_EPSILON {
+            holdings.push((
+                security,
+                Holding {
+                    volume: open,
+                    ..holding
+                },
+                id,
+            ));
+        }
+    }
+    Ok(holdings)
+}
+// is_not_found tells whether the DataLoader does not know what was requested:
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<tonic::Status>()
+            .is_some_and(|s| s.code() == tonic::Code::NotFound)
+    })
+}
+// lot is a security bought at the first close on or after its purchase:
+fn lot(id: String, holding: &Holding, closes: &Closes) -> Result<Lot> {
+    let (_, cost_basis) = closes
+        .range(holding.purchase_date..)
+        .next()
+        .ok_or_else(|| {
+            anyhow!(
+                "no close price of {} on or after {}",
+                holding.ticker,
+                holding.purchase_date
+            )
+        })?;
+    Ok(Lot {
+        id,
+        ticker: holding.ticker.to_string(),
+        purchase_date: holding.purchase_date,
+        volume: holding.volume,
+        cost_basis: *cost_basis,
+    })
+}
+
 pub type SecurityProfits = Vec<SecurityProfit>;
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SecurityProfit {
-    ticker: String,
-    security_type: i32,
-    purchase_date: String,
-    until: String,
-    purchase_price: f64,
-    profit_per_share: f64,
-    volume: f64,
-    total_profit: f64,
+    pub ticker: String,
+    pub security_type: i32,
+    pub purchase_date: String,
+    pub until: String,
+    pub purchase_price: f64,
+    pub profit_per_share: f64,
+    pub volume: f64,
+    pub total_profit: f64,
 }
 
 impl From<db_proto::SecurityProfit> for SecurityProfit {
@@ -433,6 +833,35 @@ impl From<db_proto::SecurityProfit> for SecurityProfit {
         }
     }
 }
+impl CsvRecord for SecurityProfit {
+    fn csv_header(&self) -> Vec<String> {
+        [
+            "ticker",
+            "security_type",
+            "purchase_date",
+            "until",
+            "purchase_price",
+            "profit_per_share",
+            "volume",
+            "total_profit",
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+    }
+    fn csv_row(&self, _: &[String]) -> Vec<String> {
+        vec![
+            self.ticker.to_string(),
+            self.security_type.to_string(),
+            self.purchase_date.to_string(),
+            self.until.to_string(),
+            self.purchase_price.to_string(),
+            self.profit_per_share.to_string(),
+            self.volume.to_string(),
+            self.total_profit.to_string(),
+        ]
+    }
+}
 impl From<db_proto::SecurityProfits> for Vec<SecurityProfit> {
     fn from(p: db_proto::SecurityProfits) -> Self {
         p.profits.into_iter().map(|p| p.into()).collect()
@@ -441,20 +870,117 @@ impl From<db_proto::SecurityProfits> for Vec<SecurityProfit> {
 
 pub type Movements = Vec<Movement>;
 
-#[derive(Serialize)]
-pub struct CorrelatingTickers {
-    tickers: Vec<Ticker>,
-    correlation: f64,
-    date: String,
-    period: i32,
-    volume0: f64,
-    volume1: f64,
-}
-impl From<db_proto::Correl> for CorrelatingTickers {
-    fn from(c: db_proto::Correl) -> Self {
-        let ticker0 = c.ticker0.unwrap();
-        let ticker1 = c.ticker1.unwrap();
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct MovementReq {
+    pub ticker: String,
+    pub security_type: SecurityType,
+    pub until: String,
+    pub period: Period,
+}
+impl From<MovementReq> for db_proto::MovementReq {
+    fn from(m: MovementReq) -> Self {
         Self {
+            ticker: m.ticker,
+            security_type: m.security_type.into(),
+            until: m.until,
+            period: m.period.into(),
+        }
+    }
+}
+impl Validate for MovementReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("ticker", &self.ticker);
+        v.date("until", &self.until);
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DateReq {
+    pub ticker: String,
+    pub security_type: SecurityType,
+    #[serde(default)]
+    pub intraday: Option<bool>,
+}
+impl From<DateReq> for db_proto::DateReq {
+    fn from(d: DateReq) -> Self {
+        Self {
+            ticker: d.ticker,
+            security_type: d.security_type.into(),
+            intraday: d.intraday.unwrap_or(true),
+        }
+    }
+}
+impl Validate for DateReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_blank("ticker", &self.ticker);
+    }
+}
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LatestDate {
+    pub date: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StockSplitsReq {
+    pub from: String,
+    pub until: String,
+    #[serde(default)]
+    pub limit: Option<u64>,
+}
+impl From<StockSplitsReq> for StockSplitReq {
+    fn from(s: StockSplitsReq) -> Self {
+        Self {
+            from: s.from,
+            until: s.until,
+            limit: s.limit.unwrap_or(0),
+        }
+    }
+}
+impl Validate for StockSplitsReq {
+    fn check(&self, v: &mut Validator) {
+        v.date_range(("from", &self.from), ("until", &self.until));
+        if let Some(limit) = self.limit {
+            v.limit("limit", limit);
+        }
+    }
+}
+pub type StockSplits = Vec<StockSplit>;
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct StockSplit {
+    pub ticker: String,
+    pub date: String,
+    pub numerator: f64,
+    pub denominator: f64,
+}
+impl From<db_proto::StockSplit> for StockSplit {
+    fn from(s: db_proto::StockSplit) -> Self {
+        Self {
+            ticker: s.ticker,
+            date: s.date,
+            numerator: s.numerator,
+            denominator: s.denominator,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CorrelatingTickers {
+    pub tickers: Vec<Ticker>,
+    pub correlation: f64,
+    pub date: String,
+    pub period: i32,
+    pub volume0: f64,
+    pub volume1: f64,
+}
+impl TryFrom<db_proto::Correl> for CorrelatingTickers {
+    type Error = StreamError;
+    fn try_from(c: db_proto::Correl) -> Result<Self, Self::Error> {
+        let (Some(ticker0), Some(ticker1)) = (c.ticker0, c.ticker1) else {
+            return Err(StreamError::from("Correl - missing ticker".to_string()));
+        };
+        Ok(Self {
             tickers: vec![
                 Ticker {
                     ticker: ticker0.ticker,
@@ -474,15 +1000,52 @@ impl From<db_proto::Correl> for CorrelatingTickers {
             period: c.period,
             volume0: c.volume0,
             volume1: c.volume1,
-        }
+        })
     }
 }
 
-#[derive(Deserialize)]
+impl CsvRecord for CorrelatingTickers {
+    fn csv_header(&self) -> Vec<String> {
+        [
+            "ticker0",
+            "security_type0",
+            "ticker1",
+            "security_type1",
+            "correlation",
+            "date",
+            "period",
+            "volume0",
+            "volume1",
+        ]
+        .iter()
+        .map(|h| h.to_string())
+        .collect()
+    }
+    fn csv_row(&self, _: &[String]) -> Vec<String> {
+        let ticker = |i: usize| self.tickers.get(i);
+        vec![
+            ticker(0).map(|t| t.ticker.to_string()).unwrap_or_default(),
+            ticker(0)
+                .map(|t| t.security_type.to_string())
+                .unwrap_or_default(),
+            ticker(1).map(|t| t.ticker.to_string()).unwrap_or_default(),
+            ticker(1)
+                .map(|t| t.security_type.to_string())
+                .unwrap_or_default(),
+            self.correlation.to_string(),
+            self.date.to_string(),
+            self.period.to_string(),
+            self.volume0.to_string(),
+            self.volume1.to_string(),
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct CorrelReq {
     pub tickers: Vec<BasicTicker>,
     pub until: Option<String>,
-    pub period: i32,
+    pub period: Period,
 }
 impl From<CorrelReq> for db_proto::CorrelReq {
     fn from(c: CorrelReq) -> Self {
@@ -493,86 +1056,116 @@ impl From<CorrelReq> for db_proto::CorrelReq {
                 .map(|t: BasicTicker| t.into())
                 .collect(),
             until: c.until.unwrap_or("".to_string()),
-            period: c.period,
+            period: c.period.into(),
         }
     }
 }
+impl Validate for CorrelReq {
+    fn check(&self, v: &mut Validator) {
+        v.not_empty("tickers", &self.tickers);
+        v.each("tickers", &self.tickers);
+        v.optional_date("until", self.until.as_deref());
+    }
+}
 pub struct Trading {
-    db_loader_host: String,
-    db_loader_port: u16,
-}
-
-pub type ActixStreamItem = Result<Bytes, StreamError>;
-pub type ActixStream = ReceiverStream<ActixStreamItem>;
-
-async fn gprc_to_stream<Src, ToJSON>(mut stream: Streaming<Src>, to_json: ToJSON) -> ActixStream
-where
-    Src: Send + 'static,
-    ToJSON: Send + 'static + Fn(Src) -> Result<String>,
-{
-    let (tx, rx) = mpsc::channel::<ActixStreamItem>(100);
-
-    tokio::spawn(async move {
-        tx.send(Ok(Bytes::from("["))).await?;
-        let mut entries_count = 0;
-        while let Some(entry) = stream.next().await {
-            if entries_count > 0 {
-                tx.send(Ok(Bytes::from(","))).await?;
-            }
-            entries_count += 1;
-            let entry = entry
-                .map(|entry| to_json(entry))
-                .map(|js| Bytes::from(js.unwrap()))
-                .map_err(|err| StreamError::from(err.to_string()));
-            if let Err(err) = tx.send(entry).await {
-                println!("gRPC-error: sending entry failed: {:?}", err);
-            }
-        }
-        tx.send(Ok(Bytes::from("]"))).await
-    });
-    ReceiverStream::new(rx)
+    channel: Channel,
 }
 
+// portfolio analytics carry closes forward for up to this many days, e.g. over holidays:
+const CLOSES_CARRIED_FORWARD_DAYS: i64 = 10;
+
+type TimeSeriesStream =
+    Pin<Box<dyn Stream<Item = Result<db_proto::TimeSeriesData, tonic::Status>> + Send>>;
+
 impl Trading {
-    pub fn new(envs: Envs) -> Trading {
-        Trading {
-            db_loader_host: envs.db_loader_host,
-            db_loader_port: envs.db_loader_port,
-        }
-    }
-    async fn client(&self) -> Result<DataLoaderClient<Channel>> {
-        Ok(DataLoaderClient::connect(format!(
+    // new sets up a single lazily connected channel to the DataLoader, which is shared by all
+    // requests (and actix workers). The channel reconnects on its own after the connection broke.
+    pub fn new(envs: Envs) -> Result<Trading> {
+        let endpoint = Endpoint::from_shared(format!(
             "http://{}:{}",
-            self.db_loader_host, self.db_loader_port,
-        ))
-        .await?)
+            envs.db_loader_host, envs.db_loader_port,
+        ))?
+        .connect_timeout(StdDuration::from_millis(envs.db_loader_connect_timeout_ms))
+        .timeout(StdDuration::from_millis(envs.db_loader_request_timeout_ms))
+        .http2_keep_alive_interval(StdDuration::from_secs(envs.db_loader_keep_alive_secs))
+        .keep_alive_while_idle(true)
+        .tcp_keepalive(Some(StdDuration::from_secs(envs.db_loader_keep_alive_secs)))
+        .concurrency_limit(envs.db_loader_concurrency_limit);
+        Ok(Trading {
+            channel: endpoint.connect_lazy(),
+        })
+    }
+    fn client(&self) -> DataLoaderClient<Channel> {
+        DataLoaderClient::new(self.channel.clone())
     }
 
-    pub async fn tickers(&self, filter: TickerFilter) -> Result<ActixStream> {
+    pub async fn ticker_details(&self, ticker: BasicTicker) -> Result<Ticker> {
+        Ok(self
+            .client()
+            .get_ticker_details(tonic::Request::new(ticker.into()))
+            .await?
+            .into_inner()
+            .into())
+    }
+    pub async fn tickers(&self, filter: TickerFilter, format: StreamFormat) -> Result<ActixStream> {
         println!(
             "requesting tickers - sec_type: {}, filter: {:?}",
             filter.ttype, filter.filter
         );
         let stream = self
             .client()
-            .await?
             .get_tickers(tonic::Request::new(filter.into()))
             .await?
             .into_inner();
 
-        let to_json = |t: db_proto::Ticker| -> Result<String> {
-            let t: Ticker = t.into();
-            let js = serde_json::to_string(&t).unwrap();
-            Ok(js)
-        };
-        Ok(gprc_to_stream(stream, to_json).await)
+        let convert = |t: db_proto::Ticker| -> Result<Ticker> { Ok(t.into()) };
+        Ok(gprc_to_stream(stream, convert, format).await)
+    }
+    pub async fn movement(&self, req: MovementReq) -> Result<Movement> {
+        Ok(self
+            .client()
+            .get_movement(tonic::Request::new(req.into()))
+            .await?
+            .into_inner()
+            .into())
+    }
+    pub async fn avg_movement(&self, req: MovementReq) -> Result<Movement> {
+        Ok(self
+            .client()
+            .get_avg_movement(tonic::Request::new(req.into()))
+            .await?
+            .into_inner()
+            .into())
+    }
+    pub async fn avg_movements(&self, req: MovementsReq) -> Result<Movements> {
+        Ok(self
+            .client()
+            .get_avg_movements(tonic::Request::new(req.into()))
+            .await?
+            .into_inner()
+            .movements
+            .into_iter()
+            .map(|m| m.into())
+            .collect())
     }
     pub async fn movements(&self, req: MovementsReq) -> Result<Movements> {
-        let rmv_splits = req.security_type == 0 && req.without_stock_splits.unwrap_or(false);
-        let mut client = self.client().await?;
+        let rmv_splits =
+            req.security_type == SecurityType::Stock && req.without_stock_splits.unwrap_or(false);
+        let adjust_splits =
+            req.security_type == SecurityType::Stock && req.adjusted.unwrap_or(false);
+        let sort_by = req.sort_by;
+        let mut client = self.client();
         let until = req.until.to_string();
-        let period: db_proto::Period = req.period.into();
-        let from = eval_from_date(&until, period)?;
+        let period = db_proto::Period::from(req.period);
+        let calendar_period = CalendarPeriod::from(period);
+        let from = match req.trading_days.unwrap_or(false) {
+            true => {
+                let calendar = TradingCalendar::for_security_type(req.security_type.into())
+                    .unwrap_or_else(TradingCalendar::nyse);
+                calendar_period.trading_days_before(parse_date(&until)?, &calendar)
+            }
+            false => calendar_period.before(parse_date(&until)?),
+        };
 
         let mut movements = client
             .get_movements(tonic::Request::new(req.into()))
@@ -583,47 +1176,71 @@ impl Trading {
             .map(|m| m.into())
             .collect::<Vec<Movement>>();
         println!("received movements: {}", movements.len());
+        if !rmv_splits && !adjust_splits {
+            return Ok(movements);
+        }
+        let splits = client
+            .get_stock_splits(StockSplitReq {
+                from: from.to_string(),
+                until,
+                limit: 0,
+            })
+            .await?
+            .into_inner()
+            .splits;
         if rmv_splits {
-            let splits = client
-                .get_stock_splits(StockSplitReq {
-                    from: from.to_string(),
-                    until,
-                    limit: 0,
-                })
-                .await?
-                .into_inner()
-                .splits
+            let splits = splits
                 .into_iter()
                 .map(|split| split.ticker)
                 .collect::<HashSet<_>>();
 
-            movements = movements
-                .into_iter()
-                .filter(|mov| !splits.contains(&mov.ticker.ticker))
-                .collect();
+            movements.retain(|mov| !splits.contains(&mov.ticker.ticker));
+            println!("after split filter - movements: {}", movements.len());
+        } else {
+            let mut by_ticker = HashMap::<String, Vec<db_proto::StockSplit>>::new();
+            for split in splits {
+                by_ticker
+                    .entry(split.ticker.to_string())
+                    .or_default()
+                    .push(split);
+            }
+            for mov in movements.iter_mut() {
+                if let Some(splits) = by_ticker.get(&mov.ticker.ticker) {
+                    let adjuster = SplitAdjuster::new(&mov.ticker.ticker, splits.clone())?;
+                    mov.performance = adjust_performance(mov.performance, adjuster.total());
+                }
+            }
+            // the adjusted performance changes the order:
+            sort_movements(&mut movements, sort_by);
         }
-        println!("after split filter - movements: {}", movements.len());
-
         Ok(movements)
     }
-    pub async fn correlating_tickers(&self, req: CorrelatingTickersReq) -> Result<ActixStream> {
+    pub async fn correlating_tickers(
+        &self,
+        req: CorrelatingTickersReq,
+        format: StreamFormat,
+    ) -> Result<ActixStream> {
         let stream = self
             .client()
-            .await?
             .get_correlating_tickers(tonic::Request::new(req.into()))
             .await?
             .into_inner();
 
-        let to_json = |t: db_proto::Correl| -> Result<String> {
-            let t: CorrelatingTickers = t.into();
-            let js = serde_json::to_string(&t).unwrap();
-            Ok(js)
-        };
+        let convert = |t: db_proto::Correl| -> Result<CorrelatingTickers> { Ok(t.try_into()?) };
+        Ok(gprc_to_stream(stream, convert, format).await)
+    }
+    pub async fn correlations(&self, req: CorrelReq, format: StreamFormat) -> Result<ActixStream> {
+        let stream = self
+            .client()
+            .get_correlations(tonic::Request::new(req.into()))
+            .await?
+            .into_inner();
 
-        Ok(gprc_to_stream(stream, to_json).await)
+        let convert = |t: db_proto::Correl| -> Result<CorrelatingTickers> { Ok(t.try_into()?) };
+        Ok(gprc_to_stream(stream, convert, format).await)
     }
     pub async fn mutual_correlations(&self, req: CorrelReq) -> Result<Vec<MutualCorrel>> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         let mutual_correls = client
             .get_mutual_correlations(tonic::Request::new(req.into()))
             .await?
@@ -635,23 +1252,131 @@ impl Trading {
             .collect::<Result<Vec<_>, StreamError>>()?;
         Ok(mutual_correls)
     }
-    pub async fn security_data(&self, req: TimeSeriesReq) -> Result<ActixStream> {
-        let stream = self
-            .client()
-            .await?
+    // calendar selects the ticker's trading calendar, by its security type or else its exchange:
+    async fn calendar(&self, ticker: &BasicTicker) -> Result<TradingCalendar> {
+        if let Some(calendar) = TradingCalendar::for_security_type(ticker.security_type.into()) {
+            return Ok(calendar);
+        }
+        let details = self.ticker_details(ticker.clone()).await?;
+        Ok(TradingCalendar::for_ticker(
+            details.security_type,
+            &details.custom_fields.unwrap_or_default(),
+        ))
+    }
+    // time_series requests the security data, which is split adjusted and resampled on the fly
+    // if requested:
+    async fn time_series(
+        &self,
+        req: TimeSeriesReq,
+        calendar: Option<TradingCalendar>,
+    ) -> Result<TimeSeriesStream> {
+        let mut client = self.client();
+        let resolution = req.resolution;
+        let extended_hours = req.extended_hours.unwrap_or(false);
+        let calendar = match (resolution, calendar) {
+            (Some(_), None) => Some(self.calendar(&req.ticker).await?),
+            (_, calendar) => calendar,
+        };
+        let adjuster = match req.adjusted.unwrap_or(false) {
+            true => {
+                let splits = client
+                    .get_stock_splits(StockSplitReq {
+                        from: req.from.to_string(),
+                        until: req.until.to_string(),
+                        limit: 0,
+                    })
+                    .await?
+                    .into_inner()
+                    .splits;
+                Some(SplitAdjuster::new(&req.ticker.ticker, splits)?)
+            }
+            false => None,
+        };
+        let stream = client
             .get_security_data(tonic::Request::new(req.into()))
             .await?
             .into_inner();
+        let stream: TimeSeriesStream = match adjuster {
+            Some(adjuster) if !adjuster.is_empty() => {
+                Box::pin(stream.map_ok(move |entry| adjuster.adjust(entry)))
+            }
+            _ => Box::pin(stream),
+        };
+        Ok(match (resolution, calendar) {
+            (Some(resolution), Some(calendar)) => {
+                Box::pin(resample(stream, resolution, extended_hours, calendar))
+            }
+            _ => stream,
+        })
+    }
+    pub async fn security_data(
+        &self,
+        req: TimeSeriesReq,
+        format: StreamFormat,
+    ) -> Result<ActixStream> {
+        let stream = self.time_series(req, None).await?;
 
-        let to_json = |t: db_proto::TimeSeriesData| -> Result<String> {
-            let t: TimeSeriesData = t.into();
-            let js = serde_json::to_string(&t).unwrap();
-            Ok(js)
+        let convert = |t: db_proto::TimeSeriesData| -> Result<TimeSeriesData> { Ok(t.into()) };
+        Ok(gprc_to_stream(stream, convert, format).await)
+    }
+    // indicators computes the requested indicators from the security data. The data is requested
+    // with enough history before from to warm the indicators up.
+    pub async fn indicators(
+        &self,
+        req: IndicatorsReq,
+        format: StreamFormat,
+    ) -> Result<ActixStream> {
+        let resolution = req.resolution.unwrap_or(Resolution::Day);
+        let warm_up = req.indicators.iter().map(|i| i.warm_up()).max();
+        let set = IndicatorSet::new(&req.indicators, &req.from, resolution.is_intraday());
+        let calendar = self.calendar(&req.ticker).await?;
+        let from = warm_up_start(
+            &req.from,
+            resolution,
+            warm_up.unwrap_or_default(),
+            &calendar,
+        )?;
+        let ts_req = TimeSeriesReq {
+            ticker: req.ticker,
+            from,
+            until: req.until,
+            adjusted: req.adjusted,
+            resolution: Some(resolution),
+            extended_hours: req.extended_hours,
         };
-        Ok(gprc_to_stream(stream, to_json).await)
+        let stream = self
+            .time_series(ts_req, Some(calendar))
+            .await?
+            .scan(set, |set, entry| {
+                future::ready(Some(entry.map(|entry| set.update(&entry))))
+            })
+            .filter_map(|entry| future::ready(entry.transpose()));
+
+        let convert = |t: db_proto::TimeSeriesData| -> Result<TimeSeriesData> { Ok(t.into()) };
+        Ok(gprc_to_stream(Box::pin(stream), convert, format).await)
+    }
+    pub async fn latest_security_data_date(&self, req: DateReq) -> Result<LatestDate> {
+        let date = self
+            .client()
+            .get_latest_security_data_date(tonic::Request::new(req.into()))
+            .await?
+            .into_inner()
+            .date;
+        Ok(LatestDate { date })
+    }
+    // security_data_columnar streams the time series as arrow ipc stream or parquet file:
+    pub async fn security_data_columnar(
+        &self,
+        req: TimeSeriesReq,
+        format: ColumnarFormat,
+    ) -> Result<ActixStream> {
+        let calendar = self.calendar(&req.ticker).await?;
+        let tz = calendar.timezone();
+        let stream = self.time_series(req, Some(calendar)).await?;
+        Ok(time_series_to_columnar(stream, format, BATCH_SIZE, tz).await)
     }
     pub async fn portfolio(&self, portfolio_id: String) -> Result<Portfolio> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         Ok(client
             .get_portfolio(tonic::Request::new(db_proto::Id { id: portfolio_id }))
             .await?
@@ -659,7 +1384,7 @@ impl Trading {
             .into())
     }
     pub async fn portfolios(&self, filter: String) -> Result<Portfolios> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         Ok(client
             .get_portfolios(tonic::Request::new(db_proto::PortfolioReq {
                 filter: filter.to_string(),
@@ -669,7 +1394,7 @@ impl Trading {
             .into())
     }
     pub async fn portfolio_securities(&self, portfolio_id: String) -> Result<PortfolioSecurities> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         Ok(client
             .get_portfolio_securities(tonic::Request::new(db_proto::Id { id: portfolio_id }))
             .await?
@@ -679,8 +1404,372 @@ impl Trading {
             .map(|s| s.into())
             .collect())
     }
+    // portfolio_valuation returns the profits of all securities of a portfolio until the date:
+    pub async fn portfolio_valuation(
+        &self,
+        portfolio_id: String,
+        until: String,
+    ) -> Result<SecurityProfits> {
+        let mut client = self.client();
+        let securities = client
+            .get_portfolio_securities(tonic::Request::new(db_proto::Id { id: portfolio_id }))
+            .await?
+            .into_inner()
+            .securities
+            .into_iter()
+            .map(|s| db_proto::security_profit_req::Security {
+                security_type: s.security_type,
+                ticker: s.ticker,
+                volume: s.volume,
+                purchase_date: s.purchase_date,
+                // securities, that are still held, have no sell date:
+                sell_date: Some(s.sell_date).filter(|d| !d.is_empty()),
+            })
+            .collect();
+        Ok(client
+            .get_portfolio_profits(tonic::Request::new(db_proto::SecurityProfitReq {
+                securities,
+                until,
+                partition: db_proto::Period::Day as i32,
+            }))
+            .await?
+            .into_inner()
+            .into())
+    }
+    // portfolio_analytics reconstructs the daily holdings of a portfolio from its securities and
+    // the sales of its lots, values them with the daily closes and measures the performance
+    // between from and until:
+    pub async fn portfolio_analytics(
+        &self,
+        req: PortfolioAnalyticsReq,
+        sales: &[LotSale],
+    ) -> Result<Performance> {
+        let (from, until) = (parse_date(&req.from)?, parse_date(&req.until)?);
+        // unknown portfolios have no securities, but should not look like empty ones:
+        self.portfolio(req.portfolio_id.to_string()).await?;
+        let securities = self.portfolio_securities(req.portfolio_id).await?;
+        let held = holdings(&securities, sales)?;
+        let mut holdings = vec![];
+        let mut tickers = HashMap::new();
+        for (security, holding, _) in held {
+            if holding.purchase_date > until || holding.sell_date.is_some_and(|s| s <= from) {
+                continue;
+            }
+            let security_type = SecurityType::try_from(security.security_type)?;
+            tickers.insert(holding.ticker.to_string(), security_type);
+            holdings.push(holding);
+        }
+        let start = from - Duration::days(CLOSES_CARRIED_FORWARD_DAYS);
+        let mut closes = HashMap::new();
+        for (ticker, security_type) in tickers {
+            let ticker = BasicTicker {
+                ticker,
+                security_type,
+            };
+            let daily = self.daily_closes(ticker.clone(), start, until).await?;
+            closes.insert(ticker.ticker, daily);
+        }
+        let splits = match holdings.iter().map(|h| h.purchase_date).min() {
+            Some(first_purchase) => self.splits(first_purchase, until, &closes).await?,
+            None => vec![],
+        };
+        let curve = analytics::equity_curve(&holdings, &closes, &splits, from, until)?;
+        let risk_free_rate = req.risk_free_rate.unwrap_or_default();
+        let mut performance = analytics::performance(curve, risk_free_rate);
+        if let Some(benchmark) = req.benchmark {
+            let ticker = benchmark.ticker.to_string();
+            let closes = self.daily_closes(benchmark, start, until).await?;
+            performance.benchmark = Some(analytics::benchmark(
+                &ticker,
+                &performance.equity_curve,
+                &closes,
+                risk_free_rate,
+            )?);
+        }
+        Ok(performance)
+    }
+    // close returns the latest daily close of a ticker on or before the date:
+    pub async fn close(&self, ticker: BasicTicker, date: NaiveDate) -> Result<f64> {
+        let name = ticker.ticker.to_string();
+        let start = date - Duration::days(CLOSES_CARRIED_FORWARD_DAYS);
+        let closes = self.daily_closes(ticker, start, date).await?;
+        analytics::latest_close(&closes, date)
+            .ok_or_else(|| anyhow!("no close price of {} on or before {}", name, date))
+    }
+    // daily_closes returns the unadjusted closes of the daily bars:
+    async fn daily_closes(
+        &self,
+        ticker: BasicTicker,
+        from: NaiveDate,
+        until: NaiveDate,
+    ) -> Result<Closes> {
+        let req = TimeSeriesReq {
+            ticker,
+            from: from.to_string(),
+            until: until.to_string(),
+            adjusted: None,
+            resolution: Some(Resolution::Day),
+            extended_hours: None,
+        };
+        let mut stream = self.time_series(req, None).await?;
+        let mut closes = Closes::new();
+        while let Some(entry) = stream.try_next().await? {
+            if let Some(close) = entry.values.get("close") {
+                closes.insert(parse_date(&entry.date)?, *close);
+            }
+        }
+        Ok(closes)
+    }
+    // splits returns the stock splits of the tickers between from and until:
+    async fn splits(
+        &self,
+        from: NaiveDate,
+        until: NaiveDate,
+        tickers: &HashMap<String, Closes>,
+    ) -> Result<Vec<Split>> {
+        self.client()
+            .get_stock_splits(StockSplitReq {
+                from: from.to_string(),
+                until: until.to_string(),
+                limit: 0,
+            })
+            .await?
+            .into_inner()
+            .splits
+            .into_iter()
+            .filter(|s| tickers.contains_key(&s.ticker) && s.numerator > 0.0 && s.denominator > 0.0)
+            .map(|s| {
+                Ok(Split {
+                    date: parse_date(&s.date)?,
+                    ratio: s.numerator / s.denominator,
+                    ticker: s.ticker,
+                })
+            })
+            .collect()
+    }
+    // portfolio_lots reports the gains of the lots of a portfolio: the lots sold until the date
+    // are realized at the close of their sale, the open lots are valued at the close of the date.
+    pub async fn portfolio_lots(&self, req: LotReportReq, sales: &[LotSale]) -> Result<LotReport> {
+        let until = parse_date(&req.until)?;
+        self.portfolio(req.portfolio_id.to_string()).await?;
+        let securities = self.portfolio_securities(req.portfolio_id).await?;
+        let mut held = holdings(&securities, sales)?;
+        held.retain(|(_, holding, _)| holding.purchase_date <= until);
+        let (closes, splits) = self.lot_prices(&held, until).await?;
+        let (mut realized, mut unrealized) = (vec![], vec![]);
+        for (security, holding, id) in held {
+            let ticker_closes = &closes[&holding.ticker];
+            let lot = lot(id, &holding, ticker_closes)?;
+            let (date, gains) = match holding.sell_date.filter(|d| *d <= until) {
+                Some(sell_date) => (sell_date, &mut realized),
+                None => (until, &mut unrealized),
+            };
+            let lot = lot.as_of(&splits, date);
+            let price = analytics::latest_close(ticker_closes, date).ok_or_else(|| {
+                anyhow!(
+                    "no close price of {} on or before {}",
+                    security.ticker,
+                    date
+                )
+            })?;
+            gains.push(lots::gain(&lot, lot.volume, date, price));
+        }
+        Ok(lots::report(realized, unrealized))
+    }
+    // sell_lots sells shares of a ticker from the open lots chosen by the method. It returns the
+    // realized gains and the sales, which are kept with the lots, as the DataLoader cannot tell
+    // the securities of a day apart.
+    pub async fn sell_lots(
+        &self,
+        req: LotSellReq,
+        sales: &[LotSale],
+    ) -> Result<(Vec<LotGain>, Vec<LotSale>)> {
+        let date = parse_date(&req.date)?;
+        self.portfolio(req.portfolio_id.to_string()).await?;
+        let securities = self
+            .portfolio_securities(req.portfolio_id.to_string())
+            .await?;
+        let security_type = i32::from(req.security_type);
+        let mut held = holdings(&securities, sales)?;
+        held.retain(|(security, holding, _)| {
+            security.ticker == req.ticker
+                && security.security_type == security_type
+                && holding.sell_date.is_none()
+                && holding.purchase_date <= date
+        });
+        let (closes, splits) = self.lot_prices(&held, date).await?;
+        let closes = closes.get(&req.ticker).cloned().unwrap_or_default();
+        let open = held
+            .iter()
+            .map(|(_, holding, id)| Ok(lot(id.to_string(), holding, &closes)?.as_of(&splits, date)))
+            .collect::<Result<Vec<_>>>()?;
+        let allocations = lots::allocate(&open, req.volume, req.method, &req.lot_ids)?;
+        let price = analytics::latest_close(&closes, date)
+            .ok_or_else(|| anyhow!("no close price of {} on or before {}", req.ticker, date))?;
+
+        let ordinals = kind_ordinals(&securities);
+        let mut gains = vec![];
+        let mut sold = vec![];
+        for allocation in allocations {
+            let i = open.iter().position(|l| l.id == allocation.lot_id);
+            let i = i.ok_or_else(|| anyhow!("unknown lot {}", allocation.lot_id))?;
+            gains.push(lots::gain(&open[i], allocation.volume, date, price));
+            // the sold part of the lot, in the shares of its purchase:
+            let volume = match allocation.closes {
+                true => held[i].1.volume,
+                false => held[i].1.volume * allocation.volume / open[i].volume,
+            };
+            let security = held[i].0;
+            let k = securities.iter().position(|s| std::ptr::eq(s, security));
+            let k = k.ok_or_else(|| anyhow!("unknown lot {}", allocation.lot_id))?;
+            sold.push(LotSale {
+                security_type,
+                ticker: security.ticker.to_string(),
+                purchase_date: security.purchase_date.to_string(),
+                security_volume: security.volume,
+                n: ordinals[k],
+                volume,
+                date: req.date.to_string(),
+            });
+        }
+        Ok((gains, sold))
+    }
+    // fx_valuation values the securities of a portfolio in its base currency. Sold securities
+    // are valued at their sale, the others at the date.
+    pub async fn fx_valuation(
+        &self,
+        req: FxValuationReq,
+        currencies: &PortfolioCurrencies,
+        sales: &[LotSale],
+    ) -> Result<FxValuation> {
+        let until = parse_date(&req.until)?;
+        self.portfolio(req.portfolio_id.to_string()).await?;
+        let securities = self
+            .portfolio_securities(req.portfolio_id.to_string())
+            .await?;
+        let mut held = holdings(&securities, sales)?;
+        held.retain(|(_, holding, _)| holding.purchase_date <= until);
+        let (closes, splits) = self.lot_prices(&held, until).await?;
+        let base = &currencies.base_currency;
+        let start = held
+            .iter()
+            .map(|(_, h, _)| h.purchase_date)
+            .min()
+            .unwrap_or(until)
+            - Duration::days(CLOSES_CARRIED_FORWARD_DAYS);
+        let mut rates: HashMap<String, (String, FxRates)> = HashMap::new();
+        let mut positions = vec![];
+        for (security, holding, id) in held.iter() {
+            if !rates.contains_key(&security.ticker) {
+                let ticker = BasicTicker {
+                    ticker: security.ticker.to_string(),
+                    security_type: SecurityType::try_from(security.security_type)?,
+                };
+                let currency = self.currency(&ticker, &currencies.currencies).await?;
+                let fx_rates = self.fx_rates(&currency, base, start, until).await?;
+                rates.insert(security.ticker.to_string(), (currency, fx_rates));
+            }
+            let (currency, fx_rates) = &rates[&security.ticker];
+            let ticker_closes = &closes[&holding.ticker];
+            let lot = lot(id.to_string(), holding, ticker_closes)?;
+            let date = holding.sell_date.filter(|d| *d <= until).unwrap_or(until);
+            let volume = lot.as_of(&splits, date).volume;
+            let missing =
+                |what: &str| anyhow!("no {} of {} on or before {}", what, security.ticker, date);
+            let price = analytics::latest_close(ticker_closes, date)
+                .ok_or_else(|| missing("close price"))?;
+            let conversion = fx::Conversion {
+                local_cost: lot.volume * lot.cost_basis,
+                local_value: volume * price,
+                purchase_rate: fx_rates
+                    .at(holding.purchase_date)
+                    .ok_or_else(|| missing("fx rate"))?,
+                rate: fx_rates.at(date).ok_or_else(|| missing("fx rate"))?,
+            };
+            positions.push(conversion.position(
+                &security.ticker,
+                currency,
+                volume,
+                holding.purchase_date,
+                date,
+            ));
+        }
+        Ok(fx::valuation(&req.portfolio_id, base, until, positions))
+    }
+    // currency returns the trading currency of a ticker: the explicit one, the one of its custom
+    // fields, or else the default currency:
+    pub async fn currency(
+        &self,
+        ticker: &BasicTicker,
+        explicit: &BTreeMap<String, String>,
+    ) -> Result<String> {
+        if let Some(currency) = explicit.get(&ticker.ticker) {
+            return Ok(currency.to_string());
+        }
+        let details = self.ticker_details(ticker.clone()).await?;
+        Ok(fx::currency_of(&details.custom_fields.unwrap_or_default())
+            .unwrap_or(fx::DEFAULT_CURRENCY.to_string()))
+    }
+    // fx_rates returns the daily rates of a currency in another, from the closes of their pair:
+    pub async fn fx_rates(
+        &self,
+        from: &str,
+        to: &str,
+        start: NaiveDate,
+        until: NaiveDate,
+    ) -> Result<FxRates> {
+        if from == to {
+            return Ok(FxRates::same_currency());
+        }
+        for (pair, inverted) in [(fx::pair(from, to), false), (fx::pair(to, from), true)] {
+            let ticker = BasicTicker {
+                ticker: pair,
+                security_type: SecurityType::Currency,
+            };
+            match self.daily_closes(ticker, start, until).await {
+                Ok(closes) if !closes.is_empty() => return Ok(FxRates::quoted(closes, inverted)),
+                Ok(_) => {}
+                Err(err) if is_not_found(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Err(FxError::MissingRates {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+        .into())
+    }
+    // lot_prices returns the daily closes and splits of the tickers from their first purchase:
+    async fn lot_prices(
+        &self,
+        held: &[(&PortfolioSecurity, Holding, String)],
+        until: NaiveDate,
+    ) -> Result<(HashMap<String, Closes>, Vec<Split>)> {
+        let mut first_purchases = HashMap::new();
+        for (security, holding, _) in held {
+            let first = first_purchases
+                .entry((security.ticker.to_string(), security.security_type))
+                .or_insert(holding.purchase_date);
+            *first = holding.purchase_date.min(*first);
+        }
+        let mut closes = HashMap::new();
+        for ((ticker, security_type), first) in first_purchases.iter() {
+            let ticker = BasicTicker {
+                ticker: ticker.to_string(),
+                security_type: SecurityType::try_from(*security_type)?,
+            };
+            let start = *first - Duration::days(CLOSES_CARRIED_FORWARD_DAYS);
+            let daily = self.daily_closes(ticker.clone(), start, until).await?;
+            closes.insert(ticker.ticker, daily);
+        }
+        let splits = match first_purchases.values().min() {
+            Some(first) => self.splits(*first, until, &closes).await?,
+            None => vec![],
+        };
+        Ok((closes, splits))
+    }
     pub async fn portfolio_profits(&self, req: SecurityProfitReq) -> Result<SecurityProfits> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         Ok(client
             .get_portfolio_profits(tonic::Request::new(req.into()))
             .await?
@@ -688,7 +1777,7 @@ impl Trading {
             .into())
     }
     pub async fn create_portfolio(&self, name: &str, description: &str) -> Result<Portfolio> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         Ok(client
             .create_portfolio(tonic::Request::new(db_proto::CreatePortfolioReq {
                 name: name.to_string(),
@@ -699,17 +1788,136 @@ impl Trading {
             .into())
     }
     pub async fn buy_security(&self, security: PortfolioSecurity) -> Result<()> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         client
             .buy_security(tonic::Request::new(security.into()))
             .await?;
         Ok(())
     }
     pub async fn sell_security(&self, security: PortfolioSecurity) -> Result<()> {
-        let mut client = self.client().await?;
+        let mut client = self.client();
         client
             .sell_security(tonic::Request::new(security.into()))
             .await?;
         Ok(())
     }
+    pub async fn delete_portfolio(&self, portfolio_id: String) -> Result<()> {
+        self.client()
+            .delete_portfolio(tonic::Request::new(db_proto::Id { id: portfolio_id }))
+            .await?;
+        Ok(())
+    }
+    pub async fn delete_portfolio_security(&self, security: PortfolioSecurity) -> Result<()> {
+        self.client()
+            .delete_portfolio_security(tonic::Request::new(security.into()))
+            .await?;
+        Ok(())
+    }
+    pub async fn stock_splits(&self, req: StockSplitsReq) -> Result<StockSplits> {
+        Ok(self
+            .client()
+            .get_stock_splits(tonic::Request::new(req.into()))
+            .await?
+            .into_inner()
+            .splits
+            .into_iter()
+            .map(|s| s.into())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Fixtures, MockLoader, MockServer};
+
+    #[tokio::test]
+    async fn reuses_connection() -> Result<()> {
+        let server = MockServer::start().await?;
+        let trading = Trading::new(server.envs())?;
+
+        // lazy: nothing is connected before the first request
+        assert_eq!(server.connections(), 0);
+        for _ in 0..10 {
+            let portfolios = trading.portfolios("".to_string()).await?;
+            assert_eq!(portfolios.len(), 2);
+        }
+        let concurrent = (0..10).map(|_| trading.portfolios("".to_string()));
+        for res in futures::future::join_all(concurrent).await {
+            assert_eq!(res?.len(), 2);
+        }
+        assert_eq!(server.connections(), 1);
+
+        server.stop().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn split_window_in_trading_days() -> Result<()> {
+        let mut fixtures = Fixtures::default();
+        // a split over the weekend before the holiday monday:
+        fixtures.stock_splits.push(db_proto::StockSplit {
+            ticker: "MSFT".to_string(),
+            date: "2024-01-13".to_string(),
+            numerator: 2.0,
+            denominator: 1.0,
+        });
+        let server = MockServer::serve(MockLoader::new(fixtures), "127.0.0.1:0".parse()?).await?;
+        let trading = Trading::new(server.envs())?;
+        let req = |trading_days| MovementsReq {
+            security_type: SecurityType::Stock,
+            sort_by: SortBy::Winner,
+            until: "2024-01-16".to_string(),
+            period: Period::Day,
+            limit: 10,
+            min_volume: 0,
+            min_variance: 0.0,
+            max_variance: 0.0,
+            without_stock_splits: Some(true),
+            adjusted: None,
+            trading_days: Some(trading_days),
+        };
+        let tickers = |movements: Movements| {
+            movements
+                .into_iter()
+                .map(|m| m.ticker.ticker)
+                .collect::<Vec<_>>()
+        };
+        // a calendar day back is the holiday, a session back is the friday before the split:
+        assert_eq!(
+            tickers(trading.movements(req(false)).await?),
+            vec!["MSFT", "AAPL"]
+        );
+        assert_eq!(tickers(trading.movements(req(true)).await?), vec!["AAPL"]);
+
+        server.stop().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_restart() -> Result<()> {
+        let loader = MockLoader::new(Fixtures::default());
+        let server = MockServer::serve(loader.clone(), "127.0.0.1:0".parse()?).await?;
+        let addr = server.addr();
+        let trading = Trading::new(server.envs())?;
+        assert_eq!(trading.portfolios("".to_string()).await?.len(), 2);
+
+        server.stop().await;
+        assert!(trading.portfolios("".to_string()).await.is_err());
+
+        let server = MockServer::serve(loader, addr).await?;
+        let mut recovered = false;
+        for _ in 0..20 {
+            if trading.portfolios("".to_string()).await.is_ok() {
+                recovered = true;
+                break;
+            }
+            tokio::time::sleep(StdDuration::from_millis(50)).await;
+        }
+        assert!(recovered);
+        assert_eq!(server.connections(), 1);
+
+        server.stop().await;
+        Ok(())
+    }
 }
@@ -0,0 +1,263 @@
+// validate checks requests before they are sent to the DataLoader. Request types implement
+// Validate by describing their fields to a Validator, which collects every failed check, so a
+// client gets all field errors of a request at once (as a 400 with the list of field errors).
+use crate::time::parse_date;
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+// limits of the number of results per request:
+pub const MAX_LIMIT: u64 = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct ValidationError {
+    pub errors: Vec<FieldError>,
+}
+impl std::error::Error for ValidationError {}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self
+            .errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "invalid request - {}", errors.join("; "))
+    }
+}
+
+pub trait Validate {
+    fn check(&self, v: &mut Validator);
+
+    fn validate(&self) -> Result<()> {
+        let mut v = Validator::default();
+        self.check(&mut v);
+        v.finish()
+    }
+}
+
+// Validator collects field errors. Nested structs and lists are checked with a prefix, so their
+// errors are reported as e.g. "ticker.ticker" or "securities[1].volume".
+#[derive(Default)]
+pub struct Validator {
+    prefix: String,
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn error(&mut self, field: &str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: format!("{}{}", self.prefix, field),
+            message: message.into(),
+        });
+    }
+    pub fn check(&mut self, field: &str, valid: bool, message: &str) {
+        if !valid {
+            self.error(field, message);
+        }
+    }
+    pub fn nested<T: Validate>(&mut self, field: &str, value: &T) {
+        let nested = format!("{}{}.", self.prefix, field);
+        let prefix = std::mem::replace(&mut self.prefix, nested);
+        value.check(self);
+        self.prefix = prefix;
+    }
+    pub fn each<T: Validate>(&mut self, field: &str, values: &[T]) {
+        for (i, value) in values.iter().enumerate() {
+            self.nested(&format!("{}[{}]", field, i), value);
+        }
+    }
+
+    pub fn not_blank(&mut self, field: &str, value: &str) {
+        self.check(field, !value.trim().is_empty(), "must not be empty");
+    }
+    pub fn not_empty<T>(&mut self, field: &str, values: &[T]) {
+        self.check(field, !values.is_empty(), "must not be empty");
+    }
+    // date checks the format with time::parse_date and returns the date for further checks:
+    pub fn date(&mut self, field: &str, value: &str) -> Option<NaiveDate> {
+        match parse_date(value) {
+            Ok(date) => Some(date),
+            Err(_) => {
+                self.error(
+                    field,
+                    format!("invalid date '{}' - expected e.g. '2006-12-31'", value),
+                );
+                None
+            }
+        }
+    }
+    // optional_date accepts a missing or empty date:
+    pub fn optional_date(&mut self, field: &str, value: Option<&str>) -> Option<NaiveDate> {
+        value
+            .filter(|d| !d.is_empty())
+            .and_then(|d| self.date(field, d))
+    }
+    // date_range checks both dates and, if they are valid, that from is not after until:
+    pub fn date_range(&mut self, from: (&str, &str), until: (&str, &str)) {
+        let from_date = self.date(from.0, from.1);
+        let until_date = self.date(until.0, until.1);
+        self.ordered_dates(from, from_date, until, until_date);
+    }
+    pub fn ordered_dates(
+        &mut self,
+        from: (&str, &str),
+        from_date: Option<NaiveDate>,
+        until: (&str, &str),
+        until_date: Option<NaiveDate>,
+    ) {
+        if let (Some(f), Some(u)) = (from_date, until_date) {
+            if f > u {
+                self.error(
+                    from.0,
+                    format!("{} must not be after {} ({})", from.1, until.0, until.1),
+                );
+            }
+        }
+    }
+    pub fn limit(&mut self, field: &str, value: u64) {
+        if value == 0 || value > MAX_LIMIT {
+            self.error(field, format!("must be between 1 and {}", MAX_LIMIT));
+        }
+    }
+    pub fn positive(&mut self, field: &str, value: f64) {
+        self.check(
+            field,
+            value.is_finite() && value > 0.0,
+            "must be a positive number",
+        );
+    }
+    pub fn non_negative(&mut self, field: &str, value: f64) {
+        self.check(
+            field,
+            value.is_finite() && value >= 0.0,
+            "must not be negative",
+        );
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(ValidationError {
+            errors: self.errors,
+        }
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Leg {
+        ticker: String,
+        volume: f64,
+    }
+    impl Validate for Leg {
+        fn check(&self, v: &mut Validator) {
+            v.not_blank("ticker", &self.ticker);
+            v.positive("volume", self.volume);
+        }
+    }
+    struct Order {
+        from: String,
+        until: String,
+        limit: u64,
+        legs: Vec<Leg>,
+    }
+    impl Validate for Order {
+        fn check(&self, v: &mut Validator) {
+            v.date_range(("from", &self.from), ("until", &self.until));
+            v.limit("limit", self.limit);
+            v.not_empty("legs", &self.legs);
+            v.each("legs", &self.legs);
+        }
+    }
+    fn errors(order: &Order) -> Vec<String> {
+        match order.validate() {
+            Ok(()) => vec![],
+            Err(err) => err
+                .downcast::<ValidationError>()
+                .unwrap()
+                .errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn collects_field_errors() {
+        let mut order = Order {
+            from: "2024-01-02".to_string(),
+            until: "2024-01-31".to_string(),
+            limit: 10,
+            legs: vec![Leg {
+                ticker: "AAPL".to_string(),
+                volume: 1.0,
+            }],
+        };
+        assert!(errors(&order).is_empty());
+
+        order.from = "2024-02-01".to_string();
+        order.limit = MAX_LIMIT + 1;
+        order.legs.push(Leg {
+            ticker: " ".to_string(),
+            volume: -2.0,
+        });
+        assert_eq!(
+            errors(&order),
+            vec![
+                "from: 2024-02-01 must not be after until (2024-01-31)",
+                "limit: must be between 1 and 10000",
+                "legs[1].ticker: must not be empty",
+                "legs[1].volume: must be a positive number",
+            ]
+        );
+
+        order.until = "31.01.2024".to_string();
+        order.limit = 0;
+        order.legs.clear();
+        assert_eq!(
+            errors(&order),
+            vec![
+                "until: invalid date '31.01.2024' - expected e.g. '2006-12-31'",
+                "limit: must be between 1 and 10000",
+                "legs: must not be empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        let mut v = Validator::default();
+        v.positive("a", 0.0);
+        v.positive("b", f64::NAN);
+        v.non_negative("c", 0.0);
+        v.non_negative("d", -0.5);
+        v.limit("e", MAX_LIMIT);
+        let err = v.finish().unwrap_err();
+        let fields = err
+            .downcast_ref::<ValidationError>()
+            .unwrap()
+            .errors
+            .iter()
+            .map(|e| e.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["a", "b", "d"]);
+        assert!(err.to_string().starts_with("invalid request - a: "));
+    }
+}